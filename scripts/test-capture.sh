@@ -0,0 +1,132 @@
+#!/bin/bash
+# Headless check for `rspin --capture --region`
+# Starts sway on the headless backend with a four-colour background, pins a
+# region straddling the centre, replaces the background and reads the pinned
+# pixels back with grim. Runs once per output transform.
+#
+# Requires: sway, swaybg, grim, and a built rspin (cargo build --release)
+# Usage: scripts/test-capture.sh [path/to/rspin]
+
+set -eu
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+RSPIN="${1:-$SCRIPT_DIR/../target/release/rspin}"
+TRANSFORMS=(normal 90 180 270 flipped flipped-90)
+
+# Output size and the captured region (logical pixels)
+WIDTH=640
+HEIGHT=480
+REGION="220,140 200x200"
+
+# Expected colour at the centre of each quadrant of the region
+SAMPLES=(
+    "270,190 ff0000"
+    "370,190 00ff00"
+    "270,290 0000ff"
+    "370,290 ffffff"
+)
+
+for tool in sway swaybg grim; do
+    if ! command -v "$tool" > /dev/null; then
+        echo "Missing $tool" >&2
+        exit 2
+    fi
+done
+if [ ! -x "$RSPIN" ]; then
+    echo "rspin binary not found at $RSPIN" >&2
+    exit 2
+fi
+
+WORK="$(mktemp -d)"
+SWAY_PID=""
+cleanup() {
+    if [ -n "$SWAY_PID" ]; then
+        kill "$SWAY_PID" 2> /dev/null || true
+        wait "$SWAY_PID" 2> /dev/null || true
+    fi
+    rm -rf "$WORK"
+}
+trap cleanup EXIT
+
+# Background: red, green, blue and white quadrants as a binary PPM
+LC_ALL=C awk -v w="$WIDTH" -v h="$HEIGHT" 'BEGIN {
+    printf "P6\n%d %d\n255\n", w, h
+    for (y = 0; y < h; y++)
+        for (x = 0; x < w; x++) {
+            right = x >= w / 2; bottom = y >= h / 2
+            if (!right && !bottom) printf "%c%c%c", 255, 0, 0
+            else if (right && !bottom) printf "%c%c%c", 0, 255, 0
+            else if (!right) printf "%c%c%c", 0, 0, 255
+            else printf "%c%c%c", 255, 255, 255
+        }
+}' > "$WORK/background.ppm"
+
+# Colour of one logical pixel as rrggbb
+pixel() {
+    grim -g "$1 1x1" -t ppm - | tail -c 3 | od -An -tx1 | tr -d ' \n'
+}
+
+failures=0
+for transform in "${TRANSFORMS[@]}"; do
+    # Rotated outputs swap the mode's width and height in logical space
+    case "$transform" in
+        90 | 270 | flipped-90 | flipped-270) mode="${HEIGHT}x${WIDTH}" ;;
+        *) mode="${WIDTH}x${HEIGHT}" ;;
+    esac
+    cat > "$WORK/config" << EOF
+output HEADLESS-1 mode $mode transform $transform
+output HEADLESS-1 bg $WORK/background.ppm stretch
+default_border none
+EOF
+
+    export XDG_RUNTIME_DIR="$WORK/runtime-$transform"
+    mkdir -m 700 -p "$XDG_RUNTIME_DIR"
+    WLR_BACKENDS=headless WLR_RENDERER=pixman WLR_LIBINPUT_NO_DEVICES=1 \
+        sway -c "$WORK/config" > "$WORK/sway-$transform.log" 2>&1 &
+    SWAY_PID=$!
+
+    # Wait for sway's IPC socket; the Wayland socket exists by then
+    for _ in $(seq 50); do
+        SWAYSOCK="$(find "$XDG_RUNTIME_DIR" -name 'sway-ipc.*' | head -n 1)"
+        [ -n "$SWAYSOCK" ] && break
+        sleep 0.1
+    done
+    if [ -z "$SWAYSOCK" ]; then
+        echo "sway did not start:" >&2
+        tail "$WORK/sway-$transform.log" >&2
+        exit 2
+    fi
+    WAYLAND_DISPLAY="$(find "$XDG_RUNTIME_DIR" -name 'wayland-*' ! -name '*.lock' -printf '%f\n' | head -n 1)"
+    export SWAYSOCK WAYLAND_DISPLAY
+    sleep 1
+
+    "$RSPIN" --capture --region "$REGION" > "$WORK/rspin-$transform.log" 2>&1 &
+    RSPIN_PID=$!
+    sleep 1
+
+    # Only the pin still shows the quadrants once the background is black
+    swaymsg output HEADLESS-1 bg "#000000" solid_color > /dev/null
+    sleep 1
+
+    before=$failures
+    for sample in "${SAMPLES[@]}"; do
+        read -r position expected <<< "$sample"
+        actual="$(pixel "$position")"
+        if [ "$actual" != "$expected" ]; then
+            echo "FAIL [$transform] $position: expected $expected, got $actual"
+            failures=$((failures + 1))
+        fi
+    done
+    [ "$failures" -eq "$before" ] && echo "ok [$transform]"
+
+    kill "$RSPIN_PID" 2> /dev/null || true
+    kill "$SWAY_PID" 2> /dev/null || true
+    wait "$SWAY_PID" 2> /dev/null || true
+    SWAY_PID=""
+done
+
+if [ "$failures" -gt 0 ]; then
+    echo "$failures pixel check(s) failed"
+    exit 1
+fi
+echo "All captures match"
@@ -0,0 +1,1329 @@
+// Screen capture module
+// Implements `--capture`: freezes an output with ext-image-copy-capture (or
+// wlr-screencopy), lets the user drag out a region on a fullscreen layer-shell
+// overlay and returns the cropped pixels for pinning
+
+use crate::cpu_renderer::{Damage, Rect, ShmSwapchain};
+use crate::image_loader::ImageData;
+use crate::resample::{self, FilterMode};
+use anyhow::{anyhow, bail, Context, Result};
+use image::{imageops, ImageBuffer, Rgba};
+use log::{debug, error, info, warn};
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState},
+    delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
+    delegate_registry, delegate_seat, delegate_shm,
+    output::{OutputHandler, OutputInfo, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    seat::{
+        keyboard::{KeyEvent, KeyboardHandler, Keysym, Modifiers},
+        pointer::{
+            CursorIcon, PointerEvent, PointerEventKind, PointerHandler, ThemeSpec, ThemedPointer,
+        },
+        Capability, SeatHandler, SeatState,
+    },
+    shell::{
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+        WaylandSurface,
+    },
+    shm::{slot::SlotPool, Shm, ShmHandler},
+};
+use wayland_client::{
+    globals::{registry_queue_init, GlobalList},
+    protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_shm, wl_surface},
+    Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+};
+use wayland_protocols::ext::image_capture_source::v1::client::{
+    ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+    ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1,
+};
+use wayland_protocols::ext::image_copy_capture::v1::client::{
+    ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+    ext_image_copy_capture_manager_v1::{self, ExtImageCopyCaptureManagerV1},
+    ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+/// Mouse button constants
+const BTN_LEFT: u32 = 272;
+const BTN_RIGHT: u32 = 273;
+
+/// Brightness of the frozen screen outside the selection (8.8 fixed point)
+const DIM_FACTOR: u32 = 112;
+
+/// Selection border width and color (BGRA)
+const BORDER_WIDTH: i32 = 1;
+const BORDER_COLOR: [u8; 4] = [220, 160, 80, 255];
+
+/// shm formats we can convert, in order of preference
+const SUPPORTED_FORMATS: [wl_shm::Format; 4] = [
+    wl_shm::Format::Xrgb8888,
+    wl_shm::Format::Argb8888,
+    wl_shm::Format::Xbgr8888,
+    wl_shm::Format::Abgr8888,
+];
+
+/// Rectangle in compositor coordinates, parsed from slurp's `X,Y WxH` format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A captured region ready to be pinned
+pub struct Capture {
+    /// Captured pixels at the output's buffer resolution (BGRA)
+    pub image: ImageData,
+    /// Selected region in logical coordinates relative to the output
+    pub region: Region,
+    /// Name of the captured output
+    pub output: Option<String>,
+}
+
+/// Screen copy protocol offered by the compositor
+#[derive(Clone)]
+enum Backend {
+    /// ext-image-copy-capture-v1 (preferred when available)
+    Ext {
+        copy_manager: ExtImageCopyCaptureManagerV1,
+        source_manager: ExtOutputImageCaptureSourceManagerV1,
+    },
+    /// wlr-screencopy-unstable-v1
+    Wlr(ZwlrScreencopyManagerV1),
+}
+
+impl Backend {
+    /// Bind the best available capture protocol
+    fn bind(globals: &GlobalList, qh: &QueueHandle<CaptureApp>) -> Result<Self> {
+        let copy_manager = globals.bind::<ExtImageCopyCaptureManagerV1, _, _>(qh, 1..=1, ());
+        let source_manager =
+            globals.bind::<ExtOutputImageCaptureSourceManagerV1, _, _>(qh, 1..=1, ());
+        if let (Ok(copy_manager), Ok(source_manager)) = (copy_manager, source_manager) {
+            info!("Capturing with ext-image-copy-capture-v1");
+            return Ok(Backend::Ext {
+                copy_manager,
+                source_manager,
+            });
+        }
+
+        let manager = globals
+            .bind::<ZwlrScreencopyManagerV1, _, _>(qh, 1..=3, ())
+            .context("Compositor supports neither ext-image-copy-capture nor wlr-screencopy")?;
+        info!("Capturing with wlr-screencopy-unstable-v1");
+        Ok(Backend::Wlr(manager))
+    }
+}
+
+/// Progress of a single copy from the compositor
+#[derive(Default)]
+struct CopyState {
+    /// Buffer size requested by the compositor
+    size: (u32, u32),
+    /// Row stride requested by the compositor (wlr-screencopy only)
+    stride: u32,
+    /// Offered shm formats
+    formats: Vec<wl_shm::Format>,
+    /// All buffer constraints have been announced
+    constraints_done: bool,
+    /// Rows are stored bottom-up (wlr-screencopy only)
+    y_invert: bool,
+    /// Transform of the captured contents (ext-image-copy-capture only)
+    transform: Option<wl_output::Transform>,
+    /// Outcome of the copy once the compositor is done
+    result: Option<Result<(), String>>,
+}
+
+/// Frozen output contents in logical orientation (BGRA)
+struct Screenshot {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Result of the interactive selection
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Selected(Rect),
+    Cancelled,
+}
+
+/// Wayland state for the capture session
+struct CaptureApp {
+    registry_state: RegistryState,
+    seat_state: SeatState,
+    output_state: OutputState,
+    shm: Shm,
+    layer_shell: LayerShell,
+    compositor_state: CompositorState,
+    backend: Backend,
+
+    // Copy in progress
+    copy: CopyState,
+    screenshot: Option<Screenshot>,
+
+    // Selection overlay
+    layer_surface: Option<LayerSurface>,
+    swapchain: Option<ShmSwapchain>,
+    width: u32,
+    height: u32,
+    // Integer scale of the overlay's buffer over its logical size
+    scale: i32,
+    configured: bool,
+    // Frozen screen scaled to the overlay buffer, at full and dimmed brightness
+    bright: Vec<u8>,
+    dimmed: Vec<u8>,
+    needs_redraw: bool,
+    frame_callback_pending: bool,
+    damage: Damage,
+
+    // Pointer state
+    themed_pointer: Option<ThemedPointer>,
+    drag_start: Option<(f64, f64)>,
+    selection: Option<Rect>,
+    outcome: Option<Outcome>,
+}
+
+/// Capture a region of an output
+///
+/// With `region` the capture runs without user interaction (useful for
+/// scripting and headless compositors); otherwise a selection overlay is shown.
+/// Returns `None` if the user cancels the selection.
+pub fn capture(output_name: Option<&str>, region: Option<Region>) -> Result<Option<Capture>> {
+    info!("Connecting to Wayland display for capture");
+    let conn = Connection::connect_to_env().context("Failed to connect to Wayland display")?;
+    let (globals, mut event_queue) =
+        registry_queue_init(&conn).context("Failed to initialize registry")?;
+    let qh = event_queue.handle();
+
+    let compositor_state =
+        CompositorState::bind(&globals, &qh).context("Failed to bind compositor")?;
+    let layer_shell = LayerShell::bind(&globals, &qh).context("Failed to bind layer shell")?;
+    let shm = Shm::bind(&globals, &qh).context("Failed to bind shm")?;
+    let backend = Backend::bind(&globals, &qh)?;
+
+    let mut app = CaptureApp {
+        registry_state: RegistryState::new(&globals),
+        seat_state: SeatState::new(&globals, &qh),
+        output_state: OutputState::new(&globals, &qh),
+        shm,
+        layer_shell,
+        compositor_state,
+        backend,
+        copy: CopyState::default(),
+        screenshot: None,
+        layer_surface: None,
+        swapchain: None,
+        width: 0,
+        height: 0,
+        scale: 1,
+        configured: false,
+        bright: Vec::new(),
+        dimmed: Vec::new(),
+        needs_redraw: false,
+        frame_callback_pending: false,
+        damage: Damage::Full,
+        themed_pointer: None,
+        drag_start: None,
+        selection: None,
+        outcome: None,
+    };
+
+    // Dispatch once to get output info
+    event_queue.roundtrip(&mut app)?;
+
+    let (output, info) = app.pick_output(output_name, region)?;
+    info!(
+        "Capturing output {}",
+        info.name.as_deref().unwrap_or("(unnamed)")
+    );
+
+    // Freeze the output first so the overlay never ends up in the capture
+    app.grab_output(&mut event_queue, &output, &info)?;
+
+    let origin = info.logical_position.unwrap_or((0, 0));
+    let (logical_width, logical_height) = app.logical_size(&info);
+    let selection = match region {
+        Some(region) => Rect::new(
+            region.x - origin.0,
+            region.y - origin.1,
+            region.width as i32,
+            region.height as i32,
+        )
+        .clamp_to(logical_width, logical_height)
+        .context("Region lies outside the captured output")?,
+        None => match app.select_region(&mut event_queue, &output, info.scale_factor)? {
+            Outcome::Selected(rect) => rect,
+            Outcome::Cancelled => return Ok(None),
+        },
+    };
+    let (logical_width, logical_height) = if region.is_some() {
+        (logical_width, logical_height)
+    } else {
+        (app.width, app.height)
+    };
+
+    let screenshot = app
+        .screenshot
+        .take()
+        .context("Capture finished without a screenshot")?;
+    let image = crop(&screenshot, logical_width, logical_height, selection);
+    info!(
+        "Captured {}x{} at {},{} ({}x{} pixels)",
+        selection.width, selection.height, selection.x, selection.y, image.width, image.height
+    );
+
+    Ok(Some(Capture {
+        image,
+        region: Region {
+            x: selection.x,
+            y: selection.y,
+            width: selection.width as u32,
+            height: selection.height as u32,
+        },
+        output: info.name.clone(),
+    }))
+}
+
+impl CaptureApp {
+    /// Find the output to capture by name, by region, or the first one
+    fn pick_output(
+        &self,
+        name: Option<&str>,
+        region: Option<Region>,
+    ) -> Result<(wl_output::WlOutput, OutputInfo)> {
+        let outputs: Vec<(wl_output::WlOutput, OutputInfo)> = self
+            .output_state
+            .outputs()
+            .filter_map(|output| {
+                let info = self.output_state.info(&output)?;
+                Some((output, info))
+            })
+            .collect();
+
+        let contains = |info: &OutputInfo, region: &Region| {
+            let (x, y) = info.logical_position.unwrap_or((0, 0));
+            let (w, h) = info.logical_size.unwrap_or((0, 0));
+            region.x >= x && region.x < x + w && region.y >= y && region.y < y + h
+        };
+
+        let found = match (name, region) {
+            (Some(name), _) => outputs
+                .into_iter()
+                .find(|(_, info)| info.name.as_deref() == Some(name)),
+            (None, Some(region)) => outputs
+                .into_iter()
+                .find(|(_, info)| contains(info, &region)),
+            (None, None) => outputs.into_iter().next(),
+        };
+
+        found.ok_or_else(|| match name {
+            Some(name) => anyhow!("No output named {}", name),
+            None => anyhow!("No output found to capture"),
+        })
+    }
+
+    /// Logical size of an output, derived from the capture if the compositor omits it
+    fn logical_size(&self, info: &OutputInfo) -> (u32, u32) {
+        if let Some((width, height)) = info.logical_size {
+            return (width as u32, height as u32);
+        }
+        let scale = info.scale_factor.max(1) as u32;
+        match &self.screenshot {
+            Some(shot) => (shot.width / scale, shot.height / scale),
+            None => (0, 0),
+        }
+    }
+
+    /// Dispatch events until `done` returns true
+    fn dispatch_until(
+        &mut self,
+        queue: &mut EventQueue<Self>,
+        done: impl Fn(&Self) -> bool,
+    ) -> Result<()> {
+        while !done(self) {
+            queue.blocking_dispatch(self)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the whole output into `self.screenshot`
+    fn grab_output(
+        &mut self,
+        queue: &mut EventQueue<Self>,
+        output: &wl_output::WlOutput,
+        info: &OutputInfo,
+    ) -> Result<()> {
+        let qh = queue.handle();
+        self.copy = CopyState::default();
+
+        let (pool, buffer, format) = match self.backend.clone() {
+            Backend::Ext {
+                copy_manager,
+                source_manager,
+            } => {
+                let source = source_manager.create_source(output, &qh, ());
+                let session = copy_manager.create_session(
+                    &source,
+                    ext_image_copy_capture_manager_v1::Options::empty(),
+                    &qh,
+                    (),
+                );
+                self.dispatch_until(queue, |app| {
+                    app.copy.constraints_done || app.copy.result.is_some()
+                })?;
+                let (width, height) = self.copy.size;
+                let copied = self
+                    .prepare_copy(width * 4)
+                    .and_then(|(pool, buffer, format)| {
+                        let frame = session.create_frame(&qh, ());
+                        frame.attach_buffer(buffer.wl_buffer());
+                        frame.damage_buffer(0, 0, width as i32, height as i32);
+                        frame.capture();
+                        self.dispatch_until(queue, |app| app.copy.result.is_some())?;
+                        frame.destroy();
+                        Ok((pool, buffer, format))
+                    });
+                session.destroy();
+                source.destroy();
+                copied?
+            }
+            Backend::Wlr(manager) => {
+                let frame = manager.capture_output(0, output, &qh, ());
+                self.dispatch_until(queue, |app| {
+                    app.copy.constraints_done || app.copy.result.is_some()
+                })?;
+                let copied = self.prepare_copy(self.copy.stride).and_then(|copy| {
+                    frame.copy(copy.1.wl_buffer());
+                    self.dispatch_until(queue, |app| app.copy.result.is_some())?;
+                    Ok(copy)
+                });
+                frame.destroy();
+                // wlr-screencopy copies in the output's native orientation
+                self.copy.transform = Some(info.transform);
+                copied?
+            }
+        };
+
+        if let Some(Err(reason)) = self.copy.result.take() {
+            bail!("Screen copy failed: {}", reason);
+        }
+
+        let mut pool = pool;
+        let data = buffer
+            .canvas(&mut pool)
+            .context("Captured buffer is not readable")?;
+        let (width, height) = self.copy.size;
+        let stride = buffer.stride() as usize;
+        let screenshot = to_screenshot(
+            data,
+            width,
+            height,
+            stride,
+            format,
+            self.copy.y_invert,
+            self.copy.transform.unwrap_or(wl_output::Transform::Normal),
+        );
+        debug!(
+            "Captured output: {}x{} ({:?})",
+            screenshot.width, screenshot.height, format
+        );
+        self.screenshot = Some(screenshot);
+        Ok(())
+    }
+
+    /// Allocate an shm buffer matching the announced constraints
+    fn prepare_copy(
+        &mut self,
+        stride: u32,
+    ) -> Result<(
+        SlotPool,
+        smithay_client_toolkit::shm::slot::Buffer,
+        wl_shm::Format,
+    )> {
+        if let Some(Err(reason)) = &self.copy.result {
+            bail!("Screen copy failed: {}", reason);
+        }
+        let (width, height) = self.copy.size;
+        if width == 0 || height == 0 {
+            bail!("Compositor did not announce a capture size");
+        }
+        let format = SUPPORTED_FORMATS
+            .into_iter()
+            .find(|format| self.copy.formats.contains(format))
+            .with_context(|| {
+                format!(
+                    "No supported shm format offered for capture: {:?}",
+                    self.copy.formats
+                )
+            })?;
+
+        let mut pool = SlotPool::new((stride * height) as usize, &self.shm)
+            .context("Failed to create capture pool")?;
+        let (buffer, _) = pool
+            .create_buffer(width as i32, height as i32, stride as i32, format)
+            .context("Failed to create capture buffer")?;
+        Ok((pool, buffer, format))
+    }
+
+    /// Show the selection overlay and wait for the user to pick a region
+    fn select_region(
+        &mut self,
+        queue: &mut EventQueue<Self>,
+        output: &wl_output::WlOutput,
+        scale: i32,
+    ) -> Result<Outcome> {
+        let qh = queue.handle();
+        let surface = self.compositor_state.create_surface(&qh);
+        // Start at the output's scale; scale_factor_changed follows the surface later
+        self.scale = scale.max(1);
+        surface.set_buffer_scale(self.scale);
+        let layer_surface = self.layer_shell.create_layer_surface(
+            &qh,
+            surface,
+            Layer::Overlay,
+            Some("rspin-capture"),
+            Some(output),
+        );
+        layer_surface.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
+        layer_surface.set_exclusive_zone(-1);
+        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        layer_surface.set_size(0, 0);
+        layer_surface.commit();
+        self.layer_surface = Some(layer_surface);
+
+        info!("Drag to select a region, Escape or right-click to cancel");
+        self.dispatch_until(queue, |app| app.outcome.is_some())?;
+
+        // Unmap the overlay before the pin window appears
+        self.layer_surface = None;
+        self.swapchain = None;
+        queue.roundtrip(self)?;
+
+        let outcome = self.outcome.take().unwrap_or(Outcome::Cancelled);
+        if outcome == Outcome::Cancelled {
+            info!("Capture cancelled");
+        }
+        Ok(outcome)
+    }
+
+    /// Size of the overlay's buffer in pixels
+    fn buffer_size(&self) -> (u32, u32) {
+        let scale = self.scale as u32;
+        (self.width * scale, self.height * scale)
+    }
+
+    /// Scale the frozen screen to the overlay buffer and prepare the dimmed copy
+    fn prepare_backgrounds(&mut self) {
+        let Some(shot) = &self.screenshot else {
+            return;
+        };
+        let (width, height) = self.buffer_size();
+        let mut bright = vec![0u8; width as usize * height as usize * 4];
+        resample::resample(
+            &shot.data,
+            shot.width,
+            shot.height,
+            &mut bright,
+            width,
+            height,
+            FilterMode::Bilinear,
+        );
+        let mut dimmed = bright.clone();
+        for pixel in dimmed.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * DIM_FACTOR) >> 8) as u8;
+            }
+        }
+        self.bright = bright;
+        self.dimmed = dimmed;
+    }
+
+    /// Update the selection and damage the area it covered before and after
+    fn set_selection(&mut self, selection: Option<Rect>) {
+        if selection == self.selection {
+            return;
+        }
+        for rect in [self.selection, selection].into_iter().flatten() {
+            self.damage.add(Rect::new(
+                rect.x - BORDER_WIDTH,
+                rect.y - BORDER_WIDTH,
+                rect.width + BORDER_WIDTH * 2,
+                rect.height + BORDER_WIDTH * 2,
+            ));
+        }
+        self.selection = selection;
+        self.needs_redraw = true;
+    }
+
+    /// Draw the overlay if a frame is due
+    fn draw(&mut self, qh: &QueueHandle<Self>) {
+        if !self.configured || self.frame_callback_pending || !self.needs_redraw {
+            return;
+        }
+        let Some(layer_surface) = self.layer_surface.clone() else {
+            return;
+        };
+        let (width, height) = self.buffer_size();
+
+        if self.swapchain.is_none() {
+            match ShmSwapchain::new(&self.shm, width, height) {
+                Ok(swapchain) => self.swapchain = Some(swapchain),
+                Err(e) => {
+                    error!("{:?}", e);
+                    return;
+                }
+            }
+        }
+        let Some(swapchain) = self.swapchain.as_mut() else {
+            return;
+        };
+
+        let (index, canvas, stale) = match swapchain.acquire(width, height) {
+            Ok(acquired) => acquired,
+            Err(e) => {
+                error!("{:?}", e);
+                return;
+            }
+        };
+        // Selection and damage are tracked in logical pixels
+        let frame_damage = std::mem::take(&mut self.damage).scale(self.scale);
+        let mut repair = stale;
+        repair.merge(&frame_damage);
+
+        let row_bytes = width as usize * 4;
+        let selection = self.selection.map(|sel| {
+            Rect::new(
+                sel.x * self.scale,
+                sel.y * self.scale,
+                sel.width * self.scale,
+                sel.height * self.scale,
+            )
+        });
+        for rect in repair.rects(width, height) {
+            paint_overlay(
+                canvas,
+                row_bytes,
+                &self.bright,
+                &self.dimmed,
+                rect,
+                selection,
+                BORDER_WIDTH * self.scale,
+            );
+        }
+
+        let surface = layer_surface.wl_surface();
+        if let Err(e) = swapchain.present(index, surface, &frame_damage) {
+            error!("{:?}", e);
+            return;
+        }
+        surface.frame(qh, surface.clone());
+        surface.commit();
+        self.frame_callback_pending = true;
+        self.needs_redraw = false;
+    }
+}
+
+/// Paint one region of the overlay: dimmed screen, bright selection and its border
+fn paint_overlay(
+    canvas: &mut [u8],
+    row_bytes: usize,
+    bright: &[u8],
+    dimmed: &[u8],
+    rect: Rect,
+    selection: Option<Rect>,
+    border: i32,
+) {
+    let x0 = rect.x as usize * 4;
+    let x1 = (rect.x + rect.width) as usize * 4;
+    for y in rect.y..rect.y + rect.height {
+        let row = y as usize * row_bytes;
+        let (Some(src), Some(dst)) = (
+            dimmed.get(row + x0..row + x1),
+            canvas.get_mut(row + x0..row + x1),
+        ) else {
+            return;
+        };
+        dst.copy_from_slice(src);
+
+        let Some(sel) = selection else {
+            continue;
+        };
+        let outer = Rect::new(
+            sel.x - border,
+            sel.y - border,
+            sel.width + border * 2,
+            sel.height + border * 2,
+        );
+        if y < outer.y || y >= outer.y + outer.height {
+            continue;
+        }
+
+        // Span of this row covered by the selection (plus border), clipped to the rect
+        let span_start = outer.x.max(rect.x);
+        let span_end = (outer.x + outer.width).min(rect.x + rect.width);
+        for x in span_start..span_end {
+            let inside =
+                x >= sel.x && x < sel.x + sel.width && y >= sel.y && y < sel.y + sel.height;
+            let offset = row + x as usize * 4;
+            let pixel = &mut canvas[offset..offset + 4];
+            if inside {
+                pixel.copy_from_slice(&bright[offset..offset + 4]);
+            } else {
+                pixel.copy_from_slice(&BORDER_COLOR);
+            }
+        }
+    }
+}
+
+/// Convert a captured shm buffer into tightly packed BGRA in logical orientation
+fn to_screenshot(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: wl_shm::Format,
+    y_invert: bool,
+    transform: wl_output::Transform,
+) -> Screenshot {
+    let row_bytes = width as usize * 4;
+    let mut pixels = vec![0u8; row_bytes * height as usize];
+    for (y, dst_row) in pixels.chunks_exact_mut(row_bytes).enumerate() {
+        let src_y = if y_invert { height as usize - 1 - y } else { y };
+        dst_row.copy_from_slice(&data[src_y * stride..][..row_bytes]);
+        for pixel in dst_row.chunks_exact_mut(4) {
+            match format {
+                wl_shm::Format::Xbgr8888 | wl_shm::Format::Abgr8888 => pixel.swap(0, 2),
+                _ => {}
+            }
+            if matches!(format, wl_shm::Format::Xrgb8888 | wl_shm::Format::Xbgr8888) {
+                pixel[3] = 255;
+            }
+        }
+    }
+
+    // The buffer is in the output's native orientation; undo the output
+    // transform so the image matches what is on screen. The channel order does
+    // not matter to these operations, so BGRA data can go through `Rgba`.
+    let buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
+        ImageBuffer::from_raw(width, height, pixels).expect("buffer size matches dimensions");
+    let buffer = match transform {
+        wl_output::Transform::_90 => imageops::rotate90(&buffer),
+        wl_output::Transform::_180 => imageops::rotate180(&buffer),
+        wl_output::Transform::_270 => imageops::rotate270(&buffer),
+        wl_output::Transform::Flipped => imageops::flip_horizontal(&buffer),
+        wl_output::Transform::Flipped90 => imageops::flip_horizontal(&imageops::rotate90(&buffer)),
+        wl_output::Transform::Flipped180 => {
+            imageops::flip_horizontal(&imageops::rotate180(&buffer))
+        }
+        wl_output::Transform::Flipped270 => {
+            imageops::flip_horizontal(&imageops::rotate270(&buffer))
+        }
+        _ => buffer,
+    };
+
+    Screenshot {
+        width: buffer.width(),
+        height: buffer.height(),
+        data: buffer.into_raw(),
+    }
+}
+
+/// Cut the selection (in logical coordinates) out of the screenshot
+fn crop(shot: &Screenshot, logical_width: u32, logical_height: u32, selection: Rect) -> ImageData {
+    let scale_x = shot.width as f64 / logical_width.max(1) as f64;
+    let scale_y = shot.height as f64 / logical_height.max(1) as f64;
+    let x0 = ((selection.x as f64 * scale_x).round() as u32).min(shot.width - 1);
+    let y0 = ((selection.y as f64 * scale_y).round() as u32).min(shot.height - 1);
+    let x1 = (((selection.x + selection.width) as f64 * scale_x).round() as u32)
+        .clamp(x0 + 1, shot.width);
+    let y1 = (((selection.y + selection.height) as f64 * scale_y).round() as u32)
+        .clamp(y0 + 1, shot.height);
+
+    let (width, height) = (x1 - x0, y1 - y0);
+    let src_stride = shot.width as usize * 4;
+    let row_bytes = width as usize * 4;
+    let mut data = Vec::with_capacity(row_bytes * height as usize);
+    for y in y0..y1 {
+        let start = y as usize * src_stride + x0 as usize * 4;
+        data.extend_from_slice(&shot.data[start..start + row_bytes]);
+    }
+
+    ImageData {
+        width,
+        height,
+        rgba_data: data,
+        scale: 1.0,
+        mipmaps: Vec::new(),
+    }
+}
+
+impl CompositorHandler for CaptureApp {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        let new_factor = new_factor.max(1);
+        if new_factor == self.scale {
+            return;
+        }
+        debug!("Capture overlay scale changed to {}", new_factor);
+        self.scale = new_factor;
+        surface.set_buffer_scale(new_factor);
+        if self.configured {
+            self.prepare_backgrounds();
+            self.damage.add_full();
+            self.needs_redraw = true;
+            self.draw(qh);
+        }
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _new_transform: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+        self.frame_callback_pending = false;
+        self.draw(qh);
+    }
+
+    fn surface_enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _surface: &wl_surface::WlSurface,
+        _output: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for CaptureApp {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl LayerShellHandler for CaptureApp {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+        self.outcome = Some(Outcome::Cancelled);
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        _layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let (width, height) = configure.new_size;
+        if width == 0 || height == 0 {
+            warn!("Compositor sent an empty size for the capture overlay");
+            return;
+        }
+        if (width, height) != (self.width, self.height) || !self.configured {
+            self.width = width;
+            self.height = height;
+            self.prepare_backgrounds();
+            self.damage.add_full();
+            self.needs_redraw = true;
+        }
+        self.configured = true;
+        self.draw(qh);
+    }
+}
+
+impl SeatHandler for CaptureApp {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard {
+            if let Err(e) = self.seat_state.get_keyboard(qh, &seat, None) {
+                error!("Failed to get keyboard: {}", e);
+            }
+        }
+        if capability == Capability::Pointer && self.themed_pointer.is_none() {
+            let surface = self.compositor_state.create_surface(qh);
+            match self.seat_state.get_pointer_with_theme(
+                qh,
+                &seat,
+                self.shm.wl_shm(),
+                surface,
+                ThemeSpec::default(),
+            ) {
+                Ok(pointer) => self.themed_pointer = Some(pointer),
+                Err(e) => error!("Failed to create themed pointer: {}", e),
+            }
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _seat: wl_seat::WlSeat,
+        _capability: Capability,
+    ) {
+    }
+
+    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {
+    }
+}
+
+impl KeyboardHandler for CaptureApp {
+    fn enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        _surface: &wl_surface::WlSurface,
+        _serial: u32,
+        _raw: &[u32],
+        _keysyms: &[Keysym],
+    ) {
+    }
+
+    fn leave(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        _surface: &wl_surface::WlSurface,
+        _serial: u32,
+    ) {
+    }
+
+    fn press_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        _serial: u32,
+        event: KeyEvent,
+    ) {
+        if event.keysym == Keysym::Escape {
+            self.outcome = Some(Outcome::Cancelled);
+        }
+    }
+
+    fn release_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        _serial: u32,
+        _event: KeyEvent,
+    ) {
+    }
+
+    fn update_modifiers(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        _serial: u32,
+        _modifiers: Modifiers,
+        _layout: u32,
+    ) {
+    }
+}
+
+impl PointerHandler for CaptureApp {
+    fn pointer_frame(
+        &mut self,
+        conn: &Connection,
+        qh: &QueueHandle<Self>,
+        _pointer: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        for event in events {
+            match event.kind {
+                PointerEventKind::Enter { .. } => {
+                    if let Some(themed_pointer) = &self.themed_pointer {
+                        let _ = themed_pointer.set_cursor(conn, CursorIcon::Crosshair);
+                    }
+                }
+                PointerEventKind::Motion { .. } => {
+                    if let Some(start) = self.drag_start {
+                        self.set_selection(Some(rect_between(start, event.position)));
+                    }
+                }
+                PointerEventKind::Press { button, .. } if button == BTN_LEFT => {
+                    self.drag_start = Some(event.position);
+                    self.set_selection(None);
+                }
+                PointerEventKind::Press { button, .. } if button == BTN_RIGHT => {
+                    self.outcome = Some(Outcome::Cancelled);
+                }
+                PointerEventKind::Release { button, .. } if button == BTN_LEFT => {
+                    self.drag_start = None;
+                    match self.selection {
+                        Some(rect) if !rect.is_empty() => {
+                            self.outcome = Some(Outcome::Selected(rect));
+                        }
+                        _ => self.set_selection(None),
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.draw(qh);
+    }
+}
+
+/// Rectangle spanned by two pointer positions
+fn rect_between(a: (f64, f64), b: (f64, f64)) -> Rect {
+    let x0 = a.0.min(b.0).floor() as i32;
+    let y0 = a.1.min(b.1).floor() as i32;
+    let x1 = a.0.max(b.0).ceil() as i32;
+    let y1 = a.1.max(b.1).ceil() as i32;
+    Rect::new(x0, y0, x1 - x0, y1 - y0)
+}
+
+impl ShmHandler for CaptureApp {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for CaptureApp {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState, SeatState];
+}
+
+impl Dispatch<ZwlrScreencopyManagerV1, ()> for CaptureApp {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrScreencopyManagerV1,
+        _event: <ZwlrScreencopyManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for CaptureApp {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        use wayland_client::Proxy;
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                if let WEnum::Value(format) = format {
+                    state.copy.formats.push(format);
+                }
+                state.copy.size = (width, height);
+                state.copy.stride = stride;
+                // Version 1 and 2 announce a single buffer type without buffer_done
+                if proxy.version() < 3 {
+                    state.copy.constraints_done = true;
+                }
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => {
+                state.copy.constraints_done = true;
+            }
+            zwlr_screencopy_frame_v1::Event::Flags {
+                flags: WEnum::Value(flags),
+            } => {
+                state.copy.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => {
+                state.copy.result = Some(Ok(()));
+            }
+            zwlr_screencopy_frame_v1::Event::Failed => {
+                state.copy.result = Some(Err("compositor reported a failure".to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureManagerV1, ()> for CaptureApp {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtImageCopyCaptureManagerV1,
+        _event: ext_image_copy_capture_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtOutputImageCaptureSourceManagerV1, ()> for CaptureApp {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtOutputImageCaptureSourceManagerV1,
+        _event: <ExtOutputImageCaptureSourceManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtImageCaptureSourceV1, ()> for CaptureApp {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ExtImageCaptureSourceV1,
+        _event: <ExtImageCaptureSourceV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, ()> for CaptureApp {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                state.copy.size = (width, height);
+            }
+            ext_image_copy_capture_session_v1::Event::ShmFormat {
+                format: WEnum::Value(format),
+            } => {
+                state.copy.formats.push(format);
+            }
+            ext_image_copy_capture_session_v1::Event::Done => {
+                state.copy.constraints_done = true;
+            }
+            ext_image_copy_capture_session_v1::Event::Stopped if state.copy.result.is_none() => {
+                state.copy.result = Some(Err("capture session stopped".to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for CaptureApp {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Transform {
+                transform: WEnum::Value(transform),
+            } => {
+                state.copy.transform = Some(transform);
+            }
+            ext_image_copy_capture_frame_v1::Event::Ready => {
+                state.copy.result = Some(Ok(()));
+            }
+            ext_image_copy_capture_frame_v1::Event::Failed { reason } => {
+                state.copy.result = Some(Err(format!("{:?}", reason)));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Delegate macros
+delegate_compositor!(CaptureApp);
+delegate_output!(CaptureApp);
+delegate_layer!(CaptureApp);
+delegate_seat!(CaptureApp);
+delegate_keyboard!(CaptureApp);
+delegate_pointer!(CaptureApp);
+delegate_shm!(CaptureApp);
+delegate_registry!(CaptureApp);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 XRGB buffer whose pixels are labelled 0..6 in the blue channel,
+    /// with `padding` unused bytes at the end of each row
+    fn labelled_buffer(padding: usize) -> (Vec<u8>, usize) {
+        let stride = 3 * 4 + padding;
+        let mut data = vec![0xEE; stride * 2];
+        for label in 0..6u8 {
+            let offset = (label / 3) as usize * stride + (label % 3) as usize * 4;
+            data[offset..offset + 4].copy_from_slice(&[label, 0, 0, 0]);
+        }
+        (data, stride)
+    }
+
+    /// Labels of a screenshot's pixels, row by row
+    fn labels(shot: &Screenshot) -> Vec<Vec<u8>> {
+        shot.data
+            .chunks_exact(shot.width as usize * 4)
+            .map(|row| row.chunks_exact(4).map(|pixel| pixel[0]).collect())
+            .collect()
+    }
+
+    fn convert(transform: wl_output::Transform) -> Screenshot {
+        let (data, stride) = labelled_buffer(0);
+        to_screenshot(
+            &data,
+            3,
+            2,
+            stride,
+            wl_shm::Format::Xrgb8888,
+            false,
+            transform,
+        )
+    }
+
+    #[test]
+    fn test_to_screenshot_transforms() {
+        use wl_output::Transform;
+        let cases: [(Transform, &[&[u8]]); 8] = [
+            (Transform::Normal, &[&[0, 1, 2], &[3, 4, 5]]),
+            (Transform::_90, &[&[3, 0], &[4, 1], &[5, 2]]),
+            (Transform::_180, &[&[5, 4, 3], &[2, 1, 0]]),
+            (Transform::_270, &[&[2, 5], &[1, 4], &[0, 3]]),
+            (Transform::Flipped, &[&[2, 1, 0], &[5, 4, 3]]),
+            (Transform::Flipped90, &[&[0, 3], &[1, 4], &[2, 5]]),
+            (Transform::Flipped180, &[&[3, 4, 5], &[0, 1, 2]]),
+            (Transform::Flipped270, &[&[5, 2], &[4, 1], &[3, 0]]),
+        ];
+        for (transform, expected) in cases {
+            let shot = convert(transform);
+            let rows = expected.len() as u32;
+            assert_eq!(
+                (shot.width, shot.height),
+                (expected[0].len() as u32, rows),
+                "{:?}",
+                transform
+            );
+            assert_eq!(labels(&shot), expected, "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn test_to_screenshot_y_invert_and_stride() {
+        let (data, stride) = labelled_buffer(8);
+        let shot = to_screenshot(
+            &data,
+            3,
+            2,
+            stride,
+            wl_shm::Format::Xrgb8888,
+            true,
+            wl_output::Transform::Normal,
+        );
+        assert_eq!(labels(&shot), [[3, 4, 5], [0, 1, 2]]);
+        assert_eq!(shot.data.len(), 3 * 2 * 4);
+    }
+
+    #[test]
+    fn test_to_screenshot_formats() {
+        let data = [10u8, 20, 30, 40];
+        let convert = |format| {
+            to_screenshot(&data, 1, 1, 4, format, false, wl_output::Transform::Normal).data
+        };
+        assert_eq!(convert(wl_shm::Format::Argb8888), [10, 20, 30, 40]);
+        assert_eq!(convert(wl_shm::Format::Xrgb8888), [10, 20, 30, 255]);
+        assert_eq!(convert(wl_shm::Format::Abgr8888), [30, 20, 10, 40]);
+        assert_eq!(convert(wl_shm::Format::Xbgr8888), [30, 20, 10, 255]);
+    }
+
+    #[test]
+    fn test_crop_at_buffer_scale() {
+        // 4x2 screenshot of a 2x1 logical output (scale 2)
+        let shot = Screenshot {
+            width: 4,
+            height: 2,
+            data: (0..32).collect(),
+        };
+        let image = crop(&shot, 2, 1, Rect::new(1, 0, 1, 1));
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.rgba_data[..4], [8, 9, 10, 11]);
+        assert_eq!(image.rgba_data[8..12], [24, 25, 26, 27]);
+    }
+
+    #[test]
+    fn test_rect_between_covers_both_points() {
+        assert_eq!(rect_between((10.5, 4.0), (2.2, 8.7)), Rect::new(2, 4, 9, 5));
+    }
+}
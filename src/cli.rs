@@ -1,8 +1,17 @@
 // Command line interface module
 // Handles parsing of command line arguments and stdin input
 
+use crate::capture::Region;
+use crate::config::Config;
+use crate::hud::HudMode;
+use crate::i18n::Language;
+use crate::loupe::LoupeShape;
+use crate::resample::FilterMode;
+use crate::snap;
+use crate::theme::ThemeMode;
 use anyhow::{bail, Result};
 use clap::Parser;
+use smithay_client_toolkit::seat::keyboard::Keysym;
 use std::io::{self, Read};
 use std::path::PathBuf;
 
@@ -31,6 +40,59 @@ pub struct Args {
     #[arg(short, long, default_value = "1.0")]
     pub scale: f32,
 
+    /// Resampling filter used when scaling the image
+    #[arg(long, value_enum, default_value_t = FilterMode::Bilinear)]
+    pub filter: FilterMode,
+
+    /// Reload the image whenever the file changes on disk
+    #[arg(short, long, default_value = "false")]
+    pub watch: bool,
+
+    /// Read a sequence of images from stdin, showing each one as it arrives
+    #[arg(long, default_value = "false", conflicts_with_all = ["image_path", "watch"])]
+    pub stream: bool,
+
+    /// Select a region of the screen and pin a screenshot of it
+    #[arg(long, default_value = "false", conflicts_with_all = ["image_path", "stream", "watch"])]
+    pub capture: bool,
+
+    /// Output to capture (e.g. DP-1; defaults to the first output)
+    #[arg(long, value_name = "NAME", requires = "capture")]
+    pub output: Option<String>,
+
+    /// Capture this region instead of selecting interactively ("X,Y WxH", as printed by slurp)
+    #[arg(long, value_name = "GEOMETRY", requires = "capture", value_parser = parse_region)]
+    pub region: Option<Region>,
+
+    /// Key to hold for the magnifier loupe (XKB keysym name, e.g. z, space, Alt_L)
+    #[arg(long, value_name = "KEYSYM", default_value = "z", value_parser = parse_keysym)]
+    pub loupe_key: Keysym,
+
+    /// Shape of the magnifier loupe
+    #[arg(long, value_enum, default_value_t = LoupeShape::Circle)]
+    pub loupe_shape: LoupeShape,
+
+    /// When to show the info overlay (toggle with I)
+    #[arg(long, value_enum, default_value_t = HudMode::Off)]
+    pub hud: HudMode,
+
+    /// Menu and overlay palette (overrides the config file)
+    #[arg(long, value_enum)]
+    pub theme: Option<ThemeMode>,
+
+    /// Language of the menus and messages (overrides the config file and locale)
+    #[arg(long, value_enum)]
+    pub language: Option<Language>,
+
+    /// Distance in pixels at which the pin snaps to edges and other pins, 0 to disable
+    /// (overrides the config file)
+    #[arg(long, value_name = "PX")]
+    pub snap: Option<u32>,
+
+    /// Config file to use instead of ~/.config/rspin/config.ini
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
     /// Disable GPU rendering and use CPU rendering only
     #[arg(long, default_value = "false")]
     pub cpu: bool,
@@ -47,6 +109,32 @@ pub struct ParsedArgs {
     #[allow(dead_code)]
     pub pos_y: Option<i32>,
     pub scale: f32,
+    /// Resampling filter for scaled display
+    pub filter: FilterMode,
+    /// Watch the image file and reload it when it changes
+    pub watch: bool,
+    /// stdin carries a sequence of images (read by `stream::FrameReader`)
+    pub stream: bool,
+    /// Capture a screen region instead of loading an image
+    pub capture: bool,
+    /// Output to capture
+    pub capture_output: Option<String>,
+    /// Region to capture without interaction
+    pub capture_region: Option<Region>,
+    /// Key held to show the loupe
+    pub loupe_key: Keysym,
+    /// Shape of the loupe
+    pub loupe_shape: LoupeShape,
+    /// When the info overlay is shown
+    pub hud: HudMode,
+    /// Palette chosen on the command line
+    pub theme: Option<ThemeMode>,
+    /// Language of the menus and messages
+    pub language: Language,
+    /// Distance at which moved and resized edges snap (0 = off)
+    pub snap_threshold: u32,
+    /// Contents of the config file
+    pub config: Config,
     /// Use GPU rendering (default true, set to false with --cpu)
     pub use_gpu: bool,
 }
@@ -60,6 +148,85 @@ fn parse_opacity(s: &str) -> Result<f32, String> {
     Ok(opacity)
 }
 
+/// Parse a region in slurp's "X,Y WxH" format
+fn parse_region(s: &str) -> Result<Region, String> {
+    let invalid = || format!("Invalid region \"{}\", expected \"X,Y WxH\"", s);
+    let (position, size) = s.trim().split_once(' ').ok_or_else(invalid)?;
+    let (x, y) = position.split_once(',').ok_or_else(invalid)?;
+    let (width, height) = size.trim().split_once('x').ok_or_else(invalid)?;
+    let region = Region {
+        x: x.parse().map_err(|_| invalid())?,
+        y: y.parse().map_err(|_| invalid())?,
+        width: width.parse().map_err(|_| invalid())?,
+        height: height.parse().map_err(|_| invalid())?,
+    };
+    if region.width == 0 || region.height == 0 {
+        return Err("Region must not be empty".to_string());
+    }
+    Ok(region)
+}
+
+/// Named keys accepted by `--loupe-key`, by their XKB keysym names
+const KEY_NAMES: &[(&str, Keysym)] = &[
+    ("space", Keysym::space),
+    ("Tab", Keysym::Tab),
+    ("Return", Keysym::Return),
+    ("BackSpace", Keysym::BackSpace),
+    ("Insert", Keysym::Insert),
+    ("Delete", Keysym::Delete),
+    ("Home", Keysym::Home),
+    ("End", Keysym::End),
+    ("Prior", Keysym::Prior),
+    ("Page_Up", Keysym::Page_Up),
+    ("Next", Keysym::Next),
+    ("Page_Down", Keysym::Page_Down),
+    ("Left", Keysym::Left),
+    ("Right", Keysym::Right),
+    ("Up", Keysym::Up),
+    ("Down", Keysym::Down),
+    ("Menu", Keysym::Menu),
+    ("Shift_L", Keysym::Shift_L),
+    ("Shift_R", Keysym::Shift_R),
+    ("Control_L", Keysym::Control_L),
+    ("Control_R", Keysym::Control_R),
+    ("Alt_L", Keysym::Alt_L),
+    ("Alt_R", Keysym::Alt_R),
+    ("Meta_L", Keysym::Meta_L),
+    ("Meta_R", Keysym::Meta_R),
+    ("Super_L", Keysym::Super_L),
+    ("Super_R", Keysym::Super_R),
+    ("Caps_Lock", Keysym::Caps_Lock),
+    ("ISO_Level3_Shift", Keysym::ISO_Level3_Shift),
+    ("F1", Keysym::F1),
+    ("F2", Keysym::F2),
+    ("F3", Keysym::F3),
+    ("F4", Keysym::F4),
+    ("F5", Keysym::F5),
+    ("F6", Keysym::F6),
+    ("F7", Keysym::F7),
+    ("F8", Keysym::F8),
+    ("F9", Keysym::F9),
+    ("F10", Keysym::F10),
+    ("F11", Keysym::F11),
+    ("F12", Keysym::F12),
+];
+
+/// Parse a key as a single character or an XKB keysym name from `KEY_NAMES`
+fn parse_keysym(s: &str) -> Result<Keysym, String> {
+    let mut chars = s.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        let keysym = Keysym::from_char(ch);
+        if keysym != Keysym::NoSymbol {
+            return Ok(keysym);
+        }
+    }
+    KEY_NAMES
+        .iter()
+        .find(|(name, _)| *name == s)
+        .map(|&(_, keysym)| keysym)
+        .ok_or_else(|| format!("Unknown key name \"{}\"", s))
+}
+
 /// Check if stdin has data available (is a pipe)
 fn stdin_has_data() -> bool {
     !atty::is(atty::Stream::Stdin)
@@ -75,9 +242,19 @@ fn read_stdin() -> Result<Vec<u8>> {
 /// Parse command line arguments and handle stdin input
 pub fn parse_args() -> Result<ParsedArgs> {
     let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
 
     // Check if we have image data from stdin
-    let (image_path, image_data) = if stdin_has_data() {
+    let (image_path, image_data) = if args.capture {
+        // The image comes from the screen
+        (None, None)
+    } else if args.stream {
+        // Frames are read incrementally once the window is up
+        if !stdin_has_data() {
+            bail!("--stream needs images piped to stdin");
+        }
+        (None, None)
+    } else if stdin_has_data() {
         // Read from stdin
         let data = read_stdin()?;
         if data.is_empty() {
@@ -102,6 +279,85 @@ pub fn parse_args() -> Result<ParsedArgs> {
         pos_x: args.pos_x,
         pos_y: args.pos_y,
         scale: args.scale,
+        filter: args.filter,
+        watch: args.watch,
+        stream: args.stream,
+        capture: args.capture,
+        capture_output: args.output,
+        capture_region: args.region,
+        loupe_key: args.loupe_key,
+        loupe_shape: args.loupe_shape,
+        hud: args.hud,
+        theme: args.theme,
+        language: args
+            .language
+            .unwrap_or_else(|| Language::from_config(&config)),
+        snap_threshold: args
+            .snap
+            .unwrap_or_else(|| snap::threshold_from_config(&config)),
+        config,
         use_gpu: !args.cpu, // GPU is default, --cpu disables it
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_region() {
+        let region = |x, y, width, height| Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(parse_region("10,20 300x200"), Ok(region(10, 20, 300, 200)));
+        // Outputs left of or above the origin, and surrounding whitespace
+        assert_eq!(
+            parse_region(" -1920,-5 64x48\n"),
+            Ok(region(-1920, -5, 64, 48))
+        );
+        assert_eq!(parse_region("0,0  1x1"), Ok(region(0, 0, 1, 1)));
+    }
+
+    #[test]
+    fn test_parse_region_rejects_malformed_input() {
+        for input in [
+            "",
+            "10,20",
+            "10 20 300x200",
+            "10,20 300*200",
+            "10,20 300x",
+            "a,20 300x200",
+            "10,20 -300x200",
+            "10.5,20 300x200",
+        ] {
+            assert!(parse_region(input).is_err(), "{:?}", input);
+        }
+        assert_eq!(
+            parse_region("10,20 0x200"),
+            Err("Region must not be empty".to_string())
+        );
+    }
+
+    #[test]
+    fn test_region_requires_capture() {
+        assert!(Args::try_parse_from(["rspin", "--region", "0,0 10x10"]).is_err());
+        let args = Args::try_parse_from(["rspin", "--capture", "--region", "0,0 10x10"]).unwrap();
+        assert_eq!(args.region.map(|region| region.width), Some(10));
+    }
+
+    #[test]
+    fn test_parse_keysym() {
+        assert_eq!(parse_keysym("z"), Ok(Keysym::z));
+        assert_eq!(parse_keysym("Z"), Ok(Keysym::Z));
+        assert_eq!(parse_keysym("1"), Ok(Keysym::_1));
+        assert_eq!(parse_keysym("space"), Ok(Keysym::space));
+        assert_eq!(parse_keysym("Shift_L"), Ok(Keysym::Shift_L));
+        assert_eq!(parse_keysym("F12"), Ok(Keysym::F12));
+        assert!(parse_keysym("").is_err());
+        assert!(parse_keysym("shift_l").is_err());
+        assert!(parse_keysym("NoSuchKey").is_err());
+    }
+}
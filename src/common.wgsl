@@ -0,0 +1,17 @@
+// Common shader functions
+// Prepended to the shaders that need them at include_str! time, so the
+// copies in the overlay, decoration, loupe and text passes cannot drift.
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let low = c / 12.92;
+    let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, c <= vec3<f32>(0.04045));
+}
+
+// Signed distance to the edge of a rounded rectangle (negative inside)
+fn rounded_rect_distance(p: vec2<f32>, rect: vec4<f32>, radius: f32) -> f32 {
+    let half = rect.zw * 0.5;
+    let r = min(radius, min(half.x, half.y));
+    let q = abs(p - rect.xy - half) - half + r;
+    return length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - r;
+}
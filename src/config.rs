@@ -0,0 +1,168 @@
+// Config module
+// Reads the optional INI-style config file (`[section]` headers and `key = value` lines)
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File name inside the config directory
+const FILE_NAME: &str = "config.ini";
+
+/// Values of the config file, keyed by section and key
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    sections: HashMap<String, HashMap<String, String>>,
+}
+
+impl Config {
+    /// Default location: `$XDG_CONFIG_HOME/rspin/config.ini` (or `~/.config/rspin/config.ini`)
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("rspin").join(FILE_NAME))
+    }
+
+    /// Load `path`, or the default file if it exists
+    ///
+    /// An explicitly given file must exist; a missing default file yields an empty config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read config {}", path.display()))
+            }
+        };
+        debug!("Loaded config from {}", path.display());
+        Self::parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Parse config text; `#` and `;` start comment lines
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config = Self::default();
+        let mut section = String::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_lowercase();
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                bail!("line {}: expected `key = value`", number + 1);
+            };
+            let value = value.trim().trim_matches('"');
+            config
+                .sections
+                .entry(section.clone())
+                .or_default()
+                .insert(key.trim().to_lowercase(), value.to_string());
+        }
+        Ok(config)
+    }
+
+    /// Raw value of `key` in `section`
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections.get(section)?.get(key).map(String::as_str)
+    }
+
+    /// Parsed value of `key` in `section`; invalid values are logged and ignored
+    pub fn parse_value<T: FromStr>(&self, section: &str, key: &str) -> Option<T> {
+        let value = self.get(section, key)?;
+        let parsed = value.parse().ok();
+        if parsed.is_none() {
+            warn!(
+                "Ignoring invalid config value {}.{} = {}",
+                section, key, value
+            );
+        }
+        parsed
+    }
+
+    /// All keys and values of a section
+    pub fn section(&self, section: &str) -> impl Iterator<Item = (&str, &str)> {
+        self.sections
+            .get(section)
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sections_and_values() {
+        let config = Config::parse(
+            "# comment\n; another\n\ntop = level\n[Theme]\n  Font = \"Inter:weight=medium\"  \nsize = 14 # kept\n[ colors ]\ntext=#FFFFFF\n",
+        )
+        .unwrap();
+        // Keys before the first header belong to the unnamed section
+        assert_eq!(config.get("", "top"), Some("level"));
+        // Section and key names are case-insensitive; quotes are stripped
+        assert_eq!(config.get("theme", "font"), Some("Inter:weight=medium"));
+        assert_eq!(config.get("theme", "size"), Some("14 # kept"));
+        assert_eq!(config.get("colors", "text"), Some("#FFFFFF"));
+        assert_eq!(config.get("colors", "missing"), None);
+        assert_eq!(config.get("missing", "text"), None);
+    }
+
+    #[test]
+    fn test_later_values_win() {
+        let config = Config::parse("[snap]\nthreshold = 4\n[snap]\nthreshold = 8\n").unwrap();
+        assert_eq!(config.parse_value::<u32>("snap", "threshold"), Some(8));
+    }
+
+    #[test]
+    fn test_parse_value_ignores_invalid_values() {
+        let config = Config::parse("[theme]\nfont_size = big\nradius = 6\n").unwrap();
+        assert_eq!(config.parse_value::<f32>("theme", "font_size"), None);
+        assert_eq!(config.parse_value::<u32>("theme", "radius"), Some(6));
+        assert_eq!(config.parse_value::<u32>("theme", "missing"), None);
+    }
+
+    #[test]
+    fn test_parse_rejects_lines_without_a_value() {
+        let error = Config::parse("[theme]\nfont_size\n").unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+
+    #[test]
+    fn test_section_lists_all_keys() {
+        let config = Config::parse("[colors]\ntext = #FFFFFF\nhover = #000000\n").unwrap();
+        let mut keys: Vec<_> = config.section("colors").map(|(key, _)| key).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["hover", "text"]);
+        assert_eq!(config.section("missing").count(), 0);
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("rspin-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(FILE_NAME);
+        std::fs::write(&path, "[decoration]\nradius = 8\n").unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.get("decoration", "radius"), Some("8"));
+        // An explicitly given file must exist
+        assert!(Config::load(Some(&dir.join("missing.ini"))).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,840 @@
+// CPU rendering module
+// Multithreaded software scaling and double-buffered wl_shm presentation
+
+use crate::image_loader::ImageData;
+use crate::resample::{self, FilterMode};
+use anyhow::{Context, Result};
+use log::debug;
+use smithay_client_toolkit::shm::{
+    slot::{Buffer as ShmBuffer, SlotPool},
+    Shm,
+};
+use std::thread;
+use wayland_client::protocol::{wl_shm, wl_surface};
+
+/// Minimum number of rows handed to a worker thread
+const MIN_ROWS_PER_BAND: usize = 32;
+
+/// Split `data` into horizontal bands of whole rows and process them in parallel
+///
+/// The closure receives the index of the first row in the band and the band itself.
+pub fn par_row_bands<F>(data: &mut [u8], row_bytes: usize, f: F)
+where
+    F: Fn(usize, &mut [u8]) + Sync,
+{
+    if row_bytes == 0 || data.is_empty() {
+        return;
+    }
+
+    let rows = data.len() / row_bytes;
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(rows.div_ceil(MIN_ROWS_PER_BAND))
+        .max(1);
+
+    if threads == 1 {
+        f(0, data);
+        return;
+    }
+
+    let rows_per_band = rows.div_ceil(threads);
+    thread::scope(|scope| {
+        for (band, chunk) in data.chunks_mut(rows_per_band * row_bytes).enumerate() {
+            let f = &f;
+            scope.spawn(move || f(band * rows_per_band, chunk));
+        }
+    });
+}
+
+/// Pick the source level for a target size, preferring mipmaps when downscaling
+fn select_source(image: &ImageData, width: u32, height: u32, threshold: f32) -> (u32, u32, &[u8]) {
+    let scale_ratio = (width as f32 / image.width as f32).min(height as f32 / image.height as f32);
+
+    if scale_ratio < threshold && !image.mipmaps.is_empty() {
+        // Use the smallest mipmap that is still larger than the target
+        let level = image
+            .mipmaps
+            .iter()
+            .rposition(|m| m.width >= width && m.height >= height);
+        if let Some(level) = level {
+            let mipmap = &image.mipmaps[level];
+            return (mipmap.width, mipmap.height, &mipmap.data[..]);
+        }
+    }
+
+    (image.width, image.height, &image.rgba_data[..])
+}
+
+/// Render the image scaled to `width` x `height` with the selected filter (no opacity)
+pub fn render_scaled(
+    image: &ImageData,
+    canvas: &mut [u8],
+    width: u32,
+    height: u32,
+    filter: FilterMode,
+) {
+    let (img_width, img_height, src_data) = select_source(image, width, height, 0.7);
+    resample::resample(
+        src_data, img_width, img_height, canvas, width, height, filter,
+    );
+}
+
+/// Fast nearest-neighbor rendering for responsive resize, with opacity applied
+pub fn render_fast(image: &ImageData, canvas: &mut [u8], width: u32, height: u32, opacity: f32) {
+    let (img_width, img_height, src_data) = select_source(image, width, height, 0.5);
+    if img_width == 0 || img_height == 0 || width == 0 || height == 0 {
+        return;
+    }
+
+    // Pre-compute scale factors as fixed-point for faster integer math
+    let scale_x_fp = ((img_width as u64) << 16) / width as u64;
+    let scale_y_fp = ((img_height as u64) << 16) / height as u64;
+    let opacity_i = opacity_fixed(opacity);
+    let img_stride = img_width as usize * 4;
+    let row_bytes = width as usize * 4;
+
+    // Pre-compute X lookup table to avoid repeated calculations per row
+    let x_lut: Vec<usize> = (0..width)
+        .map(|x| {
+            let src_x = ((x as u64 * scale_x_fp) >> 16) as u32;
+            src_x.min(img_width - 1) as usize * 4
+        })
+        .collect();
+
+    let canvas = &mut canvas[..row_bytes * height as usize];
+    par_row_bands(canvas, row_bytes, |first_row, band| {
+        for (i, dst_row) in band.chunks_exact_mut(row_bytes).enumerate() {
+            let y = (first_row + i) as u64;
+            let src_y = (((y * scale_y_fp) >> 16) as u32).min(img_height - 1);
+            let src_row = &src_data[src_y as usize * img_stride..][..img_stride];
+
+            for (dst, &src_x) in dst_row.chunks_exact_mut(4).zip(&x_lut) {
+                let src = &src_row[src_x..src_x + 4];
+                dst[0] = src[0];
+                dst[1] = src[1];
+                dst[2] = src[2];
+                dst[3] = ((src[3] as u32 * opacity_i) >> 8) as u8;
+            }
+        }
+    });
+}
+
+/// Copy a pre-scaled image into the canvas while applying opacity
+pub fn copy_with_opacity(src: &[u8], canvas: &mut [u8], row_bytes: usize, opacity: f32) {
+    let opacity_i = opacity_fixed(opacity);
+    let len = src.len().min(canvas.len());
+    let canvas = &mut canvas[..len];
+
+    par_row_bands(canvas, row_bytes, |first_row, band| {
+        let src = &src[first_row * row_bytes..][..band.len()];
+        band.copy_from_slice(src);
+        if opacity_i < 256 {
+            for pixel in band.chunks_exact_mut(4) {
+                pixel[3] = ((pixel[3] as u32 * opacity_i) >> 8) as u8;
+            }
+        }
+    });
+}
+
+/// Scale the alpha channel of the canvas in place
+pub fn apply_opacity(canvas: &mut [u8], row_bytes: usize, opacity: f32) {
+    let opacity_i = opacity_fixed(opacity);
+    if opacity_i >= 256 {
+        return;
+    }
+
+    par_row_bands(canvas, row_bytes, |_, band| {
+        for pixel in band.chunks_exact_mut(4) {
+            pixel[3] = ((pixel[3] as u32 * opacity_i) >> 8) as u8;
+        }
+    });
+}
+
+/// Opacity as an 8.8 fixed-point multiplier (256 = fully opaque)
+fn opacity_fixed(opacity: f32) -> u32 {
+    (opacity.clamp(0.0, 1.0) * 256.0).round() as u32
+}
+
+/// Copy one region of a pre-scaled image into the canvas while applying opacity
+pub fn copy_region_with_opacity(
+    src: &[u8],
+    canvas: &mut [u8],
+    row_bytes: usize,
+    rect: Rect,
+    opacity: f32,
+) {
+    let opacity_i = opacity_fixed(opacity);
+    let x0 = rect.x as usize * 4;
+    let len = rect.width as usize * 4;
+
+    for y in rect.y as usize..(rect.y + rect.height) as usize {
+        let start = y * row_bytes + x0;
+        let (Some(src_row), Some(dst_row)) = (
+            src.get(start..start + len),
+            canvas.get_mut(start..start + len),
+        ) else {
+            break;
+        };
+        dst_row.copy_from_slice(src_row);
+        if opacity_i < 256 {
+            for pixel in dst_row.chunks_exact_mut(4) {
+                pixel[3] = ((pixel[3] as u32 * opacity_i) >> 8) as u8;
+            }
+        }
+    }
+}
+
+/// Copy `rect` of a `src_width` wide canvas into another canvas, moved by `offset`
+pub fn copy_rect(
+    src: &[u8],
+    src_width: u32,
+    rect: Rect,
+    dst: &mut [u8],
+    dst_width: u32,
+    offset: (i32, i32),
+) {
+    let len = rect.width as usize * 4;
+    for y in rect.y..rect.y + rect.height {
+        let s = (y as usize * src_width as usize + rect.x as usize) * 4;
+        let d = ((y + offset.1) as usize * dst_width as usize + (rect.x + offset.0) as usize) * 4;
+        let (Some(src_row), Some(dst_row)) = (src.get(s..s + len), dst.get_mut(d..d + len)) else {
+            break;
+        };
+        dst_row.copy_from_slice(src_row);
+    }
+}
+
+/// Alpha-blend a BGRA panel onto the canvas at `pos`, limited to `clip`
+///
+/// Overlays (readouts, tooltips) are rendered into their own small buffer so
+/// the GPU path can upload the same pixels as an overlay texture.
+pub fn blend_panel(
+    canvas: &mut [u8],
+    canvas_width: u32,
+    canvas_height: u32,
+    panel: &[u8],
+    panel_width: u32,
+    pos: (i32, i32),
+    clip: Rect,
+) {
+    let panel_height = (panel.len() / (panel_width as usize * 4).max(1)) as i32;
+    let panel_rect = Rect::new(pos.0, pos.1, panel_width as i32, panel_height);
+    let Some(area) = panel_rect
+        .intersection(&clip)
+        .and_then(|r| r.clamp_to(canvas_width, canvas_height))
+    else {
+        return;
+    };
+
+    for y in area.y..area.y + area.height {
+        let src_row = ((y - pos.1) as usize * panel_width as usize) * 4;
+        let dst_row = y as usize * canvas_width as usize * 4;
+        for x in area.x..area.x + area.width {
+            let s = src_row + (x - pos.0) as usize * 4;
+            let d = dst_row + x as usize * 4;
+            let (Some(src), Some(dst)) = (panel.get(s..s + 4), canvas.get_mut(d..d + 4)) else {
+                continue;
+            };
+            let src_alpha = src[3] as u32;
+            if src_alpha == 0 {
+                continue;
+            }
+            let inv = 255 - src_alpha;
+            for c in 0..3 {
+                dst[c] = ((src[c] as u32 * src_alpha + dst[c] as u32 * inv) / 255) as u8;
+            }
+            dst[3] = (src_alpha + dst[3] as u32 * inv / 255) as u8;
+        }
+    }
+}
+
+/// Signed distance from a point to the edge of a rounded rectangle (negative inside)
+pub fn rounded_rect_distance(rect: Rect, radius: f32, x: f32, y: f32) -> f32 {
+    let half = (rect.width as f32 / 2.0, rect.height as f32 / 2.0);
+    let radius = radius.min(half.0).min(half.1);
+    let qx = (x - rect.x as f32 - half.0).abs() - half.0 + radius;
+    let qy = (y - rect.y as f32 - half.1).abs() - half.1 + radius;
+    let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+    outside + qx.max(qy).min(0.0) - radius
+}
+
+/// Paint a rounded rectangle onto a straight-alpha BGRA canvas with anti-aliased corners
+///
+/// With `stroke` only a band of that many pixels inside the edge is painted.
+/// Fully covered pixels are overwritten like `fill_rect` does.
+pub fn fill_rounded_rect(
+    canvas: &mut [u8],
+    canvas_width: u32,
+    canvas_height: u32,
+    rect: Rect,
+    radius: u32,
+    color: [u8; 4],
+    stroke: Option<u32>,
+) {
+    let Some(area) = rect.clamp_to(canvas_width, canvas_height) else {
+        return;
+    };
+    let radius = radius as f32;
+    for y in area.y..area.y + area.height {
+        for x in area.x..area.x + area.width {
+            let d = rounded_rect_distance(rect, radius, x as f32 + 0.5, y as f32 + 0.5);
+            let mut coverage = (0.5 - d).clamp(0.0, 1.0);
+            if let Some(width) = stroke {
+                coverage -= (0.5 - d - width as f32).clamp(0.0, 1.0);
+            }
+            if coverage <= 0.0 {
+                continue;
+            }
+            let i = (y as usize * canvas_width as usize + x as usize) * 4;
+            cover_pixel(&mut canvas[i..i + 4], color, coverage);
+        }
+    }
+}
+
+/// Paint `color` over a straight-alpha BGRA pixel with partial `coverage` (0.0-1.0)
+///
+/// Full coverage overwrites the pixel; edges over transparent pixels keep the
+/// color and only fade its alpha, so anti-aliased shapes don't darken.
+pub fn cover_pixel(pixel: &mut [u8], color: [u8; 4], coverage: f32) {
+    if coverage >= 1.0 {
+        pixel.copy_from_slice(&color);
+    } else if pixel[3] == 0 {
+        pixel[..3].copy_from_slice(&color[..3]);
+        pixel[3] = (color[3] as f32 * coverage) as u8;
+    } else {
+        for c in 0..4 {
+            let blended = pixel[c] as f32 + (color[c] as f32 - pixel[c] as f32) * coverage;
+            pixel[c] = blended as u8;
+        }
+    }
+}
+
+/// Straight-alpha "over" of `color` at `alpha` onto a BGRA pixel
+pub fn blend_over(dst: &mut [u8], color: [u8; 4], alpha: f32) {
+    let dst_alpha = dst[3] as f32 / 255.0;
+    let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+    if out_alpha <= 0.0 {
+        return;
+    }
+    for c in 0..3 {
+        let blended =
+            (color[c] as f32 * alpha + dst[c] as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+        dst[c] = blended.round().clamp(0.0, 255.0) as u8;
+    }
+    dst[3] = (out_alpha * 255.0) as u8;
+}
+
+/// A rounded rectangle filled with one color and outlined one pixel inside its
+/// edge with another: the background of an overlay panel
+///
+/// The GPU draws these in panel.wgsl with the same coverage rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanelFrame {
+    pub rect: Rect,
+    pub radius: u32,
+    /// BGRA, straight alpha
+    pub fill: [u8; 4],
+    pub border: [u8; 4],
+}
+
+impl PanelFrame {
+    /// Paint the frame onto a straight-alpha BGRA canvas
+    pub fn draw(&self, canvas: &mut [u8], canvas_width: u32, canvas_height: u32) {
+        let radius = self.radius;
+        fill_rounded_rect(
+            canvas,
+            canvas_width,
+            canvas_height,
+            self.rect,
+            radius,
+            self.fill,
+            None,
+        );
+        fill_rounded_rect(
+            canvas,
+            canvas_width,
+            canvas_height,
+            self.rect,
+            radius,
+            self.border,
+            Some(1),
+        );
+    }
+}
+
+/// Axis-aligned rectangle in buffer coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    /// Clip the rectangle to a `width` x `height` buffer
+    pub fn clamp_to(&self, width: u32, height: u32) -> Option<Rect> {
+        let x0 = self.x.clamp(0, width as i32);
+        let y0 = self.y.clamp(0, height as i32);
+        let x1 = (self.x + self.width).clamp(0, width as i32);
+        let y1 = (self.y + self.height).clamp(0, height as i32);
+        let rect = Rect::new(x0, y0, x1 - x0, y1 - y0);
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// Overlapping part of two rectangles
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width).min(other.x + other.width);
+        let y1 = (self.y + self.height).min(other.y + other.height);
+        let rect = Rect::new(x0, y0, x1 - x0, y1 - y0);
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// Smallest rectangle containing both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x0 = self.x.min(other.x);
+        let y0 = self.y.min(other.y);
+        let x1 = (self.x + self.width).max(other.x + other.width);
+        let y1 = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+/// Maximum number of separate damage rectangles before they are merged
+const MAX_DAMAGE_RECTS: usize = 8;
+
+/// Parts of the window that changed and need to be redrawn
+#[derive(Debug, Clone, PartialEq)]
+pub enum Damage {
+    /// Everything must be redrawn
+    Full,
+    /// Only the listed rectangles changed (empty means nothing changed)
+    Regions(Vec<Rect>),
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Damage::Regions(Vec::new())
+    }
+}
+
+impl Damage {
+    /// Mark a rectangle as changed
+    pub fn add(&mut self, rect: Rect) {
+        if let Damage::Regions(rects) = self {
+            if rect.is_empty() {
+                return;
+            }
+            rects.push(rect);
+            if rects.len() > MAX_DAMAGE_RECTS {
+                let bounds = rects.iter().skip(1).fold(rects[0], |acc, r| acc.union(r));
+                *rects = vec![bounds];
+            }
+        }
+    }
+
+    /// Mark the whole window as changed
+    pub fn add_full(&mut self) {
+        *self = Damage::Full;
+    }
+
+    /// Accumulate another damage set into this one
+    pub fn merge(&mut self, other: &Damage) {
+        match other {
+            Damage::Full => self.add_full(),
+            Damage::Regions(rects) => {
+                for rect in rects {
+                    self.add(*rect);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Damage::Regions(rects) if rects.is_empty())
+    }
+
+    /// The same damage moved by `(dx, dy)`, e.g. from image to surface coordinates
+    pub fn translate(&self, dx: i32, dy: i32) -> Damage {
+        match self {
+            Damage::Full => Damage::Full,
+            Damage::Regions(rects) => Damage::Regions(
+                rects
+                    .iter()
+                    .map(|r| Rect::new(r.x + dx, r.y + dy, r.width, r.height))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The same damage in a buffer `factor` times larger, e.g. from logical to buffer pixels
+    pub fn scale(&self, factor: i32) -> Damage {
+        match self {
+            Damage::Full => Damage::Full,
+            Damage::Regions(rects) => Damage::Regions(
+                rects
+                    .iter()
+                    .map(|r| {
+                        Rect::new(
+                            r.x * factor,
+                            r.y * factor,
+                            r.width * factor,
+                            r.height * factor,
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Damaged rectangles clipped to a `width` x `height` buffer
+    pub fn rects(&self, width: u32, height: u32) -> Vec<Rect> {
+        match self {
+            Damage::Full => vec![Rect::new(0, 0, width as i32, height as i32)],
+            Damage::Regions(rects) => rects
+                .iter()
+                .filter_map(|r| r.clamp_to(width, height))
+                .collect(),
+        }
+    }
+}
+
+/// Double-buffered wl_shm buffers backed by a single slot pool
+///
+/// Buffers are reused across frames and only reallocated when the size changes.
+/// A buffer is only handed out once the compositor has released it. Each buffer
+/// remembers which regions changed since it was last drawn (like EGL buffer age),
+/// so callers can repair just those regions instead of redrawing everything.
+pub struct ShmSwapchain {
+    pool: SlotPool,
+    buffers: [Option<ShmBuffer>; 2],
+    /// Regions each buffer is missing compared to the last presented frame
+    stale: [Damage; 2],
+    size: (u32, u32),
+    /// Index of the buffer that was presented last
+    front: usize,
+}
+
+impl ShmSwapchain {
+    /// Create a pool large enough for two buffers of the given size
+    pub fn new(shm: &Shm, width: u32, height: u32) -> Result<Self> {
+        let frame_bytes = width as usize * height as usize * 4;
+        let pool = SlotPool::new(frame_bytes * 2, shm).context("Failed to create slot pool")?;
+        Ok(Self {
+            pool,
+            buffers: [None, None],
+            stale: [Damage::Full, Damage::Full],
+            size: (width, height),
+            front: 1,
+        })
+    }
+
+    /// Get a released buffer of the given size and its pixels
+    ///
+    /// Returns the buffer index to pass to [`ShmSwapchain::present`] and the
+    /// regions of the buffer that are out of date (`Damage::Full` for new buffers).
+    pub fn acquire(&mut self, width: u32, height: u32) -> Result<(usize, &mut [u8], Damage)> {
+        if self.size != (width, height) {
+            debug!(
+                "Reallocating shm buffers: {}x{} -> {}x{}",
+                self.size.0, self.size.1, width, height
+            );
+            // Buffers still held by the compositor are destroyed once released
+            self.buffers = [None, None];
+            self.stale = [Damage::Full, Damage::Full];
+            self.size = (width, height);
+        }
+
+        let back = 1 - self.front;
+        let index = [back, self.front]
+            .into_iter()
+            .find(|&i| match &self.buffers[i] {
+                Some(buffer) => buffer.canvas(&mut self.pool).is_some(),
+                None => true,
+            })
+            .unwrap_or(back);
+
+        let needs_buffer = match &self.buffers[index] {
+            Some(buffer) => buffer.canvas(&mut self.pool).is_none(),
+            None => true,
+        };
+        if needs_buffer {
+            // Both buffers are still in use: replace the back buffer with a fresh one
+            let (buffer, _) = self
+                .pool
+                .create_buffer(
+                    width as i32,
+                    height as i32,
+                    width as i32 * 4,
+                    wl_shm::Format::Argb8888,
+                )
+                .with_context(|| format!("Failed to create buffer {}x{}", width, height))?;
+            self.buffers[index] = Some(buffer);
+            self.stale[index] = Damage::Full;
+        }
+
+        let stale = self.stale[index].clone();
+        let buffer = self.buffers[index]
+            .as_ref()
+            .expect("buffer was just created");
+        let canvas = buffer
+            .canvas(&mut self.pool)
+            .context("Freshly created buffer is not writable")?;
+        Ok((index, canvas, stale))
+    }
+
+    /// Attach the buffer to the surface and damage the changed regions
+    ///
+    /// The caller is responsible for committing the surface.
+    pub fn present(
+        &mut self,
+        index: usize,
+        surface: &wl_surface::WlSurface,
+        damage: &Damage,
+    ) -> Result<()> {
+        let buffer = self.buffers[index]
+            .as_ref()
+            .context("Presenting a buffer that was never acquired")?;
+        buffer
+            .attach_to(surface)
+            .context("Failed to attach buffer")?;
+        for rect in damage.rects(self.size.0, self.size.1) {
+            surface.damage_buffer(rect.x, rect.y, rect.width, rect.height);
+        }
+
+        // The other buffer now lags behind by this frame's damage
+        self.stale[index] = Damage::default();
+        self.stale[1 - index].merge(damage);
+        self.front = index;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x2 image whose pixels are labelled 0..8 in the first channel
+    fn labelled_image() -> ImageData {
+        ImageData {
+            width: 4,
+            height: 2,
+            rgba_data: (0..8u8).flat_map(|i| [i, 10, 20, 200]).collect(),
+            scale: 1.0,
+            mipmaps: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_par_row_bands_visits_every_row_once() {
+        let row_bytes = 12;
+        let rows = MIN_ROWS_PER_BAND * 5 + 7;
+        let mut data = vec![0u8; row_bytes * rows];
+        par_row_bands(&mut data, row_bytes, |first_row, band| {
+            for (i, row) in band.chunks_exact_mut(row_bytes).enumerate() {
+                row[0] = ((first_row + i) % 256) as u8;
+                row[1] += 1;
+            }
+        });
+        for (y, row) in data.chunks_exact(row_bytes).enumerate() {
+            assert_eq!(row[0], (y % 256) as u8);
+            assert_eq!(row[1], 1);
+        }
+    }
+
+    #[test]
+    fn test_render_fast_scales_and_applies_opacity() {
+        let image = labelled_image();
+        let mut canvas = vec![0u8; 8 * 4 * 4];
+        render_fast(&image, &mut canvas, 8, 4, 0.5);
+        let labels: Vec<u8> = canvas.chunks_exact(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(&labels[..8], &[0, 0, 1, 1, 2, 2, 3, 3]);
+        assert_eq!(&labels[24..], &[4, 4, 5, 5, 6, 6, 7, 7]);
+        assert!(canvas.chunks_exact(4).all(|pixel| pixel[3] == 100));
+    }
+
+    #[test]
+    fn test_opacity() {
+        assert_eq!(opacity_fixed(1.0), 256);
+        assert_eq!(opacity_fixed(2.0), 256);
+        assert_eq!(opacity_fixed(-1.0), 0);
+
+        let src = [1u8, 2, 3, 255].repeat(4);
+        let mut canvas = vec![0u8; 16];
+        copy_with_opacity(&src, &mut canvas, 8, 1.0);
+        assert_eq!(canvas, src);
+        apply_opacity(&mut canvas, 8, 0.25);
+        assert!(canvas.chunks_exact(4).all(|pixel| pixel == [1, 2, 3, 63]));
+    }
+
+    #[test]
+    fn test_copy_region_with_opacity_touches_only_the_region() {
+        let src = [9u8, 9, 9, 255].repeat(16);
+        let mut canvas = vec![0u8; 64];
+        copy_region_with_opacity(&src, &mut canvas, 16, Rect::new(1, 1, 2, 2), 1.0);
+        for (i, pixel) in canvas.chunks_exact(4).enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            let inside = (1..3).contains(&x) && (1..3).contains(&y);
+            assert_eq!(pixel[0] == 9, inside, "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn test_copy_rect_with_offset() {
+        let src: Vec<u8> = (0..16u8).flat_map(|i| [i; 4]).collect();
+        let mut dst = vec![0u8; 6 * 6 * 4];
+        copy_rect(&src, 4, Rect::new(1, 1, 2, 2), &mut dst, 6, (2, 3));
+        let at = |x: usize, y: usize| dst[(y * 6 + x) * 4];
+        assert_eq!([at(3, 4), at(4, 4), at(3, 5), at(4, 5)], [5, 6, 9, 10]);
+        assert_eq!(dst.iter().filter(|&&v| v != 0).count(), 16);
+    }
+
+    #[test]
+    fn test_blend_panel_clips_and_blends() {
+        let mut canvas = [0u8, 0, 0, 255].repeat(4 * 4);
+        // 2x2 panel: opaque white, transparent, half red, opaque white
+        let panel = [
+            [255, 255, 255, 255],
+            [0, 0, 0, 0],
+            [0, 0, 255, 128],
+            [255, 255, 255, 255],
+        ]
+        .concat();
+        // Placed half outside the canvas at the bottom right
+        blend_panel(&mut canvas, 4, 4, &panel, 2, (3, 2), Rect::new(0, 0, 4, 4));
+        let at = |x: usize, y: usize| &canvas[(y * 4 + x) * 4..][..4];
+        assert_eq!(at(3, 2), [255, 255, 255, 255]);
+        assert_eq!(at(3, 3), [0, 0, 128, 255]);
+        assert_eq!(at(2, 2), [0, 0, 0, 255]);
+
+        // A clip that excludes the panel leaves the canvas alone
+        let before = canvas.clone();
+        blend_panel(&mut canvas, 4, 4, &panel, 2, (0, 0), Rect::new(2, 2, 2, 2));
+        assert_eq!(canvas, before);
+    }
+
+    #[test]
+    fn test_rounded_rect_distance() {
+        let rect = Rect::new(0, 0, 100, 50);
+        assert_eq!(rounded_rect_distance(rect, 0.0, 50.0, 25.0), -25.0);
+        assert_eq!(rounded_rect_distance(rect, 0.0, 110.0, 25.0), 10.0);
+        // The corner of a rounded rectangle is cut off
+        assert!(rounded_rect_distance(rect, 10.0, 1.0, 1.0) > 0.0);
+        assert!(rounded_rect_distance(rect, 0.0, 1.0, 1.0) < 0.0);
+        // The radius is limited to half the shorter side
+        assert_eq!(
+            rounded_rect_distance(rect, 100.0, 50.0, 0.0),
+            rounded_rect_distance(rect, 25.0, 50.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_cover_pixel_keeps_color_over_transparency() {
+        let mut pixel = [0u8; 4];
+        cover_pixel(&mut pixel, [10, 20, 30, 200], 0.5);
+        assert_eq!(pixel, [10, 20, 30, 100]);
+        cover_pixel(&mut pixel, [1, 2, 3, 4], 1.0);
+        assert_eq!(pixel, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_panel_frame_fills_and_outlines() {
+        let frame = PanelFrame {
+            rect: Rect::new(1, 1, 4, 4),
+            radius: 0,
+            fill: [10, 20, 30, 200],
+            border: [90, 90, 90, 255],
+        };
+        let mut canvas = vec![0u8; 6 * 6 * 4];
+        frame.draw(&mut canvas, 6, 6);
+        let pixel = |x: usize, y: usize| &canvas[(y * 6 + x) * 4..(y * 6 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), [0, 0, 0, 0]);
+        assert_eq!(pixel(1, 1), frame.border);
+        assert_eq!(pixel(4, 2), frame.border);
+        assert_eq!(pixel(2, 2), frame.fill);
+        assert_eq!(pixel(3, 3), frame.fill);
+        assert_eq!(pixel(5, 5), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_damage_add_and_merge() {
+        let mut damage = Damage::default();
+        assert!(damage.is_empty());
+        damage.add(Rect::new(0, 0, 0, 10));
+        assert!(damage.is_empty());
+        damage.add(Rect::new(1, 2, 3, 4));
+        assert_eq!(damage, Damage::Regions(vec![Rect::new(1, 2, 3, 4)]));
+
+        let mut other = Damage::default();
+        other.add(Rect::new(10, 10, 5, 5));
+        damage.merge(&other);
+        assert_eq!(
+            damage,
+            Damage::Regions(vec![Rect::new(1, 2, 3, 4), Rect::new(10, 10, 5, 5)])
+        );
+        damage.merge(&Damage::Full);
+        assert_eq!(damage, Damage::Full);
+        // Nothing is added to full damage
+        damage.add(Rect::new(0, 0, 1, 1));
+        assert_eq!(damage, Damage::Full);
+    }
+
+    #[test]
+    fn test_damage_collapses_to_bounds() {
+        let mut damage = Damage::default();
+        for i in 0..=MAX_DAMAGE_RECTS as i32 {
+            damage.add(Rect::new(i * 10, i, 2, 2));
+        }
+        let last = MAX_DAMAGE_RECTS as i32;
+        assert_eq!(
+            damage,
+            Damage::Regions(vec![Rect::new(0, 0, last * 10 + 2, last + 2)])
+        );
+    }
+
+    #[test]
+    fn test_damage_rects_are_clipped() {
+        let damage = Damage::Regions(vec![
+            Rect::new(-5, -5, 10, 10),
+            Rect::new(50, 50, 10, 10),
+            Rect::new(95, 45, 10, 10),
+        ]);
+        assert_eq!(
+            damage.rects(100, 50),
+            vec![Rect::new(0, 0, 5, 5), Rect::new(95, 45, 5, 5)]
+        );
+        assert_eq!(Damage::Full.rects(100, 50), vec![Rect::new(0, 0, 100, 50)]);
+        assert!(Damage::default().rects(100, 50).is_empty());
+    }
+
+    #[test]
+    fn test_damage_translate_and_scale() {
+        let damage = Damage::Regions(vec![Rect::new(1, 2, 3, 4)]);
+        assert_eq!(
+            damage.translate(10, -2),
+            Damage::Regions(vec![Rect::new(11, 0, 3, 4)])
+        );
+        assert_eq!(
+            damage.scale(2),
+            Damage::Regions(vec![Rect::new(2, 4, 6, 8)])
+        );
+        assert_eq!(Damage::Full.translate(5, 5), Damage::Full);
+        assert_eq!(Damage::Full.scale(2), Damage::Full);
+    }
+}
@@ -0,0 +1,430 @@
+// Decoration module
+// Rounded corners, border and drop shadow of the pin, configured in the [decoration] section
+
+use crate::config::Config;
+use crate::cpu_renderer::{self, Rect};
+use crate::theme::parse_color;
+use log::warn;
+
+/// Space around the image inside the pin's surface, which the shadow is drawn into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Insets {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Insets {
+    pub fn horizontal(&self) -> u32 {
+        (self.left + self.right) as u32
+    }
+
+    pub fn vertical(&self) -> u32 {
+        (self.top + self.bottom) as u32
+    }
+}
+
+/// How the edges of the pin are drawn
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoration {
+    /// Corner radius of the image in pixels (0 = square)
+    pub radius: u32,
+    /// Width of the border drawn just inside the image's edge (0 = none)
+    pub border_width: u32,
+    /// Border color (BGRA, straight alpha)
+    pub border_color: [u8; 4],
+    /// Distance over which the drop shadow fades out (0 = no shadow)
+    pub shadow: u32,
+    /// Shadow color (BGRA, straight alpha)
+    pub shadow_color: [u8; 4],
+    /// Offset of the shadow from the image
+    pub shadow_offset: (i32, i32),
+}
+
+impl Default for Decoration {
+    fn default() -> Self {
+        // Square corners and the faint edge pins have always had; rounding is opt-in
+        Self {
+            radius: 0,
+            border_width: 1,
+            border_color: [150, 150, 150, 100],
+            shadow: 0,
+            shadow_color: [0, 0, 0, 90],
+            shadow_offset: (0, 4),
+        }
+    }
+}
+
+impl Decoration {
+    /// Square corners, no border and no shadow
+    pub fn none() -> Self {
+        Self {
+            radius: 0,
+            border_width: 0,
+            shadow: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Build the decoration from the [decoration] section
+    pub fn load(config: &Config) -> Self {
+        let defaults = Self::default();
+        let color = |key: &str, default: [u8; 4]| {
+            let Some(value) = config.get("decoration", key) else {
+                return default;
+            };
+            parse_color(value).unwrap_or_else(|| {
+                warn!("Ignoring invalid decoration color {} = {}", key, value);
+                default
+            })
+        };
+        let shadow_offset = config.get("decoration", "shadow_offset").map(|value| {
+            parse_offset(value).unwrap_or_else(|| {
+                warn!(
+                    "Ignoring invalid shadow offset \"{}\", expected \"X,Y\"",
+                    value
+                );
+                defaults.shadow_offset
+            })
+        });
+        Self {
+            radius: config
+                .parse_value("decoration", "radius")
+                .unwrap_or(defaults.radius),
+            border_width: config
+                .parse_value("decoration", "border_width")
+                .unwrap_or(defaults.border_width),
+            border_color: color("border_color", defaults.border_color),
+            shadow: config
+                .parse_value("decoration", "shadow")
+                .unwrap_or(defaults.shadow),
+            shadow_color: color("shadow_color", defaults.shadow_color),
+            shadow_offset: shadow_offset.unwrap_or(defaults.shadow_offset),
+        }
+    }
+
+    /// Room the shadow needs on each side of the image
+    pub fn insets(&self) -> Insets {
+        if self.shadow == 0 {
+            return Insets::default();
+        }
+        let blur = self.shadow as i32;
+        let (dx, dy) = self.shadow_offset;
+        Insets {
+            left: (blur - dx).max(0),
+            top: (blur - dy).max(0),
+            right: (blur + dx).max(0),
+            bottom: (blur + dy).max(0),
+        }
+    }
+
+    /// Where the shadow's shape sits for an image at `content`
+    pub fn shadow_rect(&self, content: Rect) -> Rect {
+        Rect::new(
+            content.x + self.shadow_offset.0,
+            content.y + self.shadow_offset.1,
+            content.width,
+            content.height,
+        )
+    }
+
+    /// Round the corners and draw the border of an image filling a `width`×`height` canvas
+    ///
+    /// Only pixels in `area` are touched, so partial redraws mask just what they repaired.
+    pub fn mask(&self, canvas: &mut [u8], width: u32, height: u32, area: Rect) {
+        if self.radius == 0 && self.border_width == 0 {
+            return;
+        }
+        let content = Rect::new(0, 0, width as i32, height as i32);
+        let Some(area) = area.intersection(&content) else {
+            return;
+        };
+        let radius = self.radius as f32;
+        let border_width = self.border_width as f32;
+        let border_alpha = self.border_color[3] as f32 / 255.0;
+        // Pixels further inside than the corners and the border are left alone
+        let band = self.radius.max(self.border_width) as i32 + 1;
+        let inner = Rect::new(
+            band,
+            band,
+            content.width - 2 * band,
+            content.height - 2 * band,
+        );
+        for_each_edge_pixel(area, inner, |x, y| {
+            let d = cpu_renderer::rounded_rect_distance(
+                content,
+                radius,
+                x as f32 + 0.5,
+                y as f32 + 0.5,
+            );
+            let coverage = (0.5 - d).clamp(0.0, 1.0);
+            let i = (y as usize * width as usize + x as usize) * 4;
+            let pixel = &mut canvas[i..i + 4];
+            if coverage <= 0.0 {
+                pixel.fill(0);
+                return;
+            }
+            if self.border_width > 0 {
+                let border = (d + border_width + 0.5).clamp(0.0, 1.0);
+                if border > 0.0 {
+                    cpu_renderer::blend_over(pixel, self.border_color, border_alpha * border);
+                }
+            }
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        });
+    }
+
+    /// Draw the shadow beneath the pixels of `area` that lie outside the image at `content`
+    pub fn draw_shadow(
+        &self,
+        canvas: &mut [u8],
+        width: u32,
+        height: u32,
+        content: Rect,
+        area: Rect,
+    ) {
+        if self.shadow == 0 {
+            return;
+        }
+        let Some(area) = area.clamp_to(width, height) else {
+            return;
+        };
+        let radius = self.radius as f32;
+        let blur = self.shadow as f32;
+        let shadow_rect = self.shadow_rect(content);
+        let alpha = self.shadow_color[3] as f32 / 255.0;
+        // The image fully covers everything inside its rounded corners
+        let band = self.radius as i32 + 1;
+        let inner = Rect::new(
+            content.x + band,
+            content.y + band,
+            content.width - 2 * band,
+            content.height - 2 * band,
+        );
+        for_each_edge_pixel(area, inner, |x, y| {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let outside = (0.5 + cpu_renderer::rounded_rect_distance(content, radius, px, py))
+                .clamp(0.0, 1.0);
+            if outside <= 0.0 {
+                return;
+            }
+            let d = cpu_renderer::rounded_rect_distance(shadow_rect, radius, px, py);
+            let strength = alpha * (1.0 - smoothstep(0.0, blur, d)) * outside;
+            if strength > 0.0 {
+                let i = (y as usize * width as usize + x as usize) * 4;
+                blend_under(&mut canvas[i..i + 4], self.shadow_color, strength);
+            }
+        });
+    }
+}
+
+/// Parse a shadow offset given as "X,Y"
+fn parse_offset(value: &str) -> Option<(i32, i32)> {
+    let (x, y) = value.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Call `f` for every pixel of `area` outside `inner`, row by row
+fn for_each_edge_pixel(area: Rect, inner: Rect, mut f: impl FnMut(i32, i32)) {
+    let (right, bottom) = (area.x + area.width, area.y + area.height);
+    for y in area.y..bottom {
+        if inner.is_empty() || y < inner.y || y >= inner.y + inner.height {
+            (area.x..right).for_each(|x| f(x, y));
+        } else {
+            (area.x..right.min(inner.x)).for_each(|x| f(x, y));
+            (area.x.max(inner.x + inner.width)..right).for_each(|x| f(x, y));
+        }
+    }
+}
+
+/// Hermite step from 0 at `edge0` to 1 at `edge1`, as in WGSL
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Straight-alpha "over" of a BGRA pixel onto `color` at `alpha`, putting the color beneath it
+fn blend_under(dst: &mut [u8], color: [u8; 4], alpha: f32) {
+    let dst_alpha = dst[3] as f32 / 255.0;
+    let out_alpha = dst_alpha + alpha * (1.0 - dst_alpha);
+    if out_alpha <= 0.0 {
+        return;
+    }
+    for c in 0..3 {
+        let blended =
+            (dst[c] as f32 * dst_alpha + color[c] as f32 * alpha * (1.0 - dst_alpha)) / out_alpha;
+        dst[c] = blended.round().clamp(0.0, 255.0) as u8;
+    }
+    dst[3] = (out_alpha * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(width: u32, height: u32) -> Vec<u8> {
+        [255, 255, 255, 255].repeat((width * height) as usize)
+    }
+
+    fn pixel(canvas: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        [canvas[i], canvas[i + 1], canvas[i + 2], canvas[i + 3]]
+    }
+
+    #[test]
+    fn test_default_is_square() {
+        let decoration = Decoration::default();
+        assert_eq!(decoration.radius, 0);
+        assert_eq!(decoration.border_width, 1);
+        assert_eq!(decoration.shadow, 0);
+        assert_eq!(decoration.insets(), Insets::default());
+    }
+
+    #[test]
+    fn test_load() {
+        let config = Config::parse(
+            "[decoration]\nradius = 12\nborder_width = 2\nborder_color = #FF000080\nshadow = 16\nshadow_offset = -2, 6\n",
+        )
+        .unwrap();
+        let decoration = Decoration::load(&config);
+        assert_eq!(decoration.radius, 12);
+        assert_eq!(decoration.border_width, 2);
+        assert_eq!(decoration.border_color, [0, 0, 255, 128]);
+        assert_eq!(decoration.shadow, 16);
+        assert_eq!(decoration.shadow_offset, (-2, 6));
+        assert_eq!(decoration.shadow_color, Decoration::default().shadow_color);
+    }
+
+    #[test]
+    fn test_load_ignores_invalid_values() {
+        let config =
+            Config::parse("[decoration]\nradius = round\nborder_color = red\nshadow_offset = 4\n")
+                .unwrap();
+        assert_eq!(Decoration::load(&config), Decoration::default());
+        assert_eq!(
+            Decoration::load(&Config::parse("").unwrap()),
+            Decoration::default()
+        );
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("3,4"), Some((3, 4)));
+        assert_eq!(parse_offset(" -1 , 0 "), Some((-1, 0)));
+        assert_eq!(parse_offset("3"), None);
+        assert_eq!(parse_offset("a,b"), None);
+    }
+
+    #[test]
+    fn test_insets() {
+        let decoration = Decoration {
+            shadow: 10,
+            shadow_offset: (2, 4),
+            ..Decoration::none()
+        };
+        let insets = decoration.insets();
+        assert_eq!(
+            insets,
+            Insets {
+                left: 8,
+                top: 6,
+                right: 12,
+                bottom: 14
+            }
+        );
+        assert_eq!((insets.horizontal(), insets.vertical()), (20, 20));
+
+        // An offset larger than the blur needs no room on the far side
+        let decoration = Decoration {
+            shadow: 3,
+            shadow_offset: (0, 5),
+            ..Decoration::none()
+        };
+        assert_eq!(decoration.insets().top, 0);
+        assert_eq!(decoration.insets().bottom, 8);
+    }
+
+    #[test]
+    fn test_mask_none_leaves_canvas() {
+        let mut canvas = white(8, 8);
+        Decoration::none().mask(&mut canvas, 8, 8, Rect::new(0, 0, 8, 8));
+        assert_eq!(canvas, white(8, 8));
+    }
+
+    #[test]
+    fn test_mask_rounds_corners() {
+        let decoration = Decoration {
+            radius: 8,
+            ..Decoration::none()
+        };
+        let mut canvas = white(20, 20);
+        decoration.mask(&mut canvas, 20, 20, Rect::new(0, 0, 20, 20));
+        for (x, y) in [(0, 0), (19, 0), (0, 19), (19, 19)] {
+            assert_eq!(pixel(&canvas, 20, x, y)[3], 0, "corner {},{}", x, y);
+        }
+        // Straight edges and the middle keep full coverage
+        assert_eq!(pixel(&canvas, 20, 10, 0), [255; 4]);
+        assert_eq!(pixel(&canvas, 20, 0, 10), [255; 4]);
+        assert_eq!(pixel(&canvas, 20, 10, 10), [255; 4]);
+        // Antialiased along the curve
+        let edge = pixel(&canvas, 20, 1, 3)[3];
+        assert!(edge > 0 && edge < 255, "{}", edge);
+    }
+
+    #[test]
+    fn test_mask_draws_border() {
+        let decoration = Decoration {
+            border_width: 1,
+            border_color: [0, 0, 255, 255],
+            ..Decoration::none()
+        };
+        let mut canvas = white(10, 10);
+        decoration.mask(&mut canvas, 10, 10, Rect::new(0, 0, 10, 10));
+        assert_eq!(pixel(&canvas, 10, 0, 5), [0, 0, 255, 255]);
+        assert_eq!(pixel(&canvas, 10, 9, 9), [0, 0, 255, 255]);
+        assert_eq!(pixel(&canvas, 10, 1, 5), [255; 4]);
+    }
+
+    #[test]
+    fn test_mask_only_touches_area() {
+        let decoration = Decoration {
+            radius: 8,
+            ..Decoration::none()
+        };
+        let mut canvas = white(20, 20);
+        decoration.mask(&mut canvas, 20, 20, Rect::new(10, 0, 20, 10));
+        assert_eq!(pixel(&canvas, 20, 0, 0), [255; 4]);
+        assert_eq!(pixel(&canvas, 20, 19, 0)[3], 0);
+        assert_eq!(pixel(&canvas, 20, 19, 19), [255; 4]);
+    }
+
+    #[test]
+    fn test_draw_shadow_below_image() {
+        let decoration = Decoration {
+            shadow: 4,
+            shadow_offset: (0, 2),
+            ..Decoration::none()
+        };
+        let insets = decoration.insets();
+        let (width, height) = (10 + insets.horizontal(), 10 + insets.vertical());
+        let content = Rect::new(insets.left, insets.top, 10, 10);
+        let mut canvas = vec![0; (width * height * 4) as usize];
+        let inside = (content.y as u32 + 5) * width + content.x as u32 + 5;
+        canvas[inside as usize * 4..inside as usize * 4 + 4].fill(255);
+        decoration.draw_shadow(
+            &mut canvas,
+            width,
+            height,
+            content,
+            Rect::new(0, 0, width as i32, height as i32),
+        );
+        // The image itself is left alone
+        assert_eq!(pixel(&canvas, width, 9, 7), [255; 4]);
+        // Darker just below the image than at the edge of the insets
+        let below = pixel(&canvas, width, 9, content.y as u32 + 10)[3];
+        let far = pixel(&canvas, width, 9, height - 1)[3];
+        assert!(below > far, "{} {}", below, far);
+        assert!(pixel(&canvas, width, 0, 0)[3] < below);
+    }
+}
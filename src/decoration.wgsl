@@ -0,0 +1,33 @@
+// Decoration shader
+// Draws the pin's drop shadow over the whole surface before the image, fading
+// out from a rounded rectangle like Decoration::draw_shadow on the CPU.
+
+struct Shadow {
+    // Image position and size in surface pixels
+    content: vec4<f32>,
+    // Shadow shape (the image moved by the shadow offset)
+    shape: vec4<f32>,
+    // sRGB, straight alpha
+    color: vec4<f32>,
+    radius: f32,
+    blur: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> shadow: Shadow;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // Triangle strip over the whole viewport
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u));
+    return vec4<f32>(corner.x * 2.0 - 1.0, 1.0 - corner.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // Only where the image does not cover, so translucent pins stay clean
+    let outside = clamp(0.5 + rounded_rect_distance(position.xy, shadow.content, shadow.radius), 0.0, 1.0);
+    let d = rounded_rect_distance(position.xy, shadow.shape, shadow.radius);
+    let alpha = shadow.color.a * (1.0 - smoothstep(0.0, shadow.blur, d)) * outside;
+    return vec4<f32>(srgb_to_linear(shadow.color.rgb), alpha);
+}
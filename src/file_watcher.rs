@@ -0,0 +1,151 @@
+// File watching module
+// Watches the pinned image with inotify so regenerated files trigger a reload
+
+use anyhow::{Context, Result};
+use inotify::{Inotify, WatchMask};
+use log::warn;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::Path;
+
+/// Size of the buffer events are read into (room for many events per read)
+const EVENT_BUFFER_SIZE: usize = 4096;
+
+/// Watches a single file for rewrites and atomic rename-replace
+///
+/// The parent directory is watched rather than the file itself: tools that
+/// write a temporary file and rename it over the target replace the inode,
+/// which would silently end a watch on the file.
+pub struct FileWatcher {
+    inotify: Inotify,
+    file_name: OsString,
+    buffer: Vec<u8>,
+}
+
+impl FileWatcher {
+    /// Start watching `path`
+    pub fn new(path: &Path) -> Result<Self> {
+        let file_name = path
+            .file_name()
+            .context("Watched path has no file name")?
+            .to_os_string();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let inotify = Inotify::init().context("Failed to initialize inotify")?;
+        inotify
+            .watches()
+            .add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+            .with_context(|| format!("Failed to watch directory: {}", dir.display()))?;
+
+        Ok(Self {
+            inotify,
+            file_name,
+            buffer: vec![0; EVENT_BUFFER_SIZE],
+        })
+    }
+
+    /// Duplicate the inotify descriptor for registration with the event loop
+    pub fn fd(&self) -> Result<OwnedFd> {
+        self.inotify
+            .as_fd()
+            .try_clone_to_owned()
+            .context("Failed to duplicate inotify descriptor")
+    }
+
+    /// Drain pending events and report whether the watched file changed
+    pub fn file_changed(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => {
+                    let mut any = false;
+                    for event in events {
+                        any = true;
+                        if event.name == Some(self.file_name.as_os_str()) {
+                            changed = true;
+                        }
+                    }
+                    if !any {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to read inotify events: {}", e);
+                    break;
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Fresh directory below the system temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rspin-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rewrite_is_reported() {
+        let dir = temp_dir("rewrite");
+        let path = dir.join("image.png");
+        fs::write(&path, b"one").unwrap();
+        let mut watcher = FileWatcher::new(&path).unwrap();
+        assert!(!watcher.file_changed());
+
+        fs::write(&path, b"two").unwrap();
+        assert!(watcher.file_changed());
+        // Events are drained
+        assert!(!watcher.file_changed());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rename_over_is_reported() {
+        let dir = temp_dir("rename");
+        let path = dir.join("image.png");
+        fs::write(&path, b"one").unwrap();
+        let mut watcher = FileWatcher::new(&path).unwrap();
+
+        let temp = dir.join("image.png.tmp");
+        fs::write(&temp, b"two").unwrap();
+        // Writing the temporary file alone is not a change
+        assert!(!watcher.file_changed());
+        fs::rename(&temp, &path).unwrap();
+        assert!(watcher.file_changed());
+
+        // The watch survives the inode being replaced
+        fs::write(&path, b"three").unwrap();
+        assert!(watcher.file_changed());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_other_files_are_ignored() {
+        let dir = temp_dir("other");
+        let path = dir.join("image.png");
+        fs::write(&path, b"one").unwrap();
+        let mut watcher = FileWatcher::new(&path).unwrap();
+        fs::write(dir.join("other.png"), b"two").unwrap();
+        assert!(!watcher.file_changed());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_path_without_file_name_fails() {
+        assert!(FileWatcher::new(Path::new("/")).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+// Fonts module
+// Resolves UI fonts through fontconfig and provides an embedded fallback face
+
+use cosmic_text::fontdb;
+use log::{debug, info, warn};
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+
+/// Latin subset of DejaVu Sans, always loaded so text renders without system fonts
+const FALLBACK_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-subset.ttf");
+const FALLBACK_FAMILY: &str = "DejaVu Sans";
+
+/// Font database built on a worker thread, so `fc-match` and font loading never block the UI
+pub enum Loader {
+    /// Resolving in the background
+    Pending(JoinHandle<fontdb::Database>),
+    /// The thread could not be started; resolve on first use instead
+    Deferred(Vec<String>),
+}
+
+impl Loader {
+    /// Start resolving `patterns` and loading the chosen files
+    pub fn spawn(patterns: Vec<String>) -> Self {
+        let worker_patterns = patterns.clone();
+        let spawned = thread::Builder::new()
+            .name("rspin-fonts".to_string())
+            .spawn(move || database(&resolve(&worker_patterns)));
+        match spawned {
+            Ok(handle) => Loader::Pending(handle),
+            Err(e) => {
+                warn!(
+                    "Failed to spawn font thread, resolving fonts on first use: {}",
+                    e
+                );
+                Loader::Deferred(patterns)
+            }
+        }
+    }
+
+    /// The finished database, waiting for the worker if it is still busy
+    pub fn finish(self) -> fontdb::Database {
+        match self {
+            Loader::Pending(handle) => handle.join().unwrap_or_else(|_| {
+                warn!("Font thread panicked, using the embedded fallback");
+                database(&[])
+            }),
+            Loader::Deferred(patterns) => database(&resolve(&patterns)),
+        }
+    }
+}
+
+/// Font files fontconfig picks for `patterns` (family names or full patterns), best first
+///
+/// Runs `fc-match` once per pattern, so callers should keep the result. Patterns
+/// that resolve to an already chosen file are skipped.
+pub fn resolve(patterns: &[String]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for pattern in patterns {
+        let Some(file) = fc_match(pattern) else {
+            continue;
+        };
+        debug!("fontconfig: {} -> {}", pattern, file.display());
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    if files.is_empty() {
+        warn!("fontconfig found no fonts, using the embedded fallback");
+    }
+    files
+}
+
+/// Best match for one pattern, or `None` if fontconfig is unavailable
+fn fc_match(pattern: &str) -> Option<PathBuf> {
+    let output = Command::new("fc-match")
+        .args(["--format=%{file}", pattern])
+        .output()
+        .map_err(|e| debug!("fc-match unavailable: {}", e))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let file = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    file.is_file().then_some(file)
+}
+
+/// Font database with the resolved files and the embedded fallback
+///
+/// The first file that loads becomes the sans-serif family, so `Family::SansSerif`
+/// always names the configured font (or the fallback).
+pub fn database(files: &[PathBuf]) -> fontdb::Database {
+    let mut db = fontdb::Database::new();
+    let mut primary = None;
+    for file in files {
+        let first_face = db.len();
+        if let Err(e) = db.load_font_file(file) {
+            warn!("Failed to load font {}: {}", file.display(), e);
+            continue;
+        }
+        info!("Loaded font: {}", file.display());
+        primary = primary.or_else(|| {
+            let face = db.faces().nth(first_face)?;
+            face.families.first().map(|(family, _)| family.clone())
+        });
+    }
+    db.load_font_data(FALLBACK_FONT.to_vec());
+    db.set_sans_serif_family(primary.unwrap_or_else(|| FALLBACK_FAMILY.to_string()));
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sans_serif(db: &fontdb::Database) -> String {
+        db.family_name(&fontdb::Family::SansSerif).to_string()
+    }
+
+    #[test]
+    fn test_database_always_has_the_fallback() {
+        let db = database(&[]);
+        assert!(!db.is_empty());
+        assert_eq!(sans_serif(&db), FALLBACK_FAMILY);
+        let id = db.query(&fontdb::Query {
+            families: &[fontdb::Family::SansSerif],
+            ..Default::default()
+        });
+        assert!(id.is_some());
+    }
+
+    #[test]
+    fn test_database_skips_files_that_fail_to_load() {
+        let db = database(&[PathBuf::from("/nonexistent/font.ttf")]);
+        assert_eq!(sans_serif(&db), FALLBACK_FAMILY);
+    }
+
+    #[test]
+    fn test_first_loaded_file_names_the_sans_serif_family() {
+        let dir = std::env::temp_dir().join(format!("rspin-fonts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("face.ttf");
+        std::fs::write(&file, FALLBACK_FONT).unwrap();
+        let db = database(&[PathBuf::from("/nonexistent/font.ttf"), file]);
+        // Loaded from the file, plus the embedded copy
+        assert_eq!(db.len(), 2);
+        assert_eq!(sans_serif(&db), FALLBACK_FAMILY);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_loader_finishes() {
+        let db = Loader::Deferred(Vec::new()).finish();
+        assert_eq!(sans_serif(&db), FALLBACK_FAMILY);
+        let db = Loader::spawn(Vec::new()).finish();
+        assert_eq!(sans_serif(&db), FALLBACK_FAMILY);
+    }
+
+    #[test]
+    fn test_resolve_without_patterns() {
+        assert!(resolve(&[]).is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+// Glyph atlas module
+// Packs rasterized glyphs into one coverage texture shared by the CPU and GPU text paths
+
+use crate::cpu_renderer::{self, Rect};
+use cosmic_text::{Buffer, CacheKey, FontSystem, SwashCache, SwashContent};
+use log::{debug, info};
+use std::collections::HashMap;
+
+/// Side of the square atlas (one byte per texel), which bounds the memory kept for glyphs
+pub const ATLAS_SIZE: u32 = 1024;
+/// Empty texels between neighbouring glyphs
+const GLYPH_GAP: u32 = 1;
+
+/// Where a glyph sits in the atlas and where it is drawn relative to its pen position
+#[derive(Debug, Clone, Copy)]
+struct AtlasGlyph {
+    rect: Rect,
+    left: i32,
+    top: i32,
+}
+
+/// A row of the atlas; glyphs are added left to right
+#[derive(Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    next_x: u32,
+}
+
+/// A glyph placed on a surface or panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlyphQuad {
+    /// Destination rectangle
+    pub rect: Rect,
+    /// Top-left corner of the glyph's coverage in the atlas
+    pub atlas: (i32, i32),
+    /// Text color (BGRA, straight alpha)
+    pub color: [u8; 4],
+}
+
+impl GlyphQuad {
+    /// Move the quad by `offset` and cut it to `bounds`
+    pub fn clip(&self, offset: (i32, i32), bounds: Rect) -> Option<Self> {
+        let moved = Rect::new(
+            self.rect.x + offset.0,
+            self.rect.y + offset.1,
+            self.rect.width,
+            self.rect.height,
+        );
+        let rect = moved.intersection(&bounds)?;
+        Some(Self {
+            rect,
+            atlas: (
+                self.atlas.0 + rect.x - moved.x,
+                self.atlas.1 + rect.y - moved.y,
+            ),
+            color: self.color,
+        })
+    }
+}
+
+/// Coverage masks of every glyph drawn since the atlas was last reset
+///
+/// Glyphs are rasterized once and packed into shelves. When a glyph no longer
+/// fits, the atlas is marked full; the caller resets it before the next frame
+/// and lays its text out again.
+pub struct GlyphAtlas {
+    pixels: Vec<u8>,
+    /// `None` for glyphs without coverage (spaces)
+    glyphs: HashMap<CacheKey, Option<AtlasGlyph>>,
+    shelves: Vec<Shelf>,
+    /// Region written since the last GPU upload
+    dirty: Option<Rect>,
+    full: bool,
+}
+
+impl GlyphAtlas {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            glyphs: HashMap::new(),
+            shelves: Vec::new(),
+            dirty: None,
+            full: false,
+        }
+    }
+
+    /// Coverage texels, `ATLAS_SIZE` bytes per row
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Whether a glyph was dropped for lack of space
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Forget all glyphs; quads laid out before are no longer valid
+    pub fn reset(&mut self) {
+        info!(
+            "Glyph atlas full ({} glyphs), starting over",
+            self.glyphs.len()
+        );
+        self.pixels.fill(0);
+        self.glyphs.clear();
+        self.shelves.clear();
+        self.dirty = None;
+        self.full = false;
+    }
+
+    /// Region changed since the last call, to be uploaded to the GPU
+    pub fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// Quads for every glyph of a shaped buffer whose top-left corner is at `origin`
+    pub fn layout(
+        &mut self,
+        font_system: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        buffer: &Buffer,
+        origin: (i32, i32),
+        color: [u8; 4],
+    ) -> Vec<GlyphQuad> {
+        let mut quads = Vec::new();
+        for run in buffer.layout_runs() {
+            for glyph in run.glyphs {
+                let physical = glyph.physical((0.0, 0.0), 1.0);
+                let Some(entry) = self.glyph(font_system, swash_cache, physical.cache_key) else {
+                    continue;
+                };
+                let x = origin.0 + physical.x + entry.left;
+                let y = origin.1 + run.line_y as i32 + physical.y - entry.top;
+                quads.push(GlyphQuad {
+                    rect: Rect::new(x, y, entry.rect.width, entry.rect.height),
+                    atlas: (entry.rect.x, entry.rect.y),
+                    color,
+                });
+            }
+        }
+        quads
+    }
+
+    /// Blend quads over a straight-alpha BGRA canvas
+    pub fn draw(&self, canvas: &mut [u8], width: u32, height: u32, quads: &[GlyphQuad]) {
+        let bounds = Rect::new(0, 0, width as i32, height as i32);
+        for quad in quads.iter().filter_map(|quad| quad.clip((0, 0), bounds)) {
+            let src_alpha = quad.color[3] as f32 / 255.0;
+            for row in 0..quad.rect.height {
+                let atlas_row = (quad.atlas.1 + row) as usize * ATLAS_SIZE as usize;
+                let canvas_row = (quad.rect.y + row) as usize * width as usize;
+                for col in 0..quad.rect.width {
+                    let coverage = self.pixels[atlas_row + (quad.atlas.0 + col) as usize];
+                    if coverage == 0 {
+                        continue;
+                    }
+                    let idx = (canvas_row + (quad.rect.x + col) as usize) * 4;
+                    cpu_renderer::blend_over(
+                        &mut canvas[idx..idx + 4],
+                        quad.color,
+                        src_alpha * coverage as f32 / 255.0,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Atlas entry of a glyph, rasterizing and packing it on first use
+    fn glyph(
+        &mut self,
+        font_system: &mut FontSystem,
+        swash_cache: &mut SwashCache,
+        key: CacheKey,
+    ) -> Option<AtlasGlyph> {
+        if let Some(entry) = self.glyphs.get(&key) {
+            return *entry;
+        }
+        if self.full {
+            return None;
+        }
+        // Uncached: the atlas is the only copy of the glyph kept around
+        let image = swash_cache.get_image_uncached(font_system, key)?;
+        let (width, height) = (image.placement.width, image.placement.height);
+        if width == 0 || height == 0 {
+            self.glyphs.insert(key, None);
+            return None;
+        }
+        let Some((x, y)) = self.allocate(width, height) else {
+            debug!("No room for a {}x{} glyph", width, height);
+            self.full = true;
+            return None;
+        };
+
+        // Color glyphs keep only their shape; text is drawn in one color
+        let coverage: Vec<u8> = match image.content {
+            SwashContent::Mask => image.data,
+            SwashContent::Color | SwashContent::SubpixelMask => {
+                image.data.chunks_exact(4).map(|pixel| pixel[3]).collect()
+            }
+        };
+        for (row, line) in coverage.chunks_exact(width as usize).enumerate() {
+            let start = (y as usize + row) * ATLAS_SIZE as usize + x as usize;
+            self.pixels[start..start + width as usize].copy_from_slice(line);
+        }
+
+        let rect = Rect::new(x as i32, y as i32, width as i32, height as i32);
+        self.dirty = Some(self.dirty.map_or(rect, |dirty| dirty.union(&rect)));
+        let entry = AtlasGlyph {
+            rect,
+            left: image.placement.left,
+            top: image.placement.top,
+        };
+        self.glyphs.insert(key, Some(entry));
+        Some(entry)
+    }
+
+    /// Free space for a `width`×`height` glyph: the first shelf it fits on, or a new shelf
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (width, height) = (width + GLYPH_GAP, height + GLYPH_GAP);
+        if width > ATLAS_SIZE {
+            return None;
+        }
+        // Shelves only take glyphs up to a little smaller than their height
+        if let Some(shelf) = self.shelves.iter_mut().find(|shelf| {
+            height <= shelf.height
+                && height * 4 >= shelf.height * 3
+                && shelf.next_x + width <= ATLAS_SIZE
+        }) {
+            let x = shelf.next_x;
+            shelf.next_x += width;
+            return Some((x, shelf.y));
+        }
+        let y = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        // Rounded up so glyphs a pixel or two taller share the shelf
+        let shelf_height = height.next_multiple_of(4);
+        if y + shelf_height > ATLAS_SIZE {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height: shelf_height,
+            next_x: width,
+        });
+        Some((0, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_packs_shelves() {
+        let mut atlas = GlyphAtlas::new();
+        // Side by side on one shelf, a gap apart
+        assert_eq!(atlas.allocate(10, 10), Some((0, 0)));
+        assert_eq!(atlas.allocate(10, 10), Some((11, 0)));
+        // Slightly shorter glyphs share the rounded-up shelf
+        assert_eq!(atlas.allocate(8, 9), Some((22, 0)));
+        // Taller glyphs open a shelf below
+        assert_eq!(atlas.allocate(10, 20), Some((0, 12)));
+        // Much shorter glyphs do not waste a tall shelf
+        assert_eq!(atlas.allocate(5, 5), Some((0, 36)));
+        assert_eq!(atlas.shelves.len(), 3);
+    }
+
+    #[test]
+    fn test_allocate_wraps_to_new_shelf() {
+        let mut atlas = GlyphAtlas::new();
+        let per_row = ATLAS_SIZE / (15 + GLYPH_GAP);
+        for i in 0..per_row {
+            assert_eq!(atlas.allocate(15, 15), Some((i * 16, 0)));
+        }
+        assert_eq!(atlas.allocate(15, 15), Some((0, 16)));
+    }
+
+    #[test]
+    fn test_allocate_out_of_space() {
+        let mut atlas = GlyphAtlas::new();
+        assert_eq!(atlas.allocate(ATLAS_SIZE, 1), None);
+        assert_eq!(atlas.allocate(1, ATLAS_SIZE), None);
+        assert_eq!(atlas.allocate(10, 1000), Some((0, 0)));
+        assert_eq!(atlas.allocate(10, 100), None);
+        assert_eq!(atlas.allocate(10, 1000), Some((11, 0)));
+    }
+
+    #[test]
+    fn test_reset_clears_state() {
+        let mut atlas = GlyphAtlas::new();
+        atlas.allocate(10, 10);
+        atlas.pixels[0] = 255;
+        atlas.dirty = Some(Rect::new(0, 0, 10, 10));
+        atlas.full = true;
+        atlas.reset();
+        assert!(!atlas.is_full());
+        assert!(atlas.pixels().iter().all(|&texel| texel == 0));
+        assert_eq!(atlas.take_dirty(), None);
+        assert_eq!(atlas.allocate(10, 10), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_take_dirty() {
+        let mut atlas = GlyphAtlas::new();
+        assert_eq!(atlas.take_dirty(), None);
+        atlas.dirty = Some(Rect::new(2, 3, 4, 5));
+        assert_eq!(atlas.take_dirty(), Some(Rect::new(2, 3, 4, 5)));
+        assert_eq!(atlas.take_dirty(), None);
+    }
+
+    #[test]
+    fn test_clip_quad() {
+        let quad = GlyphQuad {
+            rect: Rect::new(0, 0, 10, 10),
+            atlas: (100, 200),
+            color: [1, 2, 3, 255],
+        };
+        let bounds = Rect::new(0, 0, 20, 20);
+        let moved = quad.clip((5, 5), bounds).unwrap();
+        assert_eq!(moved.rect, Rect::new(5, 5, 10, 10));
+        assert_eq!(moved.atlas, (100, 200));
+
+        // Cutting the top-left moves the atlas corner with it
+        let cut = quad.clip((-3, -4), bounds).unwrap();
+        assert_eq!(cut.rect, Rect::new(0, 0, 7, 6));
+        assert_eq!(cut.atlas, (103, 204));
+        assert_eq!(cut.color, quad.color);
+
+        assert_eq!(quad.clip((30, 0), bounds), None);
+    }
+
+    #[test]
+    fn test_draw_blends_coverage() {
+        let mut atlas = GlyphAtlas::new();
+        atlas.pixels[0] = 255;
+        atlas.pixels[1] = 128;
+        let (width, height) = (4, 2);
+        let mut canvas = [0, 0, 0, 255].repeat((width * height) as usize);
+        let quads = [
+            GlyphQuad {
+                rect: Rect::new(1, 1, 3, 1),
+                atlas: (0, 0),
+                color: [0, 0, 255, 255],
+            },
+            // Entirely off the canvas
+            GlyphQuad {
+                rect: Rect::new(10, 10, 2, 1),
+                atlas: (0, 0),
+                color: [0, 0, 255, 255],
+            },
+        ];
+        atlas.draw(&mut canvas, width, height, &quads);
+        let pixel = |x: usize, y: usize| {
+            let idx = (y * width as usize + x) * 4;
+            [
+                canvas[idx],
+                canvas[idx + 1],
+                canvas[idx + 2],
+                canvas[idx + 3],
+            ]
+        };
+        assert_eq!(pixel(1, 1), [0, 0, 255, 255]);
+        assert_eq!(pixel(2, 1), [0, 0, 128, 255]);
+        // No coverage leaves the canvas alone
+        assert_eq!(pixel(3, 1), [0, 0, 0, 255]);
+        assert_eq!(pixel(1, 0), [0, 0, 0, 255]);
+    }
+}
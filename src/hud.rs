@@ -0,0 +1,166 @@
+// Info overlay module
+// Formats the heads-up display describing the image source and the window
+
+use crate::i18n::trf;
+use crate::image_loader::SourceInfo;
+use clap::ValueEnum;
+
+/// Longest file name shown before it is shortened from the front
+const MAX_NAME_CHARS: usize = 30;
+
+/// When the info overlay is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum HudMode {
+    /// Only when toggled with the I key
+    #[default]
+    Off,
+    /// While the pointer is over the window
+    Hover,
+    /// Always
+    Always,
+}
+
+/// Window state shown alongside the source description
+pub struct HudState<'a> {
+    pub source: &'a SourceInfo,
+    pub display_width: u32,
+    pub display_height: u32,
+    pub opacity: f32,
+    pub scale_mode: &'static str,
+}
+
+/// Lines of the info overlay
+pub fn lines(state: &HudState) -> Vec<String> {
+    let source = state.source;
+
+    let name_chars = source.name.chars().count();
+    let name = if name_chars > MAX_NAME_CHARS {
+        let tail: String = source
+            .name
+            .chars()
+            .skip(name_chars - (MAX_NAME_CHARS - 1))
+            .collect();
+        format!("…{}", tail)
+    } else {
+        source.name.clone()
+    };
+
+    let mut details = format!("{} x {}", source.width, source.height);
+    if let Some(format) = source.format {
+        details.push_str(&format!("  {}", format!("{:?}", format).to_uppercase()));
+    }
+    if let Some(size) = source.file_size {
+        details.push_str(&format!("  {}", format_size(size)));
+    }
+
+    let zoom_x = state.display_width as f32 / source.width.max(1) as f32 * 100.0;
+    let zoom_y = state.display_height as f32 / source.height.max(1) as f32 * 100.0;
+    let zoom = if (zoom_x - zoom_y).abs() < 0.5 {
+        format!("{:.0}%", zoom_x)
+    } else {
+        format!("{:.0}% x {:.0}%", zoom_x, zoom_y)
+    };
+
+    vec![
+        name,
+        details,
+        trf(
+            "Shown {} x {} ({})",
+            &[&state.display_width, &state.display_height, &zoom],
+        ),
+        trf(
+            "Opacity {}%  {}",
+            &[&(state.opacity * 100.0).round(), &state.scale_mode],
+        ),
+    ]
+}
+
+/// Human readable byte count
+fn format_size(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    const MIB: f64 = 1024.0 * 1024.0;
+    let bytes_f = bytes as f64;
+    if bytes_f >= MIB {
+        format!("{:.1} MiB", bytes_f / MIB)
+    } else if bytes_f >= KIB {
+        format!("{:.1} KiB", bytes_f / KIB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+
+    fn source(name: &str) -> SourceInfo {
+        SourceInfo {
+            name: name.to_string(),
+            format: Some(ImageFormat::Png),
+            file_size: Some(1536),
+            width: 200,
+            height: 100,
+        }
+    }
+
+    fn state(source: &SourceInfo, display_width: u32, display_height: u32) -> HudState<'_> {
+        HudState {
+            source,
+            display_width,
+            display_height,
+            opacity: 0.85,
+            scale_mode: "Keep aspect",
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let source = source("shot.png");
+        assert_eq!(
+            lines(&state(&source, 100, 50)),
+            [
+                "shot.png",
+                "200 x 100  PNG  1.5 KiB",
+                "Shown 100 x 50 (50%)",
+                "Opacity 85%  Keep aspect",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uneven_zoom_shows_both_axes() {
+        let source = source("shot.png");
+        assert_eq!(
+            lines(&state(&source, 400, 100))[2],
+            "Shown 400 x 100 (200% x 100%)"
+        );
+    }
+
+    #[test]
+    fn test_long_names_are_shortened_from_the_front() {
+        let source = source("a-very-long-screenshot-name-from-2026-10-18.png");
+        let name = &lines(&state(&source, 200, 100))[0];
+        assert_eq!(name.chars().count(), MAX_NAME_CHARS);
+        assert!(name.starts_with('…'));
+        assert!(name.ends_with("2026-10-18.png"));
+    }
+
+    #[test]
+    fn test_unknown_format_and_size_are_left_out() {
+        let source = SourceInfo {
+            name: "stdin".to_string(),
+            width: 3,
+            height: 2,
+            ..SourceInfo::default()
+        };
+        assert_eq!(lines(&state(&source, 3, 2))[1], "3 x 2");
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 512 * 1024), "5.5 MiB");
+    }
+}
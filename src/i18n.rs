@@ -0,0 +1,239 @@
+// I18n module
+// Message catalogs for the UI text, gettext-style: the English text is the message id
+
+use crate::config::Config;
+use clap::ValueEnum;
+use log::{debug, warn};
+use std::env;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Environment variables naming the message locale, in the order gettext reads them
+const LOCALE_VARS: [&str; 4] = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// Simplified Chinese translations
+const ZH_CN: &[(&str, &str)] = &[
+    // Context menu
+    ("Copy to Clipboard", "复制到剪贴板"),
+    ("Opacity +", "不透明度 +"),
+    ("Opacity -", "不透明度 -"),
+    ("Scroll", "滚轮"),
+    ("Scale Mode", "缩放模式"),
+    ("Keep aspect", "保持比例"),
+    ("Free scale", "自由缩放"),
+    ("Filter", "缩放滤波"),
+    ("Nearest", "最近邻"),
+    ("Bilinear", "双线性"),
+    ("Bicubic", "双三次"),
+    ("Lanczos", "Lanczos"),
+    ("Watch File", "监视文件"),
+    ("Pick Color", "取色"),
+    ("Measure", "测量"),
+    ("Info Overlay", "信息面板"),
+    ("Close", "关闭"),
+    // Toasts
+    ("Scale: {}", "缩放：{}"),
+    ("Filter: {}", "滤波：{}"),
+    ("Opacity {}%", "不透明度 {}%"),
+    ("Copied", "已复制"),
+    ("Copied {}", "已复制 {}"),
+    ("Copy failed", "复制失败"),
+    ("Image is still loading", "图像仍在加载"),
+    ("wl-copy or xclip not found", "未找到 wl-copy 或 xclip"),
+    // Info overlay and error screen
+    ("screen capture", "屏幕截图"),
+    ("Shown {} x {} ({})", "显示 {} x {}（{}）"),
+    ("Opacity {}%  {}", "不透明度 {}%  {}"),
+    ("Failed to load image", "图像加载失败"),
+];
+
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+/// Languages with a message catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Language {
+    /// English (the message ids themselves)
+    #[default]
+    En,
+    /// Simplified Chinese
+    #[value(name = "zh-CN", alias = "zh")]
+    ZhCn,
+}
+
+impl Language {
+    /// Language of a POSIX locale name such as `zh_CN.UTF-8`, or `None` without a catalog
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let name = locale
+            .split(['.', '@'])
+            .next()
+            .unwrap_or_default()
+            .replace('-', "_")
+            .to_lowercase();
+        let mut parts = name.split('_');
+        match (parts.next()?, parts.next()) {
+            ("en" | "c" | "posix", _) => Some(Language::En),
+            // Bare "zh" reads as Simplified; Traditional regions have no catalog yet
+            ("zh", None | Some("cn" | "sg" | "hans")) => Some(Language::ZhCn),
+            _ => None,
+        }
+    }
+
+    /// Language of the message locale set in the environment, English if it has no catalog
+    pub fn from_env() -> Self {
+        let Some(locale) = LOCALE_VARS
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+        else {
+            return Language::En;
+        };
+        // LANGUAGE is a colon separated list of preferences
+        let language = locale.split(':').find_map(Self::from_locale);
+        if language.is_none() {
+            debug!("No catalog for locale {}, using English", locale);
+        }
+        language.unwrap_or_default()
+    }
+
+    /// Language set in the `[general]` section, the environment's if unset or `auto`
+    pub fn from_config(config: &Config) -> Self {
+        match config.get("general", "language") {
+            Some(value) if !value.eq_ignore_ascii_case("auto") => Self::from_str(value, true)
+                .unwrap_or_else(|_| {
+                    warn!("Unknown language \"{}\" in config, using the locale", value);
+                    Self::from_env()
+                }),
+            _ => Self::from_env(),
+        }
+    }
+
+    /// BCP 47 tag, used by the font system to pick between CJK glyph variants
+    pub fn tag(self) -> &'static str {
+        match self {
+            Language::En => "en-US",
+            Language::ZhCn => "zh-CN",
+        }
+    }
+
+    /// fontconfig pattern for a face covering the language's script
+    pub fn font_pattern(self) -> Option<&'static str> {
+        match self {
+            Language::En => None,
+            Language::ZhCn => Some("sans-serif:lang=zh-cn"),
+        }
+    }
+
+    /// Translation of `msgid` in this language, or `msgid` itself if the catalog lacks it
+    pub fn translate(self, msgid: &'static str) -> &'static str {
+        self.catalog()
+            .iter()
+            .find(|(id, _)| *id == msgid)
+            .map_or(msgid, |&(_, text)| text)
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Language::En => &[],
+            Language::ZhCn => ZH_CN,
+        }
+    }
+}
+
+/// Set the UI language; only the first call has an effect
+pub fn init(language: Language) {
+    let _ = LANGUAGE.set(language);
+}
+
+/// The UI language, English until `init` is called
+pub fn language() -> Language {
+    LANGUAGE.get().copied().unwrap_or_default()
+}
+
+/// Translation of `msgid`, or `msgid` itself if the catalog lacks it
+pub fn tr(msgid: &'static str) -> &'static str {
+    language().translate(msgid)
+}
+
+/// Translation of `msgid` with each `{}` replaced by the next argument
+pub fn trf(msgid: &'static str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut parts = tr(msgid).split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            text.push_str(&arg.to_string());
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_locale() {
+        assert_eq!(Language::from_locale("zh_CN.UTF-8"), Some(Language::ZhCn));
+        assert_eq!(Language::from_locale("zh_SG"), Some(Language::ZhCn));
+        assert_eq!(Language::from_locale("zh-Hans"), Some(Language::ZhCn));
+        assert_eq!(Language::from_locale("zh"), Some(Language::ZhCn));
+        assert_eq!(Language::from_locale("en_US.UTF-8"), Some(Language::En));
+        assert_eq!(Language::from_locale("en_GB@euro"), Some(Language::En));
+        assert_eq!(Language::from_locale("C"), Some(Language::En));
+        assert_eq!(Language::from_locale("POSIX"), Some(Language::En));
+    }
+
+    #[test]
+    fn test_from_locale_without_catalog() {
+        assert_eq!(Language::from_locale("zh_TW.UTF-8"), None);
+        assert_eq!(Language::from_locale("de_DE"), None);
+        assert_eq!(Language::from_locale(""), None);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = Config::parse("[general]\nlanguage = zh\n").unwrap();
+        assert_eq!(Language::from_config(&config), Language::ZhCn);
+        let config = Config::parse("[general]\nlanguage = zh-CN\n").unwrap();
+        assert_eq!(Language::from_config(&config), Language::ZhCn);
+        let config = Config::parse("[general]\nlanguage = en\n").unwrap();
+        assert_eq!(Language::from_config(&config), Language::En);
+    }
+
+    #[test]
+    fn test_tags_and_fonts() {
+        assert_eq!(Language::En.tag(), "en-US");
+        assert_eq!(Language::ZhCn.tag(), "zh-CN");
+        assert_eq!(Language::En.font_pattern(), None);
+        assert!(Language::ZhCn.font_pattern().unwrap().contains("zh-cn"));
+    }
+
+    #[test]
+    fn test_catalog_placeholders_match() {
+        assert!(Language::En.catalog().is_empty());
+        for (id, text) in Language::ZhCn.catalog() {
+            assert_eq!(
+                id.matches("{}").count(),
+                text.matches("{}").count(),
+                "{}",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn test_trf_english() {
+        // Tests never call init, so the catalog is English
+        assert_eq!(tr("Copy to Clipboard"), "Copy to Clipboard");
+        assert_eq!(tr("Not in any catalog"), "Not in any catalog");
+        assert_eq!(
+            trf("Shown {} x {} ({})", &[&640, &480, &"50%"]),
+            "Shown 640 x 480 (50%)"
+        );
+        assert_eq!(trf("Opacity {}%", &[&80]), "Opacity 80%");
+        // Missing arguments leave the gap empty, extra ones are dropped
+        assert_eq!(trf("Copied {}", &[]), "Copied ");
+        assert_eq!(trf("Copied", &[&1]), "Copied");
+    }
+}
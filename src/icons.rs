@@ -0,0 +1,220 @@
+// Icons module
+// Built-in menu icons, stored as SVG path data and rasterized at the requested size
+
+use crate::cpu_renderer::{self, Rect};
+use zeno::{Cap, Fill, Join, Mask, Stroke, Style, Transform};
+
+/// Side of the square grid the icon paths are drawn on
+const GRID: f32 = 16.0;
+/// Stroke width on that grid
+const STROKE_WIDTH: f32 = 1.5;
+
+/// Icons available to menu items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icon {
+    Copy,
+    Brighter,
+    Dimmer,
+    Scale,
+    Filter,
+    Watch,
+    Picker,
+    Measure,
+    Info,
+    Close,
+}
+
+/// One shape of an icon: an outline stroked with round caps and joins, or a filled area
+enum Layer {
+    Outline(&'static str),
+    Solid(&'static str),
+}
+
+impl Icon {
+    fn layers(self) -> &'static [Layer] {
+        match self {
+            Icon::Copy => &[
+                Layer::Outline("M3 5.5h7.5v8h-7.5z"),
+                Layer::Outline("M5.5 3.5v-1h8v8h-1"),
+            ],
+            Icon::Brighter => &[
+                Layer::Solid("M8 5a3 3 0 1 1 0 6a3 3 0 1 1 0-6z"),
+                Layer::Outline("M8 1.5v1M8 13.5v1M1.5 8h1M13.5 8h1"),
+                Layer::Outline("M3.4 3.4l.7 .7M11.9 11.9l.7 .7M3.4 12.6l.7-.7M11.9 4.1l.7-.7"),
+            ],
+            Icon::Dimmer => &[Layer::Solid(
+                "M9 1.5a6.5 6.5 0 1 0 5.5 9.5a5 5 0 0 1 -5.5 -9.5z",
+            )],
+            Icon::Scale => &[
+                Layer::Outline("M9.5 2.5h4v4M13.5 2.5l-4.5 4.5"),
+                Layer::Outline("M6.5 13.5h-4v-4M2.5 13.5l4.5-4.5"),
+            ],
+            Icon::Filter => &[
+                Layer::Outline("M7 2.5a4.5 4.5 0 1 1 0 9a4.5 4.5 0 1 1 0-9z"),
+                Layer::Outline("M10.5 10.5l3.5 3.5"),
+            ],
+            Icon::Watch => &[
+                Layer::Outline("M1.5 8c1.8-3 4-4.5 6.5-4.5s4.7 1.5 6.5 4.5c-1.8 3-4 4.5-6.5 4.5s-4.7-1.5-6.5-4.5z"),
+                Layer::Solid("M8 6a2 2 0 1 1 0 4a2 2 0 1 1 0-4z"),
+            ],
+            Icon::Picker => &[
+                Layer::Outline("M9.5 4.5l2 2M10.5 5.5l-7 7v1h1l7-7"),
+                Layer::Solid("M9 4l2.5-2.5a1.8 1.8 0 0 1 2.5 2.5l-2.5 2.5z"),
+            ],
+            Icon::Measure => &[
+                Layer::Outline("M1.5 11l9.5-9.5 3.5 3.5-9.5 9.5z"),
+                Layer::Outline("M4.5 8l1.5 1.5M6.5 6l1 1M8.5 4l1.5 1.5"),
+            ],
+            Icon::Info => &[
+                Layer::Outline("M8 1.5a6.5 6.5 0 1 1 0 13a6.5 6.5 0 1 1 0-13z"),
+                Layer::Outline("M8 7.5v4"),
+                Layer::Solid("M8 3.9a1 1 0 1 1 0 2a1 1 0 1 1 0-2z"),
+            ],
+            Icon::Close => &[Layer::Outline("M4 4l8 8M12 4l-8 8")],
+        }
+    }
+
+    /// Coverage mask of the icon, `size`×`size` pixels with one byte per pixel
+    pub fn mask(self, size: u32) -> Vec<u8> {
+        let scale = size as f32 / GRID;
+        let pixels = (size * size) as usize;
+        let mut coverage = vec![0u8; pixels];
+        let mut layer = vec![0u8; pixels];
+        for shape in self.layers() {
+            let (data, style) = match shape {
+                Layer::Outline(data) => {
+                    let mut stroke = Stroke::new(STROKE_WIDTH);
+                    stroke.cap(Cap::Round).join(Join::Round);
+                    (*data, Style::from(stroke))
+                }
+                Layer::Solid(data) => (*data, Style::from(Fill::NonZero)),
+            };
+            layer.fill(0);
+            Mask::new(data)
+                .style(style)
+                .transform(Some(Transform::scale(scale, scale)))
+                .size(size, size)
+                .render_into(&mut layer, None);
+            for (total, &value) in coverage.iter_mut().zip(&layer) {
+                *total = (*total).max(value);
+            }
+        }
+        coverage
+    }
+}
+
+/// Draw `icon` centered in `cell` of a straight-alpha BGRA buffer
+pub fn draw(
+    canvas: &mut [u8],
+    width: u32,
+    height: u32,
+    cell: Rect,
+    icon: Icon,
+    size: u32,
+    color: [u8; 4],
+) {
+    let left = cell.x + (cell.width - size as i32) / 2;
+    let top = cell.y + (cell.height - size as i32) / 2;
+    for (i, &value) in icon.mask(size).iter().enumerate() {
+        let x = left + (i as u32 % size) as i32;
+        let y = top + (i as u32 / size) as i32;
+        if value == 0 || x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            continue;
+        }
+        let idx = (y as usize * width as usize + x as usize) * 4;
+        cpu_renderer::cover_pixel(&mut canvas[idx..idx + 4], color, value as f32 / 255.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Icon; 10] = [
+        Icon::Copy,
+        Icon::Brighter,
+        Icon::Dimmer,
+        Icon::Scale,
+        Icon::Filter,
+        Icon::Watch,
+        Icon::Picker,
+        Icon::Measure,
+        Icon::Info,
+        Icon::Close,
+    ];
+
+    fn total(mask: &[u8]) -> u32 {
+        mask.iter().map(|&value| value as u32).sum()
+    }
+
+    #[test]
+    fn test_every_icon_renders_inside_its_grid() {
+        for icon in ALL {
+            let mask = icon.mask(16);
+            assert_eq!(mask.len(), 256);
+            assert!(total(&mask) > 0, "{:?} is blank", icon);
+            // Nothing is cut off at the edge of the grid
+            let edge = (0..16).flat_map(|i| [i, 15 * 16 + i, i * 16, i * 16 + 15]);
+            assert!(
+                edge.map(|i| mask[i] as u32).sum::<u32>() < 255 * 4,
+                "{:?}",
+                icon
+            );
+        }
+    }
+
+    #[test]
+    fn test_masks_scale_with_the_size() {
+        // Twice the size covers about four times the area
+        for icon in ALL {
+            let (small, large) = (total(&icon.mask(16)) as f32, total(&icon.mask(32)) as f32);
+            let ratio = large / small;
+            assert!((3.0..5.0).contains(&ratio), "{:?}: {}", icon, ratio);
+        }
+    }
+
+    #[test]
+    fn test_close_is_symmetric() {
+        let mask = Icon::Close.mask(32);
+        for y in 0..32 {
+            for x in 0..32 {
+                let (a, b) = (mask[y * 32 + x] as i32, mask[y * 32 + 31 - x] as i32);
+                assert!((a - b).abs() <= 2, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_draw_centers_and_clips() {
+        let (width, height) = (20u32, 20u32);
+        let mut canvas = vec![0u8; (width * height * 4) as usize];
+        let color = [255, 255, 255, 255];
+        draw(
+            &mut canvas,
+            width,
+            height,
+            Rect::new(0, 0, 20, 20),
+            Icon::Close,
+            16,
+            color,
+        );
+        let painted =
+            |canvas: &[u8], x: u32, y: u32| canvas[((y * width + x) * 4 + 3) as usize] > 0;
+        // The icon occupies the 16x16 square in the middle
+        assert!(painted(&canvas, 10, 10));
+        assert!(!painted(&canvas, 1, 1));
+
+        // Partly outside the canvas: drawn without panicking
+        let mut canvas = vec![0u8; (width * height * 4) as usize];
+        draw(
+            &mut canvas,
+            width,
+            height,
+            Rect::new(-10, 12, 20, 20),
+            Icon::Close,
+            16,
+            color,
+        );
+        assert!(canvas.chunks_exact(4).any(|pixel| pixel[3] > 0));
+    }
+}
@@ -3,9 +3,17 @@
 
 use crate::cli::ParsedArgs;
 use anyhow::{Context, Result};
-use image::{DynamicImage, ImageFormat};
-use std::fs;
-use std::io::Cursor;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+/// Bytes read from the start of a file to look for an EXIF thumbnail
+/// (the APP1 segment is limited to 64 KiB and normally comes first)
+const PROBE_HEAD_BYTES: u64 = 128 * 1024;
+
+/// Color of the placeholder shown while decoding (BGRA)
+const PLACEHOLDER_COLOR: [u8; 4] = [48, 45, 45, 255];
 
 /// Loaded image data ready for display
 #[derive(Debug, Clone)]
@@ -15,7 +23,7 @@ pub struct ImageData {
     /// Image height in pixels
     pub height: u32,
     /// Raw RGBA pixel data (4 bytes per pixel)
-    /// Can be cleared after GPU upload to save memory
+    /// Kept after GPU upload for pixel lookups (color picker, clipboard)
     pub rgba_data: Vec<u8>,
     /// Applied scale factor
     #[allow(dead_code)]
@@ -26,17 +34,26 @@ pub struct ImageData {
 }
 
 impl ImageData {
-    /// Clear the raw image data to free memory (useful after GPU upload)
+    /// Drop the CPU mipmaps (the GPU builds its own mip chain)
     /// Returns the number of bytes freed
-    pub fn release_raw_data(&mut self) -> usize {
-        let freed = self.rgba_data.len() + self.mipmaps.iter().map(|m| m.data.len()).sum::<usize>();
-        self.rgba_data = Vec::new();
-        self.rgba_data.shrink_to_fit();
+    pub fn release_mipmaps(&mut self) -> usize {
+        let freed = self.mipmaps.iter().map(|m| m.data.len()).sum::<usize>();
         self.mipmaps.clear();
         self.mipmaps.shrink_to_fit();
         freed
     }
 
+    /// Neutral single-pixel image shown until decoding finishes
+    pub fn placeholder() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            rgba_data: PLACEHOLDER_COLOR.to_vec(),
+            scale: 1.0,
+            mipmaps: Vec::new(),
+        }
+    }
+
     /// Check if raw data is available
     #[allow(dead_code)]
     pub fn has_raw_data(&self) -> bool {
@@ -44,6 +61,39 @@ impl ImageData {
     }
 }
 
+/// Cheap information about an image, available before the full decode
+pub struct ImageProbe {
+    /// Display width in pixels (after `--scale`)
+    pub width: u32,
+    /// Display height in pixels (after `--scale`)
+    pub height: u32,
+    /// Embedded EXIF thumbnail, used as an early preview
+    pub preview: Option<ImageData>,
+    /// The preview is the full image, so no decode is needed
+    pub complete: bool,
+    /// Exact window position, bypassing the size limit and centering
+    pub position: Option<(i32, i32)>,
+    /// Name of the output the window is placed on
+    pub output: Option<String>,
+    /// Source description for the info overlay
+    pub source: SourceInfo,
+}
+
+/// Where the image came from, for the info overlay
+#[derive(Debug, Clone, Default)]
+pub struct SourceInfo {
+    /// File name, "stdin" or "screen capture"
+    pub name: String,
+    /// Encoded format, when known
+    pub format: Option<ImageFormat>,
+    /// Encoded size in bytes, when known
+    pub file_size: Option<u64>,
+    /// Original width before `--scale`
+    pub width: u32,
+    /// Original height before `--scale`
+    pub height: u32,
+}
+
 /// A single mipmap level
 #[derive(Debug, Clone)]
 pub struct MipmapLevel {
@@ -66,10 +116,197 @@ pub fn load_image(args: &ParsedArgs) -> Result<ImageData> {
         anyhow::bail!("No image source provided");
     };
 
+    Ok(prepare_image(img, args.scale))
+}
+
+/// Read the image dimensions from its header and look for an embedded preview
+pub fn probe_image(args: &ParsedArgs) -> Result<ImageProbe> {
+    let (width, height, preview, mut source) = if let Some(ref data) = args.image_data {
+        let reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+        let source = SourceInfo {
+            name: if args.stream {
+                "stdin (stream)"
+            } else {
+                "stdin"
+            }
+            .to_string(),
+            format: reader.format(),
+            file_size: Some(data.len() as u64),
+            ..SourceInfo::default()
+        };
+        let (width, height) = reader
+            .into_dimensions()
+            .context("Failed to read image header")?;
+        (
+            width,
+            height,
+            exif_thumbnail(data).and_then(decode_preview),
+            source,
+        )
+    } else if let Some(ref path) = args.image_path {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open image file: {}", path.display()))?;
+        let file_size = file.metadata().ok().map(|meta| meta.len());
+
+        // The head holds any EXIF thumbnail; the header is then read from the same file
+        let mut head = Vec::new();
+        (&mut file).take(PROBE_HEAD_BYTES).read_to_end(&mut head)?;
+        file.rewind()?;
+        let mut reader = ImageReader::new(BufReader::new(file));
+        if let Ok(format) = ImageFormat::from_path(path) {
+            reader.set_format(format);
+        }
+        let reader = reader.with_guessed_format()?;
+
+        let source = SourceInfo {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            format: reader.format(),
+            file_size,
+            ..SourceInfo::default()
+        };
+        let (width, height) = reader
+            .into_dimensions()
+            .context("Failed to read image header")?;
+        (
+            width,
+            height,
+            exif_thumbnail(&head).and_then(decode_preview),
+            source,
+        )
+    } else {
+        anyhow::bail!("No image source provided");
+    };
+    source.width = width;
+    source.height = height;
+
+    let (width, height) = if (args.scale - 1.0).abs() > f32::EPSILON {
+        (
+            ((width as f32 * args.scale) as u32).max(1),
+            ((height as f32 * args.scale) as u32).max(1),
+        )
+    } else {
+        (width, height)
+    };
+
+    Ok(ImageProbe {
+        width,
+        height,
+        preview,
+        complete: false,
+        position: None,
+        output: None,
+        source,
+    })
+}
+
+/// Decode an embedded thumbnail, ignoring any errors
+fn decode_preview(data: &[u8]) -> Option<ImageData> {
+    let img = image::load_from_memory_with_format(data, ImageFormat::Jpeg).ok()?;
+    Some(prepare_image(img, 1.0))
+}
+
+/// Find the JPEG thumbnail stored in IFD1 of a JPEG's EXIF segment
+fn exif_thumbnail(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    // Walk the marker segments until the APP1 "Exif" segment or the image data
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        match marker {
+            // Fill byte before a marker
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan or end of image: no metadata follows
+            0xDA | 0xD9 => return None,
+            _ => {}
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + length)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return tiff_thumbnail(&segment[6..]);
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+/// Read the JPEGInterchangeFormat offset/length tags from IFD1 of a TIFF structure
+fn tiff_thumbnail(tiff: &[u8]) -> Option<&[u8]> {
+    let little_endian = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+
+    // IFD0 is followed by the offset of IFD1, which describes the thumbnail
+    let ifd0 = read_u32(4)? as usize;
+    let ifd0_entries = read_u16(ifd0)? as usize;
+    let ifd1 = read_u32(ifd0 + 2 + ifd0_entries * 12)? as usize;
+    if ifd1 == 0 {
+        return None;
+    }
+
+    let mut offset = None;
+    let mut length = None;
+    for i in 0..read_u16(ifd1)? as usize {
+        let entry = ifd1 + 2 + i * 12;
+        match read_u16(entry)? {
+            0x0201 => offset = Some(read_u32(entry + 8)? as usize),
+            0x0202 => length = Some(read_u32(entry + 8)? as usize),
+            _ => {}
+        }
+    }
+
+    let offset = offset?;
+    tiff.get(offset..offset.checked_add(length?)?)
+}
+
+/// Re-read and process an image file (used for reloads)
+pub fn load_image_file(path: &Path, scale: f32) -> Result<ImageData> {
+    let data =
+        fs::read(path).with_context(|| format!("Failed to read image file: {}", path.display()))?;
+    load_image_bytes(&data, scale)
+}
+
+/// Decode and process an encoded image held in memory (used for stream frames)
+pub fn load_image_bytes(data: &[u8], scale: f32) -> Result<ImageData> {
+    let img = load_from_bytes(data)?;
+    Ok(prepare_image(img, scale))
+}
+
+/// Scale a decoded image and convert it to BGRA for display
+fn prepare_image(img: DynamicImage, scale: f32) -> ImageData {
     // Apply scaling if needed
-    let img = if (args.scale - 1.0).abs() > f32::EPSILON {
-        let new_width = (img.width() as f32 * args.scale) as u32;
-        let new_height = (img.height() as f32 * args.scale) as u32;
+    let img = if (scale - 1.0).abs() > f32::EPSILON {
+        let new_width = (img.width() as f32 * scale) as u32;
+        let new_height = (img.height() as f32 * scale) as u32;
         img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3)
     } else {
         img
@@ -86,16 +323,16 @@ pub fn load_image(args: &ParsedArgs) -> Result<ImageData> {
     }
 
     // Mipmaps will be generated on demand if needed for CPU rendering
-    // GPU rendering uses hardware mipmapping, so we don't generate them by default
+    // GPU rendering builds its own mip chain on the GPU, so we don't generate them by default
     let mipmaps = Vec::new();
 
-    Ok(ImageData {
+    ImageData {
         width,
         height,
         rgba_data: bgra_data,
-        scale: args.scale,
+        scale,
         mipmaps,
-    })
+    }
 }
 
 /// Generate mipmaps on demand for CPU rendering
@@ -208,3 +445,171 @@ pub fn format_from_extension(ext: &str) -> Option<ImageFormat> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smithay_client_toolkit::seat::keyboard::Keysym;
+    use std::path::PathBuf;
+
+    /// Encode a flat-colored image
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            width,
+            height,
+            image::Rgb([200, 100, 50]),
+        ));
+        let mut data = Vec::new();
+        img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    /// TIFF structure with an empty IFD0 and an IFD1 pointing at `thumbnail`
+    fn tiff_with_thumbnail(little_endian: bool, thumbnail: &[u8]) -> Vec<u8> {
+        let u16_bytes = |v: u16| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let u32_bytes = |v: u32| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let mut tiff = Vec::new();
+        tiff.extend(if little_endian { b"II" } else { b"MM" });
+        tiff.extend(u16_bytes(42));
+        tiff.extend(u32_bytes(8));
+        // IFD0: no entries, then the offset of IFD1
+        tiff.extend(u16_bytes(0));
+        tiff.extend(u32_bytes(14));
+        // IFD1: offset and length of the thumbnail
+        let data_offset = 14 + 2 + 2 * 12 + 4;
+        tiff.extend(u16_bytes(2));
+        for (tag, value) in [(0x0201, data_offset), (0x0202, thumbnail.len() as u32)] {
+            tiff.extend(u16_bytes(tag));
+            tiff.extend(u16_bytes(4));
+            tiff.extend(u32_bytes(1));
+            tiff.extend(u32_bytes(value));
+        }
+        tiff.extend(u32_bytes(0));
+        tiff.extend(thumbnail);
+        tiff
+    }
+
+    /// JPEG whose APP1 segment holds `tiff`, followed by `main`'s segments
+    fn jpeg_with_exif(tiff: &[u8], main: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend(((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend(&main[2..]);
+        data
+    }
+
+    fn args(image_path: Option<PathBuf>, image_data: Option<Vec<u8>>) -> ParsedArgs {
+        ParsedArgs {
+            image_path,
+            image_data,
+            opacity: 1.0,
+            pos_x: None,
+            pos_y: None,
+            scale: 1.0,
+            filter: Default::default(),
+            watch: false,
+            stream: false,
+            capture: false,
+            capture_output: None,
+            capture_region: None,
+            loupe_key: Keysym::space,
+            loupe_shape: Default::default(),
+            hud: Default::default(),
+            theme: None,
+            language: Default::default(),
+            snap_threshold: 0,
+            config: Default::default(),
+            use_gpu: false,
+        }
+    }
+
+    #[test]
+    fn test_tiff_thumbnail_both_byte_orders() {
+        let thumbnail = [1u8, 2, 3, 4, 5];
+        for little_endian in [true, false] {
+            let tiff = tiff_with_thumbnail(little_endian, &thumbnail);
+            assert_eq!(tiff_thumbnail(&tiff), Some(&thumbnail[..]));
+        }
+    }
+
+    #[test]
+    fn test_tiff_thumbnail_rejects_bad_structures() {
+        assert_eq!(tiff_thumbnail(b""), None);
+        assert_eq!(tiff_thumbnail(b"XX\0\x2a\0\0\0\x08"), None);
+        // Cut off before the thumbnail ends
+        let tiff = tiff_with_thumbnail(true, &[1, 2, 3, 4, 5]);
+        assert_eq!(tiff_thumbnail(&tiff[..tiff.len() - 1]), None);
+        // No IFD1
+        let mut tiff = tiff_with_thumbnail(true, &[1]);
+        tiff[10..14].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(tiff_thumbnail(&tiff), None);
+        // IFD offsets pointing past the end
+        let mut tiff = tiff_with_thumbnail(false, &[1]);
+        tiff[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(tiff_thumbnail(&tiff), None);
+    }
+
+    #[test]
+    fn test_exif_thumbnail_walks_segments() {
+        let thumbnail = encode(4, 2, ImageFormat::Jpeg);
+        let main = encode(32, 16, ImageFormat::Jpeg);
+        let jpeg = jpeg_with_exif(&tiff_with_thumbnail(true, &thumbnail), &main);
+        assert_eq!(exif_thumbnail(&jpeg), Some(&thumbnail[..]));
+        // Plain JPEGs and other formats have none
+        assert_eq!(exif_thumbnail(&main), None);
+        assert_eq!(exif_thumbnail(&encode(4, 4, ImageFormat::Png)), None);
+        // A segment length running past the data
+        assert_eq!(exif_thumbnail(&jpeg[..20]), None);
+    }
+
+    #[test]
+    fn test_probe_reads_header_and_preview() {
+        let thumbnail = encode(4, 2, ImageFormat::Jpeg);
+        let main = encode(32, 16, ImageFormat::Jpeg);
+        let jpeg = jpeg_with_exif(&tiff_with_thumbnail(false, &thumbnail), &main);
+
+        let probe = probe_image(&args(None, Some(jpeg.clone()))).unwrap();
+        assert_eq!((probe.width, probe.height), (32, 16));
+        assert_eq!(probe.source.name, "stdin");
+        assert_eq!(probe.source.format, Some(ImageFormat::Jpeg));
+        assert_eq!(probe.source.file_size, Some(jpeg.len() as u64));
+        let preview = probe.preview.unwrap();
+        assert_eq!((preview.width, preview.height), (4, 2));
+
+        // From a file, with --scale applied to the window size only
+        let dir = std::env::temp_dir().join(format!("rspin-probe-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+        fs::write(&path, encode(20, 10, ImageFormat::Png)).unwrap();
+        let mut file_args = args(Some(path), None);
+        file_args.scale = 0.5;
+        let probe = probe_image(&file_args).unwrap();
+        assert_eq!((probe.width, probe.height), (10, 5));
+        assert_eq!((probe.source.width, probe.source.height), (20, 10));
+        assert_eq!(probe.source.name, "image.png");
+        assert!(probe.preview.is_none());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(probe_image(&args(None, Some(b"not an image".to_vec()))).is_err());
+    }
+
+    #[test]
+    fn test_prepare_image_converts_to_bgra() {
+        let image = load_image_bytes(&encode(3, 2, ImageFormat::Png), 1.0).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(&image.rgba_data[..4], &[50, 100, 200, 255]);
+    }
+}
@@ -0,0 +1,197 @@
+// Magnifier loupe module
+// Renders an enlarged, pixel-exact view of the source image around the pointer
+
+use crate::cpu_renderer::Rect;
+use crate::image_loader::ImageData;
+use clap::ValueEnum;
+
+/// Zoom range in screen pixels per source pixel
+pub const MIN_ZOOM: u32 = 4;
+pub const MAX_ZOOM: u32 = 16;
+pub const DEFAULT_ZOOM: u32 = 8;
+
+/// Zoom change per scroll step
+pub const ZOOM_STEP: u32 = 2;
+
+/// Loupe colors (BGRA)
+pub const OUTSIDE_COLOR: [u8; 4] = [30, 30, 30, 255];
+pub const BORDER_COLOR: [u8; 4] = [80, 80, 80, 255];
+pub const HIGHLIGHT_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// Darkening applied to pixel grid lines (8.8 fixed point)
+pub const GRID_FACTOR: u32 = 180;
+
+/// Border width in pixels
+pub const BORDER_WIDTH: f64 = 2.0;
+
+/// Outline of the loupe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LoupeShape {
+    /// Round lens
+    #[default]
+    Circle,
+    /// Square lens
+    Square,
+}
+
+/// Where the GPU draws the loupe; it samples the image texture itself (see loupe.wgsl)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lens {
+    /// Loupe square in surface pixels
+    pub rect: Rect,
+    /// Pointer position as a fraction of the image size
+    pub center: (f32, f32),
+    pub zoom: u32,
+    pub shape: LoupeShape,
+}
+
+/// Render a `size` x `size` BGRA loupe centered on the source pixel under `pos`
+///
+/// Each source pixel becomes a `zoom` x `zoom` block (nearest filtering) with a
+/// darkened grid line on its edges; the pixel under the pointer is outlined.
+/// Pixels outside a circular lens are left transparent.
+pub fn render(
+    image: &ImageData,
+    surface_width: u32,
+    surface_height: u32,
+    pos: (f64, f64),
+    zoom: u32,
+    size: u32,
+    shape: LoupeShape,
+) -> Vec<u8> {
+    let mut panel = vec![0u8; size as usize * size as usize * 4];
+    if image.rgba_data.is_empty() || surface_width == 0 || surface_height == 0 {
+        return panel;
+    }
+
+    // Pointer position in source pixels
+    let center_x = pos.0 * image.width as f64 / surface_width as f64;
+    let center_y = pos.1 * image.height as f64 / surface_height as f64;
+    let (picked_x, picked_y) = (center_x.floor(), center_y.floor());
+    let zoom = zoom as f64;
+    let half = size as f64 / 2.0;
+
+    for oy in 0..size {
+        let fy = center_y + (oy as f64 + 0.5 - half) / zoom;
+        let iy = fy.floor();
+        let grid_y = fy - iy < 1.0 / zoom;
+        let edge_y = grid_y || fy - iy >= 1.0 - 1.0 / zoom;
+
+        for ox in 0..size {
+            let dx = ox as f64 + 0.5 - half;
+            let dy = oy as f64 + 0.5 - half;
+            let out = (oy as usize * size as usize + ox as usize) * 4;
+            let pixel = &mut panel[out..out + 4];
+
+            // Lens outline
+            let edge_distance = match shape {
+                LoupeShape::Circle => half - (dx * dx + dy * dy).sqrt(),
+                LoupeShape::Square => half - dx.abs().max(dy.abs()),
+            };
+            if edge_distance < 0.0 {
+                continue;
+            }
+            if edge_distance < BORDER_WIDTH {
+                pixel.copy_from_slice(&BORDER_COLOR);
+                continue;
+            }
+
+            let fx = center_x + dx / zoom;
+            let ix = fx.floor();
+            let grid_x = fx - ix < 1.0 / zoom;
+
+            // Outline the picked pixel on its inner edges
+            if ix == picked_x && iy == picked_y && (edge_y || grid_x || fx - ix >= 1.0 - 1.0 / zoom)
+            {
+                pixel.copy_from_slice(&HIGHLIGHT_COLOR);
+                continue;
+            }
+
+            let inside =
+                ix >= 0.0 && iy >= 0.0 && ix < image.width as f64 && iy < image.height as f64;
+            if !inside {
+                pixel.copy_from_slice(&OUTSIDE_COLOR);
+                continue;
+            }
+
+            let src = (iy as usize * image.width as usize + ix as usize) * 4;
+            pixel.copy_from_slice(&image.rgba_data[src..src + 4]);
+            // Show the color opaque; the picker readout reports alpha
+            pixel[3] = 255;
+            if grid_x || grid_y {
+                for channel in &mut pixel[..3] {
+                    *channel = ((*channel as u32 * GRID_FACTOR) >> 8) as u8;
+                }
+            }
+        }
+    }
+    panel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 64;
+
+    /// A 3x3 BGRA image whose pixel `i` is [i * 20, 100, 200, 128]
+    fn image() -> ImageData {
+        ImageData {
+            width: 3,
+            height: 3,
+            rgba_data: (0..9u8).flat_map(|i| [i * 20, 100, 200, 128]).collect(),
+            scale: 1.0,
+            mipmaps: Vec::new(),
+        }
+    }
+
+    fn at(panel: &[u8], x: u32, y: u32) -> &[u8] {
+        &panel[((y * SIZE + x) * 4) as usize..][..4]
+    }
+
+    #[test]
+    fn test_render_magnifies_source_pixels() {
+        // Pointer in the middle of pixel (1, 1), 8 screen pixels per source pixel
+        let panel = render(&image(), 3, 3, (1.5, 1.5), 8, SIZE, LoupeShape::Square);
+        // Inside the picked pixel, shown opaque
+        assert_eq!(at(&panel, 32, 32), [80, 100, 200, 255]);
+        // One source pixel to the right
+        assert_eq!(at(&panel, 40, 32), [100, 100, 200, 255]);
+        // Its left grid line is darkened
+        let dark = |c: u8| ((c as u32 * GRID_FACTOR) >> 8) as u8;
+        assert_eq!(at(&panel, 36, 32), [dark(100), dark(100), dark(200), 255]);
+        // The picked pixel is outlined on its inner edge
+        assert_eq!(at(&panel, 28, 32), HIGHLIGHT_COLOR);
+        // Border along the square's edge
+        assert_eq!(at(&panel, 0, 32), BORDER_COLOR);
+        assert_eq!(at(&panel, 0, 0), BORDER_COLOR);
+    }
+
+    #[test]
+    fn test_render_outside_the_image_and_lens() {
+        let panel = render(&image(), 3, 3, (0.1, 0.1), 8, SIZE, LoupeShape::Circle);
+        assert_eq!(at(&panel, 8, 32), OUTSIDE_COLOR);
+        // Corners of a round lens stay transparent
+        assert_eq!(at(&panel, 0, 0), [0, 0, 0, 0]);
+        assert_eq!(at(&panel, 0, 32), BORDER_COLOR);
+    }
+
+    #[test]
+    fn test_render_follows_the_window_size() {
+        // The same pixel picked on a surface twice the image's size
+        let small = render(&image(), 3, 3, (1.5, 1.5), 8, SIZE, LoupeShape::Square);
+        let large = render(&image(), 6, 6, (3.0, 3.0), 8, SIZE, LoupeShape::Square);
+        assert_eq!(small, large);
+    }
+
+    #[test]
+    fn test_render_without_pixels_is_blank() {
+        let empty = ImageData {
+            rgba_data: Vec::new(),
+            ..image()
+        };
+        let panel = render(&empty, 3, 3, (1.5, 1.5), 8, SIZE, LoupeShape::Square);
+        assert!(panel.iter().all(|&b| b == 0));
+        assert_eq!(panel.len(), (SIZE * SIZE * 4) as usize);
+    }
+}
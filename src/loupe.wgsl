@@ -0,0 +1,85 @@
+// Loupe shader
+// Draws the magnifier over its square viewport straight from the image texture
+// with a nearest sampler, matching loupe::render on the CPU: the grid, the
+// outlined pixel under the pointer and the lens border.
+
+struct Loupe {
+    // Loupe square in surface pixels
+    rect: vec4<f32>,
+    // Pointer position as a fraction of the image size
+    center: vec2<f32>,
+    zoom: f32,
+    // 0 = circle, 1 = square
+    shape: u32,
+    border_width: f32,
+    // Darkening of grid lines, in linear light
+    grid: f32,
+    opacity: f32,
+    // sRGB, straight alpha
+    outside_color: vec4<f32>,
+    border_color: vec4<f32>,
+    highlight_color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var t_image: texture_2d<f32>;
+@group(0) @binding(1)
+var s_nearest: sampler;
+
+@group(1) @binding(0)
+var<uniform> loupe: Loupe;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // Triangle strip over the whole viewport
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u));
+    return vec4<f32>(corner.x * 2.0 - 1.0, 1.0 - corner.y * 2.0, 0.0, 1.0);
+}
+
+fn solid(color: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(srgb_to_linear(color.rgb), color.a * loupe.opacity);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // Offset from the lens center; fragment positions are pixel centers
+    let half = loupe.rect.z * 0.5;
+    let d = position.xy - loupe.rect.xy - vec2<f32>(half);
+
+    var edge_distance: f32;
+    if loupe.shape == 1u {
+        edge_distance = half - max(abs(d.x), abs(d.y));
+    } else {
+        edge_distance = half - length(d);
+    }
+    if edge_distance < 0.0 {
+        discard;
+    }
+    if edge_distance < loupe.border_width {
+        return solid(loupe.border_color);
+    }
+
+    // Source pixel under this fragment
+    let dims = vec2<f32>(textureDimensions(t_image, 0));
+    let center = loupe.center * dims;
+    let f = center + d / loupe.zoom;
+    let pixel = floor(f);
+    let frac = f - pixel;
+    let grid = frac < vec2<f32>(1.0 / loupe.zoom);
+    let far = frac >= vec2<f32>(1.0 - 1.0 / loupe.zoom);
+
+    // Outline the picked pixel on its inner edges
+    if all(pixel == floor(center)) && (any(grid) || any(far)) {
+        return solid(loupe.highlight_color);
+    }
+    if any(pixel < vec2<f32>(0.0)) || any(pixel >= dims) {
+        return solid(loupe.outside_color);
+    }
+
+    // Show the color opaque; the picker readout reports alpha
+    var color = textureSampleLevel(t_image, s_nearest, (pixel + 0.5) / dims, 0.0).rgb;
+    if any(grid) {
+        color *= loupe.grid;
+    }
+    return vec4<f32>(color, loupe.opacity);
+}
@@ -2,35 +2,93 @@
 // Displays an image in a floating, always-on-top window with customizable opacity
 
 mod app;
+mod capture;
 mod cli;
+mod config;
+mod cpu_renderer;
+mod decoration;
+mod file_watcher;
+mod fonts;
+mod glyph_atlas;
+mod hud;
+mod i18n;
+mod icons;
 mod image_loader;
+mod loupe;
+mod measure;
+mod menu;
+mod picker;
+mod reload;
+mod resample;
+mod snap;
+mod stream;
+mod text;
+mod theme;
+mod toast;
 mod wayland;
 mod wgpu_renderer;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use log::info;
+use std::io;
 
 fn main() -> Result<()> {
     // Initialize logger
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
     // Parse command line arguments
-    let args = cli::parse_args()?;
+    let mut args = cli::parse_args()?;
+    i18n::init(args.language);
+
+    // In stream mode the first frame sizes the window; later frames are read in the background
+    let frames = if args.stream {
+        let mut frames = stream::FrameReader::new(io::stdin());
+        let first = frames.next_frame()?.context("No image received on stdin")?;
+        args.image_data = Some(first);
+        Some(frames)
+    } else {
+        None
+    };
 
     info!(
         "Starting rspin with image: {:?}, opacity: {}",
         args.image_path, args.opacity
     );
 
-    // Load the image
-    let image_data = image_loader::load_image(&args)?;
+    let probe = if args.capture {
+        // The screenshot is already decoded; pin it where it was taken
+        let Some(capture) = capture::capture(args.capture_output.as_deref(), args.capture_region)?
+        else {
+            return Ok(());
+        };
+        let source = image_loader::SourceInfo {
+            name: i18n::tr("screen capture").to_string(),
+            width: capture.image.width,
+            height: capture.image.height,
+            ..Default::default()
+        };
+        image_loader::ImageProbe {
+            width: capture.region.width,
+            height: capture.region.height,
+            preview: Some(capture.image),
+            complete: true,
+            source,
+            position: Some((capture.region.x, capture.region.y)),
+            output: capture.output,
+        }
+    } else {
+        // Probe the header only; the full decode runs in the background
+        image_loader::probe_image(&args)?
+    };
 
     info!(
-        "Image loaded: {}x{} pixels",
-        image_data.width, image_data.height
+        "Image header: {}x{} pixels (preview: {})",
+        probe.width,
+        probe.height,
+        probe.preview.is_some()
     );
 
     // Run with layer-shell (GPU rendering by default, CPU as fallback)
     info!("Using layer-shell overlay mode (GPU: {})", args.use_gpu);
-    wayland::run(image_data, args.opacity, args.use_gpu)
+    wayland::run(probe, args, frames)
 }
@@ -0,0 +1,204 @@
+// Measurement module
+// Distances between points in source image pixels for the measure tool
+
+/// Measurement colors (BGRA)
+pub const LINE_COLOR: [u8; 4] = [255, 200, 60, 255];
+pub const BOX_COLOR: [u8; 4] = [255, 200, 60, 150];
+
+/// Edge length of the end point markers in pixels
+pub const ENDPOINT_SIZE: i32 = 5;
+
+/// Conversion between surface coordinates and source image pixels
+///
+/// Source pixels are those of the original file, so `--scale` and the current
+/// window size are both undone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    /// Source pixels per surface pixel
+    scale_x: f64,
+    scale_y: f64,
+}
+
+impl Mapping {
+    pub fn new(surface: (u32, u32), image: (u32, u32), image_scale: f32) -> Self {
+        let image_scale = if image_scale > 0.0 {
+            image_scale as f64
+        } else {
+            1.0
+        };
+        Self {
+            scale_x: image.0 as f64 / surface.0.max(1) as f64 / image_scale,
+            scale_y: image.1 as f64 / surface.1.max(1) as f64 / image_scale,
+        }
+    }
+
+    pub fn to_source(self, pos: (f64, f64)) -> (f64, f64) {
+        (pos.0 * self.scale_x, pos.1 * self.scale_y)
+    }
+
+    pub fn to_surface(self, pos: (f64, f64)) -> (f64, f64) {
+        (pos.0 / self.scale_x, pos.1 / self.scale_y)
+    }
+}
+
+/// A measured line between two points, in source pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+}
+
+impl Measurement {
+    /// Start a measurement at a point snapped to the pixel grid
+    pub fn new(start: (f64, f64)) -> Self {
+        let start = (start.0.round(), start.1.round());
+        Self { start, end: start }
+    }
+
+    /// Move the end point, snapping to the pixel grid
+    ///
+    /// With `constrain` the line is locked to its dominant axis.
+    pub fn set_end(&mut self, end: (f64, f64), constrain: bool) {
+        let mut end = (end.0.round(), end.1.round());
+        if constrain {
+            if (end.0 - self.start.0).abs() >= (end.1 - self.start.1).abs() {
+                end.1 = self.start.1;
+            } else {
+                end.0 = self.start.0;
+            }
+        }
+        self.end = end;
+    }
+
+    pub fn dx(&self) -> f64 {
+        self.end.0 - self.start.0
+    }
+
+    pub fn dy(&self) -> f64 {
+        self.end.1 - self.start.1
+    }
+
+    pub fn length(&self) -> f64 {
+        self.dx().hypot(self.dy())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dx() == 0.0 && self.dy() == 0.0
+    }
+
+    /// Readout lines: length, signed deltas and the spanned box
+    pub fn label(&self) -> [String; 3] {
+        [
+            format!("{:.1} px", self.length()),
+            format!("dx {:+} dy {:+}", self.dx() as i64, self.dy() as i64),
+            format!("{} x {} px", self.dx().abs(), self.dy().abs()),
+        ]
+    }
+}
+
+/// Draw a line of the given thickness into a BGRA buffer
+pub fn draw_line(
+    canvas: &mut [u8],
+    width: u32,
+    height: u32,
+    from: (f64, f64),
+    to: (f64, f64),
+    color: [u8; 4],
+    thickness: i32,
+) {
+    let steps = (to.0 - from.0)
+        .abs()
+        .max((to.1 - from.1).abs())
+        .ceil()
+        .max(1.0) as i32;
+    let offset = thickness / 2;
+    for i in 0..=steps {
+        let t = i as f64 / steps as f64;
+        let x = (from.0 + (to.0 - from.0) * t).floor() as i32;
+        let y = (from.1 + (to.1 - from.1) * t).floor() as i32;
+        for py in y - offset..y - offset + thickness {
+            for px in x - offset..x - offset + thickness {
+                if px < 0 || py < 0 || px >= width as i32 || py >= height as i32 {
+                    continue;
+                }
+                let idx = (py as usize * width as usize + px as usize) * 4;
+                canvas[idx..idx + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping_undoes_window_and_image_scale() {
+        // A 400x200 file shown at --scale 0.5 (200x100), stretched to 100x100
+        let mapping = Mapping::new((100, 100), (200, 100), 0.5);
+        assert_eq!(mapping.to_source((10.0, 10.0)), (40.0, 20.0));
+        assert_eq!(mapping.to_surface((40.0, 20.0)), (10.0, 10.0));
+
+        // Unscaled and shown 1:1
+        let mapping = Mapping::new((300, 200), (300, 200), 1.0);
+        assert_eq!(mapping.to_source((12.5, 7.0)), (12.5, 7.0));
+    }
+
+    #[test]
+    fn test_mapping_tolerates_degenerate_input() {
+        // A zero-sized surface or a bad scale must not produce infinities
+        let mapping = Mapping::new((0, 0), (100, 50), 0.0);
+        assert_eq!(mapping.to_source((1.0, 1.0)), (100.0, 50.0));
+        let (x, y) = Mapping::new((10, 10), (100, 50), -2.0).to_source((1.0, 1.0));
+        assert!(x.is_finite() && y.is_finite());
+    }
+
+    #[test]
+    fn test_measurement_snaps_to_pixels() {
+        let mut measurement = Measurement::new((10.4, 20.6));
+        assert_eq!(measurement.start, (10.0, 21.0));
+        assert!(measurement.is_empty());
+
+        measurement.set_end((13.2, 17.4), false);
+        assert_eq!(measurement.end, (13.0, 17.0));
+        assert_eq!((measurement.dx(), measurement.dy()), (3.0, -4.0));
+        assert_eq!(measurement.length(), 5.0);
+        assert_eq!(
+            measurement.label(),
+            [
+                "5.0 px".to_string(),
+                "dx +3 dy -4".to_string(),
+                "3 x 4 px".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_measurement_constrains_to_dominant_axis() {
+        let mut measurement = Measurement::new((0.0, 0.0));
+        measurement.set_end((10.0, 3.0), true);
+        assert_eq!(measurement.end, (10.0, 0.0));
+        measurement.set_end((-2.0, -9.0), true);
+        assert_eq!(measurement.end, (0.0, -9.0));
+        // Equal deltas stay horizontal
+        measurement.set_end((5.0, 5.0), true);
+        assert_eq!(measurement.end, (5.0, 0.0));
+    }
+
+    #[test]
+    fn test_draw_line_clips_to_the_canvas() {
+        let (width, height) = (8, 4);
+        let mut canvas = vec![0u8; width * height * 4];
+        draw_line(&mut canvas, 8, 4, (-3.0, 1.0), (20.0, 1.0), LINE_COLOR, 1);
+        for (i, pixel) in canvas.chunks_exact(4).enumerate() {
+            let expected = if i / width == 1 { LINE_COLOR } else { [0; 4] };
+            assert_eq!(pixel, expected, "pixel {}", i);
+        }
+
+        // A thick point covers a square around it
+        let mut canvas = vec![0u8; width * height * 4];
+        draw_line(&mut canvas, 8, 4, (4.0, 2.0), (4.0, 2.0), LINE_COLOR, 3);
+        let painted = canvas.chunks_exact(4).filter(|p| *p == LINE_COLOR).count();
+        assert_eq!(painted, 9);
+    }
+}
@@ -0,0 +1,828 @@
+// Context menu module
+// Menu tree (items, separators, submenus), the pin's context menu, its layout and the item glyphs
+
+use crate::cpu_renderer::Rect;
+use crate::i18n::tr;
+use crate::icons::Icon;
+use crate::measure;
+use crate::resample::FilterMode;
+use crate::theme::Theme;
+
+/// Horizontal layout of a row: check column, icon column, gap before the
+/// shortcut hint and the submenu arrow column (padding and heights come from the theme)
+pub const CHECK_WIDTH: u32 = 18;
+const ICON_WIDTH: u32 = 26;
+const SHORTCUT_GAP: u32 = 24;
+const ARROW_WIDTH: u32 = 14;
+
+/// Narrowest menu panel
+const MIN_WIDTH: u32 = 120;
+
+/// Check state shown in front of an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Check {
+    #[default]
+    None,
+    /// Independent on/off option
+    Checkbox(bool),
+    /// One choice out of the neighbouring radio items
+    Radio(bool),
+}
+
+/// Keyboard movements within a menu level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuStep {
+    Next,
+    Previous,
+    First,
+    Last,
+}
+
+/// A menu item: either an action or the parent of a submenu
+#[derive(Debug, Clone)]
+pub struct MenuItem<A> {
+    pub label: String,
+    pub icon: Option<Icon>,
+    pub action: Option<A>,
+    pub enabled: bool,
+    pub check: Check,
+    pub shortcut: Option<&'static str>,
+    // Letter that selects the item from the keyboard, independent of the translated label
+    pub accelerator: Option<char>,
+    pub submenu: Vec<MenuEntry<A>>,
+}
+
+impl<A> MenuItem<A> {
+    /// Item that runs `action` when activated
+    pub fn new(label: impl Into<String>, action: A) -> Self {
+        Self {
+            label: label.into(),
+            icon: None,
+            action: Some(action),
+            enabled: true,
+            check: Check::None,
+            shortcut: None,
+            accelerator: None,
+            submenu: Vec::new(),
+        }
+    }
+
+    /// Item that opens `entries` as a submenu
+    pub fn submenu(label: impl Into<String>, entries: Vec<MenuEntry<A>>) -> Self {
+        Self {
+            label: label.into(),
+            icon: None,
+            action: None,
+            enabled: !entries.is_empty(),
+            check: Check::None,
+            shortcut: None,
+            accelerator: None,
+            submenu: entries,
+        }
+    }
+
+    pub fn icon(mut self, icon: Icon) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    pub fn shortcut(mut self, shortcut: &'static str) -> Self {
+        self.shortcut = Some(shortcut);
+        self
+    }
+
+    pub fn accelerator(mut self, letter: char) -> Self {
+        self.accelerator = Some(letter.to_ascii_lowercase());
+        self
+    }
+
+    /// Whether typing `letter` selects this item
+    pub fn matches_accelerator(&self, letter: char) -> bool {
+        self.accelerator
+            .is_some_and(|accelerator| letter.to_lowercase().eq(accelerator.to_lowercase()))
+    }
+
+    pub fn checkbox(mut self, checked: bool) -> Self {
+        self.check = Check::Checkbox(checked);
+        self
+    }
+
+    pub fn radio(mut self, selected: bool) -> Self {
+        self.check = Check::Radio(selected);
+        self
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn has_submenu(&self) -> bool {
+        !self.submenu.is_empty()
+    }
+}
+
+/// A row of a menu
+#[derive(Debug, Clone)]
+pub enum MenuEntry<A> {
+    Item(MenuItem<A>),
+    Separator,
+}
+
+impl<A> From<MenuItem<A>> for MenuEntry<A> {
+    fn from(item: MenuItem<A>) -> Self {
+        MenuEntry::Item(item)
+    }
+}
+
+impl<A> MenuEntry<A> {
+    pub fn item(&self) -> Option<&MenuItem<A>> {
+        match self {
+            MenuEntry::Item(item) => Some(item),
+            MenuEntry::Separator => None,
+        }
+    }
+}
+
+/// Scale mode for resizing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+    /// Keep aspect ratio when resizing
+    KeepAspectRatio,
+    /// Free scaling (stretch)
+    FreeScale,
+}
+
+impl ScaleMode {
+    /// Short name for the menu and the info overlay
+    pub fn label(self) -> &'static str {
+        match self {
+            ScaleMode::KeepAspectRatio => "Keep aspect",
+            ScaleMode::FreeScale => "Free scale",
+        }
+    }
+}
+
+/// Actions offered by the context menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    Close,
+    Copy,
+    OpacityUp,
+    OpacityDown,
+    SetScaleMode(ScaleMode),
+    SetFilter(FilterMode),
+    ToggleWatch,
+    TogglePicker,
+    ToggleMeasure,
+    ToggleHud,
+}
+
+/// Pin state shown by the context menu's checks and disabled items
+#[derive(Debug, Clone, Copy)]
+pub struct MenuContext {
+    pub image_loaded: bool,
+    pub opacity: f32,
+    pub scale_mode: ScaleMode,
+    pub filter: FilterMode,
+    /// Whether the pin shows a file that can be watched
+    pub has_source: bool,
+    pub watching: bool,
+    pub picker_active: bool,
+    pub measure_active: bool,
+    pub hud_always: bool,
+}
+
+/// The pin's context menu; every item of a level has its own accelerator
+pub fn context_menu(context: &MenuContext) -> Vec<MenuEntry<MenuAction>> {
+    let scale_modes = [
+        (ScaleMode::KeepAspectRatio, 'k'),
+        (ScaleMode::FreeScale, 'f'),
+    ]
+    .into_iter()
+    .map(|(mode, letter)| {
+        MenuItem::new(tr(mode.label()), MenuAction::SetScaleMode(mode))
+            .accelerator(letter)
+            .radio(context.scale_mode == mode)
+            .into()
+    })
+    .collect();
+    let filters = FilterMode::ALL
+        .into_iter()
+        .map(|filter| {
+            let letter = match filter {
+                FilterMode::Nearest => 'n',
+                FilterMode::Bilinear => 'b',
+                FilterMode::Bicubic => 'c',
+                FilterMode::Lanczos => 'l',
+            };
+            MenuItem::new(tr(filter.label()), MenuAction::SetFilter(filter))
+                .accelerator(letter)
+                .radio(context.filter == filter)
+                .into()
+        })
+        .collect();
+
+    vec![
+        MenuItem::new(tr("Copy to Clipboard"), MenuAction::Copy)
+            .accelerator('y')
+            .icon(Icon::Copy)
+            .enabled(context.image_loaded)
+            .into(),
+        MenuEntry::Separator,
+        MenuItem::new(tr("Opacity +"), MenuAction::OpacityUp)
+            .accelerator('+')
+            .icon(Icon::Brighter)
+            .shortcut(tr("Scroll"))
+            .enabled(context.opacity < 1.0)
+            .into(),
+        MenuItem::new(tr("Opacity -"), MenuAction::OpacityDown)
+            .accelerator('-')
+            .icon(Icon::Dimmer)
+            .shortcut(tr("Scroll"))
+            .enabled(context.opacity > 0.1 + f32::EPSILON)
+            .into(),
+        MenuItem::submenu(tr("Scale Mode"), scale_modes)
+            .accelerator('s')
+            .icon(Icon::Scale)
+            .into(),
+        MenuItem::submenu(tr("Filter"), filters)
+            .accelerator('f')
+            .icon(Icon::Filter)
+            .into(),
+        MenuItem::new(tr("Watch File"), MenuAction::ToggleWatch)
+            .accelerator('w')
+            .icon(Icon::Watch)
+            .checkbox(context.watching)
+            .enabled(context.has_source)
+            .into(),
+        MenuEntry::Separator,
+        MenuItem::new(tr("Pick Color"), MenuAction::TogglePicker)
+            .accelerator('p')
+            .icon(Icon::Picker)
+            .shortcut("C")
+            .checkbox(context.picker_active)
+            .into(),
+        MenuItem::new(tr("Measure"), MenuAction::ToggleMeasure)
+            .accelerator('m')
+            .icon(Icon::Measure)
+            .shortcut("M")
+            .checkbox(context.measure_active)
+            .into(),
+        MenuItem::new(tr("Info Overlay"), MenuAction::ToggleHud)
+            .accelerator('i')
+            .icon(Icon::Info)
+            .shortcut("I")
+            .checkbox(context.hud_always)
+            .into(),
+        MenuEntry::Separator,
+        MenuItem::new(tr("Close"), MenuAction::Close)
+            .accelerator('l')
+            .icon(Icon::Close)
+            .shortcut("Q")
+            .into(),
+    ]
+}
+
+/// Entries of the submenu reached by following `path` (item indices per level)
+pub fn entries_at<'a, A>(root: &'a [MenuEntry<A>], path: &[usize]) -> Option<&'a [MenuEntry<A>]> {
+    let mut entries = root;
+    for &index in path {
+        entries = &entries.get(index)?.item()?.submenu;
+    }
+    Some(entries)
+}
+
+/// Rows of a menu level that can be selected
+pub fn enabled_rows<A>(entries: &[MenuEntry<A>]) -> Vec<usize> {
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.item().is_some_and(|item| item.enabled))
+        .map(|(row, _)| row)
+        .collect()
+}
+
+/// Row selected by moving from `current` among the selectable `rows`, wrapping around
+pub fn step_row(rows: &[usize], current: Option<usize>, step: MenuStep) -> Option<usize> {
+    if rows.is_empty() {
+        return None;
+    }
+    let current = current.and_then(|row| rows.iter().position(|&r| r == row));
+    let index = match (step, current) {
+        (MenuStep::First, _) | (MenuStep::Next, None) => 0,
+        (MenuStep::Last, _) | (MenuStep::Previous, None) => rows.len() - 1,
+        (MenuStep::Next, Some(i)) => (i + 1) % rows.len(),
+        (MenuStep::Previous, Some(i)) => (i + rows.len() - 1) % rows.len(),
+    };
+    Some(rows[index])
+}
+
+/// Row that typing `letter` selects: the next enabled item after `current` with that
+/// accelerator, wrapping around, and whether it is the only such item
+pub fn accelerator_row<A>(
+    entries: &[MenuEntry<A>],
+    current: Option<usize>,
+    letter: char,
+) -> Option<(usize, bool)> {
+    let matches: Vec<usize> = enabled_rows(entries)
+        .into_iter()
+        .filter(|&row| {
+            entries[row]
+                .item()
+                .is_some_and(|item| item.matches_accelerator(letter))
+        })
+        .collect();
+    let row = matches
+        .iter()
+        .copied()
+        .find(|&row| current.is_some_and(|current| row > current))
+        .or(matches.first().copied())?;
+    Some((row, matches.len() == 1))
+}
+
+/// A laid out menu level in surface coordinates
+#[derive(Debug, Clone)]
+pub struct Panel {
+    pub rect: Rect,
+    /// One rectangle per entry, separators included
+    pub rows: Vec<Rect>,
+    /// Column offsets from the panel's left edge
+    pub check_x: i32,
+    pub icon_x: i32,
+    pub text_x: i32,
+    pub shortcut_x: i32,
+}
+
+impl Panel {
+    /// Index of the row under a point
+    pub fn row_at(&self, x: f64, y: f64) -> Option<usize> {
+        self.rows.iter().position(|row| {
+            x >= row.x as f64
+                && x < (row.x + row.width) as f64
+                && y >= row.y as f64
+                && y < (row.y + row.height) as f64
+        })
+    }
+}
+
+impl Panel {
+    /// The same panel in a buffer `scale` times larger
+    pub fn scaled(&self, scale: i32) -> Panel {
+        let scale_rect =
+            |r: &Rect| Rect::new(r.x * scale, r.y * scale, r.width * scale, r.height * scale);
+        Panel {
+            rect: scale_rect(&self.rect),
+            rows: self.rows.iter().map(scale_rect).collect(),
+            check_x: self.check_x * scale,
+            icon_x: self.icon_x * scale,
+            text_x: self.text_x * scale,
+            shortcut_x: self.shortcut_x * scale,
+        }
+    }
+
+    /// Move the panel so its top-left corner is at `(x, y)`
+    pub fn move_to(&mut self, x: i32, y: i32) {
+        let (dx, dy) = (x - self.rect.x, y - self.rect.y);
+        self.rect.x = x;
+        self.rect.y = y;
+        for row in &mut self.rows {
+            row.x += dx;
+            row.y += dy;
+        }
+    }
+
+    /// Place the panel with its top-left corner at `anchor`, inside `bounds`
+    ///
+    /// A panel that would overflow the right edge is placed so that it ends at
+    /// `flip_x` instead (left of the pointer or of the parent menu).
+    pub fn place(&mut self, anchor: (i32, i32), flip_x: i32, bounds: (u32, u32)) {
+        let (width, height) = (self.rect.width, self.rect.height);
+        let mut x = anchor.0;
+        if x + width > bounds.0 as i32 {
+            x = flip_x - width;
+        }
+        let x = x.clamp(0, (bounds.0 as i32 - width).max(0));
+        let y = anchor.1.min(bounds.1 as i32 - height).max(0);
+        self.move_to(x, y);
+    }
+}
+
+/// Lay out one menu level with its top-left corner at the origin
+///
+/// The width fits the widest label and shortcut; `measure` returns the
+/// rendered width of a text.
+pub fn layout<A>(
+    entries: &[MenuEntry<A>],
+    theme: &Theme,
+    measure: &mut impl FnMut(&str) -> u32,
+) -> Panel {
+    let padding = theme.padding;
+    let items = || entries.iter().filter_map(MenuEntry::item);
+    let check_width = if items().any(|item| item.check != Check::None) {
+        CHECK_WIDTH
+    } else {
+        0
+    };
+    let icon_width = if items().any(|item| item.icon.is_some()) {
+        ICON_WIDTH
+    } else {
+        0
+    };
+    let label_width = items().map(|item| measure(&item.label)).max().unwrap_or(0);
+    let shortcut_width = items()
+        .filter_map(|item| item.shortcut)
+        .map(&mut *measure)
+        .max()
+        .map_or(0, |width| width + SHORTCUT_GAP);
+    let arrow_width = if items().any(MenuItem::has_submenu) {
+        ARROW_WIDTH
+    } else {
+        0
+    };
+
+    let icon_x = padding + check_width;
+    let text_x = icon_x + icon_width;
+    let shortcut_x = text_x + label_width + SHORTCUT_GAP;
+    let width =
+        (text_x + label_width + shortcut_width + arrow_width + padding).max(MIN_WIDTH) as i32;
+
+    let mut rows = Vec::with_capacity(entries.len());
+    let mut row_y = 0;
+    for entry in entries {
+        let row_height = match entry {
+            MenuEntry::Item(_) => theme.item_height as i32,
+            MenuEntry::Separator => theme.separator_height as i32,
+        };
+        rows.push(Rect::new(0, row_y, width, row_height));
+        row_y += row_height;
+    }
+
+    Panel {
+        rect: Rect::new(0, 0, width, row_y),
+        rows,
+        check_x: padding as i32,
+        icon_x: icon_x as i32,
+        text_x: text_x as i32,
+        shortcut_x: shortcut_x as i32,
+    }
+}
+
+/// Draw a check mark or radio dot centered in `cell` of a BGRA buffer, `scale` times
+/// its logical size
+pub fn draw_check(
+    canvas: &mut [u8],
+    width: u32,
+    height: u32,
+    cell: Rect,
+    check: Check,
+    color: [u8; 4],
+    scale: u32,
+) {
+    let cx = cell.x as f64 + cell.width as f64 / 2.0;
+    let cy = cell.y as f64 + cell.height as f64 / 2.0;
+    let s = scale as f64;
+    match check {
+        Check::Checkbox(true) => {
+            let knee = (cx - 1.5 * s, cy + 3.0 * s);
+            let (start, end) = ((cx - 5.0 * s, cy), (cx + 5.0 * s, cy - 5.0 * s));
+            measure::draw_line(canvas, width, height, start, knee, color, 2 * scale as i32);
+            measure::draw_line(canvas, width, height, knee, end, color, 2 * scale as i32);
+        }
+        Check::Radio(true) => {
+            let radius = 3.5 * s;
+            for y in (cy - radius).floor() as i32..=(cy + radius).ceil() as i32 {
+                for x in (cx - radius).floor() as i32..=(cx + radius).ceil() as i32 {
+                    let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+                    if dx * dx + dy * dy <= radius * radius {
+                        put_pixel(canvas, width, height, x, y, color);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Draw the right-pointing submenu arrow `padding` pixels from the right end of a row,
+/// `scale` times its logical size
+pub fn draw_arrow(
+    canvas: &mut [u8],
+    width: u32,
+    height: u32,
+    row: Rect,
+    padding: u32,
+    color: [u8; 4],
+    scale: u32,
+) {
+    let tip_x = row.x + row.width - padding as i32;
+    let cy = row.y + row.height / 2;
+    // Columns narrow by one pixel per step towards the tip
+    let half = 4 * scale as i32 - 1;
+    for i in 0..=half {
+        for y in cy - half + i..=cy + half - i {
+            put_pixel(canvas, width, height, tip_x - half + i, y, color);
+        }
+    }
+}
+
+fn put_pixel(canvas: &mut [u8], width: u32, height: u32, x: i32, y: i32, color: [u8; 4]) {
+    if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+        return;
+    }
+    let idx = (y as usize * width as usize + x as usize) * 4;
+    canvas[idx..idx + 4].copy_from_slice(&color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text width used by the tests: 10 pixels per character
+    fn measure(text: &str) -> u32 {
+        text.chars().count() as u32 * 10
+    }
+
+    fn menu() -> Vec<MenuEntry<u32>> {
+        vec![
+            MenuItem::new("Copy", 1).icon(Icon::Copy).into(),
+            MenuItem::new("Watch", 2)
+                .checkbox(true)
+                .shortcut("W")
+                .into(),
+            MenuEntry::Separator,
+            MenuItem::submenu(
+                "Filter",
+                vec![
+                    MenuItem::new("Nearest", 3).radio(false).into(),
+                    MenuItem::new("Bilinear", 4).radio(true).into(),
+                ],
+            )
+            .into(),
+        ]
+    }
+
+    #[test]
+    fn test_builders() {
+        let item = MenuItem::new("Copy", 1);
+        assert!(item.enabled);
+        assert_eq!(item.action, Some(1));
+        assert!(!item.has_submenu());
+        let item = item.checkbox(true).enabled(false);
+        assert_eq!(item.check, Check::Checkbox(true));
+        assert!(!item.enabled);
+
+        // Submenus without entries cannot be opened
+        assert!(!MenuItem::<u32>::submenu("Empty", Vec::new()).enabled);
+        let parent = MenuItem::submenu("Parent", vec![MenuItem::new("Child", 1).into()]);
+        assert!(parent.enabled && parent.has_submenu() && parent.action.is_none());
+    }
+
+    #[test]
+    fn test_entries_at() {
+        let menu = menu();
+        assert_eq!(entries_at(&menu, &[]).map(<[_]>::len), Some(4));
+        let filters = entries_at(&menu, &[3]).unwrap();
+        assert_eq!(filters[1].item().map(|item| item.action), Some(Some(4)));
+        // Separators, leaf items and missing rows have no submenu entries to follow
+        assert!(entries_at(&menu, &[2]).is_none());
+        assert_eq!(entries_at(&menu, &[0]).map(<[_]>::len), Some(0));
+        assert!(entries_at(&menu, &[9]).is_none());
+        assert!(entries_at(&menu, &[3, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_layout_columns_and_rows() {
+        let theme = Theme::default();
+        let panel = layout(&menu(), &theme, &mut measure);
+        // Check column, icon column, widest label, shortcut and arrow
+        assert_eq!(panel.check_x, 8);
+        assert_eq!(panel.icon_x, 8 + 18);
+        assert_eq!(panel.text_x, 8 + 18 + 26);
+        assert_eq!(panel.shortcut_x, 52 + 60 + 24);
+        assert_eq!(panel.rect, Rect::new(0, 0, 52 + 60 + 10 + 24 + 14 + 8, 84));
+        assert_eq!(
+            panel
+                .rows
+                .iter()
+                .map(|row| (row.y, row.height))
+                .collect::<Vec<_>>(),
+            [(0, 25), (25, 25), (50, 9), (59, 25)]
+        );
+        assert!(panel.rows.iter().all(|row| row.width == panel.rect.width));
+    }
+
+    #[test]
+    fn test_layout_drops_unused_columns() {
+        let theme = Theme::default();
+        let entries: Vec<MenuEntry<u32>> = vec![MenuItem::new("A", 1).into()];
+        let panel = layout(&entries, &theme, &mut measure);
+        assert_eq!((panel.icon_x, panel.text_x), (8, 8));
+        assert_eq!(panel.rect.width, MIN_WIDTH as i32);
+    }
+
+    #[test]
+    fn test_row_at() {
+        let theme = Theme::default();
+        let panel = layout(&menu(), &theme, &mut measure);
+        assert_eq!(panel.row_at(10.0, 0.0), Some(0));
+        assert_eq!(panel.row_at(10.0, 24.9), Some(0));
+        assert_eq!(panel.row_at(10.0, 25.0), Some(1));
+        assert_eq!(panel.row_at(10.0, 55.0), Some(2));
+        assert_eq!(panel.row_at(10.0, 84.0), None);
+        assert_eq!(panel.row_at(-1.0, 10.0), None);
+    }
+
+    /// Items a, b (disabled), separator, a, c
+    fn navigation_menu() -> Vec<MenuEntry<u32>> {
+        vec![
+            MenuItem::new("Apple", 1).accelerator('a').into(),
+            MenuItem::new("Banana", 2)
+                .accelerator('b')
+                .enabled(false)
+                .into(),
+            MenuEntry::Separator,
+            MenuItem::new("Avocado", 3).accelerator('a').into(),
+            MenuItem::new("Cherry", 4).accelerator('C').into(),
+        ]
+    }
+
+    #[test]
+    fn test_enabled_rows_skip_separators_and_disabled_items() {
+        assert_eq!(enabled_rows(&navigation_menu()), [0, 3, 4]);
+        assert!(enabled_rows::<u32>(&[MenuEntry::Separator]).is_empty());
+    }
+
+    #[test]
+    fn test_step_row_wraps_around() {
+        let rows = [0, 3, 4];
+        assert_eq!(step_row(&rows, None, MenuStep::Next), Some(0));
+        assert_eq!(step_row(&rows, None, MenuStep::Previous), Some(4));
+        assert_eq!(step_row(&rows, Some(0), MenuStep::Next), Some(3));
+        assert_eq!(step_row(&rows, Some(4), MenuStep::Next), Some(0));
+        assert_eq!(step_row(&rows, Some(0), MenuStep::Previous), Some(4));
+        assert_eq!(step_row(&rows, Some(3), MenuStep::First), Some(0));
+        assert_eq!(step_row(&rows, Some(3), MenuStep::Last), Some(4));
+        // A selection on a row that cannot be selected starts over
+        assert_eq!(step_row(&rows, Some(2), MenuStep::Next), Some(0));
+        assert_eq!(step_row(&[], Some(0), MenuStep::Next), None);
+    }
+
+    #[test]
+    fn test_accelerator_row() {
+        let menu = navigation_menu();
+        // Two matches: cycle through them without activating
+        assert_eq!(accelerator_row(&menu, None, 'a'), Some((0, false)));
+        assert_eq!(accelerator_row(&menu, Some(0), 'a'), Some((3, false)));
+        assert_eq!(accelerator_row(&menu, Some(3), 'A'), Some((0, false)));
+        // A single match is activated, whatever the case
+        assert_eq!(accelerator_row(&menu, Some(0), 'c'), Some((4, true)));
+        // Disabled items and unknown letters select nothing
+        assert_eq!(accelerator_row(&menu, None, 'b'), None);
+        assert_eq!(accelerator_row(&menu, None, 'z'), None);
+    }
+
+    /// A 100x50 panel with two rows at the origin
+    fn panel() -> Panel {
+        Panel {
+            rect: Rect::new(0, 0, 100, 50),
+            rows: vec![Rect::new(0, 0, 100, 25), Rect::new(0, 25, 100, 25)],
+            check_x: 8,
+            icon_x: 8,
+            text_x: 8,
+            shortcut_x: 60,
+        }
+    }
+
+    #[test]
+    fn test_move_to_carries_the_rows() {
+        let mut panel = panel();
+        panel.move_to(30, 40);
+        assert_eq!(panel.rect, Rect::new(30, 40, 100, 50));
+        assert_eq!(panel.rows[1], Rect::new(30, 65, 100, 25));
+        // Column offsets are relative to the panel
+        assert_eq!(panel.shortcut_x, 60);
+    }
+
+    #[test]
+    fn test_place_inside_bounds() {
+        let mut panel = panel();
+        panel.place((20, 30), 20, (400, 300));
+        assert_eq!((panel.rect.x, panel.rect.y), (20, 30));
+    }
+
+    #[test]
+    fn test_place_flips_at_the_right_edge() {
+        // Would end at 420: ends at the flip point instead
+        let mut panel = panel();
+        panel.place((320, 30), 250, (400, 300));
+        assert_eq!((panel.rect.x, panel.rect.y), (150, 30));
+        // Not enough room on the left either: pushed against the left edge
+        panel.place((350, 30), 40, (400, 300));
+        assert_eq!(panel.rect.x, 0);
+    }
+
+    #[test]
+    fn test_place_moves_up_from_the_bottom_edge() {
+        let mut panel = panel();
+        panel.place((20, 280), 20, (400, 300));
+        assert_eq!((panel.rect.x, panel.rect.y), (20, 250));
+        // Taller than the bounds: the top stays visible
+        panel.place((20, 10), 20, (400, 30));
+        assert_eq!(panel.rect.y, 0);
+        // Wider than the bounds: the left edge stays visible
+        panel.place((20, 10), 20, (60, 300));
+        assert_eq!(panel.rect.x, 0);
+    }
+
+    #[test]
+    fn test_scaled_panel() {
+        let panel = panel().scaled(2);
+        assert_eq!(panel.rect, Rect::new(0, 0, 200, 100));
+        assert_eq!(panel.rows[1], Rect::new(0, 50, 200, 50));
+        assert_eq!((panel.text_x, panel.shortcut_x), (16, 120));
+    }
+
+    #[test]
+    fn test_accelerators_ignore_labels() {
+        // Translated labels keep the accelerator of the message id
+        let item = MenuItem::new("复制到剪贴板", 1).accelerator('C');
+        assert!(item.matches_accelerator('c'));
+        assert!(!item.matches_accelerator('复'));
+        assert!(!MenuItem::new("Copy", 1).matches_accelerator('c'));
+    }
+
+    fn context() -> MenuContext {
+        MenuContext {
+            image_loaded: true,
+            opacity: 0.5,
+            scale_mode: ScaleMode::KeepAspectRatio,
+            filter: FilterMode::Bilinear,
+            has_source: true,
+            watching: false,
+            picker_active: false,
+            measure_active: false,
+            hud_always: false,
+        }
+    }
+
+    /// Check that every item of every level has an accelerator no sibling shares
+    fn assert_unique_accelerators(entries: &[MenuEntry<MenuAction>]) {
+        let mut seen = Vec::new();
+        for item in entries.iter().filter_map(MenuEntry::item) {
+            let letter = item
+                .accelerator
+                .unwrap_or_else(|| panic!("{} has no accelerator", item.label));
+            assert!(
+                !seen.contains(&letter),
+                "{} repeats {:?}",
+                item.label,
+                letter
+            );
+            seen.push(letter);
+            assert_unique_accelerators(&item.submenu);
+        }
+    }
+
+    #[test]
+    fn test_context_menu_accelerators_are_unique() {
+        let entries = context_menu(&context());
+        assert_unique_accelerators(&entries);
+        // Each accelerator activates its item directly
+        for (row, item) in entries
+            .iter()
+            .enumerate()
+            .filter_map(|(row, entry)| Some((row, entry.item()?)))
+        {
+            let letter = item.accelerator.unwrap();
+            assert_eq!(accelerator_row(&entries, None, letter), Some((row, true)));
+        }
+    }
+
+    #[test]
+    fn test_context_menu_reflects_state() {
+        let entries = context_menu(&MenuContext {
+            image_loaded: false,
+            opacity: 1.0,
+            has_source: false,
+            watching: false,
+            picker_active: true,
+            filter: FilterMode::Lanczos,
+            ..context()
+        });
+        let item = |row: usize| entries[row].item().unwrap();
+        assert_eq!(item(0).action, Some(MenuAction::Copy));
+        assert!(!item(0).enabled);
+        // Opacity can only go down from fully opaque
+        assert!(!item(2).enabled);
+        assert!(item(3).enabled);
+        assert!(!item(6).enabled);
+        assert_eq!(item(8).check, Check::Checkbox(true));
+        let filters = &item(5).submenu;
+        assert_eq!(filters.len(), FilterMode::ALL.len());
+        assert_eq!(
+            filters.last().unwrap().item().unwrap().check,
+            Check::Radio(true)
+        );
+    }
+}
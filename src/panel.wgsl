@@ -0,0 +1,78 @@
+// Panel shader
+// Draws overlay panels without re-uploading them: backgrounds are instanced
+// rounded rectangles (fill plus a one-pixel outline, matching PanelFrame::draw
+// on the CPU), and the remaining pixels are read 1:1 from the layer's texture.
+
+struct Screen {
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+// The layer texture only grows, so only its top-left part holds the panel
+@group(1) @binding(0)
+var t_panel: texture_2d<f32>;
+@group(1) @binding(1)
+var<uniform> panel_rect: vec4<f32>;
+
+struct FrameInstance {
+    // x, y, width, height in surface pixels
+    @location(0) rect: vec4<f32>,
+    @location(1) radius: f32,
+    @location(2) fill: vec4<f32>,
+    @location(3) border: vec4<f32>,
+}
+
+struct FrameOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) rect: vec4<f32>,
+    @location(1) @interpolate(flat) radius: f32,
+    @location(2) @interpolate(flat) fill: vec4<f32>,
+    @location(3) @interpolate(flat) border: vec4<f32>,
+}
+
+// Triangle strip corner of a surface rectangle, in clip space
+fn corner_position(vertex_index: u32, rect: vec4<f32>) -> vec4<f32> {
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u));
+    let pos = rect.xy + corner * rect.zw;
+    return vec4<f32>(
+        pos.x / screen.size.x * 2.0 - 1.0,
+        1.0 - pos.y / screen.size.y * 2.0,
+        0.0,
+        1.0,
+    );
+}
+
+@vertex
+fn vs_frame(@builtin(vertex_index) vertex_index: u32, frame: FrameInstance) -> FrameOutput {
+    var out: FrameOutput;
+    out.clip_position = corner_position(vertex_index, frame.rect);
+    out.rect = frame.rect;
+    out.radius = frame.radius;
+    out.fill = vec4<f32>(srgb_to_linear(frame.fill.rgb), frame.fill.a);
+    out.border = vec4<f32>(srgb_to_linear(frame.border.rgb), frame.border.a);
+    return out;
+}
+
+@fragment
+fn fs_frame(in: FrameOutput) -> @location(0) vec4<f32> {
+    // Fragment positions are pixel centers, like the CPU's x + 0.5
+    let d = rounded_rect_distance(in.clip_position.xy, in.rect, in.radius);
+    let outer = clamp(0.5 - d, 0.0, 1.0);
+    let inner = clamp(0.5 - d - 1.0, 0.0, 1.0);
+    // The fill's coverage, then the outline band painted over it
+    let filled = vec4<f32>(in.fill.rgb, in.fill.a * outer);
+    return mix(filled, in.border, outer - inner);
+}
+
+@vertex
+fn vs_image(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    return corner_position(vertex_index, panel_rect);
+}
+
+@fragment
+fn fs_image(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(floor(position.xy - panel_rect.xy));
+    return textureLoad(t_panel, texel, 0);
+}
@@ -0,0 +1,169 @@
+// Color picker module
+// Maps surface positions to source pixels and formats their color for the clipboard
+
+use crate::image_loader::ImageData;
+
+/// A source pixel picked from the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PickedColor {
+    /// Image-space coordinates of the pixel
+    pub x: u32,
+    pub y: u32,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl PickedColor {
+    /// Hex notation (`#RRGGBB`, or `#RRGGBBAA` for translucent pixels)
+    pub fn hex(&self) -> String {
+        if self.a == 255 {
+            format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02X}{:02X}{:02X}{:02X}", self.r, self.g, self.b, self.a)
+        }
+    }
+
+    /// CSS functional notation (`rgb()`, or `rgba()` for translucent pixels)
+    pub fn css(&self) -> String {
+        if self.a == 255 {
+            format!("rgb({}, {}, {})", self.r, self.g, self.b)
+        } else {
+            format!(
+                "rgba({}, {}, {}, {:.2})",
+                self.r,
+                self.g,
+                self.b,
+                self.a as f32 / 255.0
+            )
+        }
+    }
+
+    /// Lines of the on-screen readout: hex, CSS and image coordinates
+    pub fn readout(&self) -> [String; 3] {
+        [self.hex(), self.css(), format!("{}, {}", self.x, self.y)]
+    }
+
+    /// Opaque BGRA color for drawing a swatch
+    pub fn swatch(&self) -> [u8; 4] {
+        [self.b, self.g, self.r, 255]
+    }
+}
+
+/// Map a surface position to the image and read the pixel under it
+///
+/// The image is stretched over the whole surface, so the mapping follows the
+/// current window size in both scale modes. Returns `None` outside the
+/// surface or when the pixel data is not available.
+pub fn sample(
+    image: &ImageData,
+    surface_width: u32,
+    surface_height: u32,
+    pos: (f64, f64),
+) -> Option<PickedColor> {
+    if surface_width == 0 || surface_height == 0 {
+        return None;
+    }
+    let (sx, sy) = pos;
+    if sx < 0.0 || sy < 0.0 || sx >= surface_width as f64 || sy >= surface_height as f64 {
+        return None;
+    }
+
+    let x = ((sx * image.width as f64 / surface_width as f64) as u32).min(image.width - 1);
+    let y = ((sy * image.height as f64 / surface_height as f64) as u32).min(image.height - 1);
+    let idx = (y as usize * image.width as usize + x as usize) * 4;
+    let pixel = image.rgba_data.get(idx..idx + 4)?;
+
+    Some(PickedColor {
+        x,
+        y,
+        r: pixel[2],
+        g: pixel[1],
+        b: pixel[0],
+        a: pixel[3],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 BGRA image: red, green / blue, half-transparent white
+    fn image() -> ImageData {
+        ImageData {
+            width: 2,
+            height: 2,
+            rgba_data: [
+                [0, 0, 255, 255],
+                [0, 255, 0, 255],
+                [255, 0, 0, 255],
+                [255, 255, 255, 128],
+            ]
+            .concat(),
+            scale: 1.0,
+            mipmaps: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_sample_follows_the_window_size() {
+        let image = image();
+        // Stretched to 100x50: each pixel covers 50x25
+        let picked = sample(&image, 100, 50, (10.0, 10.0)).unwrap();
+        assert_eq!(
+            (picked.x, picked.y, picked.r, picked.g, picked.b),
+            (0, 0, 255, 0, 0)
+        );
+        let picked = sample(&image, 100, 50, (50.0, 10.0)).unwrap();
+        assert_eq!((picked.x, picked.y, picked.g), (1, 0, 255));
+        let picked = sample(&image, 100, 50, (49.9, 25.0)).unwrap();
+        assert_eq!((picked.x, picked.y, picked.b), (0, 1, 255));
+        let picked = sample(&image, 100, 50, (99.99, 49.99)).unwrap();
+        assert_eq!((picked.x, picked.y, picked.a), (1, 1, 128));
+    }
+
+    #[test]
+    fn test_sample_outside_the_surface() {
+        let image = image();
+        assert!(sample(&image, 100, 50, (-0.1, 10.0)).is_none());
+        assert!(sample(&image, 100, 50, (100.0, 10.0)).is_none());
+        assert!(sample(&image, 100, 50, (10.0, 50.0)).is_none());
+        assert!(sample(&image, 0, 0, (0.0, 0.0)).is_none());
+        // Pixel data already released
+        let empty = ImageData {
+            rgba_data: Vec::new(),
+            ..image
+        };
+        assert!(sample(&empty, 100, 50, (10.0, 10.0)).is_none());
+    }
+
+    #[test]
+    fn test_formats() {
+        let opaque = PickedColor {
+            x: 0,
+            y: 0,
+            r: 255,
+            g: 16,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(opaque.hex(), "#FF1000");
+        assert_eq!(opaque.css(), "rgb(255, 16, 0)");
+        assert_eq!(opaque.swatch(), [0, 16, 255, 255]);
+
+        let translucent = PickedColor { a: 128, ..opaque };
+        assert_eq!(translucent.hex(), "#FF100080");
+        assert_eq!(translucent.css(), "rgba(255, 16, 0, 0.50)");
+        assert_eq!(translucent.swatch(), [0, 16, 255, 255]);
+    }
+
+    #[test]
+    fn test_readout() {
+        let picked = sample(&image(), 2, 2, (1.5, 1.5)).unwrap();
+        assert_eq!(
+            picked.readout(),
+            ["#FFFFFF80", "rgba(255, 255, 255, 0.50)", "1, 1"].map(String::from)
+        );
+    }
+}
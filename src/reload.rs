@@ -0,0 +1,203 @@
+// Reload module
+// Bookkeeping for background decodes: reloads requested while one runs are
+// coalesced, and a finished decode is shown, skipped or retried
+
+use std::time::Duration;
+
+/// Delay before retrying a failed reload (the file may be mid-write)
+pub const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Maximum number of reload retries before giving up
+pub const MAX_RETRIES: u32 = 3;
+
+/// Which request a background decode belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeKind {
+    /// First decode after startup (placeholder is showing)
+    Initial,
+    /// Reload of the source file
+    Reload,
+    /// Next frame of a `--stream` sequence
+    Stream,
+}
+
+/// What to do with a finished decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Show the decoded image
+    Show,
+    /// Drop it: a newer image is already shown or a newer reload is queued
+    Skip,
+    /// Read the file again after `RETRY_DELAY`; holds the attempt number
+    Retry(u32),
+    /// Give up and report the error
+    Fail,
+}
+
+/// Decode jobs of one pin: whether one runs, a reload waits behind it, and retries
+#[derive(Debug, Default)]
+pub struct Reloads {
+    in_flight: bool,
+    queued: bool,
+    retries: u32,
+}
+
+impl Reloads {
+    /// A new reload request (signal or file change) gets a fresh set of retries
+    ///
+    /// Returns whether the decode should start now.
+    pub fn request(&mut self) -> bool {
+        self.retries = 0;
+        self.try_start()
+    }
+
+    /// Whether a reload may start now; otherwise it waits for the running decode
+    ///
+    /// Any number of waiting requests is served by one reload.
+    pub fn try_start(&mut self) -> bool {
+        if self.in_flight {
+            self.queued = true;
+            return false;
+        }
+        true
+    }
+
+    /// A decode job is running on a worker thread
+    pub fn started(&mut self) {
+        self.in_flight = true;
+    }
+
+    /// Decide what to do with a finished decode (`ok` if it produced an image)
+    pub fn finish(&mut self, kind: DecodeKind, ok: bool, image_loaded: bool) -> Outcome {
+        // Stream frames are decoded by the stream reader, not a tracked decode job
+        if kind != DecodeKind::Stream {
+            self.in_flight = false;
+        }
+        match ok {
+            true if kind == DecodeKind::Initial && image_loaded => Outcome::Skip,
+            true => {
+                self.retries = 0;
+                Outcome::Show
+            }
+            false if self.queued => Outcome::Skip,
+            false if kind == DecodeKind::Reload && self.retries < MAX_RETRIES => {
+                self.retries += 1;
+                Outcome::Retry(self.retries)
+            }
+            false => Outcome::Fail,
+        }
+    }
+
+    /// Take the reload that was waiting for the finished decode, if any
+    pub fn take_queued(&mut self) -> bool {
+        std::mem::take(&mut self.queued)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_during_a_decode_coalesce() {
+        let mut reloads = Reloads::default();
+        assert!(reloads.request());
+        reloads.started();
+        // A burst of changes while the file is being decoded
+        assert!(!reloads.request());
+        assert!(!reloads.request());
+        assert!(!reloads.try_start());
+
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, true, true),
+            Outcome::Show
+        );
+        // One reload serves them all
+        assert!(reloads.take_queued());
+        assert!(!reloads.take_queued());
+        assert!(reloads.try_start());
+    }
+
+    #[test]
+    fn test_failed_reload_retries_then_gives_up() {
+        let mut reloads = Reloads::default();
+        assert!(reloads.request());
+        for attempt in 1..=MAX_RETRIES {
+            reloads.started();
+            assert_eq!(
+                reloads.finish(DecodeKind::Reload, false, true),
+                Outcome::Retry(attempt)
+            );
+            assert!(reloads.try_start());
+        }
+        reloads.started();
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, false, true),
+            Outcome::Fail
+        );
+
+        // A new request starts counting again
+        assert!(reloads.request());
+        reloads.started();
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, false, true),
+            Outcome::Retry(1)
+        );
+    }
+
+    #[test]
+    fn test_success_resets_retries() {
+        let mut reloads = Reloads::default();
+        reloads.started();
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, false, true),
+            Outcome::Retry(1)
+        );
+        reloads.started();
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, true, true),
+            Outcome::Show
+        );
+        reloads.started();
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, false, true),
+            Outcome::Retry(1)
+        );
+    }
+
+    #[test]
+    fn test_failure_with_a_queued_reload_is_skipped() {
+        let mut reloads = Reloads::default();
+        reloads.started();
+        assert!(!reloads.request());
+        // The queued reload reads the file again, so this failure is not retried
+        assert_eq!(
+            reloads.finish(DecodeKind::Reload, false, true),
+            Outcome::Skip
+        );
+        assert!(reloads.take_queued());
+    }
+
+    #[test]
+    fn test_initial_and_stream_decodes() {
+        let mut reloads = Reloads::default();
+        reloads.started();
+        // A stream frame arrived first, so the initial decode is stale
+        assert_eq!(
+            reloads.finish(DecodeKind::Initial, true, true),
+            Outcome::Skip
+        );
+        assert_eq!(
+            reloads.finish(DecodeKind::Initial, false, false),
+            Outcome::Fail
+        );
+
+        // Stream frames do not end a running reload, nor are they retried
+        reloads.started();
+        assert_eq!(
+            reloads.finish(DecodeKind::Stream, false, true),
+            Outcome::Fail
+        );
+        assert!(!reloads.try_start());
+    }
+}
@@ -0,0 +1,354 @@
+// Image resampling module
+// CPU implementations of the selectable scaling filters (mirrored in the GPU shader)
+
+use crate::cpu_renderer::par_row_bands;
+use clap::ValueEnum;
+use std::f32::consts::PI;
+
+/// Resampling filter used when the image is scaled to the window size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FilterMode {
+    /// Nearest neighbor (crisp pixel art and code screenshots)
+    Nearest,
+    /// Bilinear (triangle) filter
+    #[default]
+    Bilinear,
+    /// Bicubic (Catmull-Rom) filter
+    Bicubic,
+    /// Lanczos filter with a 3-lobe window
+    Lanczos,
+}
+
+impl FilterMode {
+    /// All filters in menu order
+    pub const ALL: [FilterMode; 4] = [
+        FilterMode::Nearest,
+        FilterMode::Bilinear,
+        FilterMode::Bicubic,
+        FilterMode::Lanczos,
+    ];
+
+    /// Human readable name for menus and logs
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterMode::Nearest => "Nearest",
+            FilterMode::Bilinear => "Bilinear",
+            FilterMode::Bicubic => "Bicubic",
+            FilterMode::Lanczos => "Lanczos",
+        }
+    }
+
+    /// Value passed to the fragment shader (must match `shader.wgsl`)
+    pub fn shader_id(self) -> u32 {
+        match self {
+            FilterMode::Nearest => 0,
+            FilterMode::Bilinear => 1,
+            FilterMode::Bicubic => 2,
+            FilterMode::Lanczos => 3,
+        }
+    }
+
+    /// Kernel radius in source pixels at 1:1 scale
+    fn support(self) -> f32 {
+        match self {
+            FilterMode::Nearest => 0.5,
+            FilterMode::Bilinear => 1.0,
+            FilterMode::Bicubic => 2.0,
+            FilterMode::Lanczos => 3.0,
+        }
+    }
+
+    /// Evaluate the filter kernel at distance `x`
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            FilterMode::Nearest => {
+                if x < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            FilterMode::Bilinear => (1.0 - x).max(0.0),
+            FilterMode::Bicubic => {
+                // Catmull-Rom (B = 0, C = 0.5)
+                if x < 1.0 {
+                    1.5 * x * x * x - 2.5 * x * x + 1.0
+                } else if x < 2.0 {
+                    -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                } else {
+                    0.0
+                }
+            }
+            FilterMode::Lanczos => {
+                if x < f32::EPSILON {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Fractional bits of the fixed-point filter weights
+const PRECISION_BITS: u32 = 14;
+
+/// Precomputed fixed-point filter taps for one output coordinate
+struct Contribution {
+    start: usize,
+    weights: Vec<i32>,
+}
+
+/// Compute the filter taps mapping `src_len` samples onto `dst_len` samples
+fn contributions(filter: FilterMode, src_len: u32, dst_len: u32) -> Vec<Contribution> {
+    let scale = src_len as f32 / dst_len as f32;
+    // Widen the kernel when downscaling so every source pixel contributes
+    let filter_scale = scale.max(1.0);
+    let support = filter.support() * filter_scale;
+    let one = (1i32 << PRECISION_BITS) as f32;
+
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let start = ((center - support).floor().max(0.0)) as usize;
+            let end = ((center + support).ceil() as usize).min(src_len as usize);
+
+            let weights: Vec<f32> = (start..end)
+                .map(|j| filter.weight((j as f32 + 0.5 - center) / filter_scale))
+                .collect();
+            let total: f32 = weights.iter().sum();
+
+            if total.abs() > f32::EPSILON {
+                let weights = weights
+                    .iter()
+                    .map(|w| (w / total * one).round() as i32)
+                    .collect();
+                Contribution { start, weights }
+            } else {
+                // Degenerate kernel: fall back to the nearest source sample
+                let nearest = (center as usize).min(src_len as usize - 1);
+                Contribution {
+                    start: nearest,
+                    weights: vec![1 << PRECISION_BITS],
+                }
+            }
+        })
+        .collect()
+}
+
+/// Convert a fixed-point accumulator back to an 8-bit channel value
+#[inline]
+fn to_u8(acc: i32) -> u8 {
+    ((acc + (1 << (PRECISION_BITS - 1))) >> PRECISION_BITS).clamp(0, 255) as u8
+}
+
+/// Resample a 4-channel image into `dst` using a separable filter
+///
+/// Channel order is preserved, so this works for both BGRA and RGBA data.
+/// Both passes run in row bands across threads with fixed-point weights.
+pub fn resample(
+    src: &[u8],
+    src_width: u32,
+    src_height: u32,
+    dst: &mut [u8],
+    dst_width: u32,
+    dst_height: u32,
+    filter: FilterMode,
+) {
+    if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+        return;
+    }
+
+    let dst = &mut dst[..dst_width as usize * dst_height as usize * 4];
+
+    if filter == FilterMode::Nearest {
+        resample_nearest(src, src_width, src_height, dst, dst_width, dst_height);
+        return;
+    }
+
+    let horizontal = contributions(filter, src_width, dst_width);
+    let vertical = contributions(filter, src_height, dst_height);
+
+    // Horizontal pass: src_width x src_height -> dst_width x src_height
+    let src_stride = src_width as usize * 4;
+    let temp_stride = dst_width as usize * 4;
+    let mut temp = vec![0u8; temp_stride * src_height as usize];
+    par_row_bands(&mut temp, temp_stride, |first_row, band| {
+        for (i, temp_row) in band.chunks_exact_mut(temp_stride).enumerate() {
+            let src_row = &src[(first_row + i) * src_stride..][..src_stride];
+            for (contrib, out) in horizontal.iter().zip(temp_row.chunks_exact_mut(4)) {
+                let taps = &src_row[contrib.start * 4..][..contrib.weights.len() * 4];
+                let mut acc = [0i32; 4];
+                for (px, &w) in taps.chunks_exact(4).zip(&contrib.weights) {
+                    acc[0] += px[0] as i32 * w;
+                    acc[1] += px[1] as i32 * w;
+                    acc[2] += px[2] as i32 * w;
+                    acc[3] += px[3] as i32 * w;
+                }
+                out[0] = to_u8(acc[0]);
+                out[1] = to_u8(acc[1]);
+                out[2] = to_u8(acc[2]);
+                out[3] = to_u8(acc[3]);
+            }
+        }
+    });
+
+    // Vertical pass: dst_width x src_height -> dst_width x dst_height
+    // Accumulate whole rows at a time so the inner loop vectorizes
+    par_row_bands(dst, temp_stride, |first_row, band| {
+        let mut acc = vec![0i32; temp_stride];
+        for (i, dst_row) in band.chunks_exact_mut(temp_stride).enumerate() {
+            let contrib = &vertical[first_row + i];
+            acc.fill(0);
+            for (k, &w) in contrib.weights.iter().enumerate() {
+                let temp_row = &temp[(contrib.start + k) * temp_stride..][..temp_stride];
+                for (a, &v) in acc.iter_mut().zip(temp_row) {
+                    *a += v as i32 * w;
+                }
+            }
+            for (out, &a) in dst_row.iter_mut().zip(&acc) {
+                *out = to_u8(a);
+            }
+        }
+    });
+}
+
+/// Nearest-neighbor resampling using pixel-center mapping
+fn resample_nearest(
+    src: &[u8],
+    src_width: u32,
+    src_height: u32,
+    dst: &mut [u8],
+    dst_width: u32,
+    dst_height: u32,
+) {
+    let x_lut: Vec<usize> = (0..dst_width)
+        .map(|x| {
+            let sx = ((x as u64 * 2 + 1) * src_width as u64 / (dst_width as u64 * 2)) as u32;
+            sx.min(src_width - 1) as usize * 4
+        })
+        .collect();
+
+    let src_stride = src_width as usize * 4;
+    let row_bytes = dst_width as usize * 4;
+    par_row_bands(dst, row_bytes, |first_row, band| {
+        for (i, dst_row) in band.chunks_exact_mut(row_bytes).enumerate() {
+            let y = (first_row + i) as u64;
+            let sy = ((y * 2 + 1) * src_height as u64 / (dst_height as u64 * 2)) as u32;
+            let src_row = &src[sy.min(src_height - 1) as usize * src_stride..][..src_stride];
+            for (out, &sx) in dst_row.chunks_exact_mut(4).zip(&x_lut) {
+                out.copy_from_slice(&src_row[sx..sx + 4]);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width`x`height` image whose pixels differ from their neighbours
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [
+                    (x * 37 % 256) as u8,
+                    (y * 53 % 256) as u8,
+                    ((x + y) * 11 % 256) as u8,
+                    255,
+                ]
+            })
+            .collect()
+    }
+
+    fn resampled(
+        src: &[u8],
+        src_size: (u32, u32),
+        dst_size: (u32, u32),
+        filter: FilterMode,
+    ) -> Vec<u8> {
+        let mut dst = vec![0u8; (dst_size.0 * dst_size.1 * 4) as usize];
+        resample(
+            src, src_size.0, src_size.1, &mut dst, dst_size.0, dst_size.1, filter,
+        );
+        dst
+    }
+
+    #[test]
+    fn test_weights_are_normalized() {
+        let one = 1i32 << PRECISION_BITS;
+        for filter in FilterMode::ALL {
+            for (src_len, dst_len) in [(10, 10), (10, 3), (3, 10), (1000, 7)] {
+                for contrib in contributions(filter, src_len, dst_len) {
+                    let total: i32 = contrib.weights.iter().sum();
+                    // Each weight is rounded on its own
+                    assert!(
+                        (total - one).abs() <= contrib.weights.len() as i32,
+                        "{:?} {}->{}: {}",
+                        filter,
+                        src_len,
+                        dst_len,
+                        total
+                    );
+                    assert!(contrib.start + contrib.weights.len() <= src_len as usize);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_size_is_identity() {
+        let src = gradient(9, 7);
+        for filter in FilterMode::ALL {
+            assert_eq!(resampled(&src, (9, 7), (9, 7), filter), src, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_flat_color_stays_flat() {
+        let src = [40u8, 120, 200, 255].repeat(16 * 12);
+        for filter in FilterMode::ALL {
+            for size in [(5, 4), (40, 30), (1, 1)] {
+                let dst = resampled(&src, (16, 12), size, filter);
+                for pixel in dst.chunks_exact(4) {
+                    assert_eq!(pixel, [40, 120, 200, 255], "{:?} {:?}", filter, size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_repeats_pixels() {
+        let src = gradient(2, 2);
+        let dst = resampled(&src, (2, 2), (4, 4), FilterMode::Nearest);
+        for y in 0..4 {
+            for x in 0..4 {
+                let s = ((y / 2) * 2 + x / 2) * 4;
+                let d = (y * 4 + x) * 4;
+                assert_eq!(dst[d..d + 4], src[s..s + 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ringing_is_clamped() {
+        assert_eq!(to_u8(-5 << PRECISION_BITS), 0);
+        assert_eq!(to_u8(300 << PRECISION_BITS), 255);
+        // Rounds to nearest
+        assert_eq!(
+            to_u8((10 << PRECISION_BITS) + (1 << (PRECISION_BITS - 1))),
+            11
+        );
+        // A sharp edge upscaled with Lanczos overshoots and must saturate, not wrap
+        let mut src = [0u8, 0, 0, 255].repeat(4);
+        src.extend([255u8, 255, 255, 255].repeat(4));
+        let dst = resampled(&src, (8, 1), (32, 1), FilterMode::Lanczos);
+        assert!(dst.chunks_exact(4).take(8).all(|pixel| pixel[0] < 16));
+        assert!(dst.chunks_exact(4).skip(24).all(|pixel| pixel[0] > 239));
+    }
+}
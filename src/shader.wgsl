@@ -25,11 +25,126 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+struct Uniforms {
+    opacity: f32,
+    // 0 = nearest, 1 = bilinear, 2 = bicubic, 3 = lanczos (see resample.rs)
+    filter_mode: u32,
+    // Corner radius and border width in pixels (see decoration.rs); 0 for overlays
+    radius: f32,
+    border_width: f32,
+    // Size of the quad in pixels
+    size: vec2<f32>,
+    // sRGB, straight alpha
+    border_color: vec4<f32>,
+}
+
 @group(1) @binding(0)
-var<uniform> opacity: f32;
+var<uniform> uniforms: Uniforms;
+
+const PI: f32 = 3.14159265;
+
+// Catmull-Rom cubic kernel
+fn cubic_weight(x: f32) -> f32 {
+    let ax = abs(x);
+    if ax < 1.0 {
+        return 1.5 * ax * ax * ax - 2.5 * ax * ax + 1.0;
+    } else if ax < 2.0 {
+        return -0.5 * ax * ax * ax + 2.5 * ax * ax - 4.0 * ax + 2.0;
+    }
+    return 0.0;
+}
+
+// Lanczos kernel with a 3-lobe window
+fn lanczos_weight(x: f32) -> f32 {
+    let ax = abs(x);
+    if ax < 0.0001 {
+        return 1.0;
+    } else if ax < 3.0 {
+        let px = PI * ax;
+        return 3.0 * sin(px) * sin(px / 3.0) / (px * px);
+    }
+    return 0.0;
+}
+
+fn kernel_weight(x: f32, mode: u32) -> f32 {
+    if mode == 3u {
+        return lanczos_weight(x);
+    }
+    return cubic_weight(x);
+}
+
+// Convolve the given mip level with a separable kernel of the given radius
+//
+// `scale` (1 to 2) is how many texels of the level one pixel covers; the kernel
+// is stretched by it so minifying between two mip levels does not alias.
+fn sample_kernel(uv: vec2<f32>, level: i32, radius: f32, scale: f32, mode: u32) -> vec4<f32> {
+    let dims = vec2<i32>(textureDimensions(t_diffuse, level));
+    let pos = uv * vec2<f32>(dims) - 0.5;
+    let base = floor(pos);
+    let frac = pos - base;
+    let reach = i32(ceil(radius * scale));
+
+    var acc = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var j = 1 - reach; j <= reach; j++) {
+        let wy = kernel_weight((frac.y - f32(j)) / scale, mode);
+        for (var i = 1 - reach; i <= reach; i++) {
+            let w = kernel_weight((frac.x - f32(i)) / scale, mode) * wy;
+            let p = clamp(vec2<i32>(base) + vec2<i32>(i, j), vec2<i32>(0), dims - 1);
+            acc += textureLoad(t_diffuse, p, level) * w;
+            total += w;
+        }
+    }
+    return clamp(acc / total, vec4<f32>(0.0), vec4<f32>(1.0));
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    return vec4<f32>(color.rgb, color.a * opacity);
+    // Hardware trilinear sample and mip level must be computed in uniform control flow
+    let linear = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let texel = in.tex_coords * vec2<f32>(textureDimensions(t_diffuse, 0));
+    let footprint = max(length(dpdx(texel)), length(dpdy(texel)));
+    let max_level = i32(textureNumLevels(t_diffuse)) - 1;
+    let level = clamp(i32(floor(log2(max(footprint, 1.0)))), 0, max_level);
+    let scale = clamp(footprint / exp2(f32(level)), 1.0, 2.0);
+
+    var color: vec4<f32>;
+    switch uniforms.filter_mode {
+        case 0u: {
+            let dims = vec2<i32>(textureDimensions(t_diffuse, level));
+            let p = clamp(vec2<i32>(in.tex_coords * vec2<f32>(dims)), vec2<i32>(0), dims - 1);
+            color = textureLoad(t_diffuse, p, level);
+        }
+        case 2u: {
+            color = sample_kernel(in.tex_coords, level, 2.0, scale, 2u);
+        }
+        case 3u: {
+            color = sample_kernel(in.tex_coords, level, 3.0, scale, 3u);
+        }
+        default: {
+            color = linear;
+        }
+    }
+    color.a *= uniforms.opacity;
+
+    // Rounded corners and border, matching Decoration::mask on the CPU
+    if uniforms.radius > 0.0 || uniforms.border_width > 0.0 {
+        let d = rounded_rect_distance(
+            in.tex_coords * uniforms.size,
+            vec4<f32>(vec2<f32>(0.0), uniforms.size),
+            uniforms.radius,
+        );
+        let border = clamp(d + uniforms.border_width + 0.5, 0.0, 1.0) * step(0.5, uniforms.border_width);
+        let border_alpha = uniforms.border_color.a * border;
+        let out_alpha = border_alpha + color.a * (1.0 - border_alpha);
+        if out_alpha > 0.0 {
+            let border_rgb = srgb_to_linear(uniforms.border_color.rgb);
+            color = vec4<f32>(
+                (border_rgb * border_alpha + color.rgb * color.a * (1.0 - border_alpha)) / out_alpha,
+                out_alpha,
+            );
+        }
+        color.a *= clamp(0.5 - d, 0.0, 1.0);
+    }
+    return color;
 }
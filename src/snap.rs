@@ -0,0 +1,503 @@
+// Snap module
+// Magnetic snapping of the pin to the output, its usable area and other pins while moving or resizing
+
+use crate::config::Config;
+use crate::cpu_renderer::Rect;
+use anyhow::{Context, Result};
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Distance in pixels within which edges snap, unless configured
+pub const DEFAULT_THRESHOLD: u32 = 12;
+
+/// Directory below `$XDG_RUNTIME_DIR` where running pins publish their geometry
+const REGISTRY_DIR: &str = "rspin/pins";
+
+/// Process name a live registry entry must belong to (`/proc/<pid>/comm`)
+const PROCESS_NAME: &str = "rspin";
+
+/// Snap threshold set in the `[snap]` section (0 disables snapping)
+pub fn threshold_from_config(config: &Config) -> u32 {
+    config
+        .parse_value("snap", "threshold")
+        .unwrap_or(DEFAULT_THRESHOLD)
+}
+
+/// What part of the pin a guide attracts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Edge,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Guide {
+    position: i32,
+    kind: Kind,
+}
+
+/// Edges of the pin that follow the pointer during a resize
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Edges {
+    pub left: bool,
+    pub top: bool,
+    pub right: bool,
+    pub bottom: bool,
+}
+
+/// Lines the pin is drawn to, in margin coordinates
+#[derive(Debug, Clone, Default)]
+pub struct Guides {
+    /// x positions of vertical lines
+    vertical: Vec<Guide>,
+    /// y positions of horizontal lines
+    horizontal: Vec<Guide>,
+}
+
+impl Guides {
+    /// Edges of an area the pin is kept in line with, such as the output
+    pub fn add_area(&mut self, area: Rect) {
+        for position in [area.x, area.x + area.width] {
+            self.vertical.push(Guide {
+                position,
+                kind: Kind::Edge,
+            });
+        }
+        for position in [area.y, area.y + area.height] {
+            self.horizontal.push(Guide {
+                position,
+                kind: Kind::Edge,
+            });
+        }
+    }
+
+    /// Edges and center of another pin
+    pub fn add_pin(&mut self, pin: Rect) {
+        self.add_area(pin);
+        self.vertical.push(Guide {
+            position: pin.x + pin.width / 2,
+            kind: Kind::Center,
+        });
+        self.horizontal.push(Guide {
+            position: pin.y + pin.height / 2,
+            kind: Kind::Center,
+        });
+    }
+
+    /// Position of a pin moved to `rect`, with an edge or its center pulled onto the
+    /// nearest guide within `threshold` on each axis
+    pub fn snap_move(&self, rect: Rect, threshold: u32) -> (i32, i32) {
+        let points = |start: i32, size: i32| {
+            [
+                (start, Kind::Edge),
+                (start + size, Kind::Edge),
+                (start + size / 2, Kind::Center),
+            ]
+        };
+        let dx = pull(&self.vertical, &points(rect.x, rect.width), threshold);
+        let dy = pull(&self.horizontal, &points(rect.y, rect.height), threshold);
+        (
+            rect.x + dx.map_or(0, |(d, _)| d),
+            rect.y + dy.map_or(0, |(d, _)| d),
+        )
+    }
+
+    /// A pin resized to `rect` with its `moving` edges pulled onto the nearest guides
+    ///
+    /// With an `aspect` ratio only the closest edge snaps and the other side follows it,
+    /// growing away from `anchor`: the fraction of the pin's width and height that stays
+    /// in place (0 = left/top, 1 = right/bottom).
+    pub fn snap_resize(
+        &self,
+        rect: Rect,
+        moving: Edges,
+        aspect: Option<f32>,
+        anchor: (f32, f32),
+        threshold: u32,
+    ) -> Rect {
+        let x = resize_axis(
+            &self.vertical,
+            (rect.x, rect.width),
+            (moving.left, moving.right),
+            threshold,
+        );
+        let y = resize_axis(
+            &self.horizontal,
+            (rect.y, rect.height),
+            (moving.top, moving.bottom),
+            threshold,
+        );
+
+        let mut snapped = rect;
+        match aspect.filter(|aspect| *aspect > 0.0) {
+            Some(aspect) => {
+                // Snap whichever axis needs the smaller pull; the other one follows
+                let x_first = match (x, y) {
+                    (Some((_, _, dx)), Some((_, _, dy))) => dx <= dy,
+                    _ => x.is_some(),
+                };
+                if let (true, Some((start, size, _))) = (x_first, x) {
+                    let height = (size as f32 / aspect).round() as i32;
+                    snapped.y += ((rect.height - height) as f32 * anchor.1).round() as i32;
+                    (snapped.x, snapped.width, snapped.height) = (start, size, height);
+                } else if let Some((start, size, _)) = y {
+                    let width = (size as f32 * aspect).round() as i32;
+                    snapped.x += ((rect.width - width) as f32 * anchor.0).round() as i32;
+                    (snapped.y, snapped.height, snapped.width) = (start, size, width);
+                }
+            }
+            None => {
+                if let Some((start, size, _)) = x {
+                    (snapped.x, snapped.width) = (start, size);
+                }
+                if let Some((start, size, _)) = y {
+                    (snapped.y, snapped.height) = (start, size);
+                }
+            }
+        }
+        snapped
+    }
+}
+
+/// Start and size of a span whose moving ends are pulled onto a guide, with the
+/// distance pulled, or `None` if no guide is close enough
+fn resize_axis(
+    guides: &[Guide],
+    (start, size): (i32, i32),
+    (low, high): (bool, bool),
+    threshold: u32,
+) -> Option<(i32, i32, u32)> {
+    let mut points = Vec::with_capacity(2);
+    if low {
+        points.push((start, Kind::Edge));
+    }
+    if high {
+        points.push((start + size, Kind::Edge));
+    }
+    let (d, i) = pull(guides, &points, threshold)?;
+    // The end that snapped moves; the opposite one stays
+    Some(if low && i == 0 {
+        (start + d, size - d, d.unsigned_abs())
+    } else {
+        (start, size + d, d.unsigned_abs())
+    })
+}
+
+/// Smallest move within `threshold` that puts one of `points` on a guide of its kind,
+/// with the index of that point
+fn pull(guides: &[Guide], points: &[(i32, Kind)], threshold: u32) -> Option<(i32, usize)> {
+    points
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &(point, kind))| {
+            guides
+                .iter()
+                .filter(move |guide| guide.kind == kind)
+                .map(move |guide| (guide.position - point, i))
+        })
+        .filter(|(d, _)| d.unsigned_abs() <= threshold)
+        .min_by_key(|(d, _)| d.unsigned_abs())
+}
+
+/// Geometry of the running pins, shared through one file per process in the runtime directory
+///
+/// Each file holds "X Y WIDTH HEIGHT OUTPUT" in margin coordinates. Files of processes
+/// that are gone, or whose PID now belongs to another program, are removed when the
+/// others are listed.
+pub struct Registry {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Registry {
+    /// Registry in `$XDG_RUNTIME_DIR`, or `None` without one
+    pub fn open() -> Option<Self> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+        let dir = PathBuf::from(runtime_dir).join(REGISTRY_DIR);
+        let path = dir.join(std::process::id().to_string());
+        Some(Self { dir, path })
+    }
+
+    /// Publish this pin's rectangle on `output`
+    pub fn publish(&self, output: &str, rect: Rect) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        // Written aside and renamed, so readers never see half a line
+        let temp = self.path.with_extension("tmp");
+        let line = format!(
+            "{} {} {} {} {}\n",
+            rect.x, rect.y, rect.width, rect.height, output
+        );
+        fs::write(&temp, line).with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Rectangles of the other pins on `output`
+    pub fn others(&self, output: &str) -> Vec<Rect> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut pins = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(pid) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if path == self.path || pid.parse::<u32>().is_err() {
+                continue;
+            }
+            if !is_pin_process(pid) {
+                debug!("Removing stale pin entry {}", path.display());
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            match parse_entry(text.trim_end()) {
+                Some((rect, pin_output)) if pin_output == output => pins.push(rect),
+                Some(_) => {}
+                None => debug!("Ignoring malformed pin entry {}", path.display()),
+            }
+        }
+        pins
+    }
+}
+
+impl Drop for Registry {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether `pid` is a running rspin, rather than gone or reused by another program
+fn is_pin_process(pid: &str) -> bool {
+    fs::read_to_string(Path::new("/proc").join(pid).join("comm"))
+        .is_ok_and(|comm| comm.trim_end() == PROCESS_NAME)
+}
+
+/// Parse a registry line into the pin's rectangle and output name
+fn parse_entry(line: &str) -> Option<(Rect, &str)> {
+    let mut fields = line.splitn(5, ' ');
+    let mut number = || fields.next()?.parse().ok();
+    let rect = Rect::new(number()?, number()?, number()?, number()?);
+    Some((rect, fields.next().unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Guides of a 1000x800 output
+    fn output() -> Guides {
+        let mut guides = Guides::default();
+        guides.add_area(Rect::new(0, 0, 1000, 800));
+        guides
+    }
+
+    fn edges(left: bool, top: bool, right: bool, bottom: bool) -> Edges {
+        Edges {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn test_move_snaps_nearest_edge() {
+        let guides = output();
+        // Left edge 7 away, right edge 3 away: the right one wins
+        assert_eq!(guides.snap_move(Rect::new(7, 0, 990, 50), 12), (10, 0));
+        // Out of reach on x, within reach of the bottom edge on y
+        assert_eq!(
+            guides.snap_move(Rect::new(400, 745, 100, 50), 12),
+            (400, 750)
+        );
+        assert_eq!(
+            guides.snap_move(Rect::new(400, 300, 100, 50), 12),
+            (400, 300)
+        );
+    }
+
+    #[test]
+    fn test_move_snaps_to_pin_center() {
+        let mut guides = Guides::default();
+        guides.add_pin(Rect::new(200, 200, 100, 100));
+        // Centers line up: 243 -> 250
+        assert_eq!(
+            guides.snap_move(Rect::new(143, 500, 200, 20), 12),
+            (150, 500)
+        );
+    }
+
+    #[test]
+    fn test_threshold_is_inclusive() {
+        let guides = output();
+        assert_eq!(guides.snap_move(Rect::new(12, 300, 100, 50), 12), (0, 300));
+        assert_eq!(guides.snap_move(Rect::new(13, 300, 100, 50), 12), (13, 300));
+        assert_eq!(guides.snap_move(Rect::new(5, 300, 100, 50), 0), (5, 300));
+    }
+
+    #[test]
+    fn test_ties_go_to_the_first_point() {
+        let guides = output().vertical;
+        // Two guides equally far from one point
+        let points = [(500, Kind::Edge)];
+        let mut wide = Guides::default();
+        wide.add_area(Rect::new(490, 0, 20, 20));
+        assert_eq!(pull(&wide.vertical, &points, 12), Some((-10, 0)));
+        // Two points equally far from their guides
+        let points = [(5, Kind::Edge), (995, Kind::Edge)];
+        assert_eq!(pull(&guides, &points, 12), Some((-5, 0)));
+    }
+
+    #[test]
+    fn test_pull_matches_kinds() {
+        let mut guides = Guides::default();
+        guides.add_pin(Rect::new(0, 0, 100, 100));
+        // Only the pin's center attracts a center point
+        assert_eq!(
+            pull(&guides.vertical, &[(45, Kind::Center)], 12),
+            Some((5, 0))
+        );
+        assert_eq!(pull(&guides.vertical, &[(95, Kind::Center)], 4), None);
+        assert_eq!(
+            pull(&guides.vertical, &[(95, Kind::Edge)], 12),
+            Some((5, 0))
+        );
+    }
+
+    #[test]
+    fn test_resize_axis_moves_the_snapped_end() {
+        let guides = output().vertical;
+        // Low end snaps: the start moves and the high end stays
+        assert_eq!(
+            resize_axis(&guides, (3, 990), (true, true), 12),
+            Some((0, 993, 3))
+        );
+        // High end snaps: only the size changes
+        assert_eq!(
+            resize_axis(&guides, (8, 990), (true, true), 12),
+            Some((8, 992, 2))
+        );
+        // A tie goes to the low end
+        assert_eq!(
+            resize_axis(&guides, (5, 990), (true, true), 12),
+            Some((0, 995, 5))
+        );
+        // Ends that are not moving never snap
+        assert_eq!(resize_axis(&guides, (3, 500), (false, true), 12), None);
+    }
+
+    #[test]
+    fn test_resize_snaps_both_axes_without_aspect() {
+        let guides = output();
+        let rect = Rect::new(4, 100, 300, 695);
+        let snapped =
+            guides.snap_resize(rect, edges(true, false, false, true), None, (0.0, 0.0), 12);
+        assert_eq!(snapped, Rect::new(0, 100, 304, 700));
+    }
+
+    #[test]
+    fn test_resize_with_aspect_follows_the_closest_edge() {
+        let guides = output();
+        let moving = edges(false, false, true, true);
+
+        // Only x is in reach: the height follows, the top stays with anchor (0, 0)
+        let rect = Rect::new(600, 500, 395, 198);
+        let snapped = guides.snap_resize(rect, moving, Some(2.0), (0.0, 0.0), 12);
+        assert_eq!(snapped, Rect::new(600, 500, 400, 200));
+        // The same with the bottom anchored: the top moves up by the growth
+        let snapped = guides.snap_resize(rect, moving, Some(2.0), (0.0, 1.0), 12);
+        assert_eq!(snapped, Rect::new(600, 498, 400, 200));
+
+        // Only y is in reach: the width follows, the right side stays with anchor (1, 0)
+        let rect = Rect::new(100, 100, 300, 696);
+        let snapped = guides.snap_resize(rect, moving, Some(0.5), (1.0, 0.0), 12);
+        assert_eq!(snapped, Rect::new(50, 100, 350, 700));
+
+        // Both in reach: the smaller pull (y, 3 against 8) wins
+        let rect = Rect::new(600, 100, 392, 697);
+        let snapped = guides.snap_resize(rect, moving, Some(2.0), (0.0, 0.0), 12);
+        assert_eq!(snapped, Rect::new(600, 100, 1400, 700));
+
+        // Nothing in reach leaves the rectangle alone
+        let rect = Rect::new(100, 100, 300, 150);
+        assert_eq!(
+            guides.snap_resize(rect, moving, Some(2.0), (0.0, 0.0), 12),
+            rect
+        );
+    }
+
+    #[test]
+    fn test_parse_entry_reads_valid_lines() {
+        assert_eq!(
+            parse_entry("-5 10 300 200 DP-1"),
+            Some((Rect::new(-5, 10, 300, 200), "DP-1"))
+        );
+        // The output name is the rest of the line
+        assert_eq!(
+            parse_entry("0 0 1 1 Dell U2720Q"),
+            Some((Rect::new(0, 0, 1, 1), "Dell U2720Q"))
+        );
+        assert_eq!(parse_entry("1 2 3 4"), Some((Rect::new(1, 2, 3, 4), "")));
+    }
+
+    #[test]
+    fn test_parse_entry_rejects_malformed_lines() {
+        assert_eq!(parse_entry(""), None);
+        assert_eq!(parse_entry("1 2 3"), None);
+        assert_eq!(parse_entry("a 2 3 4 DP-1"), None);
+        assert_eq!(parse_entry("1.5 2 3 4 DP-1"), None);
+        assert_eq!(parse_entry("1  2 3 4 DP-1"), None);
+    }
+
+    /// Registry in a fresh directory below the system temp dir
+    fn temp_registry(name: &str) -> Registry {
+        let dir = std::env::temp_dir().join(format!("rspin-snap-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Registry {
+            path: dir.join("self"),
+            dir,
+        }
+    }
+
+    #[test]
+    fn test_registry_publishes_and_skips_itself() {
+        let registry = temp_registry("publish");
+        registry
+            .publish("DP-1", Rect::new(10, 20, 300, 200))
+            .unwrap();
+        let text = fs::read_to_string(&registry.path).unwrap();
+        assert_eq!(text, "10 20 300 200 DP-1\n");
+        assert!(registry.others("DP-1").is_empty());
+        let dir = registry.dir.clone();
+        drop(registry);
+        assert!(!dir.join("self").exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_registry_removes_stale_and_reused_entries() {
+        let registry = temp_registry("stale");
+        fs::create_dir_all(&registry.dir).unwrap();
+        // No such process
+        let gone = registry.dir.join(u32::MAX.to_string());
+        fs::write(&gone, "0 0 10 10 DP-1\n").unwrap();
+        // A live process that is not rspin (the test binary has another name)
+        let reused = registry.dir.join(std::process::id().to_string());
+        fs::write(&reused, "0 0 10 10 DP-1\n").unwrap();
+        // Not a PID at all: ignored and left alone
+        let other = registry.dir.join("notes");
+        fs::write(&other, "0 0 10 10 DP-1\n").unwrap();
+
+        assert!(registry.others("DP-1").is_empty());
+        assert!(!gone.exists());
+        assert!(!reused.exists());
+        assert!(other.exists());
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+}
@@ -0,0 +1,380 @@
+// Image stream module
+// Splits a byte stream (stdin in --stream mode) into consecutive encoded images
+
+use anyhow::{bail, Context, Result};
+use std::io::{ErrorKind, Read};
+
+/// PNG file signature
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// JPEG start-of-image marker
+const JPEG_SOI: &[u8] = &[0xFF, 0xD8];
+
+/// QOI header magic
+const QOI_MAGIC: &[u8] = b"qoif";
+
+/// QOI header size in bytes
+const QOI_HEADER_SIZE: usize = 14;
+
+/// Padding that terminates a QOI stream
+const QOI_END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// Bytes requested from the reader per read call
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest frame accepted, so garbage input fails instead of filling memory
+const MAX_FRAME_SIZE: usize = 512 * 1024 * 1024;
+
+/// Progress of the scan through the frame at the start of the buffer
+///
+/// Positions are offsets into the buffer, so scanning resumes where it
+/// stopped when more data arrives instead of starting over.
+#[derive(Debug, Clone, Copy)]
+enum Scan {
+    /// Format not detected yet
+    Start,
+    /// PNG chunk headers starting at `pos`
+    Png { pos: usize },
+    /// JPEG marker segments (or entropy-coded data) starting at `pos`
+    Jpeg { pos: usize, entropy: bool },
+    /// QOI ops starting at `pos`, with `remaining` pixels still to decode
+    Qoi { pos: usize, remaining: u64 },
+    /// Frame with a 4-byte big-endian length prefix
+    Prefixed { end: usize },
+}
+
+/// Reads complete encoded images one at a time from a byte stream
+///
+/// Frames may be concatenated PNG, JPEG or QOI files, or arbitrary encoded
+/// images each preceded by a big-endian `u32` byte length.
+pub struct FrameReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    scan: Scan,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Wrap a reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+            scan: Scan::Start,
+        }
+    }
+
+    /// Read the next complete image, or `None` once the stream ends cleanly
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            if let Some((start, end)) = self.advance()? {
+                let rest = self.buffer.split_off(end);
+                let mut frame = std::mem::replace(&mut self.buffer, rest);
+                frame.drain(..start);
+                self.scan = Scan::Start;
+                return Ok(Some(frame));
+            }
+
+            if self.buffer.len() > MAX_FRAME_SIZE {
+                bail!("Stream frame exceeds {} bytes", MAX_FRAME_SIZE);
+            }
+
+            let filled = self.buffer.len();
+            self.buffer.resize(filled + READ_CHUNK_SIZE, 0);
+            let read = loop {
+                match self.reader.read(&mut self.buffer[filled..]) {
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    result => break result,
+                }
+            };
+            self.buffer.truncate(filled + *read.as_ref().unwrap_or(&0));
+
+            if read.context("Failed to read image stream")? == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                bail!(
+                    "Stream ended in the middle of an image ({} bytes buffered)",
+                    self.buffer.len()
+                );
+            }
+        }
+    }
+
+    /// Continue scanning the buffered data
+    ///
+    /// Returns the payload range of the first frame once it is complete.
+    fn advance(&mut self) -> Result<Option<(usize, usize)>> {
+        let data = &self.buffer[..];
+
+        if let Scan::Start = self.scan {
+            if data.len() < PNG_SIGNATURE.len() {
+                return Ok(None);
+            }
+            self.scan = if data.starts_with(PNG_SIGNATURE) {
+                Scan::Png {
+                    pos: PNG_SIGNATURE.len(),
+                }
+            } else if data.starts_with(JPEG_SOI) {
+                Scan::Jpeg {
+                    pos: JPEG_SOI.len(),
+                    entropy: false,
+                }
+            } else if data.starts_with(QOI_MAGIC) {
+                if data.len() < QOI_HEADER_SIZE {
+                    return Ok(None);
+                }
+                let width = u32::from_be_bytes(data[4..8].try_into().unwrap());
+                let height = u32::from_be_bytes(data[8..12].try_into().unwrap());
+                Scan::Qoi {
+                    pos: QOI_HEADER_SIZE,
+                    remaining: width as u64 * height as u64,
+                }
+            } else {
+                let length = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                if length == 0 || length > MAX_FRAME_SIZE {
+                    bail!(
+                        "Unrecognized data in image stream (not PNG, JPEG, QOI or length-prefixed)"
+                    );
+                }
+                Scan::Prefixed { end: 4 + length }
+            };
+        }
+
+        match &mut self.scan {
+            Scan::Start => unreachable!(),
+            Scan::Prefixed { end } => Ok((data.len() >= *end).then_some((4, *end))),
+            Scan::Png { pos } => {
+                // Chunks: length (4), type (4), data, CRC (4); IEND closes the file
+                while let Some(header) = data.get(*pos..*pos + 8) {
+                    let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+                    let end = *pos + 12 + length;
+                    if &header[4..] == b"IEND" {
+                        return Ok((data.len() >= end).then_some((0, end)));
+                    }
+                    *pos = end;
+                }
+                Ok(None)
+            }
+            Scan::Jpeg { pos, entropy } => {
+                loop {
+                    if *entropy {
+                        // Entropy-coded data runs until a marker that is neither
+                        // byte stuffing (FF 00) nor a restart marker (FF D0-D7)
+                        if *pos >= data.len() {
+                            return Ok(None);
+                        }
+                        let Some(offset) = data[*pos..].iter().position(|&b| b == 0xFF) else {
+                            *pos = data.len();
+                            return Ok(None);
+                        };
+                        *pos += offset;
+                        let Some(&next) = data.get(*pos + 1) else {
+                            return Ok(None);
+                        };
+                        if next == 0x00 || (0xD0..=0xD7).contains(&next) {
+                            *pos += 2;
+                            continue;
+                        }
+                        *entropy = false;
+                    }
+
+                    let Some(&[prefix, marker]) = data.get(*pos..*pos + 2) else {
+                        return Ok(None);
+                    };
+                    if prefix != 0xFF {
+                        bail!("Malformed JPEG in image stream");
+                    }
+                    match marker {
+                        // Fill byte before a marker
+                        0xFF => *pos += 1,
+                        // End of image
+                        0xD9 => return Ok(Some((0, *pos + 2))),
+                        // Markers without a payload
+                        0x01 | 0xD0..=0xD7 => *pos += 2,
+                        _ => {
+                            let Some(length) = data.get(*pos + 2..*pos + 4) else {
+                                return Ok(None);
+                            };
+                            *pos += 2 + u16::from_be_bytes([length[0], length[1]]) as usize;
+                            // Scan data follows the start-of-scan header
+                            *entropy = marker == 0xDA;
+                        }
+                    }
+                }
+            }
+            Scan::Qoi { pos, remaining } => {
+                while *remaining > 0 {
+                    let Some(&op) = data.get(*pos) else {
+                        return Ok(None);
+                    };
+                    let (size, pixels) = match op {
+                        0xFE => (4, 1),                                      // QOI_OP_RGB
+                        0xFF => (5, 1),                                      // QOI_OP_RGBA
+                        _ if op >> 6 == 0b10 => (2, 1),                      // QOI_OP_LUMA
+                        _ if op >> 6 == 0b11 => (1, (op & 0x3F) as u64 + 1), // QOI_OP_RUN
+                        _ => (1, 1), // QOI_OP_INDEX / QOI_OP_DIFF
+                    };
+                    // Only consume an op once all of its bytes are buffered
+                    if *pos + size > data.len() {
+                        return Ok(None);
+                    }
+                    *pos += size;
+                    *remaining = remaining.saturating_sub(pixels);
+                }
+                let end = *pos + QOI_END_MARKER.len();
+                match data.get(*pos..end) {
+                    None => Ok(None),
+                    Some(marker) if marker == QOI_END_MARKER => Ok(Some((0, end))),
+                    Some(_) => bail!("Malformed QOI in image stream"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbaImage};
+    use std::io::Cursor;
+
+    /// Encode a noisy image, so JPEG scan data contains stuffed 0xFF bytes
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let img = RgbaImage::from_fn(23, 17, |x, y| {
+            let v = (x * 97 + y * 31) as u8 ^ (x * y) as u8;
+            image::Rgba([v, v.wrapping_mul(3), 255 - v, 255])
+        });
+        let img = match format {
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(img).to_rgb8()),
+            _ => DynamicImage::ImageRgba8(img),
+        };
+        let mut data = Vec::new();
+        img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    /// Reader handing out at most the given number of bytes per call, so frames
+    /// end mid-read
+    struct Trickle<R>(R, usize);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    /// Splice `inserted` into `data` at `at`
+    fn splice(mut data: Vec<u8>, at: usize, inserted: &[u8]) -> Vec<u8> {
+        data.splice(at..at, inserted.iter().copied());
+        data
+    }
+
+    fn frames() -> Vec<Vec<u8>> {
+        let prefixed = encode(ImageFormat::Bmp);
+        vec![
+            encode(ImageFormat::Png),
+            encode(ImageFormat::Jpeg),
+            encode(ImageFormat::Qoi),
+            prefixed,
+        ]
+    }
+
+    /// The frames concatenated, the last one length-prefixed
+    fn stream(frames: &[Vec<u8>]) -> Vec<u8> {
+        let (last, rest) = frames.split_last().unwrap();
+        let mut data = rest.concat();
+        data.extend((last.len() as u32).to_be_bytes());
+        data.extend(last);
+        data
+    }
+
+    #[test]
+    fn test_splits_concatenated_frames() {
+        let frames = frames();
+        let mut reader = FrameReader::new(Cursor::new(stream(&frames)));
+        for frame in &frames {
+            assert_eq!(reader.next_frame().unwrap().as_ref(), Some(frame));
+        }
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resumes_across_short_reads() {
+        let frames = frames();
+        for step in [1, 37] {
+            let mut reader = FrameReader::new(Trickle(Cursor::new(stream(&frames)), step));
+            for frame in &frames {
+                assert_eq!(reader.next_frame().unwrap().as_ref(), Some(frame));
+            }
+            assert!(reader.next_frame().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_end_markers_inside_metadata_are_skipped() {
+        // A tEXt chunk mentioning IEND after IHDR (signature 8 + chunk 25 bytes);
+        // the CRC is not checked while splitting
+        let mut text = 12u32.to_be_bytes().to_vec();
+        text.extend(b"tEXtComment\0IEND");
+        text.extend([0; 4]);
+        let png = splice(encode(ImageFormat::Png), 33, &text);
+        // A comment segment holding EOI and restart markers, right after SOI
+        let comment = [0xFF, 0xFE, 0x00, 0x06, 0xFF, 0xD9, 0xFF, 0xD0];
+        let jpeg = splice(encode(ImageFormat::Jpeg), 2, &comment);
+        assert!(image::load_from_memory(&jpeg).is_ok());
+
+        let frames = vec![png, jpeg, encode(ImageFormat::Png)];
+        let mut reader = FrameReader::new(Trickle(Cursor::new(frames.concat()), 5));
+        for frame in &frames {
+            assert_eq!(reader.next_frame().unwrap().as_ref(), Some(frame));
+        }
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_length_prefix_bounds() {
+        let empty = 0u32.to_be_bytes().to_vec();
+        assert!(FrameReader::new(Cursor::new(empty)).next_frame().is_err());
+        let huge = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes().to_vec();
+        assert!(FrameReader::new(Cursor::new(huge)).next_frame().is_err());
+        // A one-byte payload is a frame of its own
+        let data = [&1u32.to_be_bytes()[..], &[42], &encode(ImageFormat::Qoi)].concat();
+        let mut reader = FrameReader::new(Cursor::new(data));
+        assert_eq!(reader.next_frame().unwrap(), Some(vec![42]));
+        assert!(reader.next_frame().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_frames_decode() {
+        for frame in frames() {
+            assert!(image::load_from_memory(&frame).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_empty_stream_ends_cleanly() {
+        let mut reader = FrameReader::new(Cursor::new(Vec::new()));
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_truncated_frame_is_an_error() {
+        for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Qoi] {
+            let data = encode(format);
+            let mut reader = FrameReader::new(Cursor::new(data[..data.len() - 3].to_vec()));
+            assert!(reader.next_frame().is_err(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_garbage_is_rejected() {
+        let mut reader = FrameReader::new(Cursor::new(b"\0\0\0\0garbage!".to_vec()));
+        assert!(reader.next_frame().is_err());
+        // A QOI end marker that is not one
+        let mut qoi = encode(ImageFormat::Qoi);
+        let len = qoi.len();
+        qoi[len - 1] = 7;
+        assert!(FrameReader::new(Cursor::new(qoi)).next_frame().is_err());
+    }
+}
@@ -0,0 +1,132 @@
+// Text module
+// Shapes the UI text with cosmic-text: measuring lines for panel sizes and laying
+// them out as glyph quads from the atlas
+
+use crate::glyph_atlas::{GlyphAtlas, GlyphQuad};
+use cosmic_text::{
+    fontdb, Attrs, AttrsOwned, Buffer, Family, FontSystem, Metrics, Shaping, SwashCache,
+};
+
+/// Fonts, the default size and line height, and the glyph rasterizer cache
+pub struct Typesetter {
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    metrics: Metrics,
+    attrs: AttrsOwned,
+}
+
+impl Typesetter {
+    /// Shape with the fonts in `db` for a BCP 47 `locale`, in the sans-serif family
+    /// (`fonts::database` makes the theme's font that family)
+    pub fn new(db: fontdb::Database, locale: &str, metrics: Metrics) -> Self {
+        Self {
+            font_system: FontSystem::new_with_locale_and_db(locale.to_string(), db),
+            swash_cache: SwashCache::new(),
+            metrics,
+            attrs: AttrsOwned::new(Attrs::new().family(Family::SansSerif)),
+        }
+    }
+
+    /// Shape `text` at `metrics`, wrapped and clipped to `bounds` where given
+    fn shape(
+        &mut self,
+        metrics: Metrics,
+        text: &str,
+        bounds: (Option<f32>, Option<f32>),
+    ) -> Buffer {
+        let font_system = &mut self.font_system;
+        let mut buffer = Buffer::new(font_system, metrics);
+        buffer.set_size(font_system, bounds.0, bounds.1);
+        buffer.set_text(font_system, text, self.attrs.as_attrs(), Shaping::Advanced);
+        buffer.shape_until_scroll(font_system, false);
+        buffer
+    }
+
+    /// Width of a single line of text as laid out by [`Typesetter::layout`]
+    pub fn line_width(&mut self, text: &str) -> u32 {
+        self.shape(self.metrics, text, (None, None))
+            .layout_runs()
+            .map(|run| run.line_w)
+            .fold(0.0, f32::max)
+            .ceil() as u32
+    }
+
+    /// Width of the widest line and height of `text` wrapped at `max_width`
+    pub fn wrapped_size(&mut self, text: &str, max_width: u32) -> (u32, u32) {
+        let buffer = self.shape(self.metrics, text, (Some(max_width as f32), None));
+        let (width, lines) = buffer.layout_runs().fold((0.0, 0), |(width, lines), run| {
+            (run.line_w.max(width), lines + 1)
+        });
+        (
+            width.ceil() as u32,
+            (lines as f32 * self.metrics.line_height).ceil() as u32,
+        )
+    }
+
+    /// Lay out text at `metrics` with its top-left corner at `origin`, clipped to
+    /// `bounds`, rasterizing new glyphs into the atlas
+    pub fn layout(
+        &mut self,
+        atlas: &mut GlyphAtlas,
+        metrics: Metrics,
+        origin: (i32, i32),
+        text: &str,
+        color: [u8; 4],
+        bounds: (u32, u32),
+    ) -> Vec<GlyphQuad> {
+        let bounds = (Some(bounds.0 as f32), Some(bounds.1 as f32));
+        let buffer = self.shape(metrics, text, bounds);
+        atlas.layout(
+            &mut self.font_system,
+            &mut self.swash_cache,
+            &buffer,
+            origin,
+            color,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fonts;
+    use crate::i18n::Language;
+    use crate::theme::Theme;
+
+    #[test]
+    fn test_translated_text_fits_its_panel() {
+        // Full-width text at a size whose line is taller than the old fixed 18px pitch
+        let mut theme = Theme::default();
+        theme.font_size = 24.0;
+        theme.line_height = 30.0;
+        let metrics = Metrics::new(theme.font_size, theme.line_height);
+        let language = Language::ZhCn;
+        let mut typesetter = Typesetter::new(fonts::database(&[]), language.tag(), metrics);
+        let line = language.translate("Opacity {}%").replace("{}", "80");
+        assert_ne!(line, "Opacity 80%");
+
+        // Sized like the info overlay, then laid out in the bounds the panel gives it
+        let width = typesetter.line_width(&line);
+        let (panel_width, panel_height) = theme.text_panel_size(width, 1, 0);
+        let (x, y) = theme.text_panel_line(0, 0);
+        let bounds = (panel_width - 2 * theme.padding, theme.line_pitch());
+        let mut atlas = GlyphAtlas::new();
+        let quads = typesetter.layout(&mut atlas, metrics, (x, y), &line, [255; 4], bounds);
+
+        // Nothing is dropped compared with unbounded layout, and all of it is inside
+        let unbounded = typesetter.layout(
+            &mut atlas,
+            metrics,
+            (x, y),
+            &line,
+            [255; 4],
+            (10_000, 10_000),
+        );
+        assert!(!quads.is_empty());
+        assert_eq!(quads, unbounded);
+        for quad in &quads {
+            assert!(quad.rect.x >= 0 && quad.rect.x + quad.rect.width <= panel_width as i32);
+            assert!(quad.rect.y >= 0 && quad.rect.y + quad.rect.height <= panel_height as i32);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// Text shader
+// Draws glyphs from the glyph atlas as instanced quads, one instance per glyph.
+// Colors arrive as sRGB and are decoded like the sRGB overlay textures, so
+// text blends the same way as the panels beneath it.
+
+struct Screen {
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var t_atlas: texture_2d<f32>;
+
+@group(1) @binding(0)
+var<uniform> screen: Screen;
+
+struct GlyphInstance {
+    // x, y, width, height in surface pixels
+    @location(0) rect: vec4<f32>,
+    // Top-left corner of the glyph in the atlas, in texels
+    @location(1) atlas_pos: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) atlas_pos: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, glyph: GlyphInstance) -> VertexOutput {
+    // Triangle strip over the corners (0,0) (1,0) (0,1) (1,1)
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32((vertex_index >> 1u) & 1u));
+    let pos = glyph.rect.xy + corner * glyph.rect.zw;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(
+        pos.x / screen.size.x * 2.0 - 1.0,
+        1.0 - pos.y / screen.size.y * 2.0,
+        0.0,
+        1.0,
+    );
+    out.atlas_pos = glyph.atlas_pos + corner * glyph.rect.zw;
+    out.color = vec4<f32>(srgb_to_linear(glyph.color.rgb), glyph.color.a);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Quads are pixel aligned, so each fragment reads exactly one texel
+    let coverage = textureLoad(t_atlas, vec2<i32>(in.atlas_pos), 0).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
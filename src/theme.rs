@@ -0,0 +1,400 @@
+// Theme module
+// Colors, font and spacing of the menu and overlay panels, loaded from the config file
+
+use crate::config::Config;
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use std::process::Command;
+
+/// Desktop portal queried for the color-scheme preference
+const PORTAL_DEST: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+
+/// Space above and below the text of overlay panels
+const PANEL_PADDING_Y: u32 = 4;
+
+/// Which palette to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ThemeMode {
+    /// Follow the desktop's light/dark preference (dark if unknown)
+    #[default]
+    Auto,
+    Light,
+    Dark,
+    /// Starts from the dark palette; meant to be filled in by the [colors] section
+    Custom,
+}
+
+/// Panel colors (BGRA, straight alpha)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub background: [u8; 4],
+    pub border: [u8; 4],
+    pub text: [u8; 4],
+    pub hover: [u8; 4],
+    pub text_hover: [u8; 4],
+    pub text_disabled: [u8; 4],
+    pub shortcut: [u8; 4],
+}
+
+impl Palette {
+    pub const DARK: Palette = Palette {
+        background: [45, 45, 48, 240],
+        border: [80, 80, 80, 255],
+        text: [220, 220, 220, 255],
+        hover: [100, 150, 220, 240],
+        text_hover: [255, 255, 255, 255],
+        text_disabled: [120, 120, 120, 255],
+        shortcut: [160, 160, 160, 255],
+    };
+
+    pub const LIGHT: Palette = Palette {
+        background: [246, 246, 246, 245],
+        border: [200, 200, 200, 255],
+        text: [30, 30, 30, 255],
+        hover: [215, 120, 0, 255],
+        text_hover: [255, 255, 255, 255],
+        text_disabled: [160, 160, 160, 255],
+        shortcut: [110, 110, 110, 255],
+    };
+
+    /// Color slot named by a [colors] key
+    fn slot(&mut self, key: &str) -> Option<&mut [u8; 4]> {
+        Some(match key {
+            "background" => &mut self.background,
+            "border" => &mut self.border,
+            "text" => &mut self.text,
+            "hover" => &mut self.hover,
+            "text_hover" => &mut self.text_hover,
+            "text_disabled" => &mut self.text_disabled,
+            "shortcut" => &mut self.shortcut,
+            _ => return None,
+        })
+    }
+}
+
+/// Appearance of the menu and the overlay panels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub palette: Palette,
+    /// The palette follows the desktop's light/dark preference (`mode = auto`)
+    pub follows_desktop: bool,
+    /// [colors] overrides, applied over whichever palette is picked
+    colors: Vec<(String, [u8; 4])>,
+    pub font_family: String,
+    /// Further fontconfig patterns for characters the main font lacks
+    pub fallback_fonts: Vec<String>,
+    pub font_size: f32,
+    pub line_height: f32,
+    /// Menu row heights
+    pub item_height: u32,
+    pub separator_height: u32,
+    /// Horizontal padding inside menu panels
+    pub padding: u32,
+    /// Corner radius of panels (0 for square corners)
+    pub radius: u32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            palette: Palette::DARK,
+            follows_desktop: false,
+            colors: Vec::new(),
+            font_family: "Noto Sans".to_string(),
+            fallback_fonts: Vec::new(),
+            font_size: 14.0,
+            line_height: 18.0,
+            item_height: 25,
+            separator_height: 9,
+            padding: 8,
+            radius: 0,
+        }
+    }
+}
+
+impl Theme {
+    /// Build the theme from the [theme] and [colors] sections
+    ///
+    /// `mode` (from the command line) overrides `[theme] mode`. In auto mode the dark
+    /// palette is used until [`Theme::set_dark`] reports the desktop's preference.
+    pub fn load(config: &Config, mode: Option<ThemeMode>) -> Self {
+        let mode = mode
+            .or_else(|| {
+                let value = config.get("theme", "mode")?;
+                let mode = ThemeMode::from_str(value, true).ok();
+                if mode.is_none() {
+                    warn!("Ignoring unknown theme mode \"{}\"", value);
+                }
+                mode
+            })
+            .unwrap_or_default();
+
+        let defaults = Self::default();
+        // [colors] overrides individual colors of whichever palette is picked
+        let (mut colors, mut known) = (Vec::new(), Palette::DARK);
+        for (key, value) in config.section("colors") {
+            match (known.slot(key), parse_color(value)) {
+                (Some(_), Some(color)) => colors.push((key.to_string(), color)),
+                _ => warn!("Ignoring invalid theme color {} = {}", key, value),
+            }
+        }
+
+        let font_size: f32 = config
+            .parse_value("theme", "font_size")
+            .filter(|&size: &f32| size > 0.0)
+            .unwrap_or(defaults.font_size);
+        let line_height = config
+            .parse_value("theme", "line_height")
+            .unwrap_or(defaults.line_height * font_size / defaults.font_size);
+        let item_height = config
+            .parse_value("theme", "item_height")
+            .unwrap_or((line_height.ceil() as u32 + 7).max(defaults.item_height));
+        let mut theme = Self {
+            palette: Palette::DARK,
+            follows_desktop: mode == ThemeMode::Auto,
+            colors,
+            font_family: config
+                .get("theme", "font")
+                .map_or(defaults.font_family, str::to_string),
+            fallback_fonts: config
+                .get("theme", "fallback_fonts")
+                .map(|list| {
+                    list.split(',')
+                        .map(str::trim)
+                        .filter(|family| !family.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            font_size,
+            line_height,
+            item_height,
+            separator_height: config
+                .parse_value("theme", "separator_height")
+                .unwrap_or(defaults.separator_height),
+            padding: config
+                .parse_value("theme", "padding")
+                .unwrap_or(defaults.padding),
+            radius: config
+                .parse_value("theme", "radius")
+                .unwrap_or(defaults.radius),
+        };
+        theme.set_dark(mode != ThemeMode::Light);
+        theme
+    }
+
+    /// Switch to the dark or light palette, keeping the [colors] overrides
+    pub fn set_dark(&mut self, dark: bool) {
+        self.palette = if dark { Palette::DARK } else { Palette::LIGHT };
+        for (key, color) in &self.colors {
+            if let Some(slot) = self.palette.slot(key) {
+                *slot = *color;
+            }
+        }
+    }
+
+    /// fontconfig patterns to resolve, main font first
+    pub fn font_patterns(&self) -> Vec<String> {
+        let mut patterns = vec![self.font_family.clone()];
+        patterns.extend(self.fallback_fonts.iter().cloned());
+        patterns
+    }
+
+    /// The theme's sizes for a buffer `scale` times larger than the surface
+    pub fn scaled(&self, scale: u32) -> Theme {
+        let factor = scale as f32;
+        Theme {
+            font_size: self.font_size * factor,
+            line_height: self.line_height * factor,
+            item_height: self.item_height * scale,
+            separator_height: self.separator_height * scale,
+            padding: self.padding * scale,
+            radius: self.radius * scale,
+            ..self.clone()
+        }
+    }
+
+    /// Side of the square menu icons, following the font size
+    pub fn icon_size(&self) -> u32 {
+        (self.font_size * 8.0 / 7.0).round() as u32
+    }
+
+    /// Distance between the tops of consecutive lines of overlay text
+    pub fn line_pitch(&self) -> u32 {
+        self.line_height.ceil() as u32
+    }
+
+    /// Size of an overlay panel holding `lines` lines of text, the widest `text_width`
+    /// pixels, with `indent` more pixels left of the text (the picker's swatch)
+    pub fn text_panel_size(&self, text_width: u32, lines: usize, indent: u32) -> (u32, u32) {
+        (
+            indent + text_width + 2 * self.padding,
+            lines as u32 * self.line_pitch() + 2 * PANEL_PADDING_Y,
+        )
+    }
+
+    /// Top-left corner of line `index` in a panel sized by [`Theme::text_panel_size`]
+    pub fn text_panel_line(&self, index: usize, indent: u32) -> (i32, i32) {
+        (
+            (indent + self.padding) as i32,
+            (PANEL_PADDING_Y + index as u32 * self.line_pitch()) as i32,
+        )
+    }
+
+    /// Offset of the text's top from the top of a menu row, centering the line
+    pub fn text_offset(&self) -> i32 {
+        ((self.item_height as f32 - self.line_height) / 2.0).ceil() as i32 + 1
+    }
+}
+
+/// Parse `#RRGGBB` or `#RRGGBBAA` into BGRA
+pub fn parse_color(value: &str) -> Option<[u8; 4]> {
+    let hex = value.trim().strip_prefix('#')?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Some([channel(4)?, channel(2)?, channel(0)?, alpha])
+}
+
+/// Read `org.freedesktop.appearance color-scheme` from the settings portal
+///
+/// Returns `Some(true)` for a dark and `Some(false)` for a light preference, and
+/// `None` without a portal or preference. `busctl` and `gdbus` are tried in turn
+/// so no D-Bus library is needed. Both may block for a second, so call this off
+/// the UI thread.
+pub fn portal_prefers_dark() -> Option<bool> {
+    let key = ["org.freedesktop.appearance", "color-scheme"];
+    let busctl = Command::new("busctl")
+        .args(["--user", "--timeout=1", "call", PORTAL_DEST, PORTAL_PATH])
+        .args(["org.freedesktop.portal.Settings", "Read", "ss"])
+        .args(key)
+        .output();
+    let gdbus = || {
+        Command::new("gdbus")
+            .args(["call", "--session", "--timeout", "1", "--dest", PORTAL_DEST])
+            .args(["--object-path", PORTAL_PATH])
+            .args(["--method", "org.freedesktop.portal.Settings.Read"])
+            .args(key)
+            .output()
+    };
+
+    let output = busctl
+        .ok()
+        .filter(|output| output.status.success())
+        .or_else(|| gdbus().ok().filter(|output| output.status.success()));
+    let Some(output) = output else {
+        debug!("Settings portal unavailable, using the dark theme");
+        return None;
+    };
+    parse_color_scheme(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse a portal `color-scheme` reply: 1 prefers dark, 2 prefers light
+///
+/// busctl prints `v v u 1`, gdbus `(<<uint32 1>>,)`: the value is the last number.
+fn parse_color_scheme(reply: &str) -> Option<bool> {
+    let scheme: u32 = reply
+        .split(|c: char| !c.is_ascii_digit())
+        .rfind(|part| !part.is_empty())?
+        .parse()
+        .ok()?;
+    info!("Desktop color scheme preference: {}", scheme);
+    match scheme {
+        1 => Some(true),
+        2 => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color_scheme_replies() {
+        assert_eq!(parse_color_scheme("v v u 1\n"), Some(true));
+        assert_eq!(parse_color_scheme("(<<uint32 1>>,)\n"), Some(true));
+        assert_eq!(parse_color_scheme("v v u 2\n"), Some(false));
+        assert_eq!(parse_color_scheme("(<<uint32 2>>,)\n"), Some(false));
+        // No preference, or nothing numeric at all
+        assert_eq!(parse_color_scheme("v v u 0\n"), None);
+        assert_eq!(parse_color_scheme(""), None);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#102030"), Some([0x30, 0x20, 0x10, 255]));
+        assert_eq!(parse_color(" #10203040 "), Some([0x30, 0x20, 0x10, 0x40]));
+        assert_eq!(parse_color("102030"), None);
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#gg2030"), None);
+        assert_eq!(parse_color("#ä12345"), None);
+    }
+
+    #[test]
+    fn test_load_modes_and_overrides() {
+        let config =
+            Config::parse("[theme]\nmode = light\n[colors]\ntext = #FF0000\nnope = #000000\n")
+                .unwrap();
+        let theme = Theme::load(&config, None);
+        assert!(!theme.follows_desktop);
+        assert_eq!(theme.palette.background, Palette::LIGHT.background);
+        assert_eq!(theme.palette.text, [0, 0, 255, 255]);
+
+        // The command line wins over the config file
+        let theme = Theme::load(&config, Some(ThemeMode::Dark));
+        assert_eq!(theme.palette.background, Palette::DARK.background);
+    }
+
+    #[test]
+    fn test_auto_mode_keeps_overrides_when_switching() {
+        let config = Config::parse("[colors]\nhover = #00FF00\n").unwrap();
+        let mut theme = Theme::load(&config, None);
+        assert!(theme.follows_desktop);
+        assert_eq!(theme.palette.background, Palette::DARK.background);
+
+        theme.set_dark(false);
+        assert_eq!(theme.palette.background, Palette::LIGHT.background);
+        assert_eq!(theme.palette.hover, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_sizes_follow_font_size() {
+        let config = Config::parse("[theme]\nfont_size = 28\n").unwrap();
+        let theme = Theme::load(&config, None);
+        assert_eq!(theme.line_height, 36.0);
+        assert_eq!(theme.item_height, 43);
+        assert_eq!(theme.icon_size(), 32);
+    }
+
+    #[test]
+    fn test_scaled_sizes() {
+        let theme = Theme::default();
+        let scaled = theme.scaled(2);
+        assert_eq!(scaled.font_size, 28.0);
+        assert_eq!(scaled.icon_size(), theme.icon_size() * 2);
+        assert_eq!(scaled.item_height, 50);
+        assert_eq!(scaled.palette.text, theme.palette.text);
+        assert_eq!(theme.scaled(1).icon_size(), theme.icon_size());
+    }
+
+    #[test]
+    fn test_text_panels_follow_line_height() {
+        let theme = Theme::default();
+        // Four lines of the default font: the size the info overlay always had
+        assert_eq!(theme.text_panel_size(200, 4, 0), (216, 80));
+        assert_eq!(theme.text_panel_line(0, 0), (8, 4));
+        assert_eq!(theme.text_panel_line(3, 40), (48, 58));
+
+        let config = Config::parse("[theme]\nfont_size = 24\n").unwrap();
+        let theme = Theme::load(&config, None);
+        let (_, height) = theme.text_panel_size(100, 3, 0);
+        // Every line, including the last, lies inside the panel
+        let (_, last) = theme.text_panel_line(2, 0);
+        assert!(last as u32 + theme.line_pitch() <= height);
+        assert!(theme.line_pitch() as f32 >= theme.line_height);
+    }
+}
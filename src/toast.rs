@@ -0,0 +1,110 @@
+// Toast module
+// Short messages shown inside the pin after an action, then faded out
+
+use std::time::{Duration, Instant};
+
+/// How long a toast stays fully visible
+pub const HOLD: Duration = Duration::from_millis(1000);
+
+/// Length of the fade-out after the hold
+pub const FADE: Duration = Duration::from_millis(300);
+
+/// Repaint interval while fading
+pub const FADE_STEP: Duration = Duration::from_millis(33);
+
+/// A message and the time it was shown
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub text: String,
+    shown_at: Instant,
+}
+
+impl Toast {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            shown_at: Instant::now(),
+        }
+    }
+
+    /// Opacity at `now`: 1.0 while held, falling to 0.0 over the fade
+    pub fn alpha(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.shown_at);
+        if elapsed <= HOLD {
+            return 1.0;
+        }
+        let fading = (elapsed - HOLD).as_secs_f32() / FADE.as_secs_f32();
+        (1.0 - fading).max(0.0)
+    }
+
+    /// Delay until the toast next needs a repaint, or `None` once it has expired
+    pub fn next_update(&self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.shown_at);
+        if elapsed < HOLD {
+            Some(HOLD - elapsed)
+        } else if elapsed < HOLD + FADE {
+            Some(FADE_STEP)
+        } else {
+            None
+        }
+    }
+}
+
+/// Scale the alpha channel of a BGRA panel
+pub fn fade(panel: &mut [u8], alpha: f32) {
+    if alpha >= 1.0 {
+        return;
+    }
+    for pixel in panel.chunks_exact_mut(4) {
+        fade_color(pixel, alpha);
+    }
+}
+
+/// Scale the alpha of one BGRA color, e.g. the toast's text color
+pub fn fade_color(color: &mut [u8], alpha: f32) {
+    let factor = (alpha.clamp(0.0, 1.0) * 256.0) as u32;
+    color[3] = ((color[3] as u32 * factor) >> 8) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alpha_holds_then_fades() {
+        let toast = Toast::new("Copied");
+        let start = toast.shown_at;
+        assert_eq!(toast.alpha(start), 1.0);
+        assert_eq!(toast.alpha(start + HOLD), 1.0);
+        let half = toast.alpha(start + HOLD + FADE / 2);
+        assert!((half - 0.5).abs() < 0.01, "{}", half);
+        assert_eq!(toast.alpha(start + HOLD + FADE), 0.0);
+        assert_eq!(toast.alpha(start + HOLD + FADE * 2), 0.0);
+        // A clock reading from before the toast counts as just shown
+        assert_eq!(toast.alpha(start - Duration::from_millis(5)), 1.0);
+    }
+
+    #[test]
+    fn test_next_update() {
+        let toast = Toast::new("Copied");
+        let start = toast.shown_at;
+        assert_eq!(toast.next_update(start), Some(HOLD));
+        assert_eq!(
+            toast.next_update(start + Duration::from_millis(400)),
+            Some(HOLD - Duration::from_millis(400))
+        );
+        assert_eq!(toast.next_update(start + HOLD), Some(FADE_STEP));
+        assert_eq!(toast.next_update(start + HOLD + FADE), None);
+    }
+
+    #[test]
+    fn test_fade() {
+        let mut panel = [10, 20, 30, 200, 1, 2, 3, 255];
+        fade(&mut panel, 1.0);
+        assert_eq!(panel, [10, 20, 30, 200, 1, 2, 3, 255]);
+        fade(&mut panel, 0.5);
+        assert_eq!(panel, [10, 20, 30, 100, 1, 2, 3, 127]);
+        fade(&mut panel, -1.0);
+        assert_eq!(panel, [10, 20, 30, 0, 1, 2, 3, 0]);
+    }
+}
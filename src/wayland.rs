@@ -1,19 +1,44 @@
 // Wayland integration module
 // Handles all Wayland-specific functionality using smithay-client-toolkit
 
-use crate::image_loader::ImageData;
-use crate::wgpu_renderer::WgpuRenderer;
-use anyhow::{Context, Result};
-use cosmic_text::{
-    fontdb, Attrs, AttrsOwned, Buffer, Color as TextColor, Family, FontSystem, Metrics, Shaping,
-    SwashCache,
-};
+use crate::cli::ParsedArgs;
+use crate::cpu_renderer::{self, Damage, PanelFrame, Rect, ShmSwapchain};
+use crate::decoration::{Decoration, Insets};
+use crate::file_watcher::FileWatcher;
+use crate::fonts;
+use crate::glyph_atlas::{GlyphAtlas, GlyphQuad};
+use crate::hud::{self, HudMode, HudState};
+use crate::i18n::{self, tr, trf};
+use crate::icons;
+use crate::image_loader::{self, ImageData, ImageProbe, SourceInfo};
+use crate::loupe::{self, Lens, LoupeShape};
+use crate::measure::{self, Mapping, Measurement};
+use crate::menu::{self, MenuAction, MenuContext, MenuEntry, MenuItem, MenuStep, ScaleMode};
+use crate::picker::{self, PickedColor};
+use crate::reload::{self, DecodeKind, Outcome, Reloads};
+use crate::resample::FilterMode;
+use crate::snap::{Edges, Guides, Registry};
+use crate::stream::FrameReader;
+use crate::text::Typesetter;
+use crate::theme::{self, Theme};
+use crate::toast::{self, Toast};
+use crate::wgpu_renderer::{OverlayLayer, WgpuRenderer};
+use anyhow::{anyhow, Context, Result};
+use calloop::channel::{self, Sender};
+use calloop::generic::Generic;
+use calloop::signals::{Signal, Signals};
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{EventLoop, Interest, LoopHandle, Mode, PostAction, RegistrationToken};
+use cosmic_text::Metrics;
 use log::{debug, error, info, warn};
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
+    compositor::{CompositorHandler, CompositorState, Region},
     delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
-    delegate_registry, delegate_seat, delegate_shm,
+    delegate_registry, delegate_seat, delegate_shm, delegate_xdg_popup, delegate_xdg_shell,
     output::{OutputHandler, OutputState},
+    reexports::{
+        calloop_wayland_source::WaylandSource, protocols::xdg::shell::client::xdg_positioner,
+    },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
     seat::{
@@ -28,18 +53,24 @@ use smithay_client_toolkit::{
             Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
             LayerSurfaceConfigure,
         },
+        xdg::{
+            popup::{Popup, PopupConfigure, PopupHandler},
+            window::{Window, WindowConfigure, WindowHandler},
+            XdgPositioner, XdgShell,
+        },
         WaylandSurface,
     },
-    shm::{
-        slot::{Buffer as ShmBuffer, SlotPool},
-        Shm, ShmHandler,
-    },
+    shm::{Shm, ShmHandler},
 };
-use std::process::Command;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::io::{Cursor, Stdin, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 use wayland_client::{
     globals::registry_queue_init,
-    protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_shm, wl_surface},
+    protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_surface},
     Connection, Proxy, QueueHandle,
 };
 
@@ -65,6 +96,22 @@ const MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024;
 /// Opacity adjustment step for scroll wheel
 const OPACITY_STEP: f32 = 0.05;
 
+/// Quiet period after a file change before reloading (coalesces bursts of writes)
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// How often a clipboard tool is checked for its exit status
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Background color of the in-window error message (BGRA)
+const ERROR_BACKGROUND: [u8; 4] = [40, 36, 64, 255];
+
+/// Width the error message wraps at, and its padding (horizontal, vertical)
+const ERROR_MAX_WIDTH: u32 = 480;
+const ERROR_PADDING: (u32, u32) = (12, 10);
+
+/// Decoded image (or error) sent back from a worker thread
+type DecodeResult = (DecodeKind, Result<ImageData>);
+
 /// Resize direction flags
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum ResizeEdge {
@@ -79,13 +126,44 @@ enum ResizeEdge {
     BottomRight,
 }
 
-/// Scale mode for resizing
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ScaleMode {
-    /// Keep aspect ratio when resizing
-    KeepAspectRatio,
-    /// Free scaling (stretch)
-    FreeScale,
+impl ResizeEdge {
+    /// Edges of the pin that follow the pointer
+    fn moving_edges(self) -> Edges {
+        Edges {
+            left: matches!(
+                self,
+                ResizeEdge::Left | ResizeEdge::TopLeft | ResizeEdge::BottomLeft
+            ),
+            top: matches!(
+                self,
+                ResizeEdge::Top | ResizeEdge::TopLeft | ResizeEdge::TopRight
+            ),
+            right: matches!(
+                self,
+                ResizeEdge::Right | ResizeEdge::TopRight | ResizeEdge::BottomRight
+            ),
+            bottom: matches!(
+                self,
+                ResizeEdge::Bottom | ResizeEdge::BottomLeft | ResizeEdge::BottomRight
+            ),
+        }
+    }
+
+    /// Fraction of the width and height that stays in place when the aspect ratio is kept
+    fn anchor(self) -> (f32, f32) {
+        let edges = self.moving_edges();
+        let axis = |low: bool, high: bool, centered: bool| match (low, high) {
+            (true, _) => 1.0,
+            (false, true) => 0.0,
+            // Dragging the other axis's low edge grows this one from its center
+            _ if centered => 0.5,
+            _ => 0.0,
+        };
+        (
+            axis(edges.left, edges.right, self == ResizeEdge::Top),
+            axis(edges.top, edges.bottom, self == ResizeEdge::Left),
+        )
+    }
 }
 
 /// Context menu state
@@ -95,14 +173,199 @@ enum MenuState {
     Visible,
 }
 
-/// Menu item indices
-const MENU_ITEM_CLOSE: usize = 0;
-const MENU_ITEM_COPY: usize = 1;
-const MENU_ITEM_OPACITY_UP: usize = 2;
-const MENU_ITEM_OPACITY_DOWN: usize = 3;
-const MENU_ITEM_SCALE_MODE: usize = 4;
-const MENU_ITEM_HEIGHT: u32 = 25;
-const MENU_WIDTH: u32 = 180;
+/// A menu level shown in its own xdg_popup
+struct MenuPopup {
+    popup: Popup,
+    // Submenu path of the level and the size the popup was created with
+    path: Vec<usize>,
+    size: (u32, u32),
+    // Position relative to the parent surface, known once configured
+    position: Option<(i32, i32)>,
+    // Integer scale of the popup's buffer over its logical size
+    scale: u32,
+    swapchain: Option<ShmSwapchain>,
+}
+
+/// An overlay panel: backgrounds, BGRA pixels (straight alpha) over them and text on top
+///
+/// Everything is in panel coordinates. The GPU draws the backgrounds and text from
+/// instances, so only panels with lines, icons or other pixels upload a texture.
+struct OverlayPanel {
+    rect: Rect,
+    frames: Vec<PanelFrame>,
+    // Empty for panels made only of frames and text
+    pixels: Vec<u8>,
+    // Glyphs drawn from the atlas
+    text: Vec<GlyphQuad>,
+    // Fade of the whole panel (toasts)
+    alpha: f32,
+}
+
+impl OverlayPanel {
+    fn new(rect: Rect) -> Self {
+        Self {
+            rect,
+            frames: Vec::new(),
+            pixels: Vec::new(),
+            text: Vec::new(),
+            alpha: 1.0,
+        }
+    }
+
+    /// A panel with a transparent canvas to draw into
+    fn with_pixels(rect: Rect) -> Self {
+        Self {
+            pixels: vec![0u8; (rect.width * rect.height * 4).max(0) as usize],
+            ..Self::new(rect)
+        }
+    }
+
+    /// A BGRA color at the panel's fade
+    fn faded(&self, mut color: [u8; 4]) -> [u8; 4] {
+        toast::fade_color(&mut color, self.alpha);
+        color
+    }
+
+    /// The panel as plain pixels with its frames and text drawn in, for the CPU path
+    fn flatten(&self, atlas: &GlyphAtlas) -> OverlayPanel {
+        let (width, height) = (self.rect.width as u32, self.rect.height as u32);
+        let mut flat = Self::with_pixels(self.rect);
+        for frame in &self.frames {
+            frame.draw(&mut flat.pixels, width, height);
+        }
+        for (dst, src) in flat
+            .pixels
+            .chunks_exact_mut(4)
+            .zip(self.pixels.chunks_exact(4))
+        {
+            if src[3] > 0 {
+                cpu_renderer::blend_over(dst, [src[0], src[1], src[2], 255], src[3] as f32 / 255.0);
+            }
+        }
+        atlas.draw(&mut flat.pixels, width, height, &self.text);
+        toast::fade(&mut flat.pixels, self.alpha);
+        flat
+    }
+}
+
+/// A rendered overlay panel, kept until the content it was rendered from changes
+struct CachedPanel<K> {
+    key: K,
+    panel: OverlayPanel,
+    // Fade the GPU copy was uploaded at, or `None` if the GPU has not seen this panel
+    uploaded: Option<f32>,
+}
+
+impl<K: PartialEq> CachedPanel<K> {
+    /// Keep `cached` while its key matches, otherwise render the panel again
+    fn refresh(
+        cached: Option<Self>,
+        key: K,
+        render: impl FnOnce(&K) -> OverlayPanel,
+    ) -> Option<Self> {
+        match cached {
+            Some(cached) if cached.key == key => Some(cached),
+            _ => {
+                let panel = render(&key);
+                Some(Self {
+                    key,
+                    panel,
+                    uploaded: None,
+                })
+            }
+        }
+    }
+}
+
+/// What the in-pin menu shows: its bounds, the hovered row and the open submenus
+type MenuPanelKey = (Rect, Option<(usize, usize)>, Vec<usize>);
+
+/// Upload a cached panel to its GPU overlay layer if it changed, or clear the layer
+///
+/// Panels are placed on the image; the renderer works in surface coordinates.
+fn upload_panel<K>(
+    renderer: &mut WgpuRenderer,
+    insets: Insets,
+    layer: OverlayLayer,
+    cached: Option<&mut CachedPanel<K>>,
+) {
+    let Some(cached) = cached else {
+        renderer.update_overlay_frames(layer, &[]);
+        renderer.clear_overlay_texture(layer);
+        renderer.update_overlay_text(layer, &[]);
+        return;
+    };
+    let panel = &cached.panel;
+    if cached.uploaded == Some(panel.alpha) {
+        return;
+    }
+    let rect = Rect::new(
+        panel.rect.x + insets.left,
+        panel.rect.y + insets.top,
+        panel.rect.width,
+        panel.rect.height,
+    );
+    let frames: Vec<PanelFrame> = panel
+        .frames
+        .iter()
+        .map(|frame| PanelFrame {
+            rect: Rect::new(
+                rect.x + frame.rect.x,
+                rect.y + frame.rect.y,
+                frame.rect.width,
+                frame.rect.height,
+            ),
+            fill: panel.faded(frame.fill),
+            border: panel.faded(frame.border),
+            ..*frame
+        })
+        .collect();
+    renderer.update_overlay_frames(layer, &frames);
+
+    if panel.pixels.is_empty() {
+        renderer.clear_overlay_texture(layer);
+    } else if panel.alpha < 1.0 {
+        let mut pixels = panel.pixels.clone();
+        toast::fade(&mut pixels, panel.alpha);
+        renderer.update_overlay_texture(layer, rect, &pixels);
+    } else {
+        renderer.update_overlay_texture(layer, rect, &panel.pixels);
+    }
+
+    let glyphs: Vec<GlyphQuad> = panel
+        .text
+        .iter()
+        .filter_map(|glyph| glyph.clip((rect.x, rect.y), rect))
+        .map(|glyph| GlyphQuad {
+            color: panel.faded(glyph.color),
+            ..glyph
+        })
+        .collect();
+    renderer.update_overlay_text(layer, &glyphs);
+    cached.uploaded = Some(panel.alpha);
+}
+
+/// Color picker swatch size, the room it takes left of the readout text, and the
+/// readout's offset from the pointer (panels are otherwise sized from their text)
+const PICKER_SWATCH_SIZE: u32 = 28;
+const PICKER_TEXT_INDENT: u32 = 40;
+const PICKER_PANEL_OFFSET: i32 = 16;
+
+/// Measurement label offset from the line's end point
+const MEASURE_LABEL_OFFSET: i32 = 10;
+
+/// Info overlay inset from the top-left corner
+const HUD_PANEL_INSET: i32 = 8;
+
+/// Toast padding around its text and inset from the bottom edge
+const TOAST_PADDING: (u32, u32) = (12, 6);
+const TOAST_INSET: i32 = 12;
+
+/// Measured strings remembered before the width cache starts over
+const TEXT_WIDTH_CACHE_SIZE: usize = 256;
+
+/// Largest loupe edge length in pixels (smaller in tiny windows)
+const LOUPE_SIZE: u32 = 160;
 
 /// Main Wayland application state
 struct WaylandApp {
@@ -122,8 +385,27 @@ struct WaylandApp {
     // Wayland display pointer (for GPU rendering)
     display_ptr: *mut std::ffi::c_void,
 
+    // Event loop handle for timers
+    loop_handle: LoopHandle<'static, WaylandApp>,
+    // Channel for results of background decodes
+    decode_sender: Sender<DecodeResult>,
+
     // Application-specific state
     image: ImageData,
+    // Source file for reloads (None when the image came from stdin)
+    source_path: Option<PathBuf>,
+    // Scale factor applied when (re)loading the image
+    image_scale: f32,
+    // Name, format and size of the image source for the info overlay
+    source_info: SourceInfo,
+    // Full image has been decoded (false while the placeholder or an error shows)
+    image_loaded: bool,
+    // Running decode, queued reload and retry count
+    reloads: Reloads,
+    // inotify watcher and its event source while --watch is active
+    watcher: Option<(FileWatcher, RegistrationToken)>,
+    // Debounce timer of a reload after file changes, restarted by every change
+    watch_reload_timer: Option<RegistrationToken>,
     opacity: f32,
     should_exit: bool,
 
@@ -133,8 +415,9 @@ struct WaylandApp {
 
     // Surface and buffer management
     layer_surface: Option<LayerSurface>,
-    pool: Option<SlotPool>,
-    buffer: Option<ShmBuffer>,
+    // Integer scale the compositor prefers for the pin's output; menu popups inherit it
+    scale_factor: i32,
+    swapchain: Option<ShmSwapchain>,
     width: u32,
     height: u32,
     configured: bool,
@@ -153,6 +436,18 @@ struct WaylandApp {
     drag_start_pos: (f64, f64),
     drag_start_margin: (i32, i32),
 
+    // Snap distance (0 = off) and the guides gathered when the drag or resize started
+    snap_threshold: u32,
+    snap_guides: Option<Guides>,
+    // Where other pins find this one, and what was last published there (output and rect)
+    pin_registry: Option<Registry>,
+    published_pin: Option<(String, Rect)>,
+    // Output the pin is shown on, and the size of its area not reserved by panels,
+    // reported to an unmapped probe surface
+    pin_output: Option<wl_output::WlOutput>,
+    usable_probe: Option<LayerSurface>,
+    usable_size: Option<(u32, u32)>,
+
     // Resizing state
     resizing: bool,
     resize_edge: ResizeEdge,
@@ -167,20 +462,80 @@ struct WaylandApp {
     // Context menu
     menu_state: MenuState,
     menu_pos: (i32, i32),
-    menu_hover_item: Option<usize>,
+    // Menu tree built when the menu opens, and the laid out root menu and open submenus
+    menu_entries: Vec<MenuEntry<MenuAction>>,
+    menu_panels: Vec<menu::Panel>,
+    // Row index of the open submenu's parent at each level
+    menu_path: Vec<usize>,
+    // Hovered row as (level, row)
+    menu_hover: Option<(usize, usize)>,
+    // One popup per open menu level; without xdg_shell the menu is drawn inside the pin
+    xdg_shell: Option<XdgShell>,
+    menu_popups: Vec<MenuPopup>,
+    // Menu drawn inside the pin, kept while its rectangle, hovered row and open path match
+    menu_panel: Option<CachedPanel<MenuPanelKey>>,
+    queue_handle: Option<QueueHandle<WaylandApp>>,
+    // Seat and serial of the latest input event, needed to grab popups
+    seat: Option<wl_seat::WlSeat>,
+    input_serial: u32,
+
+    // Color picker mode, the readout rectangle last drawn and the rendered readout
+    picker_active: bool,
+    picker_rect: Option<Rect>,
+    picker_panel: Option<CachedPanel<(Rect, PickedColor)>>,
+    // Current keyboard modifiers
+    modifiers: Modifiers,
+
+    // Measure mode, finished measurements and the one being dragged out
+    measure_active: bool,
+    measurements: Vec<Measurement>,
+    measure_drag: Option<Measurement>,
+    // Rendered measurement overlay, the surface size it was built for, and whether it is stale
+    measure_panel: Option<CachedPanel<()>>,
+    measure_panel_size: (u32, u32),
+    measure_dirty: bool,
+
+    // Magnifier loupe: trigger key, shape, zoom, the held key's raw code and last drawn rectangle
+    loupe_key: Keysym,
+    loupe_shape: LoupeShape,
+    loupe_zoom: u32,
+    loupe_held: Option<u32>,
+    loupe_rect: Option<Rect>,
+
+    // Info overlay: current and startup visibility, the last drawn rectangle and its panel
+    hud_mode: HudMode,
+    hud_default: HudMode,
+    hud_rect: Option<Rect>,
+    hud_panel: Option<CachedPanel<(Rect, Vec<String>)>>,
+
+    // Action feedback toast, its last drawn rectangle and panel, and whether its timer is scheduled
+    toast: Option<Toast>,
+    toast_rect: Option<Rect>,
+    toast_panel: Option<CachedPanel<(Rect, String)>>,
+    toast_timer_pending: bool,
 
     // Redraw flag
     needs_redraw: bool,
+    // Surface state (size/position) changed and must be committed
+    needs_commit: bool,
+    // A wl_surface.frame callback has been requested and not yet delivered
+    frame_callback_pending: bool,
+    // Regions changed since the last frame (used for partial CPU redraws)
+    damage: Damage,
 
     // Scale mode (keep aspect ratio or free scale)
     scale_mode: ScaleMode,
+    // Resampling filter used for the steady-state image
+    filter: FilterMode,
     // Original image aspect ratio (width / height)
     original_aspect_ratio: f32,
     // Cached scaled image data for performance during resize
     cached_scaled_image: Option<Vec<u8>>,
     cached_scaled_size: (u32, u32),
-    // Frame rate limiting for resize
-    last_resize_draw: Option<Instant>,
+    // Corners, border and shadow around the image
+    decoration: Decoration,
+    // Image-sized canvas copied into the larger buffer when the surface has room for a shadow
+    content_canvas: Vec<u8>,
 
     // GPU rendering
     use_gpu: bool,
@@ -188,15 +543,21 @@ struct WaylandApp {
     gpu_initialized: bool,
     gpu_init_pending: bool,
 
-    // Text rendering (lazy loaded to save memory)
-    font_system: Option<FontSystem>,
-    swash_cache: Option<SwashCache>,
-    menu_text_attrs: AttrsOwned,
-    menu_text_metrics: Metrics,
+    // Text rendering (fonts are loaded when text is first drawn); glyphs are
+    // rasterized once into the atlas and drawn from there by both paths
+    typesetter: Option<Typesetter>,
+    // Font database resolved in the background until the typesetter is first needed
+    font_loader: Option<fonts::Loader>,
+    glyph_atlas: GlyphAtlas,
+    // Widths of recently measured strings, so unchanged panels are not shaped again
+    text_widths: HashMap<String, u32>,
+    // Colors, font and spacing of the menu and overlay panels
+    theme: Theme,
 }
 
 impl WaylandApp {
     /// Create a new Wayland application
+    #[allow(clippy::too_many_arguments)]
     fn new(
         registry_state: RegistryState,
         seat_state: SeatState,
@@ -205,12 +566,12 @@ impl WaylandApp {
         layer_shell: LayerShell,
         compositor_state: CompositorState,
         display_ptr: *mut std::ffi::c_void,
-        image: ImageData,
-        opacity: f32,
-        use_gpu: bool,
+        loop_handle: LoopHandle<'static, WaylandApp>,
+        decode_sender: Sender<DecodeResult>,
+        probe: ImageProbe,
+        args: &ParsedArgs,
     ) -> Self {
-        let menu_text_metrics = Metrics::new(14.0, 18.0);
-        let menu_text_attrs = AttrsOwned::new(Attrs::new().family(Family::Name("Noto Sans")));
+        let theme = Theme::load(&args.config, args.theme);
 
         Self {
             registry_state,
@@ -220,26 +581,48 @@ impl WaylandApp {
             layer_shell,
             compositor_state,
             display_ptr,
-            original_aspect_ratio: image.width as f32 / image.height as f32,
-            image,
-            opacity,
+            loop_handle,
+            decode_sender,
+            original_aspect_ratio: probe.width as f32 / probe.height as f32,
+            // Show the EXIF thumbnail (or a flat placeholder) until decoding finishes
+            image: probe.preview.unwrap_or_else(ImageData::placeholder),
+            // stdin data cannot be read again, so only file sources support reloads
+            source_path: if args.image_data.is_none() {
+                args.image_path.clone()
+            } else {
+                None
+            },
+            image_scale: args.scale,
+            source_info: probe.source,
+            image_loaded: false,
+            reloads: Reloads::default(),
+            watcher: None,
+            watch_reload_timer: None,
+            opacity: args.opacity,
             should_exit: false,
             display_width: 1920,
             display_height: 1080,
             layer_surface: None,
-            pool: None,
-            buffer: None,
+            scale_factor: 1,
+            swapchain: None,
             width: 0,
             height: 0,
             configured: false,
             margin_left: 100,
             margin_top: 100,
-            pointer_pos: (0.0, 0.0),
+            pointer_pos: (-1.0, -1.0),
             themed_pointer: None,
             set_cursor_on_next_frame: None,
             dragging: false,
             drag_start_pos: (0.0, 0.0),
             drag_start_margin: (0, 0),
+            snap_threshold: args.snap_threshold,
+            snap_guides: None,
+            pin_registry: Registry::open(),
+            published_pin: None,
+            pin_output: None,
+            usable_probe: None,
+            usable_size: None,
             resizing: false,
             resize_edge: ResizeEdge::None,
             resize_start_pos: (0.0, 0.0),
@@ -249,20 +632,58 @@ impl WaylandApp {
             last_click_pos: (0.0, 0.0),
             menu_state: MenuState::Hidden,
             menu_pos: (0, 0),
-            menu_hover_item: None,
+            menu_entries: Vec::new(),
+            menu_panels: Vec::new(),
+            menu_path: Vec::new(),
+            menu_hover: None,
+            xdg_shell: None,
+            menu_popups: Vec::new(),
+            menu_panel: None,
+            queue_handle: None,
+            seat: None,
+            input_serial: 0,
+            picker_active: false,
+            picker_rect: None,
+            picker_panel: None,
+            modifiers: Modifiers::default(),
+            measure_active: false,
+            measurements: Vec::new(),
+            measure_drag: None,
+            measure_panel: None,
+            measure_panel_size: (0, 0),
+            measure_dirty: false,
+            loupe_key: args.loupe_key,
+            loupe_shape: args.loupe_shape,
+            loupe_zoom: loupe::DEFAULT_ZOOM,
+            loupe_held: None,
+            loupe_rect: None,
+            hud_mode: args.hud,
+            hud_default: args.hud,
+            hud_rect: None,
+            hud_panel: None,
+            toast: None,
+            toast_rect: None,
+            toast_panel: None,
+            toast_timer_pending: false,
             needs_redraw: false,
+            needs_commit: false,
+            frame_callback_pending: false,
+            damage: Damage::Full,
             scale_mode: ScaleMode::KeepAspectRatio,
+            filter: args.filter,
             cached_scaled_image: None,
             cached_scaled_size: (0, 0),
-            last_resize_draw: None,
-            use_gpu,
+            decoration: Decoration::load(&args.config),
+            content_canvas: Vec::new(),
+            use_gpu: args.use_gpu,
             gpu_renderer: None,
             gpu_initialized: false,
             gpu_init_pending: false,
-            font_system: None, // Lazy loaded when menu is first shown
-            swash_cache: None,
-            menu_text_attrs,
-            menu_text_metrics,
+            typesetter: None, // Lazy loaded when text is first drawn
+            font_loader: Some(fonts::Loader::spawn(font_patterns(&theme))),
+            glyph_atlas: GlyphAtlas::new(),
+            text_widths: HashMap::new(),
+            theme,
         }
     }
 
@@ -289,343 +710,1915 @@ impl WaylandApp {
         }
     }
 
-    /// Check if a point is within the menu
-    fn get_menu_item_at(&self, x: f64, y: f64) -> Option<usize> {
-        if self.menu_state != MenuState::Visible {
-            return None;
-        }
-
-        let menu_x = self.menu_pos.0 as f64;
-        let menu_y = self.menu_pos.1 as f64;
-        let menu_w = MENU_WIDTH as f64;
-        let menu_items = self.get_menu_items();
-        let menu_h = (menu_items.len() * MENU_ITEM_HEIGHT as usize) as f64;
+    /// Build the context menu for the current state
+    fn build_menu(&self) -> Vec<MenuEntry<MenuAction>> {
+        menu::context_menu(&MenuContext {
+            image_loaded: self.image_loaded,
+            opacity: self.opacity,
+            scale_mode: self.scale_mode,
+            filter: self.filter,
+            has_source: self.source_path.is_some(),
+            watching: self.watcher.is_some(),
+            picker_active: self.picker_active,
+            measure_active: self.measure_active,
+            hud_always: self.hud_mode == HudMode::Always,
+        })
+    }
 
-        if x >= menu_x && x < menu_x + menu_w && y >= menu_y && y < menu_y + menu_h {
-            let item_idx = ((y - menu_y) / MENU_ITEM_HEIGHT as f64) as usize;
-            if item_idx < menu_items.len() {
-                return Some(item_idx);
-            }
+    /// Handle menu item selection
+    fn handle_menu_action(&mut self, action: MenuAction) {
+        info!("Menu: {:?}", action);
+        match action {
+            MenuAction::Close => self.should_exit = true,
+            MenuAction::Copy => self.copy_to_clipboard(),
+            MenuAction::OpacityUp => self.adjust_opacity(OPACITY_STEP),
+            MenuAction::OpacityDown => self.adjust_opacity(-OPACITY_STEP),
+            MenuAction::SetScaleMode(mode) => self.set_scale_mode(mode),
+            MenuAction::SetFilter(filter) => self.set_filter(filter),
+            MenuAction::ToggleWatch => self.toggle_watch(),
+            MenuAction::TogglePicker => self.toggle_picker(),
+            MenuAction::ToggleMeasure => self.toggle_measure(),
+            MenuAction::ToggleHud => self.toggle_hud(),
         }
-        None
+        self.hide_menu();
     }
 
-    /// Get dynamic menu items based on current state
-    fn get_menu_items(&self) -> Vec<&'static str> {
-        let scale_mode_text = match self.scale_mode {
-            ScaleMode::KeepAspectRatio => "📐 Scale: Free",
-            ScaleMode::FreeScale => "📐 Scale: Keep Ratio",
-        };
-        vec![
-            "❌ Close",
-            "📋 Copy to Clipboard",
-            "🔆 Opacity +",
-            "🔅 Opacity -",
-            scale_mode_text,
-        ]
+    /// Open the context menu at a point
+    fn show_menu(&mut self, pos: (i32, i32)) {
+        // Popups are anchored where they were opened, so reopening starts over
+        self.close_menu_popups(0);
+        self.menu_state = MenuState::Visible;
+        self.menu_pos = pos;
+        self.menu_entries = self.build_menu();
+        self.menu_path.clear();
+        self.menu_hover = None;
+        self.layout_menu();
     }
 
-    /// Handle menu item selection
-    fn handle_menu_action(&mut self, item: usize) {
-        match item {
-            MENU_ITEM_CLOSE => {
-                info!("Menu: Close selected");
-                self.should_exit = true;
+    /// Close the context menu and repaint the area it covered
+    fn hide_menu(&mut self) {
+        if self.xdg_shell.is_none() {
+            if let Some(rect) = self.menu_rect() {
+                self.damage_rect(rect);
             }
-            MENU_ITEM_COPY => {
-                info!("Menu: Copy to clipboard selected");
-                self.copy_to_clipboard();
-            }
-            MENU_ITEM_OPACITY_UP => {
-                self.adjust_opacity(OPACITY_STEP);
+        }
+        self.close_menu_popups(0);
+        self.menu_state = MenuState::Hidden;
+        self.menu_entries.clear();
+        self.menu_panels.clear();
+        self.menu_path.clear();
+        self.menu_hover = None;
+    }
+
+    /// Lay out the root menu and every open submenu, repainting the old and new area
+    ///
+    /// With xdg_shell each level gets its own popup and the compositor decides the
+    /// final position; otherwise the panels are kept inside the pin.
+    fn layout_menu(&mut self) {
+        let in_popups = self.xdg_shell.is_some();
+        if !in_popups {
+            if let Some(rect) = self.menu_rect() {
+                self.damage_rect(rect);
             }
-            MENU_ITEM_OPACITY_DOWN => {
-                self.adjust_opacity(-OPACITY_STEP);
+        }
+
+        let entries = std::mem::take(&mut self.menu_entries);
+        let mut path = std::mem::take(&mut self.menu_path);
+        let bounds = (self.width, self.height);
+        let (mut anchor, mut flip_x) = (self.menu_pos, self.menu_pos.0);
+        let theme = std::mem::take(&mut self.theme);
+        let mut measure = |text: &str| self.measure_text(text);
+        let mut panels: Vec<menu::Panel> = Vec::with_capacity(path.len() + 1);
+        for level in 0..=path.len() {
+            let Some(level_entries) = menu::entries_at(&entries, &path[..level]) else {
+                break;
+            };
+            let mut panel = menu::layout(level_entries, &theme, &mut measure);
+            if in_popups {
+                panel.move_to(anchor.0, anchor.1);
+            } else {
+                panel.place(anchor, flip_x, bounds);
             }
-            MENU_ITEM_SCALE_MODE => {
-                self.toggle_scale_mode();
+            // Submenus open beside their parent row
+            if let Some(row) = path.get(level).and_then(|&row| panel.rows.get(row)) {
+                anchor = (panel.rect.x + panel.rect.width, row.y);
+                flip_x = panel.rect.x;
             }
-            _ => {}
+            panels.push(panel);
+        }
+        path.truncate(panels.len() - 1);
+        self.menu_panel = None;
+        self.theme = theme;
+        self.menu_path = path;
+        self.menu_entries = entries;
+        self.menu_panels = panels;
+
+        if in_popups {
+            self.sync_menu_popups();
+        } else if let Some(rect) = self.menu_rect() {
+            self.damage_rect(rect);
         }
-        self.menu_state = MenuState::Hidden;
-        self.release_font_resources();
-        self.needs_redraw = true;
     }
 
-    /// Release font system resources to save memory
-    fn release_font_resources(&mut self) {
-        if self.font_system.is_some() {
-            info!("Releasing font system resources");
-            self.font_system = None;
-            self.swash_cache = None;
+    /// Repaint the open menus after their highlight changed
+    fn repaint_menu(&mut self) {
+        if self.xdg_shell.is_some() {
+            self.draw_menu_popups();
+        } else if let Some(rect) = self.menu_rect() {
+            self.damage_rect(rect);
         }
     }
 
-    /// Toggle scale mode between keep aspect ratio and free scale
-    fn toggle_scale_mode(&mut self) {
-        self.scale_mode = match self.scale_mode {
-            ScaleMode::KeepAspectRatio => {
-                info!("Scale mode: Free scale");
-                ScaleMode::FreeScale
-            }
-            ScaleMode::FreeScale => {
-                info!("Scale mode: Keep aspect ratio");
-                ScaleMode::KeepAspectRatio
+    /// Match the popups to the open menu levels, then place and redraw them
+    fn sync_menu_popups(&mut self) {
+        // Levels with the same submenu and size keep their popup
+        let keep = self
+            .menu_popups
+            .iter()
+            .zip(&self.menu_panels)
+            .enumerate()
+            .take_while(|(level, (popup, panel))| {
+                popup.path == self.menu_path[..*level]
+                    && popup.size == (panel.rect.width as u32, panel.rect.height as u32)
+            })
+            .count();
+        self.close_menu_popups(keep);
+
+        for level in keep..self.menu_panels.len() {
+            if let Err(e) = self.open_menu_popup(level) {
+                warn!(
+                    "Failed to open menu popup, drawing menus inside the pin: {}",
+                    e
+                );
+                self.close_menu_popups(0);
+                self.xdg_shell = None;
+                self.layout_menu();
+                return;
             }
-        };
-        // Invalidate cache when mode changes
-        self.cached_scaled_image = None;
+        }
+        self.place_menu_panels();
+        self.draw_menu_popups();
     }
 
-    /// Adjust opacity by delta
-    fn adjust_opacity(&mut self, delta: f32) {
-        let new_opacity = (self.opacity + delta).clamp(0.1, 1.0);
-        if (new_opacity - self.opacity).abs() > f32::EPSILON {
-            self.opacity = new_opacity;
-            info!("Opacity adjusted to: {:.2}", self.opacity);
-            self.needs_redraw = true;
+    /// Open the popup for a menu level, anchored to the pin or to its parent row
+    fn open_menu_popup(&mut self, level: usize) -> Result<()> {
+        let (Some(xdg_shell), Some(qh), Some(layer_surface)) = (
+            self.xdg_shell.as_ref(),
+            self.queue_handle.as_ref(),
+            self.layer_surface.as_ref(),
+        ) else {
+            return Err(anyhow!("Pin surface is not ready"));
+        };
+        let panel = &self.menu_panels[level];
+        let size = (panel.rect.width as u32, panel.rect.height as u32);
+
+        let positioner = XdgPositioner::new(xdg_shell)?;
+        positioner.set_size(size.0 as i32, size.1 as i32);
+        positioner.set_gravity(xdg_positioner::Gravity::BottomRight);
+        let parent = if level == 0 {
+            // At the pointer, flipping up or left and sliding back onto the output when needed
+            let insets = self.insets();
+            let x = self.menu_pos.0.clamp(0, self.width as i32 - 1) + insets.left;
+            let y = self.menu_pos.1.clamp(0, self.height as i32 - 1) + insets.top;
+            positioner.set_anchor_rect(x, y, 1, 1);
+            positioner.set_anchor(xdg_positioner::Anchor::TopLeft);
+            positioner.set_constraint_adjustment(
+                xdg_positioner::ConstraintAdjustment::FlipX
+                    | xdg_positioner::ConstraintAdjustment::FlipY
+                    | xdg_positioner::ConstraintAdjustment::SlideX
+                    | xdg_positioner::ConstraintAdjustment::SlideY,
+            );
+            None
+        } else {
+            // Beside the parent row, flipping to the parent's left side when needed
+            let parent = &self.menu_panels[level - 1];
+            let row = parent.rows[self.menu_path[level - 1]];
+            positioner.set_anchor_rect(0, row.y - parent.rect.y, parent.rect.width, row.height);
+            positioner.set_anchor(xdg_positioner::Anchor::TopRight);
+            positioner.set_constraint_adjustment(
+                xdg_positioner::ConstraintAdjustment::FlipX
+                    | xdg_positioner::ConstraintAdjustment::SlideX
+                    | xdg_positioner::ConstraintAdjustment::SlideY,
+            );
+            Some(self.menu_popups[level - 1].popup.xdg_surface())
+        };
+
+        let surface = self.compositor_state.create_surface(qh);
+        // Until the popup reports its own scale it is assumed to be on the pin's output
+        let scale = self.scale_factor.max(1) as u32;
+        surface.set_buffer_scale(scale as i32);
+        let popup = Popup::from_surface(parent, &positioner, qh, surface, xdg_shell)?;
+        if level == 0 {
+            layer_surface.get_popup(popup.xdg_popup());
+        }
+        if let Some(seat) = &self.seat {
+            popup.xdg_popup().grab(seat, self.input_serial);
         }
+        popup.wl_surface().commit();
+
+        self.menu_popups.push(MenuPopup {
+            popup,
+            path: self.menu_path[..level].to_vec(),
+            size,
+            position: None,
+            scale,
+            swapchain: None,
+        });
+        Ok(())
     }
 
-    /// Copy image to clipboard using wl-copy or xclip
-    fn copy_to_clipboard(&self) {
-        // Create a temporary PNG file
-        let temp_path = "/tmp/rspin_clipboard.png";
-
-        // Convert BGRA back to RGBA for saving
-        let mut rgba_data = self.image.rgba_data.clone();
-        for pixel in rgba_data.chunks_exact_mut(4) {
-            pixel.swap(0, 2); // Swap B and R back
+    /// Destroy the popups from `level` on, innermost first as xdg_shell requires
+    fn close_menu_popups(&mut self, level: usize) {
+        while self.menu_popups.len() > level {
+            self.menu_popups.pop();
         }
+    }
 
-        // Save as PNG
-        if let Err(e) = image::save_buffer(
-            temp_path,
-            &rgba_data,
-            self.image.width,
-            self.image.height,
-            image::ColorType::Rgba8,
-        ) {
-            error!("Failed to save temp image: {}", e);
-            return;
+    /// Move the panels to where the compositor placed their popups, so hit testing
+    /// keeps working in pin coordinates
+    fn place_menu_panels(&mut self) {
+        // The first popup is placed relative to the surface, which starts before the image
+        let insets = self.insets();
+        let mut origin = (-insets.left, -insets.top);
+        for (popup, panel) in self.menu_popups.iter().zip(&mut self.menu_panels) {
+            let Some((x, y)) = popup.position else {
+                break;
+            };
+            origin = (origin.0 + x, origin.1 + y);
+            panel.move_to(origin.0, origin.1);
         }
+    }
 
-        // Try wl-copy first (Wayland native)
-        let result = Command::new("wl-copy")
-            .arg("--type")
-            .arg("image/png")
-            .arg("-f")
-            .arg(temp_path)
-            .spawn();
+    /// Draw every configured menu popup
+    fn draw_menu_popups(&mut self) {
+        if self.glyph_atlas.is_full() {
+            self.reset_glyph_atlas();
+        }
+        self.paint_menu_popups();
+        // Text that did not fit is drawn once the atlas has started over
+        if self.glyph_atlas.is_full() {
+            self.reset_glyph_atlas();
+            self.paint_menu_popups();
+        }
+    }
 
-        match result {
-            Ok(mut child) => {
-                let _ = child.wait();
-                info!("Image copied to clipboard via wl-copy");
-            }
-            Err(_) => {
-                // Fallback to xclip
-                let result = Command::new("xclip")
-                    .arg("-selection")
-                    .arg("clipboard")
-                    .arg("-t")
-                    .arg("image/png")
-                    .arg("-i")
-                    .arg(temp_path)
-                    .spawn();
-
-                match result {
-                    Ok(mut child) => {
-                        let _ = child.wait();
-                        info!("Image copied to clipboard via xclip");
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to copy to clipboard: {}. Install wl-copy or xclip.",
-                            e
-                        );
-                    }
-                }
+    fn paint_menu_popups(&mut self) {
+        let mut popups = std::mem::take(&mut self.menu_popups);
+        let panels = std::mem::take(&mut self.menu_panels);
+        let entries = std::mem::take(&mut self.menu_entries);
+        for (level, (popup, panel)) in popups.iter_mut().zip(&panels).enumerate() {
+            if popup.position.is_none() {
+                continue;
+            }
+            let (width, height) = (popup.size.0 * popup.scale, popup.size.1 * popup.scale);
+            let mut level_panel =
+                OverlayPanel::with_pixels(Rect::new(0, 0, width as i32, height as i32));
+            let origin = (panel.rect.x, panel.rect.y);
+            self.draw_menu_level(
+                &mut level_panel,
+                origin,
+                level,
+                panel,
+                &entries,
+                popup.scale,
+            );
+            let level_panel = level_panel.flatten(&self.glyph_atlas);
+            if let Err(e) = Self::present_menu_popup(&self.shm, popup, &level_panel.pixels) {
+                error!("Failed to draw menu popup: {:?}", e);
             }
         }
+        self.menu_popups = popups;
+        self.menu_panels = panels;
+        self.menu_entries = entries;
+    }
 
-        // Clean up temp file
-        let _ = std::fs::remove_file(temp_path);
+    /// Copy a straight-alpha BGRA panel into a popup's shm buffer and commit it
+    fn present_menu_popup(shm: &Shm, popup: &mut MenuPopup, level_panel: &[u8]) -> Result<()> {
+        let (width, height) = (popup.size.0 * popup.scale, popup.size.1 * popup.scale);
+        let swapchain = match popup.swapchain.as_mut() {
+            Some(swapchain) => swapchain,
+            None => popup
+                .swapchain
+                .insert(ShmSwapchain::new(shm, width, height)?),
+        };
+        let (index, canvas, _) = swapchain.acquire(width, height)?;
+        // Blending onto a cleared buffer yields the premultiplied alpha wl_shm expects
+        canvas.fill(0);
+        let full = Rect::new(0, 0, width as i32, height as i32);
+        cpu_renderer::blend_panel(canvas, width, height, level_panel, width, (0, 0), full);
+        let surface = popup.popup.wl_surface();
+        surface.set_buffer_scale(popup.scale as i32);
+        swapchain.present(index, surface, &Damage::Full)?;
+        surface.commit();
+        Ok(())
     }
 
-    /// Update window position using layer shell margins
-    fn update_position(&mut self) {
-        if let Some(ref layer_surface) = self.layer_surface {
-            layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT);
-            layer_surface.set_margin(self.margin_top, 0, 0, self.margin_left);
-            layer_surface.commit();
-        }
+    /// Map a point on one of our surfaces to pin coordinates (menu popups are offset, and
+    /// the pin surface is padded when it has a shadow)
+    fn surface_to_pin(&self, surface: &wl_surface::WlSurface, (x, y): (f64, f64)) -> (f64, f64) {
+        self.menu_popups
+            .iter()
+            .zip(&self.menu_panels)
+            .find(|(popup, _)| popup.popup.wl_surface() == surface)
+            .map_or_else(
+                || {
+                    let insets = self.insets();
+                    (x - insets.left as f64, y - insets.top as f64)
+                },
+                |(_, panel)| (x + panel.rect.x as f64, y + panel.rect.y as f64),
+            )
     }
 
-    /// Update window size with optional frame rate limiting
-    fn update_size(&mut self) {
-        // Frame rate limiting during resize (target ~30fps = 33ms between frames)
-        const MIN_FRAME_INTERVAL_MS: u128 = 25;
+    /// Bounding rectangle of the open menus in surface coordinates
+    fn menu_rect(&self) -> Option<Rect> {
+        self.menu_panels
+            .iter()
+            .map(|panel| panel.rect)
+            .reduce(|a, b| a.union(&b))
+    }
 
-        let max_width = self.display_width.max(MIN_SIZE).min(MAX_SIZE);
-        let max_height = self.display_height.max(MIN_SIZE).min(MAX_SIZE);
-        self.width = self.width.clamp(MIN_SIZE, max_width);
-        self.height = self.height.clamp(MIN_SIZE, max_height);
+    /// Menu row under a point as (level, row), preferring the innermost submenu
+    fn menu_row_at(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        self.menu_panels
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(level, panel)| panel.row_at(x, y).map(|row| (level, row)))
+    }
 
-        if self.resizing {
-            if let Some(last_draw) = self.last_resize_draw {
-                let elapsed = last_draw.elapsed().as_millis();
-                if elapsed < MIN_FRAME_INTERVAL_MS {
-                    // Skip this frame, just update layer shell size
-                    if let Some(ref layer_surface) = self.layer_surface {
-                        layer_surface.set_size(self.width, self.height);
-                        layer_surface.commit();
-                    }
-                    return;
-                }
+    /// Menu item at a (level, row) position
+    fn menu_item(&self, level: usize, row: usize) -> Option<&MenuItem<MenuAction>> {
+        let path = self.menu_path.get(..level)?;
+        menu::entries_at(&self.menu_entries, path)?.get(row)?.item()
+    }
+
+    /// Track the hovered row, opening submenus under the pointer and closing others
+    fn hover_menu(&mut self, x: f64, y: f64) {
+        let hover = self
+            .menu_row_at(x, y)
+            .filter(|&(level, row)| self.menu_item(level, row).is_some_and(|item| item.enabled));
+        if hover == self.menu_hover {
+            return;
+        }
+        self.menu_hover = hover;
+
+        if let Some((level, row)) = hover {
+            let opens_submenu = self
+                .menu_item(level, row)
+                .is_some_and(MenuItem::has_submenu);
+            let mut path = self.menu_path[..level.min(self.menu_path.len())].to_vec();
+            if opens_submenu {
+                path.push(row);
+            }
+            if path != self.menu_path {
+                self.menu_path = path;
+                self.layout_menu();
+                return;
             }
-            self.last_resize_draw = Some(Instant::now());
         }
+        self.repaint_menu();
+    }
 
-        if let Some(ref layer_surface) = self.layer_surface {
-            layer_surface.set_size(self.width, self.height);
-            layer_surface.commit();
-        }
-        // Reset pool to force buffer recreation
-        self.pool = None;
-        self.needs_redraw = true;
+    /// Activate the item under a click; returns false if the click missed the menu
+    fn click_menu(&mut self, x: f64, y: f64) -> bool {
+        let Some((level, row)) = self.menu_row_at(x, y) else {
+            return false;
+        };
+        self.activate_menu_row(level, row, false);
+        true
     }
 
-    /// Initialize GPU renderer from Wayland surface
-    fn init_gpu_renderer(&mut self) {
-        if self.gpu_initialized {
+    /// Run a row's action or open its submenu (selecting its first item when `select_first`)
+    fn activate_menu_row(&mut self, level: usize, row: usize, select_first: bool) {
+        let Some(item) = self.menu_item(level, row) else {
+            return;
+        };
+        if !item.enabled {
             return;
         }
-
-        let layer_surface = match &self.layer_surface {
-            Some(ls) => ls,
+        match item.action {
+            Some(action) => self.handle_menu_action(action),
             None => {
-                warn!("Cannot init GPU: no layer surface");
-                return;
+                self.menu_path.truncate(level);
+                self.menu_path.push(row);
+                self.menu_hover = Some((level, row));
+                self.layout_menu();
+                if select_first {
+                    self.step_menu_selection(level + 1, MenuStep::First);
+                }
             }
-        };
+        }
+    }
 
-        // Get raw pointers from Wayland objects
-        // The Proxy trait provides id() which gives ObjectId
-        // With wayland-backend client_system feature, ObjectId.as_ptr() is available
-        let wl_surface = layer_surface.wl_surface();
-        let surface_ptr = wl_surface.id().as_ptr() as *mut std::ffi::c_void;
+    /// Rows of a menu level that can be selected
+    fn menu_enabled_rows(&self, level: usize) -> Vec<usize> {
+        let Some(entries) = self
+            .menu_path
+            .get(..level)
+            .and_then(|path| menu::entries_at(&self.menu_entries, path))
+        else {
+            return Vec::new();
+        };
+        menu::enabled_rows(entries)
+    }
 
-        // Use the display pointer we stored
-        let display_ptr = self.display_ptr;
+    /// Select a row, closing submenus below its level
+    fn select_menu_row(&mut self, level: usize, row: usize) {
+        self.menu_hover = Some((level, row));
+        if self.menu_path.len() > level {
+            self.menu_path.truncate(level);
+            self.layout_menu();
+        } else {
+            self.repaint_menu();
+        }
+    }
 
-        if display_ptr.is_null() {
-            warn!("Display pointer is null, falling back to CPU rendering");
-            self.use_gpu = false;
-            return;
+    /// Move the keyboard selection within a menu level, wrapping around
+    fn step_menu_selection(&mut self, level: usize, step: MenuStep) {
+        let rows = self.menu_enabled_rows(level);
+        if let Some(row) = menu::step_row(&rows, self.menu_hover_row(level), step) {
+            self.select_menu_row(level, row);
         }
+    }
 
-        info!("Initializing GPU renderer...");
-        info!("  Surface ptr: {:?}", surface_ptr);
-        info!("  Display ptr: {:?}", display_ptr);
-        info!("  Size: {}x{}", self.width, self.height);
+    /// Row selected in a menu level, if the selection is on that level
+    fn menu_hover_row(&self, level: usize) -> Option<usize> {
+        self.menu_hover
+            .filter(|&(hover_level, _)| hover_level == level)
+            .map(|(_, row)| row)
+    }
 
-        match WgpuRenderer::new(display_ptr, surface_ptr, self.width, self.height) {
-            Ok(mut renderer) => {
-                // Upload initial texture
-                if let Err(e) = renderer.upload_texture(&self.image) {
-                    warn!("Failed to upload texture to GPU: {:?}", e);
-                    self.use_gpu = false;
-                    return;
-                }
-                renderer.update_opacity(self.opacity);
-                self.gpu_renderer = Some(renderer);
-                self.gpu_initialized = true;
-                info!("GPU renderer initialized successfully");
+    /// Close the innermost open submenu and select its parent; false if none is open
+    fn close_submenu(&mut self) -> bool {
+        let Some(parent) = self.menu_path.pop() else {
+            return false;
+        };
+        self.menu_hover = Some((self.menu_path.len(), parent));
+        self.layout_menu();
+        true
+    }
 
-                // Release raw image data to save memory since GPU has its own copy
-                let freed = self.image.release_raw_data();
-                info!(
-                    "Released {} bytes of CPU image data after GPU upload",
-                    freed
-                );
+    /// Select the next item with accelerator `letter`, activating it if it is the only one
+    fn menu_accelerator(&mut self, level: usize, letter: char) {
+        let Some(path) = self.menu_path.get(..level) else {
+            return;
+        };
+        let Some(entries) = menu::entries_at(&self.menu_entries, path) else {
+            return;
+        };
+        match menu::accelerator_row(entries, self.menu_hover_row(level), letter) {
+            Some((row, true)) => self.activate_menu_row(level, row, true),
+            Some((row, false)) => self.select_menu_row(level, row),
+            None => {}
+        }
+    }
 
-                // Also clear CPU rendering caches
-                self.cached_scaled_image = None;
-                self.pool = None;
+    /// Handle a key while the context menu is open (the menu takes every key)
+    fn menu_key(&mut self, event: &KeyEvent) {
+        let level = self.menu_hover.map_or(0, |(level, _)| level);
+        match event.keysym {
+            Keysym::Up => self.step_menu_selection(level, MenuStep::Previous),
+            Keysym::Down => self.step_menu_selection(level, MenuStep::Next),
+            Keysym::Home => self.step_menu_selection(level, MenuStep::First),
+            Keysym::End => self.step_menu_selection(level, MenuStep::Last),
+            Keysym::Right => {
+                if let Some((level, row)) = self.menu_hover {
+                    if self
+                        .menu_item(level, row)
+                        .is_some_and(MenuItem::has_submenu)
+                    {
+                        self.activate_menu_row(level, row, true);
+                    }
+                }
             }
-            Err(e) => {
-                warn!("Failed to initialize GPU renderer: {:?}", e);
-                warn!("Falling back to CPU rendering");
-                self.use_gpu = false;
+            Keysym::Left => {
+                self.close_submenu();
+            }
+            // Escape closes a submenu first, then the menu, never the window
+            Keysym::Escape => {
+                if !self.close_submenu() {
+                    self.hide_menu();
+                }
+            }
+            Keysym::Return | Keysym::KP_Enter | Keysym::space => {
+                if let Some((level, row)) = self.menu_hover {
+                    self.activate_menu_row(level, row, true);
+                }
+            }
+            _ => {
+                let letter = event
+                    .utf8
+                    .as_deref()
+                    .and_then(|text| text.chars().next())
+                    .filter(|c| !c.is_control());
+                if let Some(letter) = letter {
+                    self.menu_accelerator(level, letter);
+                }
             }
         }
     }
 
-    /// Mark GPU initialization to run after the first frame is displayed
-    fn request_gpu_init(&mut self) {
-        if self.use_gpu && !self.gpu_initialized {
-            self.gpu_init_pending = true;
-        }
+    /// Schedule a redraw of the whole window
+    fn request_redraw(&mut self) {
+        self.damage.add_full();
+        self.needs_redraw = true;
     }
 
-    /// Execute pending GPU initialization work outside of critical event handlers
-    fn process_gpu_init(&mut self, qh: &QueueHandle<Self>) {
-        if !self.use_gpu || self.gpu_initialized || !self.gpu_init_pending {
+    /// Schedule a redraw of a single region
+    fn damage_rect(&mut self, rect: Rect) {
+        self.damage.add(rect);
+        self.needs_redraw = true;
+    }
+
+    /// Switch between keeping the aspect ratio and free scaling
+    fn set_scale_mode(&mut self, mode: ScaleMode) {
+        if mode == self.scale_mode {
             return;
         }
+        self.scale_mode = mode;
+        info!("Scale mode: {}", mode.label());
+        // Invalidate cache when mode changes
+        self.cached_scaled_image = None;
+        self.request_redraw();
+        self.update_hud();
+        self.show_toast(trf("Scale: {}", &[&tr(mode.label())]));
+    }
 
-        self.init_gpu_renderer();
-        self.gpu_init_pending = false;
+    /// Select the resampling filter
+    fn set_filter(&mut self, filter: FilterMode) {
+        if filter == self.filter {
+            return;
+        }
+        self.filter = filter;
+        info!("Resampling filter: {}", self.filter.label());
+        // Invalidate cache so the CPU path re-renders with the new filter
+        self.cached_scaled_image = None;
+        self.request_redraw();
+        self.show_toast(trf("Filter: {}", &[&tr(self.filter.label())]));
+    }
 
-        if self.use_gpu && self.gpu_initialized {
-            // Re-render so the GPU texture path becomes active immediately
-            self.needs_redraw = true;
-            self.draw(qh);
+    /// Toggle color picker mode
+    fn toggle_picker(&mut self) {
+        if !self.picker_active && self.measure_active {
+            self.toggle_measure();
         }
+        self.picker_active = !self.picker_active;
+        info!(
+            "Color picker: {}",
+            if self.picker_active { "on" } else { "off" }
+        );
+        self.set_cursor_on_next_frame = Some(if self.picker_active {
+            CursorIcon::Crosshair
+        } else {
+            CursorIcon::Default
+        });
+        self.update_picker_readout();
     }
 
-    /// Draw the image to the surface buffer with scaling support
-    fn draw(&mut self, _qh: &QueueHandle<Self>) {
-        if !self.configured {
-            return;
+    /// Toggle measure mode (finished measurements stay until cleared)
+    fn toggle_measure(&mut self) {
+        if !self.measure_active && self.picker_active {
+            self.toggle_picker();
+        }
+        self.measure_active = !self.measure_active;
+        info!(
+            "Measure mode: {}",
+            if self.measure_active { "on" } else { "off" }
+        );
+        if !self.measure_active && self.measure_drag.take().is_some() {
+            self.invalidate_measurements();
         }
+        self.set_cursor_on_next_frame = Some(if self.measure_active {
+            CursorIcon::Crosshair
+        } else {
+            CursorIcon::Default
+        });
+    }
 
-        if self.layer_surface.is_none() {
+    /// Remove all measurements
+    fn clear_measurements(&mut self) {
+        if self.measurements.is_empty() && self.measure_drag.is_none() {
             return;
         }
+        info!("Cleared {} measurement(s)", self.measurements.len());
+        self.measurements.clear();
+        self.measure_drag = None;
+        self.invalidate_measurements();
+    }
 
-        // Clamp size to display bounds to avoid oversized buffers
-        let max_width = self.display_width.max(MIN_SIZE).min(MAX_SIZE);
-        let max_height = self.display_height.max(MIN_SIZE).min(MAX_SIZE);
+    /// Surface to source pixel mapping for the current window size
+    fn source_mapping(&self) -> Mapping {
+        Mapping::new(
+            (self.width, self.height),
+            (self.image.width, self.image.height),
+            self.image_scale,
+        )
+    }
+
+    /// Rebuild the measurement overlay before the next frame
+    fn invalidate_measurements(&mut self) {
+        self.measure_dirty = true;
+        self.needs_redraw = true;
+    }
+
+    /// Render all measurements into one panel covering their bounds
+    fn rebuild_measure_panel(&mut self) {
+        self.measure_dirty = false;
+        self.measure_panel_size = (self.width, self.height);
+        if let Some(old) = self.measure_panel.take() {
+            self.damage_rect(old.panel.rect);
+        }
+
+        let items: Vec<Measurement> = self
+            .measurements
+            .iter()
+            .chain(self.measure_drag.iter())
+            .filter(|m| !m.is_empty())
+            .copied()
+            .collect();
+        if items.is_empty() {
+            return;
+        }
+
+        // Labels are sized from their text
+        let labels: Vec<([String; 3], (i32, i32))> = items
+            .iter()
+            .map(|m| {
+                let lines = m.label();
+                let width = self.widest_line(&lines);
+                let (w, h) = self.theme.text_panel_size(width, lines.len(), 0);
+                (lines, (w as i32, h as i32))
+            })
+            .collect();
+
+        // Surface geometry: line end points and label rectangles
+        let mapping = self.source_mapping();
+        let geometry: Vec<_> = items
+            .iter()
+            .zip(&labels)
+            .map(|(m, &(_, (label_w, label_h)))| {
+                let a = mapping.to_surface(m.start);
+                let b = mapping.to_surface(m.end);
+                let mut x = b.0 as i32 + MEASURE_LABEL_OFFSET;
+                let mut y = b.1 as i32 + MEASURE_LABEL_OFFSET;
+                if x + label_w > self.width as i32 {
+                    x = b.0 as i32 - MEASURE_LABEL_OFFSET - label_w;
+                }
+                if y + label_h > self.height as i32 {
+                    y = b.1 as i32 - MEASURE_LABEL_OFFSET - label_h;
+                }
+                let label = Rect::new(
+                    x.clamp(0, (self.width as i32 - label_w).max(0)),
+                    y.clamp(0, (self.height as i32 - label_h).max(0)),
+                    label_w,
+                    label_h,
+                );
+                (a, b, label)
+            })
+            .collect();
+
+        let margin = measure::ENDPOINT_SIZE;
+        let Some(bounds) = geometry
+            .iter()
+            .map(|(a, b, label)| {
+                let x0 = a.0.min(b.0) as i32 - margin;
+                let y0 = a.1.min(b.1) as i32 - margin;
+                let x1 = a.0.max(b.0) as i32 + margin + 1;
+                let y1 = a.1.max(b.1) as i32 + margin + 1;
+                Rect::new(x0, y0, x1 - x0, y1 - y0).union(label)
+            })
+            .reduce(|acc, rect| acc.union(&rect))
+            .and_then(|rect| rect.clamp_to(self.width, self.height))
+        else {
+            return;
+        };
+
+        let (pw, ph) = (bounds.width as u32, bounds.height as u32);
+        let mut overlay = OverlayPanel::with_pixels(bounds);
+        let panel = &mut overlay.pixels;
+        let local = |p: (f64, f64)| (p.0 - bounds.x as f64, p.1 - bounds.y as f64);
+
+        for (a, b, _) in &geometry {
+            let (a, b) = (local(*a), local(*b));
+            // Spanned box, then the line and its end points on top
+            let box_rect = Rect::new(
+                a.0.min(b.0) as i32,
+                a.1.min(b.1) as i32,
+                (a.0 - b.0).abs() as i32 + 1,
+                (a.1 - b.1).abs() as i32 + 1,
+            );
+            Self::stroke_rect(panel, pw, ph, box_rect, measure::BOX_COLOR);
+            measure::draw_line(panel, pw, ph, a, b, measure::LINE_COLOR, 2);
+            for p in [a, b] {
+                let marker = Rect::new(
+                    p.0 as i32 - margin / 2,
+                    p.1 as i32 - margin / 2,
+                    margin,
+                    margin,
+                );
+                Self::fill_rect(panel, pw, ph, marker, measure::LINE_COLOR);
+            }
+        }
+
+        let pitch = self.theme.line_pitch();
+        for ((lines, _), (_, _, label)) in labels.iter().zip(&geometry) {
+            let label = Rect::new(
+                label.x - bounds.x,
+                label.y - bounds.y,
+                label.width,
+                label.height,
+            );
+            // Label backgrounds are drawn beneath the lines, so clear the lines they cover
+            let radius = self.theme.radius;
+            Self::fill_round_rect(&mut overlay.pixels, pw, ph, label, radius, [0; 4]);
+            overlay.frames.push(self.panel_frame(label));
+            let text_width = (label.width as u32).saturating_sub(2 * self.theme.padding);
+            for (i, line) in lines.iter().enumerate() {
+                let (x, y) = self.theme.text_panel_line(i, 0);
+                let text = self.layout_text(
+                    label.x + x,
+                    label.y + y,
+                    line,
+                    self.theme.palette.text,
+                    (text_width, pitch),
+                );
+                overlay.text.extend(text);
+            }
+        }
+
+        self.damage_rect(bounds);
+        self.measure_panel = Some(CachedPanel {
+            key: (),
+            panel: overlay,
+            uploaded: None,
+        });
+    }
+
+    /// Source pixel under the pointer
+    fn picked_color(&self) -> Option<PickedColor> {
+        picker::sample(&self.image, self.width, self.height, self.pointer_pos)
+    }
+
+    /// Readout rectangle next to the pointer, flipped to stay inside the window
+    fn picker_panel_rect(&mut self) -> Option<Rect> {
+        if !self.picker_active {
+            return None;
+        }
+        let lines = self.picked_color()?.readout();
+        let width = self.widest_line(&lines);
+        let (w, h) = self
+            .theme
+            .text_panel_size(width, lines.len(), PICKER_TEXT_INDENT);
+        if self.width < w || self.height < h {
+            return None;
+        }
+
+        let (w, h) = (w as i32, h as i32);
+        let (px, py) = (self.pointer_pos.0 as i32, self.pointer_pos.1 as i32);
+        let mut x = px + PICKER_PANEL_OFFSET;
+        let mut y = py + PICKER_PANEL_OFFSET;
+        if x + w > self.width as i32 {
+            x = px - PICKER_PANEL_OFFSET - w;
+        }
+        if y + h > self.height as i32 {
+            y = py - PICKER_PANEL_OFFSET - h;
+        }
+        Some(Rect::new(
+            x.clamp(0, self.width as i32 - w),
+            y.clamp(0, self.height as i32 - h),
+            w,
+            h,
+        ))
+    }
+
+    /// Repaint the readout where it was and where it is now
+    fn update_picker_readout(&mut self) {
+        let rect = self.picker_panel_rect();
+        // The readout content changes with every move, so repaint it even in place
+        for r in [self.picker_rect, rect].into_iter().flatten() {
+            self.damage_rect(r);
+        }
+        self.picker_rect = rect;
+    }
+
+    /// Loupe rectangle centered on the pointer, kept inside the window
+    fn loupe_panel_rect(&self) -> Option<Rect> {
+        self.loupe_held?;
+        let (x, y) = self.pointer_pos;
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        let size = LOUPE_SIZE.min(self.width).min(self.height) as i32;
+        Some(Rect::new(
+            (x as i32 - size / 2).clamp(0, self.width as i32 - size),
+            (y as i32 - size / 2).clamp(0, self.height as i32 - size),
+            size,
+            size,
+        ))
+    }
+
+    /// Repaint the loupe where it was and where it is now
+    fn update_loupe(&mut self) {
+        let rect = self.loupe_panel_rect();
+        for r in [self.loupe_rect, rect].into_iter().flatten() {
+            self.damage_rect(r);
+        }
+        self.loupe_rect = rect;
+    }
+
+    /// Lines of the info overlay for the current source and window
+    fn hud_lines(&self) -> Vec<String> {
+        hud::lines(&HudState {
+            source: &self.source_info,
+            display_width: self.width,
+            display_height: self.height,
+            opacity: self.opacity,
+            scale_mode: tr(self.scale_mode.label()),
+        })
+    }
+
+    /// Info overlay rectangle in the top-left corner, if it is shown and fits
+    fn hud_panel_rect(&mut self) -> Option<Rect> {
+        let visible = match self.hud_mode {
+            HudMode::Off => false,
+            HudMode::Hover => {
+                let (x, y) = self.pointer_pos;
+                x >= 0.0 && y >= 0.0 && x < self.width as f64 && y < self.height as f64
+            }
+            HudMode::Always => true,
+        };
+        if !visible {
+            return None;
+        }
+        let lines = self.hud_lines();
+        let width = self.widest_line(&lines);
+        let (w, h) = self.theme.text_panel_size(width, lines.len(), 0);
+        let (w, h) = (w as i32, h as i32);
+        if (self.width as i32) < w + 2 * HUD_PANEL_INSET
+            || (self.height as i32) < h + 2 * HUD_PANEL_INSET
+        {
+            return None;
+        }
+        Some(Rect::new(HUD_PANEL_INSET, HUD_PANEL_INSET, w, h))
+    }
+
+    /// Repaint the info overlay where it was and where it is now
+    fn update_hud(&mut self) {
+        let rect = self.hud_panel_rect();
+        // The text may have changed even if the panel did not move
+        for r in [self.hud_rect, rect].into_iter().flatten() {
+            self.damage_rect(r);
+        }
+        self.hud_rect = rect;
+    }
+
+    /// Show the info overlay permanently, or go back to the startup behaviour
+    fn toggle_hud(&mut self) {
+        self.hud_mode = if self.hud_mode != HudMode::Always {
+            HudMode::Always
+        } else if self.hud_default != HudMode::Always {
+            self.hud_default
+        } else {
+            HudMode::Off
+        };
+        info!("Info overlay: {:?}", self.hud_mode);
+        self.update_hud();
+    }
+
+    /// Change the loupe magnification by one step
+    fn adjust_loupe_zoom(&mut self, steps: i32) {
+        let zoom = (self.loupe_zoom as i32 + steps * loupe::ZOOM_STEP as i32)
+            .clamp(loupe::MIN_ZOOM as i32, loupe::MAX_ZOOM as i32) as u32;
+        if zoom != self.loupe_zoom {
+            self.loupe_zoom = zoom;
+            debug!("Loupe zoom: {}x", zoom);
+            self.update_loupe();
+        }
+    }
+
+    /// Show a short message in the pin, replacing any toast still on screen
+    fn show_toast(&mut self, text: impl Into<String>) {
+        self.toast = Some(Toast::new(text));
+        self.update_toast();
+        if self.toast_timer_pending {
+            // The pending timer picks up the new toast's schedule when it fires
+            return;
+        }
+        let timer = Timer::from_duration(toast::HOLD);
+        match self
+            .loop_handle
+            .insert_source(timer, |_, _, app| match app.tick_toast() {
+                Some(delay) => TimeoutAction::ToDuration(delay),
+                None => {
+                    app.toast_timer_pending = false;
+                    TimeoutAction::Drop
+                }
+            }) {
+            Ok(_) => self.toast_timer_pending = true,
+            Err(e) => error!("Failed to schedule toast timer: {}", e.error),
+        }
+    }
+
+    /// Advance the toast fade, returning the delay until the next step
+    fn tick_toast(&mut self) -> Option<Duration> {
+        let next = self
+            .toast
+            .as_ref()
+            .and_then(|toast| toast.next_update(Instant::now()));
+        if next.is_none() {
+            self.toast = None;
+        }
+        self.update_toast();
+        next
+    }
+
+    /// Toast rectangle centered near the bottom edge, sized to its shaped text
+    fn toast_panel_rect(&mut self) -> Option<Rect> {
+        let text = self.toast.as_ref()?.text.clone();
+        let max_width = self.width as i32 - 2 * TOAST_INSET;
+        let h = (self.theme.line_pitch() + 2 * TOAST_PADDING.1) as i32;
+        if max_width <= 0 || (self.height as i32) < h + 2 * TOAST_INSET {
+            return None;
+        }
+        let text_width = self.measure_text(&text) as i32;
+        let w = (text_width + 2 * TOAST_PADDING.0 as i32).min(max_width);
+        Some(Rect::new(
+            (self.width as i32 - w) / 2,
+            self.height as i32 - TOAST_INSET - h,
+            w,
+            h,
+        ))
+    }
+
+    /// Repaint the toast where it was and where it is now
+    fn update_toast(&mut self) {
+        let rect = self.toast_panel_rect();
+        // The text and fade change in place, so repaint it even if it did not move
+        for r in [self.toast_rect, rect].into_iter().flatten() {
+            self.damage_rect(r);
+        }
+        self.toast_rect = rect;
+    }
+
+    /// Copy the picked color as hex (or CSS `rgb()` with Shift held)
+    fn copy_picked_color(&mut self) {
+        let Some(color) = self.picked_color() else {
+            return;
+        };
+        let text = if self.modifiers.shift {
+            color.css()
+        } else {
+            color.hex()
+        };
+        info!("Picked {} at {},{}", text, color.x, color.y);
+        let done = trf("Copied {}", &[&text]);
+        self.copy_bytes_to_clipboard(None, text.as_bytes(), done);
+    }
+
+    /// Adjust opacity by delta
+    fn adjust_opacity(&mut self, delta: f32) {
+        let new_opacity = (self.opacity + delta).clamp(0.1, 1.0);
+        if (new_opacity - self.opacity).abs() > f32::EPSILON {
+            self.opacity = new_opacity;
+            info!("Opacity adjusted to: {:.2}", self.opacity);
+            self.show_toast(trf("Opacity {}%", &[&(self.opacity * 100.0).round()]));
+            self.request_redraw();
+        }
+    }
+
+    /// Reload the image from its source file (triggered by SIGUSR1/SIGHUP)
+    fn request_reload(&mut self) {
+        if self.reloads.request() {
+            self.reload_image();
+        }
+    }
+
+    /// Turn file watching on or off
+    fn toggle_watch(&mut self) {
+        if self.watcher.is_some() {
+            self.stop_watching();
+        } else {
+            self.start_watching();
+        }
+    }
+
+    /// Watch the source file and reload it whenever it is rewritten or replaced
+    fn start_watching(&mut self) {
+        if self.watcher.is_some() {
+            return;
+        }
+        let Some(path) = self.source_path.clone() else {
+            warn!("Cannot watch: image was read from stdin");
+            return;
+        };
+
+        let watcher = match FileWatcher::new(&path) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("{:#}", e);
+                return;
+            }
+        };
+        let fd = match watcher.fd() {
+            Ok(fd) => fd,
+            Err(e) => {
+                error!("{:#}", e);
+                return;
+            }
+        };
+
+        let source = Generic::new(fd, Interest::READ, Mode::Level);
+        match self.loop_handle.insert_source(source, |_, _, app| {
+            let changed = app
+                .watcher
+                .as_mut()
+                .is_some_and(|(watcher, _)| watcher.file_changed());
+            if changed {
+                app.schedule_watch_reload();
+            }
+            Ok(PostAction::Continue)
+        }) {
+            Ok(token) => {
+                info!("Watching {} for changes", path.display());
+                self.watcher = Some((watcher, token));
+            }
+            Err(e) => error!("Failed to register file watcher: {}", e.error),
+        }
+    }
+
+    /// Stop watching the source file
+    fn stop_watching(&mut self) {
+        if let Some((_, token)) = self.watcher.take() {
+            self.loop_handle.remove(token);
+            info!("Stopped watching for changes");
+        }
+        if let Some(token) = self.watch_reload_timer.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Reload shortly after the last change so multi-step writes settle first
+    ///
+    /// Every change restarts the quiet period, so the file is read once writing stops.
+    fn schedule_watch_reload(&mut self) {
+        if let Some(token) = self.watch_reload_timer.take() {
+            self.loop_handle.remove(token);
+        }
+
+        let timer = Timer::from_duration(WATCH_DEBOUNCE);
+        match self.loop_handle.insert_source(timer, |_, _, app| {
+            app.watch_reload_timer = None;
+            debug!("Source file changed, reloading");
+            app.request_reload();
+            TimeoutAction::Drop
+        }) {
+            Ok(token) => self.watch_reload_timer = Some(token),
+            Err(e) => error!("Failed to schedule reload: {}", e.error),
+        }
+    }
+
+    /// Decode the source file again in the background
+    fn reload_image(&mut self) {
+        let Some(path) = self.source_path.clone() else {
+            warn!("Cannot reload: image was read from stdin");
+            return;
+        };
+
+        // Coalesce requests that arrive while a decode is running
+        if !self.reloads.try_start() {
+            return;
+        }
+
+        let scale = self.image_scale;
+        self.spawn_decode(DecodeKind::Reload, move || {
+            image_loader::load_image_file(&path, scale)
+        });
+    }
+
+    /// Run a decode job on a worker thread and deliver the result to the event loop
+    fn spawn_decode<F>(&mut self, kind: DecodeKind, job: F)
+    where
+        F: FnOnce() -> Result<ImageData> + Send + 'static,
+    {
+        let sender = self.decode_sender.clone();
+        let spawned = thread::Builder::new()
+            .name("rspin-decode".to_string())
+            .spawn(move || {
+                let start = Instant::now();
+                let result = job();
+                debug!("Decode finished in {:?}", start.elapsed());
+                // The receiver is gone only if the application is exiting
+                let _ = sender.send((kind, result));
+            });
+
+        match spawned {
+            Ok(_) => self.reloads.started(),
+            Err(e) => error!("Failed to spawn decode thread: {}", e),
+        }
+    }
+
+    /// Apply the result of a background decode
+    fn handle_decoded(&mut self, kind: DecodeKind, result: Result<ImageData>) {
+        let outcome = self.reloads.finish(kind, result.is_ok(), self.image_loaded);
+
+        match (outcome, result) {
+            (Outcome::Skip, Ok(_)) => {
+                debug!("Ignoring initial decode, a newer stream frame is already shown");
+            }
+            (_, Ok(image)) => {
+                info!(
+                    "Image decoded: {}x{} pixels ({:?})",
+                    image.width, image.height, kind
+                );
+                self.image_loaded = true;
+                self.refresh_source_info(kind, &image);
+                self.replace_image(image);
+            }
+            (Outcome::Skip, Err(e)) => {
+                debug!("Ignoring failed decode, a newer reload is queued: {:#}", e);
+            }
+            (Outcome::Retry(attempt), Err(e)) => {
+                warn!(
+                    "Reload failed ({:#}), retrying in {}ms ({}/{})",
+                    e,
+                    reload::RETRY_DELAY.as_millis(),
+                    attempt,
+                    reload::MAX_RETRIES
+                );
+                let timer = Timer::from_duration(reload::RETRY_DELAY);
+                if let Err(e) = self.loop_handle.insert_source(timer, |_, _, app| {
+                    app.reload_image();
+                    TimeoutAction::Drop
+                }) {
+                    error!("Failed to schedule reload retry: {}", e.error);
+                }
+            }
+            (_, Err(e)) => {
+                error!("Failed to load image: {:#}", e);
+                // Keep showing the last good image; only replace the placeholder
+                if !self.image_loaded {
+                    self.show_error(&e);
+                }
+            }
+        }
+
+        if self.reloads.take_queued() {
+            self.reload_image();
+        }
+    }
+
+    /// Update the info overlay's source description for a newly decoded image
+    fn refresh_source_info(&mut self, kind: DecodeKind, image: &ImageData) {
+        let scale = if self.image_scale > 0.0 {
+            self.image_scale
+        } else {
+            1.0
+        };
+        self.source_info.width = (image.width as f32 / scale).round() as u32;
+        self.source_info.height = (image.height as f32 / scale).round() as u32;
+        match kind {
+            DecodeKind::Reload => {
+                if let Some(ref path) = self.source_path {
+                    self.source_info.file_size = std::fs::metadata(path).ok().map(|m| m.len());
+                    self.source_info.format = image::ImageFormat::from_path(path)
+                        .ok()
+                        .or(self.source_info.format);
+                }
+            }
+            // Frame sizes are not reported by the stream reader
+            DecodeKind::Stream => self.source_info.file_size = None,
+            DecodeKind::Initial => {}
+        }
+    }
+
+    /// Read and decode `--stream` frames on a worker thread, showing each as it arrives
+    fn start_stream(&mut self, mut frames: FrameReader<Stdin>) {
+        // A single slot keeps the reader from racing ahead of the display
+        let (sender, frame_channel) = channel::sync_channel::<DecodeResult>(1);
+        if let Err(e) = self
+            .loop_handle
+            .insert_source(frame_channel, |event, _, app| {
+                if let channel::Event::Msg((kind, result)) = event {
+                    app.handle_decoded(kind, result);
+                }
+            })
+        {
+            error!("Failed to register stream channel: {}", e.error);
+            return;
+        }
+
+        let scale = self.image_scale;
+        let spawned = thread::Builder::new()
+            .name("rspin-stream".to_string())
+            .spawn(move || loop {
+                let frame = match frames.next_frame() {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => {
+                        info!("Image stream ended, keeping the last frame");
+                        break;
+                    }
+                    Err(e) => {
+                        // Frame boundaries are lost, so no later image can be found
+                        let _ = sender.send((DecodeKind::Stream, Err(e)));
+                        break;
+                    }
+                };
+                // A frame that fails to decode is reported but does not end the stream
+                let result = image_loader::load_image_bytes(&frame, scale);
+                // The receiver is gone only if the application is exiting
+                if sender.send((DecodeKind::Stream, result)).is_err() {
+                    break;
+                }
+            });
+
+        if let Err(e) = spawned {
+            error!("Failed to spawn stream thread: {}", e);
+        }
+    }
+
+    /// Ask the settings portal for the desktop's light/dark preference on a worker thread
+    ///
+    /// Menus start dark and switch palette when the answer arrives, so a slow or
+    /// missing portal never delays the window.
+    fn query_color_scheme(&mut self) {
+        let (sender, scheme_channel) = channel::channel::<bool>();
+        if let Err(e) = self
+            .loop_handle
+            .insert_source(scheme_channel, |event, _, app| {
+                if let channel::Event::Msg(dark) = event {
+                    app.apply_color_scheme(dark);
+                }
+            })
+        {
+            error!("Failed to register color scheme channel: {}", e.error);
+            return;
+        }
+
+        let spawned = thread::Builder::new()
+            .name("rspin-portal".to_string())
+            .spawn(move || {
+                if let Some(dark) = theme::portal_prefers_dark() {
+                    let _ = sender.send(dark);
+                }
+            });
+        if let Err(e) = spawned {
+            error!("Failed to spawn portal thread: {}", e);
+        }
+    }
+
+    /// Switch the menu and panel palette to the desktop's preference
+    fn apply_color_scheme(&mut self, dark: bool) {
+        let palette = self.theme.palette;
+        self.theme.set_dark(dark);
+        if self.theme.palette == palette {
+            return;
+        }
+        debug!(
+            "Switching to the {} palette",
+            if dark { "dark" } else { "light" }
+        );
+        self.measure_dirty = true;
+        self.request_redraw();
+        self.draw_menu_popups();
+    }
+
+    /// Replace the placeholder with a message describing a decode failure
+    fn show_error(&mut self, err: &anyhow::Error) {
+        let title = format!("⚠ {}", tr("Failed to load image"));
+        let message = format!("{:#}", err);
+
+        // The pin takes the size of the message, whatever size it had before
+        let (pad_x, pad_y) = ERROR_PADDING;
+        let wrap_width = ERROR_MAX_WIDTH - 2 * pad_x;
+        let (title_width, _) = self.measure_wrapped(&title, wrap_width);
+        let (message_width, message_height) = self.measure_wrapped(&message, wrap_width);
+        let text_width = title_width.max(message_width);
+        let width = (text_width + 2 * pad_x).max(MIN_SIZE);
+        let height = (self.theme.item_height + message_height + 2 * pad_y).max(MIN_SIZE);
+        let mut canvas = ERROR_BACKGROUND.repeat((width * height) as usize);
+
+        self.draw_text(
+            &mut canvas,
+            width,
+            height,
+            pad_x,
+            pad_y,
+            &title,
+            [255, 255, 255, 255],
+            (text_width, self.theme.item_height),
+        );
+        self.draw_text(
+            &mut canvas,
+            width,
+            height,
+            pad_x,
+            pad_y + self.theme.item_height,
+            &message,
+            [200, 200, 220, 255],
+            (text_width, message_height),
+        );
+
+        self.replace_image(ImageData {
+            width,
+            height,
+            rgba_data: canvas,
+            scale: 1.0,
+            mipmaps: Vec::new(),
+        });
+        self.width = width;
+        self.height = height;
+        self.update_size();
+    }
+
+    /// Swap in a new image while keeping the window size and position
+    fn replace_image(&mut self, mut image: ImageData) {
+        self.original_aspect_ratio = image.width as f32 / image.height as f32;
+        self.cached_scaled_image = None;
+        self.cached_scaled_size = (0, 0);
+
+        if let Some(ref mut renderer) = self.gpu_renderer {
+            if let Err(e) = renderer.upload_texture(&image) {
+                error!("Failed to upload reloaded image to GPU: {:?}", e);
+                return;
+            }
+            image.release_mipmaps();
+        }
+
+        self.image = image;
+        self.measure_dirty = true;
+        self.request_redraw();
+    }
+
+    /// Copy image to clipboard using wl-copy or xclip
+    fn copy_to_clipboard(&mut self) {
+        if !self.image_loaded {
+            warn!("Image is not loaded yet, nothing to copy");
+            self.show_toast(tr("Image is still loading"));
+            return;
+        }
+
+        // Convert BGRA back to RGBA for encoding
+        let mut rgba_data = self.image.rgba_data.clone();
+        for pixel in rgba_data.chunks_exact_mut(4) {
+            pixel.swap(0, 2); // Swap B and R back
+        }
+
+        let mut png = Vec::new();
+        if let Err(e) = image::write_buffer_with_format(
+            &mut Cursor::new(&mut png),
+            &rgba_data,
+            self.image.width,
+            self.image.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        ) {
+            error!("Failed to encode image for the clipboard: {}", e);
+            self.show_toast(tr("Copy failed"));
+            return;
+        }
+
+        self.copy_bytes_to_clipboard(Some("image/png"), &png, tr("Copied").to_string());
+    }
+
+    /// Pipe `bytes` into wl-copy or xclip and toast `done` once the tool exits successfully
+    fn copy_bytes_to_clipboard(&mut self, mime: Option<&str>, bytes: &[u8], done: String) {
+        let spawn = |program: &str, args: &[&str], type_flag: &str| {
+            let mut command = Command::new(program);
+            command.args(args);
+            if let Some(mime) = mime {
+                command.args([type_flag, mime]);
+            }
+            command.stdin(Stdio::piped()).spawn()
+        };
+
+        let (mut child, tool) = match spawn("wl-copy", &[], "--type") {
+            Ok(child) => (child, "wl-copy"),
+            Err(_) => match spawn("xclip", &["-selection", "clipboard"], "-t") {
+                Ok(child) => (child, "xclip"),
+                Err(e) => {
+                    error!(
+                        "Failed to copy to clipboard: {}. Install wl-copy or xclip.",
+                        e
+                    );
+                    self.show_toast(tr("wl-copy or xclip not found"));
+                    return;
+                }
+            },
+        };
+
+        // Dropping stdin closes the pipe so the tool can finish
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(e) = stdin.write_all(bytes) {
+                error!("Failed to write to {}: {}", tool, e);
+                self.show_toast(tr("Copy failed"));
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+        }
+
+        // The tool forks to serve the selection; poll for its exit instead of blocking
+        let mut done = Some(done);
+        let timer = Timer::from_duration(CLIPBOARD_POLL_INTERVAL);
+        let result =
+            self.loop_handle
+                .insert_source(timer, move |_, _, app| match child.try_wait() {
+                    Ok(None) => TimeoutAction::ToDuration(CLIPBOARD_POLL_INTERVAL),
+                    Ok(Some(status)) if status.success() => {
+                        info!("Copied to clipboard via {}", tool);
+                        app.show_toast(done.take().unwrap_or_default());
+                        TimeoutAction::Drop
+                    }
+                    Ok(Some(status)) => {
+                        error!("{} failed: {}", tool, status);
+                        app.show_toast(tr("Copy failed"));
+                        TimeoutAction::Drop
+                    }
+                    Err(e) => {
+                        error!("Failed to wait for {}: {}", tool, e);
+                        app.show_toast(tr("Copy failed"));
+                        TimeoutAction::Drop
+                    }
+                });
+        if let Err(e) = result {
+            error!("Failed to schedule clipboard check: {}", e.error);
+        }
+    }
+
+    /// Logical size of the output the pin is on
+    fn output_size(&self) -> (u32, u32) {
+        self.pin_output
+            .as_ref()
+            .and_then(|output| self.output_state.info(output))
+            .and_then(|info| info.logical_size)
+            .map_or(
+                (self.display_width, self.display_height),
+                |(width, height)| (width as u32, height as u32),
+            )
+    }
+
+    /// Name of the output the pin is on, which other pins match against
+    fn output_name(&self) -> String {
+        self.pin_output
+            .as_ref()
+            .and_then(|output| self.output_state.info(output))
+            .and_then(|info| info.name)
+            .unwrap_or_default()
+    }
+
+    /// Follow the usable area of `output` through an unmapped layer surface spanning it
+    ///
+    /// Layer shell never says where panels are, but a surface anchored to all four edges
+    /// should be configured to the area they leave free. Compositors that never configure
+    /// it leave `usable_size` unset, and snapping falls back to the output's size.
+    fn create_usable_probe(&mut self, qh: &QueueHandle<Self>, output: &wl_output::WlOutput) {
+        let surface = self.compositor_state.create_surface(qh);
+        let probe = self.layer_shell.create_layer_surface(
+            qh,
+            surface,
+            Layer::Background,
+            Some("rspin-probe"),
+            Some(output),
+        );
+        probe.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
+        probe.set_keyboard_interactivity(KeyboardInteractivity::None);
+        probe.set_size(0, 0);
+        probe.commit();
+        self.usable_probe = Some(probe);
+        self.usable_size = None;
+    }
+
+    /// Lines the pin snaps to, or `None` if snapping is off
+    fn snap_guides(&self) -> Option<Guides> {
+        if self.snap_threshold == 0 {
+            return None;
+        }
+        let mut guides = Guides::default();
+
+        // Without an exclusive zone of its own, the pin's margins are relative to the
+        // usable area. The output's edges coincide with it wherever no panel reserves
+        // space; beyond a panel they are unknown, since layer shell doesn't say which side
+        // the space is reserved on.
+        let (width, height) = self.usable_size.unwrap_or_else(|| self.output_size());
+        guides.add_area(Rect::new(0, 0, width as i32, height as i32));
+
+        if let Some(ref registry) = self.pin_registry {
+            for pin in registry.others(&self.output_name()) {
+                guides.add_pin(pin);
+            }
+        }
+        Some(guides)
+    }
+
+    /// Guides of the current drag or resize, unless Ctrl is held to place the pin freely
+    fn active_snap_guides(&self) -> Option<&Guides> {
+        self.snap_guides.as_ref().filter(|_| !self.modifiers.ctrl)
+    }
+
+    /// Let other pins know where this one is, once it has settled
+    fn publish_pin(&mut self) {
+        let Some(ref registry) = self.pin_registry else {
+            return;
+        };
+        if !self.configured || self.dragging || self.resizing {
+            return;
+        }
+        let rect = Rect::new(
+            self.margin_left,
+            self.margin_top,
+            self.width as i32,
+            self.height as i32,
+        );
+        let pin = (self.output_name(), rect);
+        if self.published_pin.as_ref() == Some(&pin) {
+            return;
+        }
+        if let Err(e) = registry.publish(&pin.0, pin.1) {
+            warn!("Failed to publish pin geometry: {:#}", e);
+        }
+        self.published_pin = Some(pin);
+    }
+
+    /// Update window position using layer shell margins (committed with the next frame)
+    fn update_position(&mut self) {
+        if let Some(ref layer_surface) = self.layer_surface {
+            // The margins place the image; the shadow hangs over them
+            let insets = self.insets();
+            layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT);
+            layer_surface.set_margin(
+                self.margin_top - insets.top,
+                0,
+                0,
+                self.margin_left - insets.left,
+            );
+            self.needs_commit = true;
+        }
+    }
+
+    /// Room around the image that the surface keeps for the shadow
+    fn insets(&self) -> Insets {
+        self.decoration.insets()
+    }
+
+    /// Where the image sits in the surface
+    fn content_rect(&self) -> Rect {
+        let insets = self.insets();
+        Rect::new(
+            insets.left,
+            insets.top,
+            self.width as i32,
+            self.height as i32,
+        )
+    }
+
+    /// Size of the surface: the image plus the room for its shadow
+    fn surface_size(&self) -> (u32, u32) {
+        let insets = self.insets();
+        (
+            self.width + insets.horizontal(),
+            self.height + insets.vertical(),
+        )
+    }
+
+    /// Ask for the surface size of the current image size, taking input only over the image
+    fn request_surface_size(&self, layer_surface: &LayerSurface) {
+        let (width, height) = self.surface_size();
+        layer_surface.set_size(width, height);
+
+        // Clicks on the shadow go to whatever is beneath the pin
+        match Region::new(&self.compositor_state) {
+            Ok(region) => {
+                let content = self.content_rect();
+                region.add(content.x, content.y, content.width, content.height);
+                layer_surface.set_input_region(Some(region.wl_region()));
+            }
+            Err(e) => warn!("Failed to create input region: {}", e),
+        }
+    }
+
+    /// Update window size (committed and redrawn with the next frame)
+    fn update_size(&mut self) {
+        let max_width = self.display_width.clamp(MIN_SIZE, MAX_SIZE);
+        let max_height = self.display_height.clamp(MIN_SIZE, MAX_SIZE);
         self.width = self.width.clamp(MIN_SIZE, max_width);
         self.height = self.height.clamp(MIN_SIZE, max_height);
 
-        let menu_pos = self.menu_pos;
-        let menu_hover = self.menu_hover_item;
-        let menu_items = if self.menu_state == MenuState::Visible {
-            Some(self.get_menu_items())
-        } else {
-            None
+        if let Some(ref layer_surface) = self.layer_surface {
+            self.request_surface_size(layer_surface);
+            self.needs_commit = true;
+        }
+        self.request_redraw();
+    }
+
+    /// Initialize GPU renderer from Wayland surface
+    fn init_gpu_renderer(&mut self) {
+        if self.gpu_initialized {
+            return;
+        }
+
+        let layer_surface = match &self.layer_surface {
+            Some(ls) => ls,
+            None => {
+                warn!("Cannot init GPU: no layer surface");
+                return;
+            }
+        };
+
+        // Get raw pointers from Wayland objects
+        // The Proxy trait provides id() which gives ObjectId
+        // With wayland-backend client_system feature, ObjectId.as_ptr() is available
+        let wl_surface = layer_surface.wl_surface();
+        let surface_ptr = wl_surface.id().as_ptr() as *mut std::ffi::c_void;
+
+        // Use the display pointer we stored
+        let display_ptr = self.display_ptr;
+
+        if display_ptr.is_null() {
+            warn!("Display pointer is null, falling back to CPU rendering");
+            self.use_gpu = false;
+            return;
+        }
+
+        info!("Initializing GPU renderer...");
+        info!("  Surface ptr: {:?}", surface_ptr);
+        info!("  Display ptr: {:?}", display_ptr);
+        let (width, height) = self.surface_size();
+        info!("  Size: {}x{}", width, height);
+
+        match WgpuRenderer::new(display_ptr, surface_ptr, width, height) {
+            Ok(mut renderer) => {
+                renderer.set_content(self.content_rect());
+                renderer.set_decoration(&self.decoration);
+                // Upload initial texture
+                if let Err(e) = renderer.upload_texture(&self.image) {
+                    warn!("Failed to upload texture to GPU: {:?}", e);
+                    self.use_gpu = false;
+                    return;
+                }
+                renderer.update_opacity(self.opacity);
+                self.gpu_renderer = Some(renderer);
+                self.gpu_initialized = true;
+                info!("GPU renderer initialized successfully");
+
+                // Keep the base level for pixel lookups; the GPU has its own mip chain
+                let freed = self.image.release_mipmaps();
+                info!("Released {} bytes of CPU mipmaps after GPU upload", freed);
+
+                // Also clear CPU rendering caches
+                self.cached_scaled_image = None;
+                self.swapchain = None;
+            }
+            Err(e) => {
+                warn!("Failed to initialize GPU renderer: {:?}", e);
+                warn!("Falling back to CPU rendering");
+                self.use_gpu = false;
+            }
+        }
+    }
+
+    /// Mark GPU initialization to run after the first frame is displayed
+    fn request_gpu_init(&mut self) {
+        if self.use_gpu && !self.gpu_initialized {
+            self.gpu_init_pending = true;
+        }
+    }
+
+    /// Execute pending GPU initialization work outside of critical event handlers
+    fn process_gpu_init(&mut self, qh: &QueueHandle<Self>) {
+        if !self.use_gpu || self.gpu_initialized || !self.gpu_init_pending {
+            return;
+        }
+
+        self.init_gpu_renderer();
+        self.gpu_init_pending = false;
+
+        if self.use_gpu && self.gpu_initialized {
+            // Re-render so the GPU texture path becomes active on the next frame
+            self.request_redraw();
+            self.flush_frame(qh);
+        }
+    }
+
+    /// Send pending state to the compositor once it is ready for a new frame
+    ///
+    /// All state changes between two frame callbacks are coalesced into a single
+    /// draw (or a bare commit when only the position or size changed).
+    fn flush_frame(&mut self, qh: &QueueHandle<Self>) {
+        self.publish_pin();
+
+        if self.frame_callback_pending || !self.configured {
+            return;
+        }
+
+        if self.needs_redraw {
+            self.draw(qh);
+        } else if self.needs_commit {
+            if let Some(ref layer_surface) = self.layer_surface {
+                let surface = layer_surface.wl_surface();
+                surface.frame(qh, surface.clone());
+                surface.commit();
+                self.frame_callback_pending = true;
+            }
+            self.needs_commit = false;
+        }
+    }
+
+    /// Draw a frame and request a callback for the next one
+    fn draw(&mut self, qh: &QueueHandle<Self>) {
+        if !self.configured {
+            return;
+        }
+
+        let surface = match self.layer_surface {
+            Some(ref layer_surface) => layer_surface.wl_surface().clone(),
+            None => return,
         };
 
+        // Pace the next redraw by the compositor's frame callback
+        surface.frame(qh, surface.clone());
+        self.frame_callback_pending = true;
+
+        // Presenting through wgpu commits the surface (with the frame request and any
+        // pending size/position); otherwise commit here, even if rendering was skipped
+        if !self.render_frame() {
+            surface.commit();
+        }
+        self.needs_commit = false;
+    }
+
+    /// Render the image (and overlays) with the active renderer
+    ///
+    /// Returns whether the GPU renderer presented, which commits the surface.
+    fn render_frame(&mut self) -> bool {
+        // Clamp size to display bounds to avoid oversized buffers
+        let max_width = self.display_width.clamp(MIN_SIZE, MAX_SIZE);
+        let max_height = self.display_height.clamp(MIN_SIZE, MAX_SIZE);
+        self.width = self.width.clamp(MIN_SIZE, max_width);
+        self.height = self.height.clamp(MIN_SIZE, max_height);
+
+        // A glyph did not fit last frame: start the atlas over
+        if self.glyph_atlas.is_full() {
+            self.reset_glyph_atlas();
+        }
+
+        // Measurements follow the window size, so rebuild them after a resize
+        if self.measure_dirty || self.measure_panel_size != (self.width, self.height) {
+            self.rebuild_measure_panel();
+        }
+        self.refresh_panels();
+        let loupe_rect = self.loupe_panel_rect();
+
         // Try GPU rendering first if enabled
         if self.use_gpu && self.gpu_renderer.is_some() {
-            if let Some(ref items) = menu_items {
-                self.update_gpu_menu_overlay(menu_pos, menu_hover, items);
-            } else if let Some(renderer) = self.gpu_renderer.as_mut() {
-                renderer.clear_overlay_texture();
-            }
+            self.sync_glyph_atlas();
+            self.upload_gpu_panels();
+            self.update_gpu_loupe(loupe_rect);
 
             if self.draw_gpu() {
-                return;
+                self.redraw_if_atlas_full();
+                return true;
             }
             // Fall back to CPU rendering if GPU fails
             warn!("GPU rendering failed, falling back to CPU");
         }
 
-        // CPU rendering path
-        self.draw_cpu();
+        // CPU rendering path (panels back to front, flattened with their text)
+        let loupe_panel = loupe_rect.map(|rect| {
+            let size = rect.width as u32;
+            let mut panel = OverlayPanel::new(rect);
+            panel.pixels = loupe::render(
+                &self.image,
+                self.width,
+                self.height,
+                self.pointer_pos,
+                self.loupe_zoom,
+                size,
+                self.loupe_shape,
+            );
+            panel
+        });
+        let flatten =
+            |cached: Option<&OverlayPanel>| cached.map(|panel| panel.flatten(&self.glyph_atlas));
+        let flat = [
+            flatten(self.measure_panel.as_ref().map(|c| &c.panel)),
+            loupe_panel,
+            flatten(self.hud_panel.as_ref().map(|c| &c.panel)),
+            flatten(self.picker_panel.as_ref().map(|c| &c.panel)),
+            flatten(self.toast_panel.as_ref().map(|c| &c.panel)),
+            flatten(self.menu_panel.as_ref().map(|c| &c.panel)),
+        ];
+        let panels: Vec<&OverlayPanel> = flat.iter().flatten().collect();
+        self.draw_cpu(&panels);
+        self.redraw_if_atlas_full();
+        false
+    }
+
+    /// Draw again once the atlas is reset if text was dropped for lack of space
+    fn redraw_if_atlas_full(&mut self) {
+        if self.glyph_atlas.is_full() {
+            self.request_redraw();
+        }
+    }
+
+    /// Empty the glyph atlas; all text is laid out again on the next frame
+    fn reset_glyph_atlas(&mut self) {
+        self.glyph_atlas.reset();
+        // The cached panels hold quads into the old atlas
+        self.drop_overlay_panels();
+        self.request_redraw();
+    }
+
+    /// Forget every rendered overlay panel, so each is rendered and uploaded again
+    fn drop_overlay_panels(&mut self) {
+        self.measure_dirty = true;
+        self.picker_panel = None;
+        self.hud_panel = None;
+        self.toast_panel = None;
+        self.menu_panel = None;
+    }
+
+    /// Render the overlay panels whose content changed since the last frame
+    fn refresh_panels(&mut self) {
+        let picker = self.picker_panel_rect().zip(self.picked_color());
+        let cached = self.picker_panel.take();
+        self.picker_panel = picker.and_then(|key| {
+            CachedPanel::refresh(cached, key, |(rect, color)| {
+                self.render_picker_panel(*rect, color)
+            })
+        });
+
+        self.hud_rect = self.hud_panel_rect();
+        let hud = self.hud_rect.map(|rect| (rect, self.hud_lines()));
+        let cached = self.hud_panel.take();
+        self.hud_panel = hud.and_then(|key| {
+            CachedPanel::refresh(cached, key, |(rect, lines)| {
+                self.render_hud_panel(*rect, lines)
+            })
+        });
+
+        self.toast_rect = self.toast_panel_rect();
+        let toast = self.toast.clone().zip(self.toast_rect);
+        let cached = self.toast_panel.take();
+        self.toast_panel = toast.and_then(|(toast, rect)| {
+            let mut cached =
+                CachedPanel::refresh(cached, (rect, toast.text.clone()), |(rect, text)| {
+                    self.render_toast_panel(*rect, text)
+                })?;
+            // Fading only changes the panel's alpha, so the text is not laid out again
+            cached.panel.alpha = toast.alpha(Instant::now());
+            Some(cached)
+        });
+
+        let menu = self
+            .menu_panel_rect()
+            .map(|rect| (rect, self.menu_hover, self.menu_path.clone()));
+        let cached = self.menu_panel.take();
+        self.menu_panel = menu.and_then(|key| {
+            CachedPanel::refresh(cached, key, |(rect, _, _)| self.render_menu_panel(*rect))
+        });
+    }
+
+    /// Upload the overlay panels that changed since they were last drawn by the GPU
+    fn upload_gpu_panels(&mut self) {
+        let Some(renderer) = self.gpu_renderer.as_mut() else {
+            return;
+        };
+        let insets = self.decoration.insets();
+        upload_panel(
+            renderer,
+            insets,
+            OverlayLayer::Measure,
+            self.measure_panel.as_mut(),
+        );
+        upload_panel(renderer, insets, OverlayLayer::Hud, self.hud_panel.as_mut());
+        upload_panel(
+            renderer,
+            insets,
+            OverlayLayer::Picker,
+            self.picker_panel.as_mut(),
+        );
+        upload_panel(
+            renderer,
+            insets,
+            OverlayLayer::Toast,
+            self.toast_panel.as_mut(),
+        );
+        upload_panel(
+            renderer,
+            insets,
+            OverlayLayer::Menu,
+            self.menu_panel.as_mut(),
+        );
+    }
+
+    /// Upload the glyphs rasterized since the last frame
+    fn sync_glyph_atlas(&mut self) {
+        let Some(renderer) = self.gpu_renderer.as_mut() else {
+            return;
+        };
+        if let Some(region) = self.glyph_atlas.take_dirty() {
+            renderer.update_glyph_atlas(self.glyph_atlas.pixels(), region);
+        }
     }
 
     /// Draw using GPU (wgpu)
@@ -636,19 +2629,27 @@ impl WaylandApp {
         };
 
         // Handle resize
-        renderer.resize(self.width, self.height);
-
-        // Update opacity
+        let insets = self.decoration.insets();
+        renderer.resize(
+            self.width + insets.horizontal(),
+            self.height + insets.vertical(),
+        );
+        renderer.set_content(Rect::new(
+            insets.left,
+            insets.top,
+            self.width as i32,
+            self.height as i32,
+        ));
+
+        // Update opacity and resampling filter
         renderer.update_opacity(self.opacity);
+        renderer.set_filter(self.filter);
 
-        // Render
+        // Render (presenting commits the surface)
         match renderer.render() {
             Ok(true) => {
-                // Commit the surface to show the frame
-                if let Some(ref layer_surface) = self.layer_surface {
-                    layer_surface.wl_surface().commit();
-                }
                 self.needs_redraw = false;
+                self.damage = Damage::default();
                 true
             }
             Ok(false) => {
@@ -662,81 +2663,54 @@ impl WaylandApp {
         }
     }
 
-    fn update_gpu_menu_overlay(
-        &mut self,
-        menu_pos: (i32, i32),
-        menu_hover_item: Option<usize>,
-        menu_items: &[&str],
-    ) {
-        let surface_width = self.width;
-        let surface_height = self.height;
-        if surface_width == 0 || surface_height == 0 {
-            return;
-        }
-
-        let menu_x = menu_pos.0.max(0).min(surface_width as i32 - 1).max(0);
-        let menu_y = menu_pos.1.max(0).min(surface_height as i32 - 1).max(0);
-
-        let menu_width = MENU_WIDTH.min(surface_width.saturating_sub(menu_x as u32));
-        let menu_height = (menu_items.len() as u32 * MENU_ITEM_HEIGHT)
-            .min(surface_height.saturating_sub(menu_y as u32));
-
-        if menu_width == 0 || menu_height == 0 {
-            if let Some(renderer) = self.gpu_renderer.as_mut() {
-                renderer.clear_overlay_texture();
-            }
-            return;
-        }
-
-        let mut buffer = vec![0u8; (menu_width * menu_height * 4) as usize];
-        self.render_menu_overlay_contents(
-            &mut buffer,
-            menu_width,
-            menu_height,
-            menu_hover_item,
-            menu_items,
-        );
-        for pixel in buffer.chunks_exact_mut(4) {
-            pixel.swap(0, 2);
-        }
-
-        let viewport = [
-            menu_x as f32,
-            menu_y as f32,
-            menu_width as f32,
-            menu_height as f32,
-        ];
-
+    /// Place the GPU loupe pass, which magnifies the image texture without an upload
+    fn update_gpu_loupe(&mut self, rect: Option<Rect>) {
+        let insets = self.decoration.insets();
+        let lens = rect.map(|rect| Lens {
+            rect: Rect::new(
+                rect.x + insets.left,
+                rect.y + insets.top,
+                rect.width,
+                rect.height,
+            ),
+            center: (
+                (self.pointer_pos.0 / self.width.max(1) as f64) as f32,
+                (self.pointer_pos.1 / self.height.max(1) as f64) as f32,
+            ),
+            zoom: self.loupe_zoom,
+            shape: self.loupe_shape,
+        });
         if let Some(renderer) = self.gpu_renderer.as_mut() {
-            if let Err(e) =
-                renderer.update_overlay_texture(menu_width, menu_height, viewport, &buffer)
-            {
-                warn!("Failed to upload menu overlay: {:?}", e);
-            }
+            renderer.set_loupe(lens);
         }
     }
 
     /// Draw using CPU (shared memory buffer)
-    fn draw_cpu(&mut self) {
+    fn draw_cpu(&mut self, panels: &[&OverlayPanel]) {
         // Clamp window size to prevent buffer allocation failures
         self.width = self.width.clamp(MIN_SIZE, MAX_SIZE);
         self.height = self.height.clamp(MIN_SIZE, MAX_SIZE);
 
         let width = self.width;
         let height = self.height;
+        let content = self.content_rect();
+        let (surface_width, surface_height) = self.surface_size();
+        // With a shadow the image is drawn into its own canvas and copied into the padded buffer
+        let padded = (surface_width, surface_height) != (width, height);
 
         // Calculate buffer size (4 bytes per pixel for ARGB)
-        let stride = width as i32 * 4;
-        let buffer_size = (stride * height as i32) as usize;
+        let stride = width as usize * 4;
+        let buffer_size = stride * height as usize;
+        let surface_size = surface_width as usize * surface_height as usize * 4;
 
         // Check if buffer size is reasonable
-        if buffer_size > MAX_BUFFER_SIZE {
+        if surface_size > MAX_BUFFER_SIZE {
             error!(
                 "Buffer size too large: {} bytes, max: {} bytes",
-                buffer_size, MAX_BUFFER_SIZE
+                surface_size, MAX_BUFFER_SIZE
             );
             // Scale down to fit
-            let scale = (MAX_BUFFER_SIZE as f32 / buffer_size as f32).sqrt();
+            let scale = (MAX_BUFFER_SIZE as f32 / surface_size as f32).sqrt();
             self.width = (width as f32 * scale) as u32;
             self.height = (height as f32 * scale) as u32;
             return; // Will redraw on next frame with new size
@@ -745,473 +2719,518 @@ impl WaylandApp {
         // Gather state needed for rendering before mutable borrow
         let is_resizing = self.resizing;
         let opacity = self.opacity;
-        let menu_visible = self.menu_state == MenuState::Visible;
-        let menu_pos = self.menu_pos;
-        let menu_hover = self.menu_hover_item;
-        let menu_items: Vec<&'static str> = if menu_visible {
-            self.get_menu_items()
-        } else {
-            vec![]
-        };
         let fast_boot_preview = self.use_gpu && !self.gpu_initialized;
+        let filter = self.filter;
 
-        // Initialize pool if needed
-        if self.pool.is_none() {
-            match SlotPool::new(buffer_size, &self.shm) {
-                Ok(pool) => self.pool = Some(pool),
+        // Initialize the double-buffered swapchain if needed
+        if self.swapchain.is_none() {
+            match ShmSwapchain::new(&self.shm, surface_width, surface_height) {
+                Ok(swapchain) => self.swapchain = Some(swapchain),
                 Err(e) => {
-                    error!(
-                        "Failed to create slot pool: {}. Buffer size: {} bytes",
-                        e, buffer_size
-                    );
+                    error!("{:?}. Buffer size: {} bytes", e, surface_size);
                     return;
                 }
             }
         }
 
-        // Temporarily take ownership of the pool to avoid borrow conflicts during rendering
-        let mut pool = match self.pool.take() {
-            Some(pool) => pool,
+        // Temporarily take ownership of the swapchain to avoid borrow conflicts during rendering
+        let mut swapchain = match self.swapchain.take() {
+            Some(swapchain) => swapchain,
             None => return,
         };
 
-        // Resize pool if needed
-        if pool.len() < buffer_size {
-            if let Err(e) = pool.resize(buffer_size) {
-                error!("Failed to resize pool to {} bytes: {}", buffer_size, e);
-                // Drop pool so a new one will be created next frame
-                self.pool = None;
-                return;
-            }
+        let cache_enabled = !self.use_gpu;
+        let cache_valid =
+            self.cached_scaled_size == (width, height) && self.cached_scaled_image.is_some();
+
+        // Partial redraws need the cached base image to restore damaged regions from
+        let mut frame_damage = std::mem::take(&mut self.damage);
+        if is_resizing || fast_boot_preview || !cache_enabled || !cache_valid {
+            frame_damage.add_full();
         }
 
-        // Create buffer
-        let (buffer, canvas) = match pool.create_buffer(
-            width as i32,
-            height as i32,
-            stride,
-            wl_shm::Format::Argb8888,
-        ) {
-            Ok(buf) => buf,
-            Err(e) => {
-                error!("Failed to create buffer {}x{}: {}", width, height, e);
-                self.pool = Some(pool);
-                return;
-            }
-        };
+        // Nothing changed since the last presented frame
+        if frame_damage.is_empty() {
+            self.swapchain = Some(swapchain);
+            self.needs_redraw = false;
+            return;
+        }
 
-        let cache_enabled = !self.use_gpu;
+        let (buffer_index, surface_canvas, stale) =
+            match swapchain.acquire(surface_width, surface_height) {
+                Ok(acquired) => acquired,
+                Err(e) => {
+                    error!("{:?}", e);
+                    self.swapchain = Some(swapchain);
+                    return;
+                }
+            };
+
+        // Repair everything this buffer missed since it was last drawn, plus this frame's changes
+        // (damage is tracked in image coordinates, the buffer's in surface coordinates)
+        let mut repair = stale.translate(-content.x, -content.y);
+        repair.merge(&frame_damage);
+
+        let mut content_canvas = std::mem::take(&mut self.content_canvas);
+        let (canvas, surface_canvas) = if padded {
+            content_canvas.resize(buffer_size, 0);
+            (&mut content_canvas[..], Some(surface_canvas))
+        } else {
+            (surface_canvas, None)
+        };
 
-        // Choose rendering method based on whether we're resizing
-        if is_resizing || fast_boot_preview {
-            // Use fast nearest-neighbor during resize or before GPU is ready
-            Self::render_image_fast(&self.image, canvas, width, height, opacity);
-        } else if cache_enabled {
-            // Use high-quality bilinear interpolation when not resizing
-            // Check if we can use cached image
-            if self.cached_scaled_size == (width, height) {
+        if repair == Damage::Full {
+            // Choose rendering method based on whether we're resizing
+            if is_resizing || fast_boot_preview {
+                // Use fast nearest-neighbor during resize or before GPU is ready
+                cpu_renderer::render_fast(&self.image, canvas, width, height, opacity);
+            } else if cache_enabled {
+                // Render the scaled image once with the selected filter and keep it
+                // (without opacity) so opacity changes and overlays only need a copy
+                if !cache_valid {
+                    let mut cached = self.cached_scaled_image.take().unwrap_or_default();
+                    cached.resize(buffer_size, 0);
+                    cpu_renderer::render_scaled(&self.image, &mut cached, width, height, filter);
+                    self.cached_scaled_image = Some(cached);
+                    self.cached_scaled_size = (width, height);
+                }
                 if let Some(ref cached) = self.cached_scaled_image {
-                    // Apply opacity to cached image
-                    Self::apply_opacity_to_canvas(cached, canvas, opacity);
-                } else {
-                    Self::render_image_static(&self.image, canvas, width, height, opacity);
+                    cpu_renderer::copy_with_opacity(cached, canvas, stride, opacity);
                 }
             } else {
-                Self::render_image_static(&self.image, canvas, width, height, opacity);
-                // Cache the scaled image (without opacity applied)
-                let mut cached = vec![0u8; buffer_size];
-                Self::render_image_static(&self.image, &mut cached, width, height, 1.0);
-                self.cached_scaled_image = Some(cached);
-                self.cached_scaled_size = (width, height);
+                cpu_renderer::render_scaled(&self.image, canvas, width, height, filter);
+                cpu_renderer::apply_opacity(canvas, stride, opacity);
+                self.cached_scaled_image = None;
+                self.cached_scaled_size = (0, 0);
             }
-        } else {
-            Self::render_image_static(&self.image, canvas, width, height, opacity);
-            self.cached_scaled_image = None;
-            self.cached_scaled_size = (0, 0);
+        } else if let Some(ref cached) = self.cached_scaled_image {
+            // Restore only the damaged regions from the cached base image
+            for rect in repair.rects(width, height) {
+                cpu_renderer::copy_region_with_opacity(cached, canvas, stride, rect, opacity);
+            }
+        }
+
+        let repair_rects = repair.rects(width, height);
+
+        // Round the corners and draw the border; overlays stay on top like on the GPU
+        for rect in &repair_rects {
+            self.decoration.mask(canvas, width, height, *rect);
         }
 
-        // Draw context menu if visible
-        if menu_visible {
-            self.render_menu(canvas, width, height, menu_pos, menu_hover, &menu_items);
+        // Draw overlay panels (measurements, loupe, readouts, toast, menu) over the repaired regions
+        for panel in panels {
+            let rect = panel.rect;
+            for clip in &repair_rects {
+                cpu_renderer::blend_panel(
+                    canvas,
+                    width,
+                    height,
+                    &panel.pixels,
+                    rect.width as u32,
+                    (rect.x, rect.y),
+                    *clip,
+                );
+            }
         }
 
-        // Draw resize handles (subtle border)
-        Self::render_resize_border_static(canvas, width, height);
+        // Copy the image into the padded buffer and draw the shadow around it
+        if let Some(surface_canvas) = surface_canvas {
+            let surface_rect = Rect::new(0, 0, surface_width as i32, surface_height as i32);
+            if repair == Damage::Full {
+                surface_canvas.fill(0);
+            }
+            for rect in &repair_rects {
+                cpu_renderer::copy_rect(
+                    canvas,
+                    width,
+                    *rect,
+                    surface_canvas,
+                    surface_width,
+                    (content.x, content.y),
+                );
+                let area = if repair == Damage::Full {
+                    surface_rect
+                } else {
+                    Rect::new(
+                        rect.x + content.x,
+                        rect.y + content.y,
+                        rect.width,
+                        rect.height,
+                    )
+                };
+                self.decoration.draw_shadow(
+                    surface_canvas,
+                    surface_width,
+                    surface_height,
+                    content,
+                    area,
+                );
+            }
+        }
+        self.content_canvas = content_canvas;
 
-        // Attach and commit
+        // Attach, damaging only what changed since the previous frame (committed by draw)
         let layer_surface = self.layer_surface.as_ref().unwrap();
-        let surface = layer_surface.wl_surface();
-        buffer.attach_to(surface).expect("Failed to attach buffer");
-        surface.damage_buffer(0, 0, width as i32, height as i32);
-        surface.commit();
+        let surface_damage = frame_damage.translate(content.x, content.y);
+        if let Err(e) = swapchain.present(buffer_index, layer_surface.wl_surface(), &surface_damage)
+        {
+            error!("{:?}", e);
+        }
 
-        self.pool = Some(pool);
-        self.buffer = Some(buffer);
+        self.swapchain = Some(swapchain);
         self.needs_redraw = false;
     }
 
-    /// Render the image to the canvas (static version to avoid borrow issues)
-    fn render_image_static(
-        image: &ImageData,
-        canvas: &mut [u8],
-        width: u32,
-        height: u32,
-        opacity: f32,
+    /// Bounds of the open menus when they are drawn inside the pin
+    ///
+    /// Only used when the menus cannot be shown in popups.
+    fn menu_panel_rect(&self) -> Option<Rect> {
+        if self.menu_state != MenuState::Visible || self.xdg_shell.is_some() {
+            return None;
+        }
+        self.menu_rect()?.clamp_to(self.width, self.height)
+    }
+
+    /// Render the open menus into one panel covering `bounds`
+    fn render_menu_panel(&mut self, bounds: Rect) -> OverlayPanel {
+        let mut overlay = OverlayPanel::with_pixels(bounds);
+        let panels = std::mem::take(&mut self.menu_panels);
+        let entries = std::mem::take(&mut self.menu_entries);
+        let origin = (bounds.x, bounds.y);
+        for (level, panel) in panels.iter().enumerate() {
+            self.draw_menu_level(&mut overlay, origin, level, panel, &entries, 1);
+        }
+        self.menu_panels = panels;
+        self.menu_entries = entries;
+        overlay
+    }
+
+    /// Draw one menu level into a panel whose top-left corner is at `origin`: its
+    /// background as a frame, rows and icons into the pixels and its text
+    fn draw_menu_level(
+        &mut self,
+        target: &mut OverlayPanel,
+        origin: (i32, i32),
+        level: usize,
+        panel: &menu::Panel,
+        entries: &[MenuEntry<MenuAction>],
+        scale: u32,
     ) {
-        // Choose best mipmap level for quality rendering
-        let scale_ratio =
-            (width as f32 / image.width as f32).min(height as f32 / image.height as f32);
-
-        let (img_width, img_height, src_data) = if scale_ratio < 0.7 && !image.mipmaps.is_empty() {
-            // Find the best mipmap level (choose one slightly larger than needed)
-            let mut best_level = 0;
-            for (i, mipmap) in image.mipmaps.iter().enumerate() {
-                let mip_scale = mipmap.width as f32 / image.width as f32;
-                if mip_scale >= scale_ratio {
-                    best_level = i.saturating_sub(1); // Use previous level for better quality
-                    break;
+        let Some(level_entries) = self
+            .menu_path
+            .get(..level)
+            .and_then(|path| menu::entries_at(entries, path))
+        else {
+            return;
+        };
+        let (width, height) = (target.rect.width as u32, target.rect.height as u32);
+        let canvas = &mut target.pixels;
+        let text = &mut target.text;
+        // Popups on HiDPI outputs are drawn at buffer scale; the layout stays logical
+        let s = scale as i32;
+        let theme = self.theme.scaled(scale);
+        let metrics = Metrics::new(theme.font_size, theme.line_height);
+        let panel = &panel.scaled(s);
+        let origin = (origin.0 * s, origin.1 * s);
+        let local = |rect: Rect| {
+            Rect::new(
+                rect.x - origin.0,
+                rect.y - origin.1,
+                rect.width,
+                rect.height,
+            )
+        };
+        let palette = theme.palette;
+        let (radius, padding) = (theme.radius, theme.padding);
+        let panel_rect = local(panel.rect);
+        target.frames.push(PanelFrame {
+            rect: panel_rect,
+            radius,
+            fill: palette.background,
+            border: palette.border,
+        });
+
+        for (row, entry) in level_entries.iter().enumerate() {
+            let Some(&row_rect) = panel.rows.get(row) else {
+                break;
+            };
+            let row_rect = local(row_rect);
+            let item = match entry {
+                MenuEntry::Item(item) => item,
+                MenuEntry::Separator => {
+                    let line = Rect::new(
+                        row_rect.x + 6 * s,
+                        row_rect.y + row_rect.height / 2,
+                        row_rect.width - 12 * s,
+                        s,
+                    );
+                    Self::fill_rect(canvas, width, height, line, palette.border);
+                    continue;
                 }
-                best_level = i;
-            }
+            };
 
-            if best_level >= image.mipmaps.len() {
-                best_level = image.mipmaps.len() - 1;
+            // Hovered items and the parents of open submenus are highlighted
+            let highlighted = item.enabled
+                && (self.menu_hover == Some((level, row))
+                    || self.menu_path.get(level) == Some(&row));
+            if highlighted {
+                // With rounded panels the highlight is inset so it clears the corners
+                let (hover, hover_radius) = if radius > 0 {
+                    let inset = Rect::new(
+                        row_rect.x + 3 * s,
+                        row_rect.y,
+                        row_rect.width - 6 * s,
+                        row_rect.height,
+                    );
+                    (inset, radius / 2)
+                } else {
+                    // Square highlights stay inside the outline, which is drawn beneath them
+                    let inside = Rect::new(
+                        panel_rect.x + s,
+                        panel_rect.y + s,
+                        panel_rect.width - 2 * s,
+                        panel_rect.height - 2 * s,
+                    );
+                    (row_rect.intersection(&inside).unwrap_or(row_rect), 0)
+                };
+                Self::fill_round_rect(canvas, width, height, hover, hover_radius, palette.hover);
             }
-
-            if best_level > 0 && best_level <= image.mipmaps.len() {
-                let mipmap = &image.mipmaps[best_level - 1];
-                (mipmap.width, mipmap.height, &mipmap.data[..])
+            let color = if !item.enabled {
+                palette.text_disabled
+            } else if highlighted {
+                palette.text_hover
             } else {
-                (image.width, image.height, &image.rgba_data[..])
-            }
-        } else {
-            (image.width, image.height, &image.rgba_data[..])
-        };
+                palette.text
+            };
 
-        // Fill with transparent background first
-        for pixel in canvas.chunks_exact_mut(4) {
-            pixel[0] = 0; // B
-            pixel[1] = 0; // G
-            pixel[2] = 0; // R
-            pixel[3] = 0; // A
-        }
-
-        // Calculate scale factors for rendering
-        let scale_x = img_width as f32 / width as f32;
-        let scale_y = img_height as f32 / height as f32;
-
-        // Render with bilinear interpolation for smooth scaling
-        for y in 0..height {
-            for x in 0..width {
-                let src_x = x as f32 * scale_x;
-                let src_y = y as f32 * scale_y;
-
-                let x0 = src_x.floor() as u32;
-                let y0 = src_y.floor() as u32;
-                let x1 = (x0 + 1).min(img_width - 1);
-                let y1 = (y0 + 1).min(img_height - 1);
-
-                let fx = src_x - x0 as f32;
-                let fy = src_y - y0 as f32;
-
-                let get_pixel = |px: u32, py: u32| -> [u8; 4] {
-                    let idx = ((py * img_width + px) * 4) as usize;
-                    if idx + 3 < src_data.len() {
-                        [
-                            src_data[idx],
-                            src_data[idx + 1],
-                            src_data[idx + 2],
-                            src_data[idx + 3],
-                        ]
-                    } else {
-                        [0, 0, 0, 0]
-                    }
+            let check_cell = Rect::new(
+                row_rect.x + panel.check_x,
+                row_rect.y,
+                (menu::CHECK_WIDTH * scale) as i32,
+                row_rect.height,
+            );
+            menu::draw_check(canvas, width, height, check_cell, item.check, color, scale);
+
+            // The logical offset, scaled, so text sits where it does at scale 1
+            let text_y = row_rect.y + self.theme.text_offset() * s;
+            let text_height = row_rect.height as u32;
+            let label_width = (panel.shortcut_x - panel.text_x).max(0) as u32;
+            if let Some(icon) = item.icon {
+                let cell = Rect::new(
+                    row_rect.x + panel.icon_x,
+                    row_rect.y,
+                    panel.text_x - panel.icon_x,
+                    row_rect.height,
+                );
+                let size = theme.icon_size();
+                icons::draw(canvas, width, height, cell, icon, size, color);
+            }
+            text.extend(self.layout_text_with(
+                metrics,
+                row_rect.x + panel.text_x,
+                text_y,
+                &item.label,
+                color,
+                (label_width, text_height),
+            ));
+            if let Some(shortcut) = item.shortcut {
+                let shortcut_color = if item.enabled && !highlighted {
+                    palette.shortcut
+                } else {
+                    color
                 };
+                text.extend(self.layout_text_with(
+                    metrics,
+                    row_rect.x + panel.shortcut_x,
+                    text_y,
+                    shortcut,
+                    shortcut_color,
+                    (
+                        (row_rect.width - panel.shortcut_x).max(0) as u32,
+                        text_height,
+                    ),
+                ));
+            }
+            if item.has_submenu() {
+                menu::draw_arrow(canvas, width, height, row_rect, padding, color, scale);
+            }
+        }
+    }
 
-                let p00 = get_pixel(x0, y0);
-                let p10 = get_pixel(x1, y0);
-                let p01 = get_pixel(x0, y1);
-                let p11 = get_pixel(x1, y1);
-
-                let interpolate = |c: usize| -> u8 {
-                    let v00 = p00[c] as f32;
-                    let v10 = p10[c] as f32;
-                    let v01 = p01[c] as f32;
-                    let v11 = p11[c] as f32;
-
-                    let v0 = v00 * (1.0 - fx) + v10 * fx;
-                    let v1 = v01 * (1.0 - fx) + v11 * fx;
-                    let v = v0 * (1.0 - fy) + v1 * fy;
-
-                    v.round().clamp(0.0, 255.0) as u8
-                };
+    /// Render the color picker readout (swatch, hex, CSS and coordinates)
+    fn render_picker_panel(&mut self, rect: Rect, color: &PickedColor) -> OverlayPanel {
+        let mut panel = OverlayPanel::new(rect);
+        let full = Rect::new(0, 0, rect.width, rect.height);
+        panel.frames.push(self.panel_frame(full));
+
+        let swatch = Rect::new(
+            self.theme.padding as i32,
+            (rect.height - PICKER_SWATCH_SIZE as i32) / 2,
+            PICKER_SWATCH_SIZE as i32,
+            PICKER_SWATCH_SIZE as i32,
+        );
+        let text_color = self.theme.palette.text;
+        panel.frames.push(PanelFrame {
+            rect: swatch,
+            radius: 0,
+            fill: color.swatch(),
+            border: text_color,
+        });
+
+        let bounds = (
+            (rect.width as u32).saturating_sub(PICKER_TEXT_INDENT + 2 * self.theme.padding),
+            self.theme.line_pitch(),
+        );
+        for (i, line) in color.readout().iter().enumerate() {
+            let (x, y) = self.theme.text_panel_line(i, PICKER_TEXT_INDENT);
+            let text = self.layout_text(x, y, line, text_color, bounds);
+            panel.text.extend(text);
+        }
+        panel
+    }
 
-                let dst_idx = ((y * width + x) * 4) as usize;
-                if dst_idx + 3 < canvas.len() {
-                    let src_alpha = interpolate(3) as f32 / 255.0;
-                    let final_alpha = (src_alpha * opacity * 255.0) as u8;
+    /// Render the info overlay (source and window state)
+    fn render_hud_panel(&mut self, rect: Rect, lines: &[String]) -> OverlayPanel {
+        let mut panel = OverlayPanel::new(rect);
+        let full = Rect::new(0, 0, rect.width, rect.height);
+        panel.frames.push(self.panel_frame(full));
 
-                    canvas[dst_idx] = interpolate(0);
-                    canvas[dst_idx + 1] = interpolate(1);
-                    canvas[dst_idx + 2] = interpolate(2);
-                    canvas[dst_idx + 3] = final_alpha;
-                }
-            }
+        let text_color = self.theme.palette.text;
+        let bounds = (
+            (rect.width as u32).saturating_sub(2 * self.theme.padding),
+            self.theme.line_pitch(),
+        );
+        for (i, line) in lines.iter().enumerate() {
+            let (x, y) = self.theme.text_panel_line(i, 0);
+            let text = self.layout_text(x, y, line, text_color, bounds);
+            panel.text.extend(text);
         }
+        panel
+    }
+
+    /// Render the toast's text at full opacity; the fade is applied when it is drawn
+    fn render_toast_panel(&mut self, rect: Rect, text: &str) -> OverlayPanel {
+        let mut panel = OverlayPanel::new(rect);
+        let full = Rect::new(0, 0, rect.width, rect.height);
+        panel.frames.push(self.panel_frame(full));
+        panel.text = self.layout_text(
+            TOAST_PADDING.0 as i32,
+            TOAST_PADDING.1 as i32,
+            text,
+            self.theme.palette.text,
+            (
+                (rect.width as u32).saturating_sub(2 * TOAST_PADDING.0),
+                self.theme.line_pitch(),
+            ),
+        );
+        panel
     }
 
-    /// Fast nearest-neighbor rendering for responsive resize with mipmap optimization
-    fn render_image_fast(
-        image: &ImageData,
+    /// Fill a rectangle of the canvas with a solid color
+    fn fill_rect(
         canvas: &mut [u8],
-        width: u32,
-        height: u32,
-        opacity: f32,
+        canvas_width: u32,
+        canvas_height: u32,
+        rect: Rect,
+        color: [u8; 4],
     ) {
-        // Choose best mipmap level based on target size
-        // Use mipmap when downscaling significantly for better performance
-        let scale_ratio =
-            (width as f32 / image.width as f32).min(height as f32 / image.height as f32);
-
-        let (img_width, img_height, src_data) = if scale_ratio < 0.5 && !image.mipmaps.is_empty() {
-            // Find the best mipmap level
-            let mut best_level = 0;
-            for (i, mipmap) in image.mipmaps.iter().enumerate() {
-                let mip_scale = mipmap.width as f32 / image.width as f32;
-                if mip_scale >= scale_ratio * 0.75 {
-                    best_level = i;
-                    break;
-                }
-                best_level = i;
-            }
-
-            let mipmap = &image.mipmaps[best_level];
-            (mipmap.width, mipmap.height, &mipmap.data[..])
-        } else {
-            (image.width, image.height, &image.rgba_data[..])
+        let Some(rect) = rect.clamp_to(canvas_width, canvas_height) else {
+            return;
         };
-
-        // Pre-compute scale factors as fixed-point for faster integer math
-        let scale_x_fp = ((img_width as u64) << 16) / width as u64;
-        let scale_y_fp = ((img_height as u64) << 16) / height as u64;
-        let opacity_i = (opacity * 255.0) as u32;
-        let img_stride = img_width * 4;
-
-        // Pre-compute X lookup table to avoid repeated calculations per row
-        let x_lut: Vec<u32> = (0..width)
-            .map(|x| {
-                let src_x = ((x as u64 * scale_x_fp) >> 16) as u32;
-                src_x.min(img_width - 1)
-            })
-            .collect();
-
-        // Process each row with SIMD-friendly memory access patterns
-        for y in 0..height {
-            let src_y = (((y as u64) * scale_y_fp) >> 16) as u32;
-            let src_y = src_y.min(img_height - 1);
-            let src_row_offset = (src_y * img_stride) as usize;
-            let dst_row_offset = (y * width * 4) as usize;
-
-            // Process row with pre-computed X values
-            for (x, &src_x) in x_lut.iter().enumerate() {
-                let src_idx = src_row_offset + (src_x * 4) as usize;
-                let dst_idx = dst_row_offset + x * 4;
-
-                if src_idx + 3 < src_data.len() && dst_idx + 3 < canvas.len() {
-                    // Fast alpha blend with integer math
-                    let src_alpha = src_data[src_idx + 3] as u32;
-                    let final_alpha = ((src_alpha * opacity_i) >> 8) as u8;
-
-                    // Direct copy (compiler can optimize this to vector operations)
-                    canvas[dst_idx] = src_data[src_idx];
-                    canvas[dst_idx + 1] = src_data[src_idx + 1];
-                    canvas[dst_idx + 2] = src_data[src_idx + 2];
-                    canvas[dst_idx + 3] = final_alpha;
-                }
+        for y in rect.y..rect.y + rect.height {
+            let row = (y as u32 * canvas_width) as usize * 4;
+            let start = row + rect.x as usize * 4;
+            let end = start + rect.width as usize * 4;
+            for pixel in canvas[start..end].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
             }
         }
     }
 
-    /// Apply opacity to cached image data
-    fn apply_opacity_to_canvas(cached: &[u8], canvas: &mut [u8], opacity: f32) {
-        for (dst, src) in canvas.chunks_exact_mut(4).zip(cached.chunks_exact(4)) {
-            let src_alpha = src[3] as f32 / 255.0;
-            let final_alpha = (src_alpha * opacity * 255.0) as u8;
-            dst[0] = src[0];
-            dst[1] = src[1];
-            dst[2] = src[2];
-            dst[3] = final_alpha;
-        }
-    }
-
-    /// Render the context menu (static version)
-    fn render_menu(
-        &mut self,
+    /// Fill a rectangle whose corners are rounded by `radius`
+    fn fill_round_rect(
         canvas: &mut [u8],
         canvas_width: u32,
         canvas_height: u32,
-        menu_pos: (i32, i32),
-        menu_hover_item: Option<usize>,
-        menu_items: &[&str],
+        rect: Rect,
+        radius: u32,
+        color: [u8; 4],
     ) {
-        let menu_x = menu_pos.0.max(0) as u32;
-        let menu_y = menu_pos.1.max(0) as u32;
-
-        for (i, item) in menu_items.iter().enumerate() {
-            let item_y = menu_y + (i as u32 * MENU_ITEM_HEIGHT);
-            let is_hovered = menu_hover_item == Some(i);
-
-            // Draw menu item background with rounded appearance
-            let bg_color: [u8; 4] = if is_hovered {
-                [100, 150, 220, 240] // Highlighted: BGRA blue
-            } else {
-                [45, 45, 48, 240] // Normal: BGRA dark gray (GTK-like)
-            };
-
-            for y in item_y..(item_y + MENU_ITEM_HEIGHT).min(canvas_height) {
-                for x in menu_x..(menu_x + MENU_WIDTH).min(canvas_width) {
-                    let idx = ((y * canvas_width + x) * 4) as usize;
-                    if idx + 3 < canvas.len() {
-                        canvas[idx] = bg_color[0];
-                        canvas[idx + 1] = bg_color[1];
-                        canvas[idx + 2] = bg_color[2];
-                        canvas[idx + 3] = bg_color[3];
-                    }
-                }
-            }
-
-            // Draw text using cosmic-text
-            let text_x = menu_x + 12;
-            let text_y = item_y + 5;
-            let text_color = if is_hovered {
-                [255, 255, 255, 255] // White when hovered
-            } else {
-                [220, 220, 220, 255] // Light gray normally
-            };
-            self.draw_text_cosmic(
+        if radius == 0 {
+            Self::fill_rect(canvas, canvas_width, canvas_height, rect, color);
+        } else {
+            cpu_renderer::fill_rounded_rect(
                 canvas,
                 canvas_width,
                 canvas_height,
-                text_x,
-                text_y,
-                item,
-                text_color,
+                rect,
+                radius,
+                color,
+                None,
             );
         }
+    }
 
-        // Draw menu border with shadow effect
-        let border_color: [u8; 4] = [80, 80, 80, 255];
-        let menu_height = menu_items.len() as u32 * MENU_ITEM_HEIGHT;
-
-        // Top and bottom borders
-        for x in menu_x..(menu_x + MENU_WIDTH).min(canvas_width) {
-            for &y in &[menu_y, (menu_y + menu_height - 1).min(canvas_height - 1)] {
-                let idx = ((y * canvas_width + x) * 4) as usize;
-                if idx + 3 < canvas.len() {
-                    canvas[idx] = border_color[0];
-                    canvas[idx + 1] = border_color[1];
-                    canvas[idx + 2] = border_color[2];
-                    canvas[idx + 3] = border_color[3];
-                }
-            }
-        }
-
-        // Left and right borders
-        for y in menu_y..(menu_y + menu_height).min(canvas_height) {
-            for &x in &[menu_x, (menu_x + MENU_WIDTH - 1).min(canvas_width - 1)] {
-                let idx = ((y * canvas_width + x) * 4) as usize;
-                if idx + 3 < canvas.len() {
-                    canvas[idx] = border_color[0];
-                    canvas[idx + 1] = border_color[1];
-                    canvas[idx + 2] = border_color[2];
-                    canvas[idx + 3] = border_color[3];
-                }
-            }
+    /// An overlay panel's background, outlined in the theme's colors
+    fn panel_frame(&self, rect: Rect) -> PanelFrame {
+        PanelFrame {
+            rect,
+            radius: self.theme.radius,
+            fill: self.theme.palette.background,
+            border: self.theme.palette.border,
         }
     }
 
-    /// Render menu contents into a local buffer (used for GPU overlay)
-    fn render_menu_overlay_contents(
-        &mut self,
+    /// Draw a one-pixel outline just inside a rectangle
+    fn stroke_rect(
         canvas: &mut [u8],
         canvas_width: u32,
         canvas_height: u32,
-        menu_hover_item: Option<usize>,
-        menu_items: &[&str],
+        rect: Rect,
+        color: [u8; 4],
     ) {
-        for (i, item) in menu_items.iter().enumerate() {
-            let item_y = (i as u32) * MENU_ITEM_HEIGHT;
-            if item_y >= canvas_height {
-                break;
-            }
-            let is_hovered = menu_hover_item == Some(i);
-
-            let bg_color: [u8; 4] = if is_hovered {
-                [100, 150, 220, 240]
-            } else {
-                [45, 45, 48, 240]
-            };
-
-            for y in item_y..(item_y + MENU_ITEM_HEIGHT).min(canvas_height) {
-                for x in 0..canvas_width.min(MENU_WIDTH) {
-                    let idx = ((y * canvas_width + x) * 4) as usize;
-                    if idx + 3 < canvas.len() {
-                        canvas[idx] = bg_color[0];
-                        canvas[idx + 1] = bg_color[1];
-                        canvas[idx + 2] = bg_color[2];
-                        canvas[idx + 3] = bg_color[3];
-                    }
-                }
-            }
-
-            let text_x = 12;
-            let text_y = item_y + 5;
-            let text_color = if is_hovered {
-                [255, 255, 255, 255]
-            } else {
-                [220, 220, 220, 255]
-            };
-            self.draw_text_cosmic(
-                canvas,
-                canvas_width,
-                canvas_height,
-                text_x,
-                text_y,
-                item,
-                text_color,
-            );
+        let edges = [
+            Rect::new(rect.x, rect.y, rect.width, 1),
+            Rect::new(rect.x, rect.y + rect.height - 1, rect.width, 1),
+            Rect::new(rect.x, rect.y, 1, rect.height),
+            Rect::new(rect.x + rect.width - 1, rect.y, 1, rect.height),
+        ];
+        for edge in edges {
+            Self::fill_rect(canvas, canvas_width, canvas_height, edge, color);
         }
+    }
 
-        let border_color: [u8; 4] = [80, 80, 80, 255];
-        let menu_height = canvas_height.min(menu_items.len() as u32 * MENU_ITEM_HEIGHT);
-
-        for x in 0..canvas_width.min(MENU_WIDTH) {
-            for &y in &[0, menu_height.saturating_sub(1)] {
-                let idx = ((y * canvas_width + x) * 4) as usize;
-                if idx + 3 < canvas.len() {
-                    canvas[idx] = border_color[0];
-                    canvas[idx + 1] = border_color[1];
-                    canvas[idx + 2] = border_color[2];
-                    canvas[idx + 3] = border_color[3];
-                }
-            }
-        }
+    /// Lay out text with its top-left corner at `(x, y)`, clipped to `bounds`
+    fn layout_text(
+        &mut self,
+        x: i32,
+        y: i32,
+        text: &str,
+        color: [u8; 4],
+        bounds: (u32, u32),
+    ) -> Vec<GlyphQuad> {
+        let metrics = Metrics::new(self.theme.font_size, self.theme.line_height);
+        self.layout_text_with(metrics, x, y, text, color, bounds)
+    }
 
-        for y in 0..menu_height {
-            for &x in &[0, canvas_width.min(MENU_WIDTH).saturating_sub(1)] {
-                let idx = ((y * canvas_width + x) * 4) as usize;
-                if idx + 3 < canvas.len() {
-                    canvas[idx] = border_color[0];
-                    canvas[idx + 1] = border_color[1];
-                    canvas[idx + 2] = border_color[2];
-                    canvas[idx + 3] = border_color[3];
-                }
-            }
-        }
+    /// Like `layout_text`, at other font metrics (menu popups drawn at buffer scale)
+    fn layout_text_with(
+        &mut self,
+        metrics: Metrics,
+        x: i32,
+        y: i32,
+        text: &str,
+        color: [u8; 4],
+        bounds: (u32, u32),
+    ) -> Vec<GlyphQuad> {
+        self.ensure_typesetter();
+        let typesetter = self.typesetter.as_mut().unwrap();
+        typesetter.layout(&mut self.glyph_atlas, metrics, (x, y), text, color, bounds)
     }
 
-    /// Draw text using cosmic-text for proper font rendering
-    fn draw_text_cosmic(
+    /// Draw text straight into a BGRA canvas
+    #[allow(clippy::too_many_arguments)]
+    fn draw_text(
         &mut self,
         canvas: &mut [u8],
         canvas_width: u32,
@@ -1220,223 +3239,78 @@ impl WaylandApp {
         y: u32,
         text: &str,
         color: [u8; 4],
+        bounds: (u32, u32),
     ) {
-        // Lazy initialize font system when first needed
-        if self.font_system.is_none() {
-            info!("Initializing font system for menu rendering...");
-
-            // Create a minimal font database with only the fonts we need
-            let mut db = fontdb::Database::new();
-
-            // Load text font (try multiple paths for different distros)
-            let text_font_paths = [
-                "/usr/share/fonts/noto/NotoSans-Regular.ttf",
-                "/usr/share/fonts/TTF/NotoSans-Regular.ttf",
-                "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
-                "/usr/share/fonts/google-noto/NotoSans-Regular.ttf",
-                "/usr/share/fonts/TTF/DejaVuSans.ttf",
-                "/usr/share/fonts/dejavu/DejaVuSans.ttf",
-                "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
-            ];
-
-            let mut text_loaded = false;
-            for path in &text_font_paths {
-                if std::path::Path::new(path).exists() {
-                    if db.load_font_file(path).is_ok() {
-                        info!("Loaded text font: {}", path);
-                        text_loaded = true;
-                        break;
-                    }
-                }
-            }
-
-            // Load emoji font for menu icons
-            let emoji_font_paths = [
-                "/usr/share/fonts/noto/NotoColorEmoji.ttf",
-                "/usr/share/fonts/google-noto/NotoColorEmoji.ttf",
-                "/usr/share/fonts/truetype/noto/NotoColorEmoji.ttf",
-                "/usr/share/fonts/TTF/NotoColorEmoji.ttf",
-                "/usr/share/fonts/noto-emoji/NotoColorEmoji.ttf",
-            ];
-
-            for path in &emoji_font_paths {
-                if std::path::Path::new(path).exists() {
-                    if db.load_font_file(path).is_ok() {
-                        info!("Loaded emoji font: {}", path);
-                        break;
-                    }
-                }
-            }
-
-            // Fallback: load system fonts only if no text font found
-            if !text_loaded {
-                warn!("No preferred font found, loading system fonts...");
-                db.load_system_fonts();
-            }
+        let quads = self.layout_text(x as i32, y as i32, text, color, bounds);
+        self.glyph_atlas
+            .draw(canvas, canvas_width, canvas_height, &quads);
+    }
 
-            let font_system = FontSystem::new_with_locale_and_db("en-US".to_string(), db);
-            self.font_system = Some(font_system);
-            self.swash_cache = Some(SwashCache::new());
+    /// Width of a single line of text as rendered by `layout_text`
+    fn measure_text(&mut self, text: &str) -> u32 {
+        if let Some(&width) = self.text_widths.get(text) {
+            return width;
         }
-
-        let font_system = self.font_system.as_mut().unwrap();
-        let swash_cache = self.swash_cache.as_mut().unwrap();
-
-        let mut buffer = Buffer::new(font_system, self.menu_text_metrics);
-        buffer.set_size(
-            font_system,
-            Some(MENU_WIDTH as f32 - 24.0),
-            Some(MENU_ITEM_HEIGHT as f32),
-        );
-        buffer.set_text(
-            font_system,
-            text,
-            self.menu_text_attrs.as_attrs(),
-            Shaping::Advanced,
-        );
-        buffer.shape_until_scroll(font_system, false);
-
-        let rgba = TextColor::rgba(color[2], color[1], color[0], color[3]);
-        let origin_x = x as i32;
-        let origin_y = y as i32;
-
-        buffer.draw(
-            font_system,
-            swash_cache,
-            rgba,
-            |px, py, _w, _h, glyph_color| {
-                let pixel_x = origin_x + px;
-                let pixel_y = origin_y + py;
-
-                if pixel_x < 0
-                    || pixel_x >= canvas_width as i32
-                    || pixel_y < 0
-                    || pixel_y >= canvas_height as i32
-                {
-                    return;
-                }
-
-                let idx = ((pixel_y as u32 * canvas_width + pixel_x as u32) * 4) as usize;
-                if idx + 3 >= canvas.len() {
-                    return;
-                }
-
-                let [r, g, b, a] = glyph_color.as_rgba();
-                let src = [b, g, r, a];
-
-                let src_alpha = src[3] as f32 / 255.0;
-                if src_alpha <= 0.0 {
-                    return;
-                }
-                let dst_alpha = canvas[idx + 3] as f32 / 255.0;
-                let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
-                if out_alpha <= 0.0 {
-                    return;
-                }
-
-                let blend = |src_channel: u8, dst_channel: u8| -> u8 {
-                    ((src_channel as f32 * src_alpha
-                        + dst_channel as f32 * dst_alpha * (1.0 - src_alpha))
-                        / out_alpha)
-                        .round()
-                        .clamp(0.0, 255.0) as u8
-                };
-
-                canvas[idx] = blend(src[0], canvas[idx]);
-                canvas[idx + 1] = blend(src[1], canvas[idx + 1]);
-                canvas[idx + 2] = blend(src[2], canvas[idx + 2]);
-                canvas[idx + 3] = (out_alpha * 255.0) as u8;
-            },
-        );
+        let width = self.shape_width(text);
+        if self.text_widths.len() >= TEXT_WIDTH_CACHE_SIZE {
+            self.text_widths.clear();
+        }
+        self.text_widths.insert(text.to_string(), width);
+        width
     }
 
-    /// Render resize border indicator (static version)
-    fn render_resize_border_static(canvas: &mut [u8], width: u32, height: u32) {
-        let border_color: [u8; 4] = [150, 150, 150, 100];
+    /// Shape a single line of text and return its width
+    fn shape_width(&mut self, text: &str) -> u32 {
+        self.ensure_typesetter();
+        self.typesetter.as_mut().unwrap().line_width(text)
+    }
 
-        // Draw subtle corner indicators
-        let corner_size = RESIZE_MARGIN as u32;
+    /// Width of the widest of several lines
+    fn widest_line(&mut self, lines: &[String]) -> u32 {
+        lines
+            .iter()
+            .map(|line| self.measure_text(line))
+            .max()
+            .unwrap_or(0)
+    }
 
-        // Draw corner indicators
-        for i in 0..corner_size {
-            // Top-left
-            Self::draw_pixel(canvas, width, height, i, 0, border_color);
-            Self::draw_pixel(canvas, width, height, 0, i, border_color);
-            // Top-right
-            Self::draw_pixel(
-                canvas,
-                width,
-                height,
-                width.saturating_sub(1).saturating_sub(i),
-                0,
-                border_color,
-            );
-            Self::draw_pixel(
-                canvas,
-                width,
-                height,
-                width.saturating_sub(1),
-                i,
-                border_color,
-            );
-            // Bottom-left
-            Self::draw_pixel(
-                canvas,
-                width,
-                height,
-                i,
-                height.saturating_sub(1),
-                border_color,
-            );
-            Self::draw_pixel(
-                canvas,
-                width,
-                height,
-                0,
-                height.saturating_sub(1).saturating_sub(i),
-                border_color,
-            );
-            // Bottom-right
-            Self::draw_pixel(
-                canvas,
-                width,
-                height,
-                width.saturating_sub(1).saturating_sub(i),
-                height.saturating_sub(1),
-                border_color,
-            );
-            Self::draw_pixel(
-                canvas,
-                width,
-                height,
-                width.saturating_sub(1),
-                height.saturating_sub(1).saturating_sub(i),
-                border_color,
-            );
-        }
+    /// Width of the widest line and height of `text` wrapped at `max_width`
+    fn measure_wrapped(&mut self, text: &str, max_width: u32) -> (u32, u32) {
+        self.ensure_typesetter();
+        self.typesetter
+            .as_mut()
+            .unwrap()
+            .wrapped_size(text, max_width)
     }
 
-    /// Helper to draw a single pixel
-    fn draw_pixel(
-        canvas: &mut [u8],
-        canvas_width: u32,
-        canvas_height: u32,
-        x: u32,
-        y: u32,
-        color: [u8; 4],
-    ) {
-        if x < canvas_width && y < canvas_height {
-            let idx = ((y * canvas_width + x) * 4) as usize;
-            if idx + 3 < canvas.len() {
-                canvas[idx] = color[0];
-                canvas[idx + 1] = color[1];
-                canvas[idx + 2] = color[2];
-                canvas[idx + 3] = color[3];
-            }
+    /// Lazily load the fonts used for the menu and overlay text
+    fn ensure_typesetter(&mut self) {
+        if self.typesetter.is_some() {
+            return;
         }
+        info!("Initializing font system for menu rendering...");
+
+        // Kept for the rest of the session; the glyph atlas bounds what it caches
+        let language = i18n::language();
+        let db = self
+            .font_loader
+            .take()
+            .unwrap_or_else(|| fonts::Loader::Deferred(font_patterns(&self.theme)))
+            .finish();
+        let metrics = Metrics::new(self.theme.font_size, self.theme.line_height);
+        self.typesetter = Some(Typesetter::new(db, language.tag(), metrics));
     }
 }
 
+/// fontconfig patterns for the UI text
+///
+/// The language's script comes after the configured fonts, so translations never show tofu.
+fn font_patterns(theme: &Theme) -> Vec<String> {
+    let mut patterns = theme.font_patterns();
+    patterns.extend(i18n::language().font_pattern().map(str::to_string));
+    patterns
+}
+
 // Implement required traits for smithay-client-toolkit
 
 impl CompositorHandler for WaylandApp {
@@ -1444,10 +3318,29 @@ impl CompositorHandler for WaylandApp {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
-        debug!("Scale factor changed");
+        debug!("Scale factor changed to {}", new_factor);
+        let scale = new_factor.max(1);
+        let is_pin = self
+            .layer_surface
+            .as_ref()
+            .is_some_and(|layer| layer.wl_surface() == surface);
+        if is_pin {
+            self.scale_factor = scale;
+        }
+        // Menu popups follow the output they are on
+        let mut repaint = false;
+        for popup in &mut self.menu_popups {
+            if popup.popup.wl_surface() == surface && popup.scale != scale as u32 {
+                popup.scale = scale as u32;
+                repaint = true;
+            }
+        }
+        if repaint {
+            self.draw_menu_popups();
+        }
     }
 
     fn transform_changed(
@@ -1467,18 +3360,27 @@ impl CompositorHandler for WaylandApp {
         _surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
-        if self.needs_redraw {
-            self.draw(qh);
-        }
+        self.frame_callback_pending = false;
+        self.flush_frame(qh);
     }
 
     fn surface_enter(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
+        qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        output: &wl_output::WlOutput,
     ) {
+        let is_pin = self
+            .layer_surface
+            .as_ref()
+            .is_some_and(|layer_surface| layer_surface.wl_surface() == surface);
+        if !is_pin || self.pin_output.as_ref() == Some(output) {
+            return;
+        }
+        self.pin_output = Some(output.clone());
+        debug!("Pin shown on output {:?}", self.output_name());
+        self.create_usable_probe(qh, output);
     }
 
     fn surface_leave(
@@ -1525,7 +3427,12 @@ impl OutputHandler for WaylandApp {
 }
 
 impl LayerShellHandler for WaylandApp {
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        if self.usable_probe.as_ref() == Some(layer) {
+            self.usable_probe = None;
+            self.usable_size = None;
+            return;
+        }
         info!("Layer surface closed");
         self.should_exit = true;
     }
@@ -1534,40 +3441,90 @@ impl LayerShellHandler for WaylandApp {
         &mut self,
         _conn: &Connection,
         qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
+        layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
+        if self.usable_probe.as_ref() == Some(layer) {
+            debug!("Usable area of the output: {:?}", configure.new_size);
+            self.usable_size =
+                Some(configure.new_size).filter(|&(width, height)| width > 0 && height > 0);
+            return;
+        }
         debug!("Layer surface configured: {:?}", configure);
 
         // When dragging or resizing, ignore compositor's size suggestions
         // to allow the window to extend beyond screen boundaries
         if !self.dragging && !self.resizing {
             // Only accept compositor's size if we're not actively manipulating the window
-            if configure.new_size.0 > 0 && configure.new_size.0 != self.width {
-                self.width = configure.new_size.0;
+            // (the surface includes the room for the shadow)
+            let insets = self.insets();
+            let width = configure.new_size.0.saturating_sub(insets.horizontal());
+            let height = configure.new_size.1.saturating_sub(insets.vertical());
+            if width > 0 && width != self.width {
+                self.width = width;
             }
-            if configure.new_size.1 > 0 && configure.new_size.1 != self.height {
-                self.height = configure.new_size.1;
+            if height > 0 && height != self.height {
+                self.height = height;
             }
         }
         // If dragging/resizing, keep our own size and re-request it
         else if let Some(ref layer_surface) = self.layer_surface {
-            layer_surface.set_size(self.width, self.height);
-            layer_surface.commit();
+            self.request_surface_size(layer_surface);
+            self.needs_commit = true;
         }
 
         self.configured = true;
-        self.needs_redraw = true;
+        self.request_redraw();
 
-        // Draw initial frame
-        self.draw(qh);
+        // Draw now if no frame is in flight (always the case for the initial configure)
+        self.flush_frame(qh);
 
         // Initialize GPU after the first frame so startup feels instant
         self.request_gpu_init();
     }
 }
 
+impl PopupHandler for WaylandApp {
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        popup: &Popup,
+        config: PopupConfigure,
+    ) {
+        let Some(menu_popup) = self.menu_popups.iter_mut().find(|p| &p.popup == popup) else {
+            return;
+        };
+        debug!("Menu popup configured at {:?}", config.position);
+        menu_popup.position = Some(config.position);
+        self.place_menu_panels();
+        self.draw_menu_popups();
+    }
+
+    fn done(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, popup: &Popup) {
+        // Dismissed by the compositor, e.g. after a click into another client
+        if self.menu_popups.iter().any(|p| &p.popup == popup) {
+            self.hide_menu();
+        }
+    }
+}
+
+// XdgShell::bind requires a window handler; the pin never creates toplevels
+impl WindowHandler for WaylandApp {
+    fn request_close(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _window: &Window) {}
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _window: &Window,
+        _configure: WindowConfigure,
+        _serial: u32,
+    ) {
+    }
+}
+
 impl SeatHandler for WaylandApp {
     fn seat_state(&mut self) -> &mut SeatState {
         &mut self.seat_state
@@ -1585,6 +3542,7 @@ impl SeatHandler for WaylandApp {
         capability: Capability,
     ) {
         debug!("New capability: {:?}", capability);
+        self.seat = Some(seat.clone());
 
         if capability == Capability::Keyboard {
             if let Err(e) = self.seat_state.get_keyboard(qh, &seat, None) {
@@ -1648,6 +3606,9 @@ impl KeyboardHandler for WaylandApp {
         _serial: u32,
     ) {
         debug!("Keyboard left surface");
+        // The release would go elsewhere, so hide the loupe now
+        self.loupe_held = None;
+        self.update_loupe();
     }
 
     fn press_key(
@@ -1655,15 +3616,58 @@ impl KeyboardHandler for WaylandApp {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
         _keyboard: &wl_keyboard::WlKeyboard,
-        _serial: u32,
+        serial: u32,
         event: KeyEvent,
     ) {
         debug!("Key pressed: {:?}", event.keysym);
+        self.input_serial = serial;
+
+        if self.menu_state == MenuState::Visible {
+            self.menu_key(&event);
+            return;
+        }
+
+        // Menu or Shift+F10 opens the menu at the center of the pin
+        if event.keysym == Keysym::Menu || (event.keysym == Keysym::F10 && self.modifiers.shift) {
+            self.show_menu(((self.width / 2) as i32, (self.height / 2) as i32));
+            self.step_menu_selection(0, MenuStep::First);
+            return;
+        }
+
+        // Show the loupe while its key is held
+        if event.keysym == self.loupe_key {
+            if self.loupe_held.is_none() {
+                self.loupe_held = Some(event.raw_code);
+                self.update_loupe();
+            }
+            return;
+        }
 
-        // Close on Escape or Q key
-        if event.keysym == Keysym::Escape || event.keysym == Keysym::q {
-            info!("Exit key pressed");
-            self.should_exit = true;
+        // Escape leaves picker or measure mode before it closes the window
+        if event.keysym == Keysym::Escape && self.picker_active {
+            self.toggle_picker();
+            return;
+        }
+        if event.keysym == Keysym::Escape && self.measure_active {
+            self.toggle_measure();
+            return;
+        }
+
+        // Letter shortcuts match both cases, so Caps Lock and Shift do not disable them
+        match event.keysym {
+            // Close on Escape or Q key
+            Keysym::Escape | Keysym::q | Keysym::Q => {
+                info!("Exit key pressed");
+                self.should_exit = true;
+            }
+            // C toggles the color picker, M the measure tool
+            Keysym::c | Keysym::C => self.toggle_picker(),
+            Keysym::m | Keysym::M => self.toggle_measure(),
+            // I shows or hides the info overlay
+            Keysym::i | Keysym::I => self.toggle_hud(),
+            // Delete or Backspace clears measurements
+            Keysym::Delete | Keysym::BackSpace => self.clear_measurements(),
+            _ => {}
         }
     }
 
@@ -1673,8 +3677,13 @@ impl KeyboardHandler for WaylandApp {
         _qh: &QueueHandle<Self>,
         _keyboard: &wl_keyboard::WlKeyboard,
         _serial: u32,
-        _event: KeyEvent,
+        event: KeyEvent,
     ) {
+        // Match the physical key, since modifiers may change the keysym
+        if self.loupe_held == Some(event.raw_code) {
+            self.loupe_held = None;
+            self.update_loupe();
+        }
     }
 
     fn update_modifiers(
@@ -1683,9 +3692,10 @@ impl KeyboardHandler for WaylandApp {
         _qh: &QueueHandle<Self>,
         _keyboard: &wl_keyboard::WlKeyboard,
         _serial: u32,
-        _modifiers: Modifiers,
+        modifiers: Modifiers,
         _layout: u32,
     ) {
+        self.modifiers = modifiers;
     }
 }
 
@@ -1693,34 +3703,65 @@ impl PointerHandler for WaylandApp {
     fn pointer_frame(
         &mut self,
         _conn: &Connection,
-        qh: &QueueHandle<Self>,
+        _qh: &QueueHandle<Self>,
         _pointer: &wl_pointer::WlPointer,
         events: &[PointerEvent],
     ) {
         for event in events {
+            // Menu popups report positions relative to themselves
+            let position = self.surface_to_pin(&event.surface, event.position);
             match event.kind {
-                PointerEventKind::Enter { .. } => {
+                PointerEventKind::Enter { serial } => {
                     debug!("Pointer entered");
-                    self.set_cursor_on_next_frame = Some(CursorIcon::Default);
+                    self.input_serial = serial;
+                    self.pointer_pos = position;
+                    self.update_hud();
+                    let over_menu = self
+                        .menu_popups
+                        .iter()
+                        .any(|menu_popup| menu_popup.popup.wl_surface() == &event.surface);
+                    self.set_cursor_on_next_frame = Some(
+                        if !over_menu && (self.picker_active || self.measure_active) {
+                            CursorIcon::Crosshair
+                        } else {
+                            CursorIcon::Default
+                        },
+                    );
                 }
                 PointerEventKind::Leave { .. } => {
                     debug!("Pointer left");
                     self.dragging = false;
                     self.resizing = false;
+                    self.snap_guides = None;
+                    // Hide the loupe and picker readout until the pointer comes back
+                    self.pointer_pos = (-1.0, -1.0);
+                    self.update_loupe();
+                    self.update_picker_readout();
+                    self.update_hud();
                 }
                 PointerEventKind::Motion { .. } => {
-                    let (x, y) = event.position;
+                    let (x, y) = position;
                     self.pointer_pos = (x, y);
+                    if self.loupe_held.is_some() {
+                        self.update_loupe();
+                    }
 
                     // Update menu hover state
                     if self.menu_state == MenuState::Visible {
-                        let prev_hover = self.menu_hover_item;
-                        self.menu_hover_item = self.get_menu_item_at(x, y);
-                        if prev_hover != self.menu_hover_item {
-                            self.needs_redraw = true;
-                        }
+                        self.hover_menu(x, y);
                         // Set default cursor when over menu
                         self.set_cursor_on_next_frame = Some(CursorIcon::Default);
+                    } else if self.picker_active {
+                        self.set_cursor_on_next_frame = Some(CursorIcon::Crosshair);
+                        self.update_picker_readout();
+                    } else if self.measure_active {
+                        self.set_cursor_on_next_frame = Some(CursorIcon::Crosshair);
+                        let end = self.source_mapping().to_source((x, y));
+                        let constrain = self.modifiers.shift;
+                        if let Some(drag) = self.measure_drag.as_mut() {
+                            drag.set_end(end, constrain);
+                            self.invalidate_measurements();
+                        }
                     } else if !self.dragging && !self.resizing {
                         // Update cursor based on resize edge detection
                         let edge = self.detect_resize_edge(x, y);
@@ -1740,8 +3781,22 @@ impl PointerHandler for WaylandApp {
                         let dy = y - self.drag_start_pos.1;
 
                         // Allow window to go beyond screen boundaries
-                        self.margin_left = self.drag_start_margin.0 + dx as i32;
-                        self.margin_top = self.drag_start_margin.1 + dy as i32;
+                        let mut margin = (
+                            self.drag_start_margin.0 + dx as i32,
+                            self.drag_start_margin.1 + dy as i32,
+                        );
+
+                        // Pull edges and center onto nearby guides unless Ctrl is held
+                        if let Some(guides) = self.active_snap_guides() {
+                            let rect = Rect::new(
+                                margin.0,
+                                margin.1,
+                                self.width as i32,
+                                self.height as i32,
+                            );
+                            margin = guides.snap_move(rect, self.snap_threshold);
+                        }
+                        (self.margin_left, self.margin_top) = margin;
 
                         self.update_position();
                     }
@@ -1861,6 +3916,20 @@ impl PointerHandler for WaylandApp {
                             ResizeEdge::None => {}
                         }
 
+                        // Pull the dragged edges onto nearby guides unless Ctrl is held
+                        if let Some(guides) = self.active_snap_guides() {
+                            let rect = guides.snap_resize(
+                                Rect::new(new_ml, new_mt, new_w as i32, new_h as i32),
+                                self.resize_edge.moving_edges(),
+                                keep_ratio.then_some(aspect_ratio),
+                                self.resize_edge.anchor(),
+                                self.snap_threshold,
+                            );
+                            (new_ml, new_mt) = (rect.x, rect.y);
+                            new_w = rect.width.max(MIN_SIZE as i32) as u32;
+                            new_h = rect.height.max(MIN_SIZE as i32) as u32;
+                        }
+
                         // Apply size constraints (min and max)
                         new_w = new_w.clamp(MIN_SIZE, MAX_SIZE);
                         new_h = new_h.clamp(MIN_SIZE, MAX_SIZE);
@@ -1888,24 +3957,32 @@ impl PointerHandler for WaylandApp {
                         self.update_size();
                     }
                 }
-                PointerEventKind::Press { button, .. } => {
+                PointerEventKind::Press { button, serial, .. } => {
                     debug!("Pointer button pressed: {}", button);
+                    self.input_serial = serial;
                     let (x, y) = self.pointer_pos;
 
                     if button == BTN_LEFT {
                         // Check if clicking on menu
                         if self.menu_state == MenuState::Visible {
-                            if let Some(item) = self.get_menu_item_at(x, y) {
-                                self.handle_menu_action(item);
-                                self.draw(qh);
+                            if self.click_menu(x, y) {
                                 continue;
-                            } else {
-                                // Close menu if clicking outside
-                                self.menu_state = MenuState::Hidden;
-                                self.release_font_resources();
-                                self.needs_redraw = true;
-                                self.draw(qh);
                             }
+                            // Close menu if clicking outside
+                            self.hide_menu();
+                        }
+
+                        // In picker mode a click copies the color instead of moving the window
+                        if self.picker_active {
+                            self.copy_picked_color();
+                            continue;
+                        }
+
+                        // In measure mode a drag draws a measurement
+                        if self.measure_active {
+                            let start = self.source_mapping().to_source((x, y));
+                            self.measure_drag = Some(Measurement::new(start));
+                            continue;
                         }
 
                         // Check for double-click
@@ -1937,60 +4014,55 @@ impl PointerHandler for WaylandApp {
                             self.resize_start_pos = (x, y);
                             self.resize_start_size = (self.width, self.height);
                             self.resize_start_margin = (self.margin_left, self.margin_top);
+                            self.snap_guides = self.snap_guides();
                         } else {
                             // Start dragging for window move
                             self.dragging = true;
                             self.drag_start_pos = (x, y);
                             self.drag_start_margin = (self.margin_left, self.margin_top);
+                            self.snap_guides = self.snap_guides();
                         }
                     } else if button == BTN_RIGHT {
-                        // Show context menu
-                        self.menu_state = MenuState::Visible;
-                        self.menu_pos = (x as i32, y as i32);
-
-                        // Adjust menu position to stay within window bounds
-                        let menu_items = self.get_menu_items();
-                        let menu_height = menu_items.len() as i32 * MENU_ITEM_HEIGHT as i32;
-                        if self.menu_pos.0 + MENU_WIDTH as i32 > self.width as i32 {
-                            self.menu_pos.0 = self.width as i32 - MENU_WIDTH as i32;
-                        }
-                        if self.menu_pos.1 + menu_height > self.height as i32 {
-                            self.menu_pos.1 = self.height as i32 - menu_height;
-                        }
-                        self.menu_pos.0 = self.menu_pos.0.max(0);
-                        self.menu_pos.1 = self.menu_pos.1.max(0);
-
-                        self.needs_redraw = true;
-                        self.draw(qh);
+                        // Show context menu (laid out to stay within the window)
+                        self.show_menu((x as i32, y as i32));
                     }
                 }
                 PointerEventKind::Release { button, .. } => {
                     if button == BTN_LEFT {
+                        if let Some(drag) = self.measure_drag.take() {
+                            if !drag.is_empty() {
+                                info!("Measured {}", drag.label().join(", "));
+                                self.measurements.push(drag);
+                            }
+                            self.invalidate_measurements();
+                        }
+
                         // If we were resizing, trigger high quality redraw
                         let was_resizing = self.resizing;
 
                         self.dragging = false;
                         self.resizing = false;
                         self.resize_edge = ResizeEdge::None;
+                        self.snap_guides = None;
 
                         if was_resizing {
                             // Invalidate cache to force high-quality render
                             self.cached_scaled_image = None;
-                            self.needs_redraw = true;
-                            self.draw(qh);
+                            self.request_redraw();
                         }
                     }
                 }
                 PointerEventKind::Axis { vertical, .. } => {
-                    // Scroll wheel to adjust opacity
-                    if vertical.absolute != 0.0 {
+                    // Scroll wheel zooms the loupe while it is shown, otherwise adjusts opacity
+                    if vertical.absolute != 0.0 && self.loupe_held.is_some() {
+                        self.adjust_loupe_zoom(if vertical.absolute > 0.0 { -1 } else { 1 });
+                    } else if vertical.absolute != 0.0 {
                         let delta = if vertical.absolute > 0.0 {
                             -OPACITY_STEP
                         } else {
                             OPACITY_STEP
                         };
                         self.adjust_opacity(delta);
-                        self.draw(qh);
                     }
                 }
             }
@@ -2028,9 +4100,48 @@ delegate_keyboard!(WaylandApp);
 delegate_pointer!(WaylandApp);
 delegate_shm!(WaylandApp);
 delegate_registry!(WaylandApp);
+delegate_xdg_shell!(WaylandApp);
+delegate_xdg_popup!(WaylandApp);
 
 /// Run the Wayland application
-pub fn run(image: ImageData, opacity: f32, use_gpu: bool) -> Result<()> {
+pub fn run(probe: ImageProbe, args: ParsedArgs, frames: Option<FrameReader<Stdin>>) -> Result<()> {
+    // Create the event loop and block signals before any threads are spawned,
+    // so they are delivered through the signal source instead of killing us
+    let mut event_loop: EventLoop<'static, WaylandApp> =
+        EventLoop::try_new().context("Failed to create event loop")?;
+    let signals = Signals::new(&[
+        Signal::SIGTERM,
+        Signal::SIGINT,
+        Signal::SIGUSR1,
+        Signal::SIGHUP,
+    ])
+    .context("Failed to set up signal handling")?;
+    event_loop
+        .handle()
+        .insert_source(signals, |event, _, app| match event.signal() {
+            Signal::SIGTERM | Signal::SIGINT => {
+                info!("Received {:?}, shutting down", event.signal());
+                app.should_exit = true;
+            }
+            Signal::SIGUSR1 | Signal::SIGHUP => {
+                info!("Received {:?}, reloading image", event.signal());
+                app.request_reload();
+            }
+            _ => {}
+        })
+        .map_err(|e| anyhow!("Failed to insert signal source: {}", e.error))?;
+
+    // Decoded images arrive from worker threads through a channel
+    let (decode_sender, decode_channel) = channel::channel::<DecodeResult>();
+    event_loop
+        .handle()
+        .insert_source(decode_channel, |event, _, app| {
+            if let channel::Event::Msg((kind, result)) = event {
+                app.handle_decoded(kind, result);
+            }
+        })
+        .map_err(|e| anyhow!("Failed to insert decode channel: {}", e.error))?;
+
     info!("Connecting to Wayland display");
 
     // Connect to Wayland display
@@ -2046,10 +4157,17 @@ pub fn run(image: ImageData, opacity: f32, use_gpu: bool) -> Result<()> {
         CompositorState::bind(&globals, &qh).context("Failed to bind compositor")?;
     let layer_shell = LayerShell::bind(&globals, &qh).context("Failed to bind layer shell")?;
     let shm = Shm::bind(&globals, &qh).context("Failed to bind shm")?;
+    let xdg_shell = XdgShell::bind(&globals, &qh)
+        .map_err(|e| warn!("xdg_shell unavailable, menus stay inside the pin: {}", e))
+        .ok();
 
     // Get the display pointer for GPU rendering
     let display_ptr = conn.backend().display_ptr() as *mut std::ffi::c_void;
 
+    // The window is sized from the header probe, not the (preview) image
+    let (image_width, image_height) = (probe.width, probe.height);
+    let (complete, position, output_name) = (probe.complete, probe.position, probe.output.clone());
+
     // Create application state
     let mut app = WaylandApp::new(
         RegistryState::new(&globals),
@@ -2059,10 +4177,30 @@ pub fn run(image: ImageData, opacity: f32, use_gpu: bool) -> Result<()> {
         layer_shell,
         compositor_state,
         display_ptr,
-        image,
-        opacity,
-        use_gpu,
+        event_loop.handle(),
+        decode_sender,
+        probe,
+        &args,
     );
+    app.xdg_shell = xdg_shell;
+    app.queue_handle = Some(qh.clone());
+
+    if args.watch {
+        app.start_watching();
+    }
+    if let Some(frames) = frames {
+        app.start_stream(frames);
+    }
+    if app.theme.follows_desktop {
+        app.query_color_scheme();
+    }
+
+    // Decode the full image while the window is being mapped
+    if complete {
+        app.image_loaded = true;
+    } else {
+        app.spawn_decode(DecodeKind::Initial, move || image_loader::load_image(&args));
+    }
 
     // Dispatch once to get output info
     event_queue.roundtrip(&mut app)?;
@@ -2074,49 +4212,76 @@ pub fn run(image: ImageData, opacity: f32, use_gpu: bool) -> Result<()> {
     info!("Display dimensions: {}x{}", display_width, display_height);
 
     // Calculate the target size (limit to 10% of screen area)
-    let (target_width, target_height) = calculate_limited_size(
-        app.image.width,
-        app.image.height,
-        display_width,
-        display_height,
-        0.10,
-    );
+    let (target_width, target_height) = if position.is_some() {
+        (image_width, image_height)
+    } else {
+        calculate_limited_size(
+            image_width,
+            image_height,
+            display_width,
+            display_height,
+            0.10,
+        )
+    };
     info!(
         "Image size: {}x{} -> Display size: {}x{}",
-        app.image.width, app.image.height, target_width, target_height
+        image_width, image_height, target_width, target_height
     );
 
-    // Set initial window position (centered)
-    app.margin_left = ((display_width - target_width) / 2) as i32;
-    app.margin_top = ((display_height - target_height) / 2) as i32;
+    // Set initial window position (exact for captures, otherwise centered)
+    (app.margin_left, app.margin_top) = position.unwrap_or((
+        (display_width.saturating_sub(target_width) / 2) as i32,
+        (display_height.saturating_sub(target_height) / 2) as i32,
+    ));
     app.width = target_width;
     app.height = target_height;
 
+    // Captures are pinned on the output they were taken from
+    let output = output_name.and_then(|name| {
+        app.output_state.outputs().find(|output| {
+            app.output_state
+                .info(output)
+                .is_some_and(|info| info.name.as_deref() == Some(name.as_str()))
+        })
+    });
+
     // Create the layer surface
     let surface = app.compositor_state.create_surface(&qh);
-    let layer_surface =
-        app.layer_shell
-            .create_layer_surface(&qh, surface, Layer::Overlay, Some("rspin"), None);
+    let layer_surface = app.layer_shell.create_layer_surface(
+        &qh,
+        surface,
+        Layer::Overlay,
+        Some("rspin"),
+        output.as_ref(),
+    );
 
     // Configure the layer surface with anchoring for positioning
-    layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT);
-    layer_surface.set_margin(app.margin_top, 0, 0, app.margin_left);
-    layer_surface.set_size(target_width, target_height);
     layer_surface.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+    app.request_surface_size(&layer_surface);
+    app.layer_surface = Some(layer_surface);
+    app.update_position();
 
     // Commit the surface to trigger configure
-    layer_surface.commit();
-
-    app.layer_surface = Some(layer_surface);
+    if let Some(ref layer_surface) = app.layer_surface {
+        layer_surface.commit();
+    }
 
     info!("Starting event loop");
     info!("Controls: Double-click to close, Right-click for menu, Scroll to adjust opacity");
     info!("Drag edges to resize, Drag center to move");
 
+    // Wayland events are dispatched by the event loop from now on
+    WaylandSource::new(conn, event_queue)
+        .insert(event_loop.handle())
+        .map_err(|e| anyhow!("Failed to insert Wayland source: {}", e.error))?;
+
     // Main event loop
     loop {
-        event_queue.blocking_dispatch(&mut app)?;
+        event_loop
+            .dispatch(None, &mut app)
+            .context("Event loop dispatch failed")?;
         app.process_gpu_init(&qh);
+        app.flush_frame(&qh);
 
         if app.should_exit {
             info!("Exiting application");
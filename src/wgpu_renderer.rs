@@ -1,7 +1,12 @@
 // GPU-accelerated rendering using wgpu with raw Wayland surface
 // This renderer integrates with layer-shell surfaces without winit
 
+use crate::cpu_renderer::{PanelFrame, Rect};
+use crate::decoration::Decoration;
+use crate::glyph_atlas::{GlyphQuad, ATLAS_SIZE};
 use crate::image_loader::ImageData;
+use crate::loupe::{self, Lens, LoupeShape};
+use crate::resample::FilterMode;
 use anyhow::{Context, Result};
 use log::{debug, info, warn};
 use std::ptr::NonNull;
@@ -12,22 +17,91 @@ use wgpu::util::DeviceExt;
 const MAX_SURFACE_SIZE: u32 = 4096;
 const MAX_TEXTURE_SIZE: u32 = 8192;
 
+/// Texture format used for the image and its mip chain
+const IMAGE_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Overlays drawn over the image, in back-to-front order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayLayer {
+    /// Measure tool lines and labels
+    Measure,
+    /// Magnifier loupe
+    Loupe,
+    /// Info overlay
+    Hud,
+    /// Color picker readout
+    Picker,
+    /// Action feedback toast
+    Toast,
+    /// Context menu
+    Menu,
+}
+
+impl OverlayLayer {
+    const COUNT: usize = 6;
+}
+
+/// An overlay layer's pixels, drawn 1:1 at the rect in `rect_buffer`
+///
+/// The texture only grows, so smaller panels reuse it and its bind group.
+struct OverlayTexture {
+    texture: wgpu::Texture,
+    size: (u32, u32),
+    rect_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    visible: bool,
+}
+
+/// Instances drawn for an overlay (glyphs or panel frames); the buffer is reused
+/// while it is large enough
+struct InstanceBatch {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    count: u32,
+}
+
 pub struct WgpuRenderer {
     surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     render_pipeline: wgpu::RenderPipeline,
+    mipmap_pipeline: wgpu::RenderPipeline,
+    mipmap_sampler: wgpu::Sampler,
     texture: Option<wgpu::Texture>,
     texture_bind_group: Option<wgpu::BindGroup>,
-    overlay_texture: Option<wgpu::Texture>,
-    overlay_texture_bind_group: Option<wgpu::BindGroup>,
-    overlay_viewport: Option<[f32; 4]>,
+    overlays: [Option<OverlayTexture>; OverlayLayer::COUNT],
+    frames: [Option<InstanceBatch>; OverlayLayer::COUNT],
+    texts: [Option<InstanceBatch>; OverlayLayer::COUNT],
+    frame_pipeline: wgpu::RenderPipeline,
+    panel_image_pipeline: wgpu::RenderPipeline,
+    panel_image_layout: wgpu::BindGroupLayout,
+    panel_screen_bind_group: wgpu::BindGroup,
+    text_pipeline: wgpu::RenderPipeline,
+    atlas_texture: wgpu::Texture,
+    atlas_bind_group: wgpu::BindGroup,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    shadow_pipeline: wgpu::RenderPipeline,
+    shadow_buffer: wgpu::Buffer,
+    shadow_bind_group: wgpu::BindGroup,
+    decoration: Decoration,
+    /// Where the image is drawn in the surface
+    content: Rect,
+    loupe_pipeline: wgpu::RenderPipeline,
+    loupe_sampler: wgpu::Sampler,
+    /// The image texture seen through the nearest sampler
+    loupe_texture_bind_group: Option<wgpu::BindGroup>,
+    loupe_buffer: wgpu::Buffer,
+    loupe_bind_group: wgpu::BindGroup,
+    loupe: Option<Lens>,
     sampler: wgpu::Sampler,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
+    opacity: f32,
+    filter: FilterMode,
     width: u32,
     height: u32,
     max_texture_size: u32,
@@ -86,7 +160,153 @@ const INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct Uniforms {
     opacity: f32,
-    _padding: [f32; 3],
+    filter_mode: u32,
+    radius: f32,
+    border_width: f32,
+    size: [f32; 2],
+    _padding: [f32; 2],
+    /// RGBA, straight alpha
+    border_color: [f32; 4],
+}
+
+impl Uniforms {
+    /// Uniforms for a quad without corners or border
+    fn plain(opacity: f32, filter: FilterMode) -> Self {
+        Self {
+            opacity,
+            filter_mode: filter.shader_id(),
+            radius: 0.0,
+            border_width: 0.0,
+            size: [0.0; 2],
+            _padding: [0.0; 2],
+            border_color: [0.0; 4],
+        }
+    }
+}
+
+/// Drop shadow parameters for decoration.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniforms {
+    content: [f32; 4],
+    shape: [f32; 4],
+    /// RGBA, straight alpha
+    color: [f32; 4],
+    radius: f32,
+    blur: f32,
+    _padding: [f32; 2],
+}
+
+/// Surface size for the text shader, which places glyphs in pixels
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUniforms {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// One glyph of the text pipeline (see text.wgsl)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlyphInstance {
+    rect: [f32; 4],
+    atlas_pos: [f32; 2],
+    /// RGBA, straight alpha
+    color: [u8; 4],
+}
+
+impl GlyphInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x2, 2 => Unorm8x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GlyphInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+impl From<&GlyphQuad> for GlyphInstance {
+    fn from(quad: &GlyphQuad) -> Self {
+        let rect = quad.rect;
+        let [b, g, r, a] = quad.color;
+        Self {
+            rect: [
+                rect.x as f32,
+                rect.y as f32,
+                rect.width as f32,
+                rect.height as f32,
+            ],
+            atlas_pos: [quad.atlas.0 as f32, quad.atlas.1 as f32],
+            color: [r, g, b, a],
+        }
+    }
+}
+
+/// Loupe parameters for loupe.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LoupeUniforms {
+    rect: [f32; 4],
+    center: [f32; 2],
+    zoom: f32,
+    shape: u32,
+    border_width: f32,
+    grid: f32,
+    opacity: f32,
+    _padding: f32,
+    /// RGBA, straight alpha
+    outside_color: [f32; 4],
+    border_color: [f32; 4],
+    highlight_color: [f32; 4],
+}
+
+/// One overlay panel background of the frame pipeline (see panel.wgsl)
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FrameInstance {
+    rect: [f32; 4],
+    radius: f32,
+    /// RGBA, straight alpha
+    fill: [u8; 4],
+    border: [u8; 4],
+}
+
+impl FrameInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32,
+        2 => Unorm8x4,
+        3 => Unorm8x4
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<FrameInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+impl From<&PanelFrame> for FrameInstance {
+    fn from(frame: &PanelFrame) -> Self {
+        let rect = frame.rect;
+        let rgba = |[b, g, r, a]: [u8; 4]| [r, g, b, a];
+        Self {
+            rect: [
+                rect.x as f32,
+                rect.y as f32,
+                rect.width as f32,
+                rect.height as f32,
+            ],
+            radius: frame.radius as f32,
+            fill: rgba(frame.fill),
+            border: rgba(frame.border),
+        }
+    }
 }
 
 impl WgpuRenderer {
@@ -191,8 +411,8 @@ impl WgpuRenderer {
         info!("Max texture size: {}", max_texture_size);
 
         // Clamp dimensions to safe limits
-        let safe_width = width.max(1).min(MAX_SURFACE_SIZE).min(max_texture_size);
-        let safe_height = height.max(1).min(MAX_SURFACE_SIZE).min(max_texture_size);
+        let safe_width = width.clamp(1, MAX_SURFACE_SIZE.min(max_texture_size));
+        let safe_height = height.clamp(1, MAX_SURFACE_SIZE.min(max_texture_size));
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -210,7 +430,9 @@ impl WgpuRenderer {
         // Shader
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("common.wgsl"), include_str!("shader.wgsl")).into(),
+            ),
         });
 
         // Texture bind group layout
@@ -256,10 +478,7 @@ impl WgpuRenderer {
         // Uniform buffer
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[Uniforms {
-                opacity: 1.0,
-                _padding: [0.0; 3],
-            }]),
+            contents: bytemuck::cast_slice(&[Uniforms::plain(1.0, FilterMode::default())]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -336,22 +555,368 @@ impl WgpuRenderer {
             ..Default::default()
         });
 
+        // Mipmap generation pipeline (renders each level from the previous one)
+        let mipmap_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Mipmap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("mipmap.wgsl").into()),
+        });
+
+        let mipmap_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mipmap Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &mipmap_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &mipmap_shader,
+                entry_point: "fs_main",
+                targets: &[Some(IMAGE_TEXTURE_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let mipmap_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmap_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // Text pipeline: glyphs are instanced quads reading the coverage atlas
+        let text_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Text Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("common.wgsl"), include_str!("text.wgsl")).into(),
+            ),
+        });
+
+        let text_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &text_shader,
+                entry_point: "vs_main",
+                buffers: &[GlyphInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &text_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let atlas_texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("glyph_atlas"),
+            view_formats: &[],
+        });
+        let atlas_view = atlas_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &text_pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&atlas_view),
+            }],
+            label: Some("glyph_atlas_bind_group"),
+        });
+
+        let screen_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Screen Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[ScreenUniforms {
+                size: [safe_width as f32, safe_height as f32],
+                _padding: [0.0; 2],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &text_pipeline.get_bind_group_layout(1),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            }],
+            label: Some("screen_bind_group"),
+        });
+
+        // Panel pipelines: instanced backgrounds and the layer textures drawn 1:1,
+        // sharing one screen bind group through explicit layouts
+        let panel_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Panel Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("common.wgsl"), include_str!("panel.wgsl")).into(),
+            ),
+        });
+        let panel_screen_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("panel_screen_bind_group_layout"),
+            });
+        let panel_image_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("panel_image_bind_group_layout"),
+            });
+        let panel_screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &panel_screen_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            }],
+            label: Some("panel_screen_bind_group"),
+        });
+        let panel_pipeline = |label: &str,
+                              layouts: &[&wgpu::BindGroupLayout],
+                              entry: (&str, &str),
+                              buffers: &[wgpu::VertexBufferLayout]| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &panel_shader,
+                    entry_point: entry.0,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &panel_shader,
+                    entry_point: entry.1,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let frame_pipeline = panel_pipeline(
+            "Panel Frame Pipeline",
+            &[&panel_screen_layout],
+            ("vs_frame", "fs_frame"),
+            &[FrameInstance::desc()],
+        );
+        let panel_image_pipeline = panel_pipeline(
+            "Panel Image Pipeline",
+            &[&panel_screen_layout, &panel_image_layout],
+            ("vs_image", "fs_image"),
+            &[],
+        );
+
+        // Shadow pipeline: one quad over the whole surface, drawn before the image
+        let shadow_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Decoration Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("common.wgsl"), include_str!("decoration.wgsl")).into(),
+            ),
+        });
+        let shadow_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shadow_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shadow_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let shadow_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            size: std::mem::size_of::<ShadowUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shadow_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shadow_pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: shadow_buffer.as_entire_binding(),
+            }],
+            label: Some("shadow_bind_group"),
+        });
+
+        // Loupe pipeline: reads the image texture directly, so moving it uploads nothing
+        let loupe_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Loupe Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("common.wgsl"), include_str!("loupe.wgsl")).into(),
+            ),
+        });
+        let loupe_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Loupe Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &loupe_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &loupe_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let loupe_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("loupe_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let loupe_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Loupe Uniform Buffer"),
+            size: std::mem::size_of::<LoupeUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let loupe_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &loupe_pipeline.get_bind_group_layout(1),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: loupe_buffer.as_entire_binding(),
+            }],
+            label: Some("loupe_bind_group"),
+        });
+
         Ok(Self {
             surface,
             device,
             queue,
             config,
             render_pipeline,
+            mipmap_pipeline,
+            mipmap_sampler,
             texture: None,
             texture_bind_group: None,
-            overlay_texture: None,
-            overlay_texture_bind_group: None,
-            overlay_viewport: None,
+            overlays: Default::default(),
+            frames: Default::default(),
+            texts: Default::default(),
+            frame_pipeline,
+            panel_image_pipeline,
+            panel_image_layout,
+            panel_screen_bind_group,
+            text_pipeline,
+            atlas_texture,
+            atlas_bind_group,
+            screen_buffer,
+            screen_bind_group,
+            shadow_pipeline,
+            shadow_buffer,
+            shadow_bind_group,
+            decoration: Decoration::none(),
+            content: Rect::new(0, 0, safe_width as i32, safe_height as i32),
+            loupe_pipeline,
+            loupe_sampler,
+            loupe_texture_bind_group: None,
+            loupe_buffer,
+            loupe_bind_group,
+            loupe: None,
             sampler,
             vertex_buffer,
             index_buffer,
             uniform_buffer,
             uniform_bind_group,
+            opacity: 1.0,
+            filter: FilterMode::default(),
             width: safe_width,
             height: safe_height,
             max_texture_size,
@@ -372,22 +937,52 @@ impl WgpuRenderer {
 
                 // Reconfigure surface with new size
                 self.surface.configure(&self.device, &self.config);
+                self.queue.write_buffer(
+                    &self.screen_buffer,
+                    0,
+                    bytemuck::cast_slice(&[ScreenUniforms {
+                        size: [safe_width as f32, safe_height as f32],
+                        _padding: [0.0; 2],
+                    }]),
+                );
                 debug!("Resized to {}x{}", safe_width, safe_height);
             }
         }
     }
 
     pub fn upload_texture(&mut self, image: &ImageData) -> Result<()> {
-        // Clamp texture size to device limits
-        let tex_width = image.width.min(MAX_TEXTURE_SIZE).min(self.max_texture_size);
-        let tex_height = image
-            .height
-            .min(MAX_TEXTURE_SIZE)
-            .min(self.max_texture_size);
+        // Downscale on the CPU only if the image exceeds the device texture limit
+        let max_size = MAX_TEXTURE_SIZE.min(self.max_texture_size);
+        let downscaled = if image.width > max_size || image.height > max_size {
+            let scale =
+                (max_size as f32 / image.width as f32).min(max_size as f32 / image.height as f32);
+            let new_width = ((image.width as f32 * scale) as u32).clamp(1, max_size);
+            let new_height = ((image.height as f32 * scale) as u32).clamp(1, max_size);
+            let source = image::ImageBuffer::<image::Rgba<u8>, &[u8]>::from_raw(
+                image.width,
+                image.height,
+                &image.rgba_data[..],
+            )
+            .context("Image data does not match its dimensions")?;
+            Some(image::imageops::resize(
+                &source,
+                new_width,
+                new_height,
+                image::imageops::FilterType::Triangle,
+            ))
+        } else {
+            None
+        };
 
+        let (tex_width, tex_height, source_data) = match downscaled {
+            Some(ref resized) => (resized.width(), resized.height(), &resized.as_raw()[..]),
+            None => (image.width, image.height, &image.rgba_data[..]),
+        };
+
+        let mip_level_count = mip_level_count(tex_width, tex_height);
         debug!(
-            "Uploading texture: {}x{} (clamped from {}x{})",
-            tex_width, tex_height, image.width, image.height
+            "Uploading texture: {}x{} with {} mip levels (source {}x{})",
+            tex_width, tex_height, mip_level_count, image.width, image.height
         );
 
         let texture_size = wgpu::Extent3d {
@@ -398,53 +993,27 @@ impl WgpuRenderer {
 
         let texture = self.device.create_texture(&wgpu::TextureDescriptor {
             size: texture_size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: IMAGE_TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
             label: Some("image_texture"),
             view_formats: &[],
         });
 
-        // Select appropriate mipmap level if texture was clamped
-        let (source_width, source_height, source_data) =
-            if tex_width < image.width || tex_height < image.height {
-                // Use mipmap to reduce upload size
-                let scale_ratio = (tex_width as f32 / image.width as f32)
-                    .min(tex_height as f32 / image.height as f32);
-
-                let mip_level = if !image.mipmaps.is_empty() && scale_ratio < 0.5 {
-                    let ideal_level = (1.0 / scale_ratio).log2().floor() as usize;
-                    ideal_level.min(image.mipmaps.len())
-                } else {
-                    0
-                };
-
-                if mip_level > 0 && mip_level <= image.mipmaps.len() {
-                    let mipmap = &image.mipmaps[mip_level - 1];
-                    debug!(
-                        "Using mipmap level {} ({}x{})",
-                        mip_level, mipmap.width, mipmap.height
-                    );
-                    (mipmap.width, mipmap.height, &mipmap.data[..])
-                } else {
-                    (image.width, image.height, &image.rgba_data[..])
-                }
-            } else {
-                (image.width, image.height, &image.rgba_data[..])
-            };
-
         // Convert BGRA to RGBA for wgpu using a streaming approach
         // to avoid allocating a full copy of the image
         // Process in chunks to reduce peak memory usage
         const CHUNK_ROWS: u32 = 256;
-        let row_bytes = (source_width * 4) as usize;
+        let row_bytes = (tex_width * 4) as usize;
         let mut row_buffer = vec![0u8; row_bytes * CHUNK_ROWS as usize];
 
         let mut y_offset = 0u32;
-        while y_offset < source_height {
-            let rows_to_process = CHUNK_ROWS.min(source_height - y_offset);
+        while y_offset < tex_height {
+            let rows_to_process = CHUNK_ROWS.min(tex_height - y_offset);
             let chunk_bytes = row_bytes * rows_to_process as usize;
             let src_start = (y_offset as usize) * row_bytes;
             let src_end = src_start + chunk_bytes;
@@ -470,7 +1039,7 @@ impl WgpuRenderer {
                     &row_buffer[..chunk_bytes],
                     wgpu::ImageDataLayout {
                         offset: 0,
-                        bytes_per_row: Some(4 * source_width),
+                        bytes_per_row: Some(4 * tex_width),
                         rows_per_image: Some(rows_to_process),
                     },
                     wgpu::Extent3d {
@@ -484,6 +1053,9 @@ impl WgpuRenderer {
             y_offset += rows_to_process;
         }
 
+        // Build the rest of the mip chain on the GPU
+        self.generate_mipmaps(&texture, mip_level_count);
+
         let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let texture_bind_group_layout = &self.render_pipeline.get_bind_group_layout(0);
 
@@ -502,21 +1074,185 @@ impl WgpuRenderer {
             label: Some("texture_bind_group"),
         });
 
+        let loupe_texture_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.loupe_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.loupe_sampler),
+                },
+            ],
+            label: Some("loupe_texture_bind_group"),
+        });
+
         self.texture = Some(texture);
         self.texture_bind_group = Some(texture_bind_group);
+        self.loupe_texture_bind_group = Some(loupe_texture_bind_group);
 
         Ok(())
     }
 
+    /// Fill mip levels 1..N by rendering each level from the one above it
+    fn generate_mipmaps(&self, texture: &wgpu::Texture, mip_level_count: u32) {
+        if mip_level_count <= 1 {
+            return;
+        }
+
+        let layout = self.mipmap_pipeline.get_bind_group_layout(0);
+        let views: Vec<wgpu::TextureView> = (0..mip_level_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("mip_level_view"),
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Mipmap Encoder"),
+            });
+
+        for target in 1..mip_level_count as usize {
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[target - 1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.mipmap_sampler),
+                    },
+                ],
+                label: Some("mipmap_bind_group"),
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &views[target],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.mipmap_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
     pub fn update_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity;
+        self.write_uniforms();
+        self.write_loupe_uniforms();
+    }
+
+    /// Show the magnifier loupe, or hide it with `None`
+    pub fn set_loupe(&mut self, lens: Option<Lens>) {
+        if self.loupe != lens {
+            self.loupe = lens;
+            self.write_loupe_uniforms();
+        }
+    }
+
+    /// Select the resampling filter used for the image quad
+    pub fn set_filter(&mut self, filter: FilterMode) {
+        if self.filter != filter {
+            self.filter = filter;
+            self.write_uniforms();
+        }
+    }
+
+    /// Set the corners, border and shadow drawn around the image
+    pub fn set_decoration(&mut self, decoration: &Decoration) {
+        self.decoration = decoration.clone();
+        self.write_uniforms();
+        self.write_shadow_uniforms();
+    }
+
+    /// Place the image inside the surface (inset when the surface has room for a shadow)
+    pub fn set_content(&mut self, content: Rect) {
+        if self.content != content {
+            self.content = content;
+            self.write_uniforms();
+            self.write_shadow_uniforms();
+        }
+    }
+
+    fn write_uniforms(&self) {
         let uniforms = Uniforms {
-            opacity,
-            _padding: [0.0; 3],
+            radius: self.decoration.radius as f32,
+            border_width: self.decoration.border_width as f32,
+            size: [self.content.width as f32, self.content.height as f32],
+            border_color: rgba(self.decoration.border_color),
+            ..Uniforms::plain(self.opacity, self.filter)
         };
         self.queue
             .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
     }
 
+    fn write_shadow_uniforms(&self) {
+        let rect = |r: Rect| [r.x as f32, r.y as f32, r.width as f32, r.height as f32];
+        let uniforms = ShadowUniforms {
+            content: rect(self.content),
+            shape: rect(self.decoration.shadow_rect(self.content)),
+            color: rgba(self.decoration.shadow_color),
+            radius: self.decoration.radius as f32,
+            blur: self.decoration.shadow as f32,
+            _padding: [0.0; 2],
+        };
+        self.queue
+            .write_buffer(&self.shadow_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+
+    fn write_loupe_uniforms(&self) {
+        let Some(lens) = self.loupe else {
+            return;
+        };
+        let rect = lens.rect;
+        let uniforms = LoupeUniforms {
+            rect: [
+                rect.x as f32,
+                rect.y as f32,
+                rect.width as f32,
+                rect.height as f32,
+            ],
+            center: [lens.center.0, lens.center.1],
+            zoom: lens.zoom as f32,
+            shape: match lens.shape {
+                LoupeShape::Circle => 0,
+                LoupeShape::Square => 1,
+            },
+            border_width: loupe::BORDER_WIDTH as f32,
+            // The CPU darkens sRGB bytes; gamma 2.2 approximates that in linear light
+            grid: (loupe::GRID_FACTOR as f32 / 256.0).powf(2.2),
+            opacity: self.opacity,
+            _padding: 0.0,
+            outside_color: rgba(loupe::OUTSIDE_COLOR),
+            border_color: rgba(loupe::BORDER_COLOR),
+            highlight_color: rgba(loupe::HIGHLIGHT_COLOR),
+        };
+        self.queue
+            .write_buffer(&self.loupe_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+
     /// Render a frame and return whether successful
     pub fn render(&mut self) -> Result<bool> {
         if self.texture_bind_group.is_none() {
@@ -575,28 +1311,70 @@ impl WgpuRenderer {
                 timestamp_writes: None,
             });
 
+            if self.decoration.shadow > 0 {
+                render_pass.set_pipeline(&self.shadow_pipeline);
+                render_pass.set_bind_group(0, &self.shadow_bind_group, &[]);
+                render_pass.draw(0..4, 0..1);
+            }
+
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, self.texture_bind_group.as_ref().unwrap(), &[]);
             render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.set_viewport(0.0, 0.0, self.width as f32, self.height as f32, 0.0, 1.0);
+            let content = self.content;
+            render_pass.set_viewport(
+                content.x as f32,
+                content.y as f32,
+                content.width as f32,
+                content.height as f32,
+                0.0,
+                1.0,
+            );
             render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
 
-            if let (Some(overlay_bind_group), Some(viewport)) = (
-                self.overlay_texture_bind_group.as_ref(),
-                self.overlay_viewport,
-            ) {
-                render_pass.set_viewport(
-                    viewport[0],
-                    viewport[1],
-                    viewport[2],
-                    viewport[3],
-                    0.0,
-                    1.0,
-                );
-                render_pass.set_bind_group(0, overlay_bind_group, &[]);
-                render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+            // Each overlay's backgrounds, pixels and text, so later layers cover all three
+            for index in 0..OverlayLayer::COUNT {
+                if index == OverlayLayer::Loupe as usize {
+                    if let (Some(lens), Some(bind_group)) =
+                        (self.loupe, self.loupe_texture_bind_group.as_ref())
+                    {
+                        let rect = lens.rect;
+                        render_pass.set_pipeline(&self.loupe_pipeline);
+                        render_pass.set_viewport(
+                            rect.x as f32,
+                            rect.y as f32,
+                            rect.width as f32,
+                            rect.height as f32,
+                            0.0,
+                            1.0,
+                        );
+                        render_pass.set_bind_group(0, bind_group, &[]);
+                        render_pass.set_bind_group(1, &self.loupe_bind_group, &[]);
+                        render_pass.draw(0..4, 0..1);
+                    }
+                }
+                // Everything else is placed in surface pixels
+                render_pass.set_viewport(0.0, 0.0, self.width as f32, self.height as f32, 0.0, 1.0);
+                if let Some(frames) = self.frames[index].as_ref().filter(|b| b.count > 0) {
+                    render_pass.set_pipeline(&self.frame_pipeline);
+                    render_pass.set_bind_group(0, &self.panel_screen_bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, frames.buffer.slice(..));
+                    render_pass.draw(0..4, 0..frames.count);
+                }
+                if let Some(overlay) = self.overlays[index].as_ref().filter(|o| o.visible) {
+                    render_pass.set_pipeline(&self.panel_image_pipeline);
+                    render_pass.set_bind_group(0, &self.panel_screen_bind_group, &[]);
+                    render_pass.set_bind_group(1, &overlay.bind_group, &[]);
+                    render_pass.draw(0..4, 0..1);
+                }
+                if let Some(text) = self.texts[index].as_ref().filter(|b| b.count > 0) {
+                    render_pass.set_pipeline(&self.text_pipeline);
+                    render_pass.set_bind_group(0, &self.atlas_bind_group, &[]);
+                    render_pass.set_bind_group(1, &self.screen_bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, text.buffer.slice(..));
+                    render_pass.draw(0..4, 0..text.count);
+                }
             }
         }
 
@@ -606,55 +1384,90 @@ impl WgpuRenderer {
         Ok(true)
     }
 
-    pub fn update_overlay_texture(
-        &mut self,
-        width: u32,
-        height: u32,
-        viewport: [f32; 4],
-        rgba_data: &[u8],
-    ) -> Result<()> {
-        if width == 0 || height == 0 {
-            self.clear_overlay_texture();
-            return Ok(());
+    /// Draw a BGRA panel (straight alpha) 1:1 at `rect` in an overlay layer
+    ///
+    /// The layer's texture and bind group are kept and only replaced when the
+    /// panel outgrows them, so callers upload only when the pixels change.
+    pub fn update_overlay_texture(&mut self, layer: OverlayLayer, rect: Rect, bgra: &[u8]) {
+        let (width, height) = (rect.width.max(0) as u32, rect.height.max(0) as u32);
+        if width == 0 || height == 0 || bgra.len() < (width * height * 4) as usize {
+            self.clear_overlay_texture(layer);
+            return;
         }
-
-        let texture_size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
+        let fits = |size: (u32, u32)| size.0 >= width && size.1 >= height;
+        let current = self.overlays[layer as usize].as_ref().map(|o| o.size);
+        if !current.is_some_and(fits) {
+            let (w, h) = current.unwrap_or((0, 0));
+            let overlay = self.create_overlay_texture((w.max(width), h.max(height)));
+            self.overlays[layer as usize] = Some(overlay);
+        }
+        let Some(overlay) = self.overlays[layer as usize]
+            .as_mut()
+            .filter(|o| fits(o.size))
+        else {
+            self.clear_overlay_texture(layer);
+            return;
         };
-
-        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            label: Some("overlay_texture"),
-            view_formats: &[],
-        });
-
         self.queue.write_texture(
             wgpu::ImageCopyTexture {
-                texture: &texture,
+                texture: &overlay.texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            rgba_data,
+            bgra,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Some(4 * width),
                 rows_per_image: Some(height),
             },
-            texture_size,
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
         );
+        let position = [
+            rect.x as f32,
+            rect.y as f32,
+            rect.width as f32,
+            rect.height as f32,
+        ];
+        self.queue
+            .write_buffer(&overlay.rect_buffer, 0, bytemuck::cast_slice(&position));
+        overlay.visible = true;
+    }
 
+    /// Create an overlay layer texture of at least `size` and its bind group
+    fn create_overlay_texture(&self, size: (u32, u32)) -> OverlayTexture {
+        let size = (
+            size.0.min(self.max_texture_size),
+            size.1.min(self.max_texture_size),
+        );
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Panels are rendered as BGRA, so they upload without a swizzle
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("overlay_texture"),
+            view_formats: &[],
+        });
+        let rect_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Overlay Rect Buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let layout = &self.render_pipeline.get_bind_group_layout(0);
         let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
+            layout: &self.panel_image_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -662,21 +1475,134 @@ impl WgpuRenderer {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    resource: rect_buffer.as_entire_binding(),
                 },
             ],
             label: Some("overlay_texture_bind_group"),
         });
+        OverlayTexture {
+            texture,
+            size,
+            rect_buffer,
+            bind_group,
+            visible: false,
+        }
+    }
 
-        self.overlay_texture = Some(texture);
-        self.overlay_texture_bind_group = Some(bind_group);
-        self.overlay_viewport = Some(viewport);
-        Ok(())
+    /// Stop drawing an overlay layer's pixels; its texture is kept for the next panel
+    pub fn clear_overlay_texture(&mut self, layer: OverlayLayer) {
+        if let Some(overlay) = self.overlays[layer as usize].as_mut() {
+            overlay.visible = false;
+        }
+    }
+
+    /// Replace the backgrounds drawn beneath an overlay layer's pixels (surface coordinates)
+    pub fn update_overlay_frames(&mut self, layer: OverlayLayer, frames: &[PanelFrame]) {
+        let instances: Vec<FrameInstance> = frames.iter().map(FrameInstance::from).collect();
+        let slot = &mut self.frames[layer as usize];
+        Self::write_instances(
+            &self.device,
+            &self.queue,
+            slot,
+            &instances,
+            "Frame Instance Buffer",
+        );
+    }
+
+    /// Copy a region of the glyph atlas (`ATLAS_SIZE` bytes per row) to the GPU
+    pub fn update_glyph_atlas(&mut self, pixels: &[u8], region: Rect) {
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.atlas_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: region.x as u32,
+                    y: region.y as u32,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: (region.y as u32 * ATLAS_SIZE + region.x as u32) as u64,
+                bytes_per_row: Some(ATLAS_SIZE),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: region.width as u32,
+                height: region.height as u32,
+                depth_or_array_layers: 1,
+            },
+        );
     }
 
-    pub fn clear_overlay_texture(&mut self) {
-        self.overlay_texture = None;
-        self.overlay_texture_bind_group = None;
-        self.overlay_viewport = None;
+    /// Replace the glyphs drawn over an overlay layer (surface coordinates)
+    pub fn update_overlay_text(&mut self, layer: OverlayLayer, quads: &[GlyphQuad]) {
+        let instances: Vec<GlyphInstance> = quads.iter().map(GlyphInstance::from).collect();
+        let slot = &mut self.texts[layer as usize];
+        Self::write_instances(
+            &self.device,
+            &self.queue,
+            slot,
+            &instances,
+            "Glyph Instance Buffer",
+        );
+    }
+
+    /// Write instances into a batch, replacing its buffer only when they do not fit
+    fn write_instances<T: bytemuck::Pod>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        slot: &mut Option<InstanceBatch>,
+        instances: &[T],
+        label: &str,
+    ) {
+        match slot {
+            Some(batch) if instances.is_empty() => batch.count = 0,
+            Some(batch) if batch.capacity >= instances.len() => {
+                queue.write_buffer(&batch.buffer, 0, bytemuck::cast_slice(instances));
+                batch.count = instances.len() as u32;
+            }
+            _ if instances.is_empty() => {}
+            _ => {
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents: bytemuck::cast_slice(instances),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                });
+                *slot = Some(InstanceBatch {
+                    buffer,
+                    capacity: instances.len(),
+                    count: instances.len() as u32,
+                });
+            }
+        }
+    }
+}
+
+/// BGRA bytes as RGBA floats
+fn rgba(color: [u8; 4]) -> [f32; 4] {
+    let [b, g, r, a] = color.map(|c| c as f32 / 255.0);
+    [r, g, b, a]
+}
+
+/// Number of mip levels for a full chain down to 1x1
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mip_level_count() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 1), 2);
+        assert_eq!(mip_level_count(1920, 1080), 11);
+        assert_eq!(mip_level_count(1024, 1024), 11);
+        assert_eq!(mip_level_count(1025, 3), 11);
+        // Degenerate sizes still get one level
+        assert_eq!(mip_level_count(0, 0), 1);
     }
 }